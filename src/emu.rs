@@ -1,28 +1,52 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::{env, io};
+use std::path::{Path, PathBuf};
 use std::time::{Instant};
 use sdl2::event::Event;
 use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
 use sdl2::{EventPump};
 use sdl2::render::{Texture, WindowCanvas};
 use crate::nes::NES;
+#[cfg(debug_assertions)]
+use crate::nes::cpu::editor::{MemoryEditor, MEMORY_EDITOR_HEIGHT, MEMORY_EDITOR_WIDTH};
+use crate::nes::cpu::trace::CpuLogLine;
+use crate::nes::cpu::CpuState;
 use crate::nes::io::frame::Frame;
+use crate::nes::io::joycon::ControllerInputs;
 use crate::nes::io::joycon::joycon_status::JoyconButton;
-use crate::nes::ppu::registers::mask::MaskFlag::{ShowBackground, ShowSprites};
-use crate::nes::rom::ROM;
-use crate::util::bitvec::BitVector;
+use crate::nes::ppu::palette::Palette;
+use crate::nes::ppu::viewer::{render_nametables, render_palettes, render_pattern_tables, render_sprites, NAMETABLES_HEIGHT, NAMETABLES_WIDTH, PALETTES_HEIGHT, PALETTES_WIDTH, PATTERN_TABLES_HEIGHT, PATTERN_TABLES_WIDTH, SPRITES_HEIGHT, SPRITES_WIDTH};
+use crate::nes::rom::{RomError, ROM};
+use crate::nes::rom::mappers::mapper20::Mapper20;
+use crate::util::audio::{AudioConfig, Channel};
+use crate::util::display::DisplayConfig;
+use crate::util::replay::{Replay, ReplayHeader, ReplayPlayer, ReplayRecorder};
 use crate::util::savestate::{SaveState};
-use crate::util::sleep::PreciseSleeper;
+use crate::util::timestamp;
+use crate::util::sleep::FrameTimer;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DebugCommand {
+    Step,
+    Continue,
+    RunUntil(u16),
+    Quit,
+}
 
 pub struct Emulator {
     pub nes: NES,
-    pub sleeper: PreciseSleeper,
+    pub frame_timer: FrameTimer,
 
     pub fps_timestamp: Instant,
-    pub frame_timestamp: Instant,
     pub fps: f64,
     pub frames: u64,
+    pub battery_flush_timer: u64,
+    // set while a replay's power cycle has wiped the battery RAM, until the next ROM load
+    pub battery_suspended: bool,
+    // older versions kept battery saves here, they're picked up if there's no save next to the ROM
+    pub legacy_saves_dir: PathBuf,
 
     pub volume: f32,
     pub mute: bool,
@@ -32,22 +56,53 @@ pub struct Emulator {
     pub mute_noise: bool,
     pub mute_dmc: bool,
     pub fast_forward: bool,
+    pub paused: bool,
+    pub advance_frame: bool,
     pub hide_background: bool,
     pub hide_sprites: bool,
+    pub show_nametables: bool,
+    pub nametable_frame: Frame,
+    pub show_palettes: bool,
+    pub palette_frame: Frame,
+    pub show_sprite_viewer: bool,
+    pub sprite_viewer_frame: Frame,
+    pub show_pattern_tables: bool,
+    pub pattern_table_frame: Frame,
+    pub recorder: Option<ReplayRecorder>,
+    pub replay_player: Option<ReplayPlayer>,
+    pub auto_patch: bool,
+    pub audio_config: AudioConfig,
+    #[cfg(debug_assertions)]
+    pub memory_editor: MemoryEditor,
+    #[cfg(debug_assertions)]
+    pub memory_editor_frame: Frame,
+}
+
+struct ViewTextures<'a> {
+    frame: Texture<'a>,
+    nametables: Texture<'a>,
+    palettes: Texture<'a>,
+    sprites: Texture<'a>,
+    pattern_tables: Texture<'a>,
 }
 
 impl Emulator {
-    const TARGET_FPS: f64 = 60.0;
+    const BATTERY_FLUSH_FRAMES: u64 = 60;
+    // 341 * 262 / 3 PPU dots, rounded down (odd frames are a dot shorter with rendering on)
+    pub const NTSC_CPU_CYCLES_PER_FRAME: usize = 29780;
+    const SCREENSHOT_DIR_VAR: &'static str = "ALPINES_SCREENSHOT_DIR";
 
     pub fn new() -> Self {
         Emulator {
             nes: NES::new(),
-            sleeper: PreciseSleeper::new(),
+            frame_timer: FrameTimer::new(FrameTimer::NES_FPS),
 
             fps_timestamp: Instant::now(),
-            frame_timestamp: Instant::now(),
             fps: 0.0,
             frames: 0,
+            battery_flush_timer: 0,
+            battery_suspended: false,
+            legacy_saves_dir: PathBuf::from("Saves"),
 
             volume: 1.00, // todo: implement
             mute: false,
@@ -57,59 +112,218 @@ impl Emulator {
             mute_noise: false,
             mute_dmc: false,
             fast_forward: false,
+            paused: false,
+            advance_frame: false,
             hide_background: false,
             hide_sprites: false,
+            show_nametables: false,
+            nametable_frame: Frame::with_size(NAMETABLES_WIDTH, NAMETABLES_HEIGHT),
+            show_palettes: false,
+            palette_frame: Frame::with_size(PALETTES_WIDTH, PALETTES_HEIGHT),
+            show_sprite_viewer: false,
+            sprite_viewer_frame: Frame::with_size(SPRITES_WIDTH, SPRITES_HEIGHT),
+            show_pattern_tables: false,
+            pattern_table_frame: Frame::with_size(PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT),
+            recorder: None,
+            replay_player: None,
+            auto_patch: true,
+            audio_config: AudioConfig::new(),
+            #[cfg(debug_assertions)]
+            memory_editor: MemoryEditor::new(),
+            #[cfg(debug_assertions)]
+            memory_editor_frame: Frame::with_size(MEMORY_EDITOR_WIDTH, MEMORY_EDITOR_HEIGHT),
         }
     }
 
     pub fn run_rom(&mut self, rom: &ROM) {
+        self.run_with_display_config(rom, &DisplayConfig::new());
+    }
+
+    pub fn run_with_display_config(&mut self, rom: &ROM, config: &DisplayConfig) {
         self.load_rom(&rom);
 
-        const SCALE: f32 = 3.0;
-        const WINDOW_WIDTH: u32 = (SCALE * Frame::WIDTH as f32) as u32;
-        const WINDOW_HEIGHT: u32 = (SCALE * Frame::HEIGHT as f32) as u32;
+        let (window_width, window_height) = config.get_window_size();
+        let viewport = config.get_viewport();
+        let visible_rect = Rect::new(viewport.x1 as i32, viewport.y1 as i32,
+            (viewport.x2 - viewport.x1) as u32, (viewport.y2 - viewport.y1) as u32);
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
-        let window = video_subsystem.window("alpiNES", WINDOW_WIDTH, WINDOW_HEIGHT)
+        let window = video_subsystem.window("alpiNES", window_width, window_height)
             .position_centered().build().unwrap();
         let mut canvas = window.into_canvas().build().unwrap();
         let mut event_pump = sdl_context.event_pump().unwrap();
         let creator = canvas.texture_creator();
-        let mut texture = creator.create_texture_target(PixelFormatEnum::RGB24, Frame::WIDTH as u32, Frame::HEIGHT as u32).unwrap();
-
-        self.nes.cpu.memory.apu.init_audio_player(&sdl_context);
+        let mut textures = ViewTextures {
+            frame: creator.create_texture_target(PixelFormatEnum::RGB24, Frame::WIDTH as u32, Frame::HEIGHT as u32).unwrap(),
+            nametables: creator.create_texture_target(PixelFormatEnum::RGB24, NAMETABLES_WIDTH as u32, NAMETABLES_HEIGHT as u32).unwrap(),
+            palettes: creator.create_texture_target(PixelFormatEnum::RGB24, PALETTES_WIDTH as u32, PALETTES_HEIGHT as u32).unwrap(),
+            sprites: creator.create_texture_target(PixelFormatEnum::RGB24, SPRITES_WIDTH as u32, SPRITES_HEIGHT as u32).unwrap(),
+            pattern_tables: creator.create_texture_target(PixelFormatEnum::RGB24, PATTERN_TABLES_WIDTH as u32, PATTERN_TABLES_HEIGHT as u32).unwrap(),
+        };
+
+        // debug builds get a memory editor in a second window, next to the game
+        #[cfg(debug_assertions)]
+        let mut editor_canvas = video_subsystem.window("alpiNES - Memory", 2 * MEMORY_EDITOR_WIDTH as u32, 2 * MEMORY_EDITOR_HEIGHT as u32)
+            .build().unwrap().into_canvas().build().unwrap();
+        #[cfg(debug_assertions)]
+        let editor_creator = editor_canvas.texture_creator();
+        #[cfg(debug_assertions)]
+        let mut editor_texture = editor_creator.create_texture_target(PixelFormatEnum::RGB24, MEMORY_EDITOR_WIDTH as u32, MEMORY_EDITOR_HEIGHT as u32).unwrap();
+
+        self.nes.cpu.memory.apu.init_audio_player(&sdl_context, &self.audio_config);
 
         loop {
+            // while paused, only the event loop runs, and F steps a frame at a time
+            if self.paused {
+                self.handle_input(&mut event_pump);
+                #[cfg(debug_assertions)]
+                self.tick_memory_editor(&mut editor_canvas, &mut editor_texture);
+                if self.advance_frame {
+                    self.advance_frame = false;
+                    self.step_frame();
+                    self.render_view(&mut canvas, &mut textures, visible_rect);
+                }
+                self.frame_timer.sync();
+                continue;
+            }
+
             if self.nes.cpu.memory.ppu.poll_nmi() {
                 self.nes.cpu.handle_nmi();
                 self.nes.cpu.memory.ppu.clear_nmi();
 
                 self.handle_input(&mut event_pump);
-                self.render_frame(&mut canvas, &mut texture);
+                self.tick_replay();
+                self.render_view(&mut canvas, &mut textures, visible_rect);
+                #[cfg(debug_assertions)]
+                self.tick_memory_editor(&mut editor_canvas, &mut editor_texture);
                 self.sleep_frame();
-            } else if rom.mapper_id == 4 && self.nes.cpu.memory.ppu.memory.rom.mapper4.poll_irq() {
-               self.nes.cpu.handle_irq();
+                self.tick_battery_flush();
+            } else if self.nes.cpu.memory.poll_mapper_irq() || self.nes.cpu.memory.apu.poll_irq() {
+                self.nes.cpu.handle_irq();
             }
 
-            let Ok(_) = self.nes.step() else { return };
+            if self.nes.step().is_err() {
+                self.flush_battery_ram();
+                return;
+            }
         }
     }
 
-    fn render_frame(&mut self, canvas: &mut WindowCanvas, texture: &mut Texture) {
+    // Runs one frame's worth of CPU cycles (see NES::run_frame) and returns the picture.
+    pub fn step_frame(&mut self) -> Frame {
+        self.nes.run_frame();
+        // the PPU's frame keeps its layers apart, so the hide background/sprites toggles still work
+        let mut frame = self.nes.cpu.memory.ppu.frame.clone();
+        frame.compose();
+        frame
+    }
+
+    fn render_view(&mut self, canvas: &mut WindowCanvas, textures: &mut ViewTextures, visible_rect: Rect) {
+        if self.show_nametables {
+            self.render_nametables(canvas, &mut textures.nametables);
+        } else if self.show_palettes {
+            self.render_palettes(canvas, &mut textures.palettes);
+        } else if self.show_sprite_viewer {
+            self.render_sprites(canvas, &mut textures.sprites);
+        } else if self.show_pattern_tables {
+            self.render_pattern_tables(canvas, &mut textures.pattern_tables);
+        } else {
+            self.render_frame(canvas, &mut textures.frame, visible_rect);
+        }
+    }
+
+    // Only the part of the frame inside the overscan is shown, stretched over the whole window.
+    fn render_frame(&mut self, canvas: &mut WindowCanvas, texture: &mut Texture, visible_rect: Rect) {
         let ppu = &mut self.nes.cpu.memory.ppu;
-        let show_background = !self.hide_background && ppu.mask.is_set(ShowBackground);
-        let show_sprites = !self.hide_sprites && ppu.mask.is_set(ShowSprites);
+        // PPUMASK is already applied per scanline while rendering, only the debug toggles remain
+        let show_background = !self.hide_background;
+        let show_sprites = !self.hide_sprites;
         match (show_background, show_sprites) {
-            (true, true) => texture.update(None, ppu.frame.compose(), Frame::WIDTH * 3).unwrap(),
+            (true, true) => texture.update(None, ppu.frame.clone().compose(), Frame::WIDTH * 3).unwrap(),
             (true, false) => texture.update(None, &ppu.frame.background, Frame::WIDTH * 3).unwrap(),
             (false, true) => texture.update(None, &ppu.frame.sprite, Frame::WIDTH * 3).unwrap(),
             (false, false) => texture.update(None, &[0; 3 * Frame::WIDTH * Frame::HEIGHT], Frame::WIDTH * 3).unwrap(),
         }
-        canvas.copy(&texture, None, None).unwrap();
+        canvas.copy(texture, visible_rect, None).unwrap();
+        canvas.present();
+    }
+
+    fn render_nametables(&mut self, canvas: &mut WindowCanvas, texture: &mut Texture) {
+        render_nametables(&self.nes.cpu.memory.ppu, &mut self.nametable_frame);
+        texture.update(None, &self.nametable_frame.background, NAMETABLES_WIDTH * 3).unwrap();
+        canvas.copy(texture, None, None).unwrap();
+        canvas.present();
+    }
+
+    fn render_palettes(&mut self, canvas: &mut WindowCanvas, texture: &mut Texture) {
+        render_palettes(&self.nes.cpu.memory.ppu, &mut self.palette_frame);
+        texture.update(None, &self.palette_frame.background, PALETTES_WIDTH * 3).unwrap();
+        canvas.copy(texture, None, None).unwrap();
+        canvas.present();
+    }
+
+    fn render_sprites(&mut self, canvas: &mut WindowCanvas, texture: &mut Texture) {
+        render_sprites(&self.nes.cpu.memory.ppu, &mut self.sprite_viewer_frame);
+        texture.update(None, &self.sprite_viewer_frame.background, SPRITES_WIDTH * 3).unwrap();
+        canvas.copy(texture, None, None).unwrap();
+        canvas.present();
+    }
+
+    fn render_pattern_tables(&mut self, canvas: &mut WindowCanvas, texture: &mut Texture) {
+        render_pattern_tables(&self.nes.cpu.memory.ppu, &mut self.pattern_table_frame, 0);
+        texture.update(None, &self.pattern_table_frame.background, PATTERN_TABLES_WIDTH * 3).unwrap();
+        canvas.copy(texture, None, None).unwrap();
         canvas.present();
     }
 
-    fn handle_input(&mut self, event_pump: &mut EventPump) {
+    #[cfg(debug_assertions)]
+    fn tick_memory_editor(&mut self, canvas: &mut WindowCanvas, texture: &mut Texture) {
+        if self.memory_editor.tick(&mut self.nes.cpu.memory) {
+            self.memory_editor.render(&self.nes.cpu.memory, &mut self.memory_editor_frame);
+            texture.update(None, &self.memory_editor_frame.background, MEMORY_EDITOR_WIDTH * 3).unwrap();
+            canvas.copy(texture, None, None).unwrap();
+            canvas.present();
+        }
+    }
+
+    // Screenshots go to $ALPINES_SCREENSHOT_DIR, or the working directory when it isn't set.
+    pub fn save_screenshot(&mut self) {
+        let screenshot_dir = env::var_os(Emulator::SCREENSHOT_DIR_VAR).map(PathBuf::from).unwrap_or_default();
+        let screenshot_path = screenshot_dir.join(format!("screenshot_{}.png", timestamp::now()));
+        let mut frame = self.nes.cpu.memory.ppu.frame.clone();
+        frame.compose();
+        match frame.export_png(&screenshot_path) {
+            Ok(()) => println!("saved {}", screenshot_path.display()),
+            Err(err) => println!("[WARNING] unable to save {}: {}", screenshot_path.display(), err),
+        }
+    }
+
+    // Writes the mixed APU output to a 16-bit WAV file until stop_audio_recording is called.
+    pub fn start_audio_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.nes.cpu.memory.apu.start_audio_recording(path)
+    }
+
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        self.nes.cpu.memory.apu.stop_audio_recording()
+    }
+
+    // How far ahead of the audio device emulation is allowed to run, see RateControl.
+    pub fn set_audio_latency(&mut self, latency_ms: f64) {
+        self.audio_config.buffer_ms = latency_ms as u32;
+        self.nes.cpu.memory.apu.set_audio_latency(latency_ms);
+    }
+
+    // Swaps the colors at runtime, ex: a PAL palette for PAL games. The change shows from the next
+    // pixel drawn.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.nes.cpu.memory.ppu.set_palette(&palette);
+    }
+
+    pub fn set_emphasis_attenuation(&mut self, attenuation: f64) {
+        self.nes.cpu.memory.ppu.set_emphasis_attenuation(attenuation);
+    }
+
+    pub fn handle_input(&mut self, event_pump: &mut EventPump) {
         let mut keymap_one = HashMap::new();
         keymap_one.insert(Keycode::Down, JoyconButton::Down);
         keymap_one.insert(Keycode::Up, JoyconButton::Up);
@@ -132,8 +346,17 @@ impl Emulator {
 
         for event in event_pump.poll_iter() {
             match event {
+                #[cfg(debug_assertions)]
+                Event::KeyDown { keycode: Some(keycode), .. } if self.handle_memory_editor_input(keycode) => {},
                 Event::Quit { .. } |
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    self.flush_battery_ram();
+                    if let Err(err) = self.stop_recording() {
+                        println!("[WARNING] unable to finish recording: {}", err);
+                    }
+                    if let Err(err) = self.stop_audio_recording() {
+                        println!("[WARNING] unable to finish audio recording: {}", err);
+                    }
                     std::process::exit(0)
                 },
                 Event::KeyDown { keycode: Some(Keycode::Num1), keymod, .. } => {
@@ -168,27 +391,39 @@ impl Emulator {
                 },
                 Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
                     self.mute_pulse_one = !self.mute_pulse_one;
-                    self.nes.cpu.memory.apu.audio_player.as_mut().unwrap().device.lock().mute_pulse_one = self.mute_pulse_one;
+                    self.nes.cpu.memory.apu.mute_channel(Channel::PulseOne, self.mute_pulse_one);
                 },
                 Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
                     self.mute_pulse_two = !self.mute_pulse_two;
-                    self.nes.cpu.memory.apu.audio_player.as_mut().unwrap().device.lock().mute_pulse_two = self.mute_pulse_two;
+                    self.nes.cpu.memory.apu.mute_channel(Channel::PulseTwo, self.mute_pulse_two);
                 },
                 Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
                     self.mute_triangle = !self.mute_triangle;
-                    self.nes.cpu.memory.apu.audio_player.as_mut().unwrap().device.lock().mute_triangle = self.mute_triangle;
+                    self.nes.cpu.memory.apu.mute_channel(Channel::Triangle, self.mute_triangle);
                 },
                 Event::KeyDown { keycode: Some(Keycode::F4), .. } => {
                     self.mute_noise = !self.mute_noise;
-                    self.nes.cpu.memory.apu.audio_player.as_mut().unwrap().device.lock().mute_noise = self.mute_noise;
+                    self.nes.cpu.memory.apu.mute_channel(Channel::Noise, self.mute_noise);
                 },
                 Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
                     self.mute_dmc = !self.mute_dmc;
-                    self.nes.cpu.memory.apu.audio_player.as_mut().unwrap().device.lock().mute_dmc = self.mute_dmc;
+                    self.nes.cpu.memory.apu.mute_channel(Channel::Dmc, self.mute_dmc);
                 },
                 Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
                     self.mute = !self.mute;
-                    self.nes.cpu.memory.apu.audio_player.as_mut().unwrap().device.lock().mute = self.mute;
+                    self.nes.cpu.memory.apu.mixer.mute = self.mute;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                    self.show_pattern_tables = !self.show_pattern_tables;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => {
+                    self.show_sprite_viewer = !self.show_sprite_viewer;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                    self.show_nametables = !self.show_nametables;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F10), .. } => {
+                    self.show_palettes = !self.show_palettes;
                 },
                 Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
                     self.hide_background = !self.hide_background;
@@ -196,12 +431,21 @@ impl Emulator {
                 Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
                     self.hide_sprites = !self.hide_sprites;
                 },
-                Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
+                Event::KeyDown { keycode: Some(Keycode::PrintScreen), .. } => {
+                    self.save_screenshot();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
                     self.fast_forward = true;
                 },
-                Event::KeyUp { keycode: Some(Keycode::Space), .. } => {
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => {
                     self.fast_forward = false;
                 },
+                Event::KeyDown { keycode: Some(Keycode::Space), repeat: false, .. } => {
+                    self.paused = !self.paused;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F), .. } if self.paused => {
+                    self.advance_frame = true;
+                },
                 Event::KeyDown { keycode, .. } => {
                     if let Some(key) = keymap_one.get(&keycode.unwrap_or(Keycode::Ampersand)) {
                         self.nes.cpu.memory.joycon1.set_button((*key).clone());
@@ -223,6 +467,36 @@ impl Emulator {
         }
     }
 
+    // PageUp/PageDown/Home/End scroll the memory editor, and G opens its jump dialog, which takes
+    // every key until the address is entered (Return) or dismissed (Escape).
+    #[cfg(debug_assertions)]
+    fn handle_memory_editor_input(&mut self, keycode: Keycode) -> bool {
+        let editor = &mut self.memory_editor;
+        if editor.is_jumping() {
+            match keycode {
+                Keycode::Return | Keycode::KpEnter => editor.confirm_jump(),
+                Keycode::Escape => editor.cancel_jump(),
+                Keycode::Backspace => editor.delete_digit(),
+                _ => {
+                    let name = keycode.name();
+                    if name.len() == 1 {
+                        editor.input_digit(name.chars().next().unwrap());
+                    }
+                }
+            }
+            return true;
+        }
+        match keycode {
+            Keycode::PageUp => editor.page_up(),
+            Keycode::PageDown => editor.page_down(),
+            Keycode::Home => editor.home(),
+            Keycode::End => editor.end(),
+            Keycode::G => editor.begin_jump(),
+            _ => return false,
+        }
+        true
+    }
+
     fn handle_savestate_input(&mut self, keymod: Mod, save_idx: u8) {
         if keymod == Mod::LGUIMOD.union(Mod::LALTMOD) {
             self.load_state(save_idx);
@@ -234,12 +508,8 @@ impl Emulator {
     fn sleep_frame(&mut self) {
         self.tick_fps();
         if !self.fast_forward {
-            let mut sleep_time = 1.0 / Emulator::TARGET_FPS - self.frame_timestamp.elapsed().as_secs_f64();
-            if sleep_time > 0.0 {
-                PreciseSleeper::new().precise_sleep(sleep_time);
-            }
+            self.frame_timer.sync();
         }
-        self.frame_timestamp = Instant::now();
     }
 
     fn tick_fps(&mut self) {
@@ -255,7 +525,7 @@ impl Emulator {
     pub fn load_state(&mut self, save_idx: u8) {
         println!("loading state {}...", save_idx);
 
-        let save_path_str = format!("Saves/{}/{}.savestate", self.nes.cpu.memory.rom.game_title, save_idx);
+        let save_path_str = format!("Saves/{}/{}.savestate", self.nes.cpu.memory.rom().game_title, save_idx);
         let save_path = Path::new(save_path_str.as_str());
         if let Some(save_state) = SaveState::deserialize(save_path) {
             SaveState::load_nes_state(&mut self.nes, &save_state);
@@ -265,14 +535,140 @@ impl Emulator {
     pub fn save_state(&mut self, save_idx: u8) {
         println!("saving state {}...", save_idx);
 
-        let game_title = &self.nes.cpu.memory.rom.game_title;
+        let game_title = &self.nes.cpu.memory.rom().game_title;
         let save_path_str = format!("Saves/{}/{}.savestate", game_title, save_idx);
         let save_path = Path::new(save_path_str.as_str());
         SaveState::serialize(save_path, &SaveState::new(&self.nes));
     }
 
+    // Power cycles the console and records every frame's controller inputs from then on. Battery RAM
+    // isn't loaded, so the replay plays back the same on any machine, and the save file is left alone
+    // until the next ROM load.
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_recording()?;
+        self.replay_player = None;
+        let rom = self.nes.cpu.memory.rom().clone();
+        self.flush_battery_ram();
+        self.battery_suspended = true;
+        self.nes.load_rom(&rom);
+        let header = ReplayHeader::new(&rom, self.nes.cpu.get_rng_seed());
+        self.recorder = Some(ReplayRecorder::create(path, &header)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    // Power cycles the console with the replay's RNG seed, and feeds it the recorded inputs instead of
+    // the keyboard's until the replay runs out. The loaded ROM has to be the one it was recorded on.
+    pub fn play_recording(&mut self, path: &Path) -> io::Result<()> {
+        let replay = Replay::load(path)?;
+        let rom = self.nes.cpu.memory.rom().clone();
+        if !replay.header.matches(&rom) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "replay was recorded on a different ROM"));
+        }
+        self.stop_recording()?;
+        self.flush_battery_ram();
+        self.battery_suspended = true;
+        self.nes.load_rom(&rom);
+        self.nes.cpu.set_rng_seed(replay.header.seed);
+        self.replay_player = Some(ReplayPlayer::new(replay.events));
+        Ok(())
+    }
+
+    // Runs once per frame, right after the keyboard has been read.
+    fn tick_replay(&mut self) {
+        let memory = &mut self.nes.cpu.memory;
+        if let Some(player) = &mut self.replay_player {
+            match player.next_frame() {
+                Some((controller_one, controller_two)) => {
+                    memory.joycon1.set_buttons(controller_one);
+                    memory.joycon2.set_buttons(controller_two);
+                }
+                None => {
+                    println!("replay finished after {} frames", player.get_frame());
+                    self.replay_player = None;
+                }
+            }
+        }
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.record_frame(memory.joycon1.get_buttons(), memory.joycon2.get_buttons()) {
+                println!("[WARNING] recording stopped: {}", err);
+                self.recorder = None;
+            }
+        }
+    }
+
+    // Loads a ROM from disk. With auto_patch on, a patch sitting next to it is applied, see
+    // ROM::find_patch.
+    pub fn load_rom_path(&mut self, path: &Path) -> Result<(), RomError> {
+        match ROM::find_patch(path).filter(|_| self.auto_patch) {
+            Some(patch_path) => self.load_rom_with_patch(path, &patch_path),
+            None => {
+                self.load_rom(&ROM::from_path(path)?);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn load_rom_with_patch(&mut self, rom_path: &Path, patch_path: &Path) -> Result<(), RomError> {
+        println!("patching {} with {}...", rom_path.display(), patch_path.display());
+        self.load_rom(&ROM::from_path_with_patch(rom_path, patch_path)?);
+        Ok(())
+    }
+
     pub fn load_rom(&mut self, rom: &ROM) {
+        self.flush_battery_ram();
         self.nes.load_rom(rom);
+        self.frame_timer = FrameTimer::new(self.nes.region.get_fps());
+        self.battery_flush_timer = 0;
+        self.battery_suspended = false;
+        self.load_battery_ram();
+    }
+
+    // Falls back to the old <legacy_saves_dir>/<title>/battery.sav location, and marks the PRG RAM
+    // dirty so the save moves next to the ROM on the next flush.
+    pub fn load_battery_ram(&mut self) {
+        let memory = &mut self.nes.cpu.memory;
+        let Some(save_path) = memory.rom().save_path.clone() else { return };
+        if !memory.rom().has_battery() {
+            return;
+        }
+        if save_path.exists() {
+            println!("loading battery ram from {}...", save_path.display());
+            memory.load_battery_ram(&save_path).expect("unable to load save file");
+            return;
+        }
+        let legacy_path = memory.rom().legacy_save_path(&self.legacy_saves_dir);
+        if legacy_path.exists() {
+            println!("loading battery ram from {}, it will be saved to {}...", legacy_path.display(), save_path.display());
+            memory.load_battery_ram(&legacy_path).expect("unable to load save file");
+            memory.prg_ram_dirty = true;
+        }
+    }
+
+    // Writes battery-backed PRG RAM next to the ROM, if it has changed since it was last written.
+    pub fn flush_battery_ram(&mut self) {
+        if self.battery_suspended {
+            return;
+        }
+        let memory = &mut self.nes.cpu.memory;
+        let Some(save_path) = memory.rom().save_path.clone() else { return };
+        if memory.rom().has_battery() && memory.prg_ram_dirty {
+            memory.save_battery_ram(&save_path).expect("unable to write save file");
+        }
+    }
+
+    fn tick_battery_flush(&mut self) {
+        self.battery_flush_timer += 1;
+        if self.battery_flush_timer >= Emulator::BATTERY_FLUSH_FRAMES {
+            self.battery_flush_timer = 0;
+            self.flush_battery_ram();
+        }
     }
 
     pub fn load(&mut self, program: &Vec<u8>) {
@@ -288,31 +684,356 @@ impl Emulator {
         self.run()
     }
 
+    // Runs until the CPU halts.
     pub fn run(&mut self) {
-        self.run_with_callback(|_| {});
-    }
-
-    pub fn run_with_callback<F>(&mut self, mut callback: F) where F: FnMut(&mut NES) {
         loop {
             if self.nes.cpu.memory.ppu.poll_nmi() {
                 self.tick_fps();
                 self.nes.cpu.handle_nmi();
             }
-            callback(&mut self.nes);
             let Ok(_) = self.nes.step() else { return };
         }
     }
 
+    // Runs a frame at a time until the CPU halts. The callback gets the finished frame and the CPU's
+    // registers, and answers with the buttons to hold during the next frame. It can't reach into the
+    // emulator, so it can't leave it in a state the hardware couldn't be in.
+    pub fn run_with_callback<F>(&mut self, mut callback: F) where F: FnMut(&Frame, &CpuState) -> ControllerInputs {
+        loop {
+            let running = self.nes.run_frame();
+            let state = self.nes.cpu.get_state();
+            let frame = &mut self.nes.cpu.memory.ppu.frame;
+            frame.compose();
+            let inputs = callback(frame, &state);
+            self.nes.cpu.memory.joycon1.set_buttons(inputs.player1);
+            self.nes.cpu.memory.joycon2.set_buttons(inputs.player2);
+            if !running { return }
+        }
+    }
+
+    pub fn run_with_debugger<F>(&mut self, rom: &ROM, mut breakpoint_fn: F) where F: FnMut(&NES) -> DebugCommand {
+        self.load_rom(rom);
+
+        let mut command = DebugCommand::Step;
+        loop {
+            self.poll_interrupts();
+
+            // the breakpoint callback sees the NES right before the next opcode is fetched
+            let should_break = match command {
+                DebugCommand::Step => true,
+                DebugCommand::Continue => false,
+                DebugCommand::RunUntil(address) => self.nes.cpu.program_counter == address,
+                DebugCommand::Quit => return,
+            };
+            if should_break {
+                command = breakpoint_fn(&self.nes);
+                if command == DebugCommand::Quit { return }
+            }
+
+            let Ok(_) = self.nes.step() else { return };
+        }
+    }
+
+    pub fn run_logged(&mut self, rom: &ROM) -> impl Iterator<Item = CpuLogLine> + '_ {
+        self.load_rom(rom);
+        self.trace()
+    }
+
+    // Runs from the current state, yielding the CPU state before each instruction until the CPU halts.
+    // nestest.nes is run headless by starting at $C000 (and CYC:7) instead of the reset vector.
+    pub fn trace(&mut self) -> impl Iterator<Item = CpuLogLine> + '_ {
+        let mut halted = false;
+        std::iter::from_fn(move || {
+            if halted { return None }
+            self.poll_interrupts();
+            let line = CpuLogLine::capture(&mut self.nes.cpu);
+            halted = self.nes.step().is_err();
+            Some(line)
+        })
+    }
+
+    pub fn poll_interrupts(&mut self) {
+        self.nes.poll_interrupts();
+    }
+
     pub fn reset(&mut self) {
         self.nes.reset();
     }
+
+    // Flips or swaps the Famicom Disk System disk, ex: when a game asks for side B. The drive reads
+    // as empty for a moment first, which is how the BIOS notices the change.
+    pub fn set_fds_side(&mut self, side: usize) {
+        let Some(mapper20) = self.nes.cpu.memory.rom_mut().mapper_as_mut::<Mapper20>() else {
+            println!("[WARNING] Attempt to change disk side of a cartridge");
+            return;
+        };
+        if side >= mapper20.get_side_count() {
+            println!("[WARNING] Attempt to insert disk side {}, the disk only has {}", side, mapper20.get_side_count());
+        } else {
+            mapper20.insert_disk(Some(side));
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use crate::nes::cpu::CPU;
     use crate::nes::cpu::mem::Memory;
+    use crate::nes::cpu::trace::{compare_log, Mismatch};
+    use crate::nes::rom::tests::test_rom;
+
+    // A disk with just the disk info block, and a BIOS that polls the drive for its first 16 bytes.
+    fn fds_rom(sides: usize) -> ROM {
+        let mut side = vec![0x01];
+        side.extend(b"*NINTENDO-HVC*");
+        side.resize(56, 0);
+        side.resize(65500, 0);
+        let disk = side.repeat(sides);
+
+        let mut bios = vec![0; 0x2000];
+        let program = [
+            0xA9, 0x01, 0x8D, 0x23, 0x40, // LDA #$01, STA $4023 (disk registers on)
+            0xA9, 0x45, 0x8D, 0x25, 0x40, // LDA #$45, STA $4025 (motor on, read mode, ready)
+            0xA2, 0x00,                   // LDX #$00
+            0xAD, 0x30, 0x40,             // LDA $4030
+            0x29, 0x02,                   // AND #$02 (byte transferred)
+            0xF0, 0xF9,                   // BEQ $E00C
+            0xAD, 0x31, 0x40,             // LDA $4031
+            0x9D, 0x00, 0x02,             // STA $0200,X
+            0xE8,                         // INX
+            0xE0, 0x10,                   // CPX #$10
+            0xD0, 0xEE,                   // BNE $E00C
+            0x4C, 0x1E, 0xE0,             // JMP $E01E
+        ];
+        bios[..program.len()].copy_from_slice(&program);
+        bios[0x1FFC] = 0x00; // reset vector -> $E000
+        bios[0x1FFD] = 0xE0;
+        ROM::from_fds(&disk, &bios).unwrap()
+    }
+
+    #[test]
+    fn test_fds_read_disk() {
+        let mut emu = Emulator::new();
+        emu.load_rom(&fds_rom(1));
+        while emu.nes.cpu.program_counter != 0xE01E && emu.nes.cpu.cycles < 1_000_000 {
+            emu.nes.step().unwrap();
+        }
+        // the block mark comes through first, then the disk info block
+        let data = emu.nes.cpu.memory.dump(0x0200, 16);
+        assert_eq!(data[0], 0x80);
+        assert_eq!(&data[1..], b"\x01*NINTENDO-HVC*");
+        assert_eq!(emu.nes.cpu.memory.ppu.memory.get_mirroring(), crate::nes::rom::Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_set_fds_side() {
+        let mut emu = Emulator::new();
+        emu.load_rom(&fds_rom(2));
+        emu.set_fds_side(1);
+        assert_eq!(emu.nes.cpu.memory.rom().mapper_as::<Mapper20>().unwrap().get_disk_side(), None);
+        while emu.nes.cpu.cycles < 2_000_000 {
+            emu.nes.step().unwrap();
+        }
+        assert_eq!(emu.nes.cpu.memory.rom().mapper_as::<Mapper20>().unwrap().get_disk_side(), Some(1));
+
+        emu.set_fds_side(2);
+        assert_eq!(emu.nes.cpu.memory.rom().mapper_as::<Mapper20>().unwrap().get_disk_side(), Some(1));
+    }
+
+    #[test]
+    fn test_load_rom_path_auto_patch() {
+        let rom_path = std::env::temp_dir().join("alpines_test_auto_patch.nes");
+        let patch_path = rom_path.with_extension("ips");
+        fs::write(&rom_path, test_rom(&[]).to_ines_bytes()).unwrap();
+        // $8000: LDA #$42
+        fs::write(&patch_path, b"PATCH\x00\x00\x10\x00\x02\xA9\x42EOF").unwrap();
+
+        let mut emu = Emulator::new();
+        emu.load_rom_path(&rom_path).unwrap();
+        assert_eq!(emu.nes.cpu.memory.rom().prg_rom[0..2], [0xA9, 0x42]);
+
+        emu.auto_patch = false;
+        emu.load_rom_path(&rom_path).unwrap();
+        assert_eq!(emu.nes.cpu.memory.rom().prg_rom[0..2], [0x00, 0x00]);
+
+        fs::write(&patch_path, b"PATCH").unwrap();
+        assert!(matches!(emu.load_rom_with_patch(&rom_path, &patch_path), Err(RomError::InvalidPatch(_))));
+        fs::remove_file(&patch_path).unwrap();
+        fs::remove_file(&rom_path).unwrap();
+    }
+
+    fn battery_rom(save_name: &str) -> ROM {
+        let mut rom = test_rom(&[CPU::JMP_AB, 0x00, 0x80]);
+        rom.has_save_ram = true;
+        rom.save_path = Some(std::env::temp_dir().join(save_name));
+        rom
+    }
+
+    #[test]
+    fn test_battery_ram_restored() {
+        let rom = battery_rom("alpines_test_battery_restored.sav");
+        let save_path = rom.save_path.clone().unwrap();
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        emu.nes.cpu.memory.write_byte(0x6000, 0x5A);
+        emu.nes.cpu.memory.write_byte(0x7FFF, 0xA5);
+        emu.flush_battery_ram();
+        assert!(save_path.exists());
+
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x6000), 0x5A);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x7FFF), 0xA5);
+        std::fs::remove_file(&save_path).unwrap();
+    }
+
+    #[test]
+    fn test_battery_ram_legacy_path() {
+        let mut rom = battery_rom("alpines_test_battery_legacy.sav");
+        rom.game_title = String::from("alpines_test_battery_legacy");
+        let save_path = rom.save_path.clone().unwrap();
+        let saves_dir = env::temp_dir().join("alpines_test_saves");
+        let legacy_path = rom.legacy_save_path(&saves_dir);
+        fs::create_dir_all(legacy_path.parent().unwrap()).unwrap();
+        fs::write(&legacy_path, [0x5A]).unwrap();
+
+        let mut emu = Emulator::new();
+        emu.legacy_saves_dir = saves_dir.clone();
+        emu.load_rom(&rom);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x6000), 0x5A);
+        emu.flush_battery_ram();
+        assert_eq!(fs::read(&save_path).unwrap()[0], 0x5A);
+        fs::remove_file(&save_path).unwrap();
+        fs::remove_dir_all(&saves_dir).unwrap();
+    }
+
+    #[test]
+    fn test_battery_ram_periodic_flush() {
+        let rom = battery_rom("alpines_test_battery_periodic.sav");
+        let save_path = rom.save_path.clone().unwrap();
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        emu.nes.cpu.memory.write_byte(0x6000, 0x5A);
+        for _ in 0..Emulator::BATTERY_FLUSH_FRAMES - 1 {
+            emu.tick_battery_flush();
+        }
+        assert!(!save_path.exists());
+        emu.tick_battery_flush();
+        assert!(save_path.exists());
+        assert!(!emu.nes.cpu.memory.prg_ram_dirty);
+        std::fs::remove_file(&save_path).unwrap();
+    }
+
+    #[test]
+    fn test_battery_ram_kept_by_replays() {
+        let rom = battery_rom("alpines_test_battery_replay.sav");
+        let save_path = rom.save_path.clone().unwrap();
+        let replay_path = env::temp_dir().join("alpines_test_battery_replay.rpl");
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        emu.nes.cpu.memory.write_byte(0x6000, 0x5A);
+
+        // the unsaved write is flushed before the power cycle, then the wiped RAM is never written
+        emu.start_recording(&replay_path).unwrap();
+        let save = fs::read(&save_path).unwrap();
+        assert_eq!(save[0], 0x5A);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x6000), 0x00);
+        emu.nes.cpu.memory.write_byte(0x6001, 0xA5);
+        for _ in 0..Emulator::BATTERY_FLUSH_FRAMES {
+            emu.tick_replay();
+            emu.tick_battery_flush();
+        }
+        emu.stop_recording().unwrap();
+        emu.flush_battery_ram();
+        assert_eq!(fs::read(&save_path).unwrap(), save);
+
+        emu.play_recording(&replay_path).unwrap();
+        emu.nes.cpu.memory.write_byte(0x6001, 0xA5);
+        for _ in 0..Emulator::BATTERY_FLUSH_FRAMES {
+            emu.tick_replay();
+            emu.tick_battery_flush();
+        }
+        emu.flush_battery_ram();
+        assert_eq!(fs::read(&save_path).unwrap(), save);
+
+        // reloading the ROM brings the save back
+        emu.load_rom(&rom);
+        assert_eq!(fs::read(&save_path).unwrap(), save);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x6000), 0x5A);
+        fs::remove_file(&save_path).unwrap();
+        fs::remove_file(&replay_path).unwrap();
+    }
+
+    #[test]
+    fn test_load_rom_power_cycles() {
+        let rom_a = test_rom(&[CPU::LDA_IM, 0x42, CPU::LDX_IM, 0x24, CPU::STA_ZP, 0x10, CPU::PHA, CPU::JMP_AB, 0x08, 0x80]);
+        let mut rom_b = test_rom(&[]);
+        rom_b.prg_rom[0x1000] = CPU::JMP_AB;
+        rom_b.prg_rom[0x1001] = 0x00;
+        rom_b.prg_rom[0x1002] = 0x90;
+        rom_b.prg_rom[0x3ffd] = 0x90; // reset vector -> $9000
+
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom_a);
+        for _ in 0..5 {
+            emu.nes.step().unwrap();
+        }
+        assert_eq!(emu.nes.cpu.program_counter, 0x8008);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x10), 0x42);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x1FD), 0x42);
+        emu.nes.cpu.memory.write_byte(0x2000, 0x80);
+
+        emu.load_rom(&rom_b);
+        let cpu = &mut emu.nes.cpu;
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!((cpu.register_a, cpu.register_x, cpu.register_y), (0, 0, 0));
+        assert_eq!(cpu.stack, 0xfd);
+        assert_eq!(cpu.status.value, 0b0010_0100);
+        assert_eq!(cpu.cycles, 0);
+        assert_eq!(cpu.memory.read_byte(0x10), 0x00);
+        assert_eq!(cpu.memory.read_byte(0x1FD), 0x00);
+        assert_eq!(cpu.memory.ppu.ctrl.get_value(), 0x00);
+        assert_eq!(cpu.memory.ppu.cycles, 0);
+        assert_eq!(cpu.memory.rom().prg_rom[0x1000], CPU::JMP_AB);
+    }
+
+    #[test]
+    fn test_dmc_sample_playback() {
+        let rom = test_rom(&[
+            CPU::LDA_IM, 0x8F, CPU::STA_AB, 0x10, 0x40, // IRQ enabled, fastest rate
+            CPU::LDA_IM, 0xFF, CPU::STA_AB, 0x12, 0x40, // sample at $FFC0
+            CPU::LDA_IM, 0x04, CPU::STA_AB, 0x13, 0x40, // 65 bytes
+            CPU::LDA_IM, 0x10, CPU::STA_AB, 0x15, 0x40,
+            CPU::JMP_AB, 0x14, 0x80,
+        ]);
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        for _ in 0..7 {
+            emu.nes.step().unwrap();
+        }
+
+        // 65 bytes at 432 cycles each, wrapping from $FFFF to $8000 for the last one
+        let mut dma_cycles = 0;
+        while !emu.nes.cpu.memory.apu.poll_irq() {
+            let instruction_cycles = if emu.nes.cpu.program_counter == 0x8011 { 4 } else { 3 };
+            dma_cycles += emu.nes.cpu.step().unwrap() as usize - instruction_cycles;
+            assert!(emu.nes.cpu.cycles < 65 * 432 + 1000);
+            if dma_cycles == 4 {
+                assert_eq!(emu.nes.cpu.memory.peek_byte(0x4015) & 0x10, 0x10);
+            }
+        }
+        assert_eq!(emu.nes.cpu.memory.apu.dmc.get_bytes_remaining(), 0);
+        assert_eq!(dma_cycles, 65 * 4);
+        assert_eq!(emu.nes.cpu.memory.peek_byte(0x4015) & 0x90, 0x80);
+
+        // the IRQ stays up until $4010 or $4015 is written
+        emu.nes.cpu.memory.read_byte(0x4015);
+        assert!(emu.nes.cpu.memory.apu.poll_irq());
+        emu.nes.cpu.memory.write_byte(0x4015, 0x00);
+        assert!(!emu.nes.cpu.memory.apu.poll_irq());
+    }
 
     #[test]
     fn test_load_and_reset() {
@@ -582,4 +1303,245 @@ mod tests {
         assert_eq!(cpu.status.value, 0b0010_0111);
         assert_eq!(cpu.program_counter, 0x0736);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_run_logged() {
+        let mut emu = Emulator::new();
+        let rom = test_rom(&[CPU::LDX_IM, 0x02, CPU::DEX, CPU::BNE, 0xfd, CPU::LAX_ZP, 0x10, CPU::BRK]);
+        let expected = "\
+8000  A2 02     LDX #$02                        A:00 X:00 Y:00 P:24 SP:FD CYC:0
+8002  CA        DEX                             A:00 X:02 Y:00 P:24 SP:FD CYC:2
+8003  D0 FD     BNE $8002                       A:00 X:01 Y:00 P:24 SP:FD CYC:4
+8002  CA        DEX                             A:00 X:01 Y:00 P:24 SP:FD CYC:7
+8003  D0 FD     BNE $8002                       A:00 X:00 Y:00 P:26 SP:FD CYC:9
+8005  A7 10    *LAX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC:11
+8007  00        BRK                             A:00 X:00 Y:00 P:26 SP:FD CYC:14";
+
+        let trace: Vec<CpuLogLine> = emu.run_logged(&rom).collect();
+        assert_eq!(trace.len(), 7);
+        assert_eq!(trace[1].to_string(), expected.lines().nth(1).unwrap());
+        assert_eq!(compare_log(&trace, expected), vec![]);
+
+        let diverging = expected.replace("P:26 SP:FD CYC:11", "P:24 SP:FD CYC:11");
+        assert_eq!(compare_log(&trace, &diverging), vec![
+            Mismatch { line: 6, field: "P", expected: "24".to_string(), actual: "26".to_string() }
+        ]);
+    }
+
+    #[test]
+    fn test_run_with_debugger_step() {
+        let mut emu = Emulator::new();
+        let rom = test_rom(&[CPU::LDA_IM, 0x05, CPU::PHA, CPU::LDX_IM, 0x10, CPU::INX, CPU::BRK]);
+
+        let mut trace = Vec::new();
+        emu.run_with_debugger(&rom, |nes| {
+            trace.push((nes.cpu.program_counter, nes.cpu.register_a, nes.cpu.register_x));
+            DebugCommand::Step
+        });
+
+        assert_eq!(trace, vec![
+            (0x8000, 0x00, 0x00),
+            (0x8002, 0x05, 0x00),
+            (0x8003, 0x05, 0x00),
+            (0x8005, 0x05, 0x10),
+            (0x8006, 0x05, 0x11),
+        ]);
+    }
+
+    #[test]
+    fn test_run_with_debugger_run_until() {
+        let mut emu = Emulator::new();
+        let rom = test_rom(&[
+            CPU::LDA_IM, 0x05, CPU::PHA, CPU::LDA_IM, 0x0a, CPU::PHA, CPU::STA_AB, 0x00, 0x02, CPU::INX, CPU::BRK
+        ]);
+
+        let mut breaks = Vec::new();
+        emu.run_with_debugger(&rom, |nes| {
+            breaks.push(nes.cpu.program_counter);
+            match nes.cpu.program_counter {
+                0x8000 => DebugCommand::RunUntil(0x8009),
+                0x8009 => {
+                    assert_eq!(&nes.cpu.peek_stack()[..2], &[0x0a, 0x05]);
+                    assert_eq!(nes.cpu.memory.dump(0x0200, 2), &[0x0a, 0x00]);
+                    DebugCommand::Quit
+                },
+                _ => panic!("unexpected break at 0x{:0>4X}", nes.cpu.program_counter),
+            }
+        });
+
+        assert_eq!(breaks, vec![0x8000, 0x8009]);
+        assert_eq!(emu.nes.cpu.register_x, 0);
+    }
+
+    #[test]
+    fn test_run_with_debugger_continue() {
+        let mut emu = Emulator::new();
+        let rom = test_rom(&[CPU::LDA_IM, 0x80, CPU::STA_AB, 0x00, 0x20, CPU::INX, CPU::BRK]);
+
+        let mut breaks = 0;
+        emu.run_with_debugger(&rom, |nes| {
+            breaks += 1;
+            assert_eq!(nes.cpu.memory.ppu.peek_register("PPUCTRL"), Some(0));
+            assert_eq!(nes.cpu.memory.ppu.peek_register("NOTAREG"), None);
+            DebugCommand::Continue
+        });
+
+        assert_eq!(breaks, 1);
+        assert_eq!(emu.nes.cpu.register_x, 1);
+        assert_eq!(emu.nes.cpu.memory.ppu.peek_register("PPUCTRL"), Some(0x80));
+    }
+
+    fn run_joycon_reads_with_dmc(dmc_dma_glitch: bool) -> Vec<u8> {
+        let mut emu = Emulator::new();
+        let rom = test_rom(&[
+            CPU::LDA_IM, 0x8f, CPU::STA_AB, 0x10, 0x40, // loop samples at the fastest rate
+            CPU::LDA_IM, 0xff, CPU::STA_AB, 0x13, 0x40,
+            CPU::LDA_IM, 0x10, CPU::STA_AB, 0x15, 0x40,
+            CPU::LDX_IM, 0x00,
+            // strobe the controller and shift its 8 buttons into $00
+            CPU::LDA_IM, 0x01, CPU::STA_AB, 0x16, 0x40, CPU::LDA_IM, 0x00, CPU::STA_AB, 0x16, 0x40,
+            CPU::STA_ZP, 0x00, CPU::LDY_IM, 0x08,
+            CPU::LDA_AB, 0x16, 0x40, CPU::LSR, CPU::ROL_ZP, 0x00, CPU::DEY, CPU::BNE, 0xf7,
+            CPU::LDA_ZP, 0x00, CPU::STA_AB_X, 0x00, 0x03, CPU::INX, CPU::BNE, 0xe1,
+            CPU::BRK
+        ]);
+        emu.load_rom(&rom);
+        emu.nes.cpu.memory.dmc_dma_glitch = dmc_dma_glitch;
+        emu.nes.cpu.memory.joycon1.set_button(JoyconButton::A);
+        emu.run();
+        emu.nes.cpu.memory.dump(0x0300, 0x100)
+    }
+
+    #[test]
+    fn test_dmc_dma_joycon_glitch() {
+        let reads = run_joycon_reads_with_dmc(true);
+        let corrupted = reads.iter().filter(|&&buttons| buttons != 0b1000_0000).count();
+        assert!(corrupted > 0 && corrupted < reads.len() / 4, "{} corrupted reads", corrupted);
+
+        let reads = run_joycon_reads_with_dmc(false);
+        assert!(reads.iter().all(|&buttons| buttons == 0b1000_0000));
+    }
+
+    #[test]
+    fn test_record_and_play_replay() {
+        let path = std::env::temp_dir().join("alpines_test_emu_replay.rpl");
+        let rom = test_rom(&[CPU::JMP_AB, 0x00, 0x80]);
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        emu.nes.cpu.set_rng_seed(42);
+        emu.nes.step().unwrap();
+        emu.start_recording(&path).unwrap();
+        assert_eq!(emu.nes.cpu.cycles, 0);
+
+        let recorded = [0x00, 0x01, 0x01, 0x90, 0x00];
+        for buttons in recorded {
+            emu.nes.cpu.memory.joycon1.set_buttons(buttons);
+            emu.nes.cpu.memory.joycon2.set_buttons(!buttons);
+            emu.tick_replay();
+        }
+        emu.stop_recording().unwrap();
+
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        emu.play_recording(&path).unwrap();
+        assert_eq!(emu.nes.cpu.get_rng_seed(), 42);
+        for buttons in recorded {
+            emu.nes.cpu.memory.joycon1.set_buttons(0xFF); // the keyboard is ignored while playing back
+            emu.tick_replay();
+            assert_eq!(emu.nes.cpu.memory.joycon1.get_buttons(), buttons);
+            assert_eq!(emu.nes.cpu.memory.joycon2.get_buttons(), !buttons);
+        }
+        emu.tick_replay();
+        assert!(emu.replay_player.is_none());
+
+        let mut other_rom = rom.clone();
+        other_rom.prg_rom[0] = CPU::NOP;
+        emu.load_rom(&other_rom);
+        assert_eq!(emu.play_recording(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_with_callback() {
+        // polls controller one until A is held, then halts
+        let mut emu = Emulator::new();
+        emu.load_rom(&test_rom(&[
+            CPU::LDA_IM, 0x01, CPU::STA_AB, 0x16, 0x40, CPU::LDA_IM, 0x00, CPU::STA_AB, 0x16, 0x40,
+            CPU::LDA_AB, 0x16, 0x40, CPU::AND_IM, 0x01, CPU::BEQ, 0xEF, CPU::BRK,
+        ]));
+        let mut frames = 0;
+        let mut last_cycles = 0;
+        emu.run_with_callback(|frame, state| {
+            frames += 1;
+            assert_eq!((frame.width, frame.height), (Frame::WIDTH, Frame::HEIGHT));
+            assert!(state.cycles > last_cycles);
+            last_cycles = state.cycles;
+            let player1 = if frames == 3 { 1 << JoyconButton::A as u8 } else { 0 };
+            ControllerInputs { player1, player2: 0 }
+        });
+        assert_eq!(frames, 4);
+        assert_eq!(emu.nes.cpu.get_state().register_a, 0x01);
+    }
+
+    #[test]
+    fn test_step_frame() {
+        // turns on NMI and spins, counting frames at $10 in the NMI handler at $8010
+        let mut rom = test_rom(&[CPU::LDA_IM, 0x80, CPU::STA_AB, 0x00, 0x20, CPU::JMP_AB, 0x05, 0x80]);
+        rom.prg_rom[0x0010..0x0013].copy_from_slice(&[CPU::INC_ZP, 0x10, CPU::RTI]);
+        rom.prg_rom[0x3ffa] = 0x10; // NMI vector -> $8010
+        rom.prg_rom[0x3ffb] = 0x80;
+        let mut emu = Emulator::new();
+        emu.load_rom(&rom);
+        emu.nes.cpu.memory.ppu.memory.write_byte(0x3F00, 0x21);
+
+        let frame = emu.step_frame();
+        assert_eq!((frame.width, frame.height), (Frame::WIDTH, Frame::HEIGHT));
+        let first_frame_cycles = emu.nes.cpu.cycles;
+        assert!(first_frame_cycles >= Emulator::NTSC_CPU_CYCLES_PER_FRAME);
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x10), 1);
+
+        for _ in 0..59 {
+            emu.step_frame();
+        }
+        assert_eq!(emu.nes.cpu.memory.read_byte(0x10), 60);
+        // the overshoot of each frame is taken off the next, so frames don't drift
+        let cycles = emu.nes.cpu.cycles;
+        assert!(cycles >= 60 * Emulator::NTSC_CPU_CYCLES_PER_FRAME);
+        assert!(cycles < 60 * Emulator::NTSC_CPU_CYCLES_PER_FRAME + 8);
+    }
+
+    #[test]
+    fn test_audio_recording() {
+        let path = std::env::temp_dir().join("alpines_test_audio_recording.wav");
+        // a constant-volume square wave on pulse one, then spin
+        let mut emu = Emulator::new();
+        emu.load_rom(&test_rom(&[
+            CPU::LDA_IM, 0x01, CPU::STA_AB, 0x15, 0x40,
+            CPU::LDA_IM, 0xBF, CPU::STA_AB, 0x00, 0x40,
+            CPU::LDA_IM, 0xFD, CPU::STA_AB, 0x02, 0x40,
+            CPU::LDA_IM, 0x00, CPU::STA_AB, 0x03, 0x40,
+            CPU::JMP_AB, 0x14, 0x80,
+        ]));
+        emu.step_frame();
+
+        emu.start_audio_recording(&path).unwrap();
+        for _ in 0..3 {
+            emu.step_frame();
+        }
+        emu.stop_audio_recording().unwrap();
+        emu.step_frame();
+
+        let bytes = fs::read(&path).unwrap();
+        let data_size = u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), AudioConfig::DEFAULT_SAMPLE_RATE);
+        assert_eq!(bytes.len(), 44 + data_size);
+        // three frames at 44.1kHz is 2201 samples
+        assert!((data_size / 2).abs_diff(2201) <= 1);
+
+        let samples: Vec<i16> = bytes[44..].chunks_exact(2).map(|pcm| i16::from_le_bytes([pcm[0], pcm[1]])).collect();
+        assert!(samples.iter().any(|sample| *sample > 1000));
+        assert!(samples.iter().any(|sample| *sample < -1000));
+        fs::remove_file(&path).unwrap();
+    }
+}
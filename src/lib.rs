@@ -1,3 +1,6 @@
+#[cfg(feature = "sdl")]
 pub mod emu;
 pub mod nes;
 pub mod util;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,6 +1,5 @@
+use std::env;
 use std::path::Path;
-use std::thread::sleep;
-use std::time::Duration;
 use rand::Rng;
 
 use sdl2::event::Event;
@@ -12,7 +11,12 @@ use sdl2::pixels::PixelFormatEnum;
 use alpines::emu::Emulator;
 use alpines::nes::NES;
 use alpines::nes::io::frame::Frame;
-use alpines::nes::rom::ROM;
+use alpines::nes::io::joycon::ControllerInputs;
+use alpines::nes::io::joycon::joycon_status::JoyconButton;
+use alpines::nes::ppu::viewer::{render_pattern_tables, render_ppu_viewer, PALETTE_GROUPS, PATTERN_TABLES_HEIGHT, PATTERN_TABLES_WIDTH, PPU_VIEWER_HEIGHT, PPU_VIEWER_WIDTH};
+use alpines::nes::rom::{RomError, ROM};
+use alpines::nes::rom::mappers::supported_mappers;
+use alpines::util::sleep::FrameTimer;
 
 // snake - 6502 CPU game
 
@@ -47,27 +51,37 @@ fn read_screen_state(nes: &mut NES, frame: &mut [u8; 32 * 3 * 32]) -> bool {
     update
 }
 
-fn handle_user_input(nes: &mut NES, event_pump: &mut EventPump) {
+fn handle_user_input(event_pump: &mut EventPump) -> ControllerInputs {
+    let mut inputs = ControllerInputs::new();
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                 std::process::exit(0);
             },
             Event::KeyDown { keycode: Some(Keycode::W | Keycode::Up), .. } => {
-                nes.cpu.memory.write_byte(0xff, 0x77);
+                inputs.player1 |= 1 << JoyconButton::Up as u8;
             },
             Event::KeyDown { keycode: Some(Keycode::S | Keycode::Down), .. } => {
-                nes.cpu.memory.write_byte(0xff, 0x73);
+                inputs.player1 |= 1 << JoyconButton::Down as u8;
             },
             Event::KeyDown { keycode: Some(Keycode::A | Keycode::Left), .. } => {
-                nes.cpu.memory.write_byte(0xff, 0x61);
+                inputs.player1 |= 1 << JoyconButton::Left as u8;
             },
             Event::KeyDown { keycode: Some(Keycode::D | Keycode::Right), .. } => {
-                nes.cpu.memory.write_byte(0xff, 0x64);
+                inputs.player1 |= 1 << JoyconButton::Right as u8;
             }
             _ => {}
         }
     }
+    inputs
+}
+
+// Snake has no controller, it polls $FF for the ASCII code of the last key pressed (WASD).
+fn snake_key(inputs: ControllerInputs) -> Option<u8> {
+    [(JoyconButton::Up, b'w'), (JoyconButton::Down, b's'), (JoyconButton::Left, b'a'), (JoyconButton::Right, b'd')]
+        .into_iter()
+        .find(|(button, _)| inputs.player1 & (1 << button.clone() as u8) != 0)
+        .map(|(_, key)| key)
 }
 
 fn run_snake() {
@@ -83,88 +97,69 @@ fn run_snake() {
     let creator = canvas.texture_creator();
     let mut texture = creator.create_texture_target(PixelFormatEnum::RGB24, 32, 32).unwrap();
 
-    let mut emulator = Emulator::new();
-    emulator.load_rom(&ROM::from_path(Path::new("rom/test/cpu/snake.nes")).unwrap());
+    // snake isn't an NES game, it does its I/O through zero page, so it runs on the bare console
+    // rather than through the Emulator
+    let mut nes = NES::new();
+    nes.load_rom(&load_rom("rom/test/cpu/snake.nes"));
 
+    // snake isn't frame based, so it gets a fixed budget of instructions per frame
+    const INSTRUCTIONS_PER_FRAME: usize = 240;
     let mut screen_state = [0 as u8; 32 * 32 * 3];
     let mut rng = rand::thread_rng();
+    let mut frame_timer = FrameTimer::new(60.0);
+    let mut instructions = 0;
 
-    emulator.run_with_callback(|nes| {
-        handle_user_input(nes, &mut event_pump);
+    loop {
+        if let Some(key) = snake_key(handle_user_input(&mut event_pump)) {
+            nes.cpu.memory.write_byte(0xff, key);
+        }
         nes.cpu.memory.write_byte(0xfe, rng.gen_range(1..16));
+        if nes.step().is_err() { return }
+
+        instructions += 1;
+        if instructions < INSTRUCTIONS_PER_FRAME { continue }
+        instructions = 0;
 
-        if read_screen_state(nes, &mut screen_state) {
+        if read_screen_state(&mut nes, &mut screen_state) {
             texture.update(None, &screen_state, 32 * 3).unwrap();
             canvas.copy(&texture, None, None).unwrap();
             canvas.present();
         }
-
-        sleep(Duration::new(0, 70_000));
-    });
-}
-
-// chrdump - chr rom dump of pacman for the nes
-
-fn render_tile(chr_rom: &Vec<u8>, bank: usize, tile_n: usize, frame: &mut Frame) {
-    let tile_addr = 0x1000 * bank + 16 * tile_n;
-    let tile = &chr_rom[tile_addr..(tile_addr + 16)];
-    for y in 0..8 {
-        let mut high_byte = tile[y];
-        let mut low_byte = tile[y + 8];
-        for x in (0..8).rev() {
-            let value = (1 & high_byte) << 1 | (1 & low_byte);
-            let rgb = match value {
-                0 => (0, 0, 0),
-                1 => (170, 170, 170),
-                2 => (255, 255, 255),
-                3 => (85, 85, 85),
-                _ => panic!("chr_rom value out of range: {}", value),
-            };
-            const TILE_SIZE: usize = 8;
-            const PADDING: usize = 1;
-            const BOX_SIZE: usize = TILE_SIZE + 2 * PADDING;
-            const TILES_PER_ROW: usize = Frame::WIDTH / BOX_SIZE;
-            const TILES_PER_COL_BANK: usize = 256 / TILES_PER_ROW + (256 % TILES_PER_ROW > 0) as usize;
-            const MARGIN: usize = (Frame::WIDTH - BOX_SIZE * TILES_PER_ROW) / 2;
-            let bank_offset: usize = (TILES_PER_COL_BANK + 1) * BOX_SIZE * (bank % 2);
-            let tile_x = x + BOX_SIZE * (tile_n % TILES_PER_ROW) + PADDING + MARGIN;
-            let tile_y = y + BOX_SIZE * (tile_n / TILES_PER_ROW) + PADDING + MARGIN + bank_offset;
-            frame.set_background_color(tile_x, tile_y, rgb);
-            high_byte = high_byte >> 1;
-            low_byte = low_byte >> 1;
-        }
+        frame_timer.sync();
     }
 }
 
+// chrdump - live view of the pattern tables as the game sees them
+
 fn run_chrdump(path: &str) {
     const SCALE: f32 = 3.0;
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("alpiNES - CHR Dump", (SCALE * Frame::WIDTH as f32) as u32, (SCALE * Frame::HEIGHT as f32) as u32)
+        .window("alpiNES - CHR Dump", (SCALE * PATTERN_TABLES_WIDTH as f32) as u32, (SCALE * PATTERN_TABLES_HEIGHT as f32) as u32)
         .position_centered()
         .build().unwrap();
     let mut canvas = window.into_canvas().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
     let creator = canvas.texture_creator();
-    let mut texture = creator.create_texture_target(PixelFormatEnum::RGB24, Frame::WIDTH as u32, Frame::HEIGHT as u32).unwrap();
+    let mut texture = creator.create_texture_target(PixelFormatEnum::RGB24, PATTERN_TABLES_WIDTH as u32, PATTERN_TABLES_HEIGHT as u32).unwrap();
 
     let mut emulator = Emulator::new();
-    let rom = ROM::from_path(Path::new(path)).unwrap();
-    let mut tile_frame = Frame::new();
+    let rom = load_rom(path);
+    let mut tile_frame = Frame::with_size(PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT);
     emulator.load_rom(&rom);
+    let mut frame_timer = FrameTimer::new(emulator.nes.region.get_fps());
 
-    let max_page = rom.get_chr_bank_count();
-    let mut page  = 0;
-
+    // the game keeps running so that CHR bank switches and CHR RAM uploads show up as they happen
+    let mut palette_group = 0;
     loop {
-        tile_frame.clear();
-        for i in 0..256 {
-            render_tile(&rom.chr_rom, page * 2, i, &mut tile_frame);
-            render_tile(&rom.chr_rom, page * 2 + 1, i, &mut tile_frame);
-        }
+        let is_vblank = emulator.nes.cpu.memory.ppu.poll_nmi();
+        emulator.poll_interrupts();
+        emulator.nes.step().unwrap();
+        if !is_vblank { continue }
 
-        texture.update(None, &tile_frame.background, Frame::WIDTH * 3).unwrap();
+        render_pattern_tables(&emulator.nes.cpu.memory.ppu, &mut tile_frame, palette_group);
+        texture.update(None, &tile_frame.background, PATTERN_TABLES_WIDTH * 3).unwrap();
         canvas.copy(&texture, None, None).unwrap();
         canvas.present();
 
@@ -175,13 +170,16 @@ fn run_chrdump(path: &str) {
                     std::process::exit(0)
                 },
                 Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
-                    if page + 1 < max_page {
-                        page += 1;
-                    }
+                    palette_group = (palette_group + 1) % PALETTE_GROUPS;
                 },
                 Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
-                    if page > 0 {
-                        page -= 1;
+                    palette_group = (palette_group + PALETTE_GROUPS - 1) % PALETTE_GROUPS;
+                },
+                Event::KeyDown { keycode: Some(Keycode::S), .. } => {
+                    let png_path = format!("{}.chr.png", rom.game_title);
+                    match tile_frame.export_png(Path::new(&png_path)) {
+                        Ok(()) => println!("saved {}", png_path),
+                        Err(err) => println!("[WARNING] unable to save {}: {}", png_path, err),
                     }
                 },
                 _ => {
@@ -189,6 +187,90 @@ fn run_chrdump(path: &str) {
                 }
             }
         }
+        frame_timer.sync();
+    }
+}
+
+// ppu viewer - the game next to a live view of its nametables, pattern tables and palettes
+
+fn run_ppu_viewer(path: &str) {
+    const SCALE: f32 = 2.0;
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let game_window = video_subsystem
+        .window("alpiNES", (SCALE * Frame::WIDTH as f32) as u32, (SCALE * Frame::HEIGHT as f32) as u32)
+        .position(0, 0)
+        .build().unwrap();
+    let viewer_window = video_subsystem
+        .window("alpiNES - PPU Viewer", PPU_VIEWER_WIDTH as u32, PPU_VIEWER_HEIGHT as u32)
+        .position((SCALE * Frame::WIDTH as f32) as i32, 0)
+        .build().unwrap();
+    let mut game_canvas = game_window.into_canvas().build().unwrap();
+    let mut viewer_canvas = viewer_window.into_canvas().build().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let game_creator = game_canvas.texture_creator();
+    let viewer_creator = viewer_canvas.texture_creator();
+    let mut game_texture = game_creator.create_texture_target(PixelFormatEnum::RGB24, Frame::WIDTH as u32, Frame::HEIGHT as u32).unwrap();
+    let mut viewer_texture = viewer_creator.create_texture_target(PixelFormatEnum::RGB24, PPU_VIEWER_WIDTH as u32, PPU_VIEWER_HEIGHT as u32).unwrap();
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&load_rom(path));
+    let mut viewer_frame = Frame::with_size(PPU_VIEWER_WIDTH, PPU_VIEWER_HEIGHT);
+    let mut frame_timer = FrameTimer::new(emulator.nes.region.get_fps());
+
+    // both windows are redrawn once per frame, so the viewer never lags behind the game
+    loop {
+        emulator.handle_input(&mut event_pump);
+        let frame = emulator.step_frame();
+        game_texture.update(None, &frame.background, Frame::WIDTH * 3).unwrap();
+        game_canvas.copy(&game_texture, None, None).unwrap();
+        game_canvas.present();
+
+        render_ppu_viewer(&emulator.nes.cpu.memory.ppu, &mut viewer_frame, 0);
+        viewer_texture.update(None, &viewer_frame.background, PPU_VIEWER_WIDTH * 3).unwrap();
+        viewer_canvas.copy(&viewer_texture, None, None).unwrap();
+        viewer_canvas.present();
+
+        frame_timer.sync();
+    }
+}
+
+// load rom, or explain why it can't be played
+
+fn load_rom(path: &str) -> ROM {
+    let rom_path = Path::new(path);
+    match ROM::find_patch(rom_path) {
+        Some(patch_path) => unwrap_rom(path, ROM::from_path_with_patch(rom_path, &patch_path)),
+        None => unwrap_rom(path, ROM::from_path(rom_path)),
+    }
+}
+
+fn load_fds(path: &str, bios_path: &str) -> ROM {
+    unwrap_rom(path, ROM::from_fds_path(Path::new(path), Path::new(bios_path)))
+}
+
+fn unwrap_rom(path: &str, result: Result<ROM, RomError>) -> ROM {
+    match result {
+        Ok(rom) => rom,
+        Err(err) => {
+            match &err {
+                RomError::Io(_) => eprintln!("Unable to open {}: {}", path, err),
+                RomError::InvalidMagic(_) | RomError::TruncatedHeader =>
+                    eprintln!("{} isn't an iNES ROM: {}", path, err),
+                RomError::UnsupportedVersion(_) | RomError::UnknownBoard(_) =>
+                    eprintln!("{} has a header alpiNES doesn't understand: {}", path, err),
+                RomError::UnsupportedMapper(mapper_id) =>
+                    eprintln!("{} needs mapper {}, supported mappers are {:?}", path, mapper_id, supported_mappers()),
+                RomError::PrgRomTooShort { .. } | RomError::ChrRomTooShort { .. } | RomError::UnevenRomSize { .. }
+                | RomError::TruncatedChunk(_) =>
+                    eprintln!("{} looks truncated or corrupt: {}", path, err),
+                RomError::NoRomInArchive | RomError::InvalidBios(_) | RomError::TruncatedDisk(_) =>
+                    eprintln!("{}: {}", path, err),
+                RomError::InvalidPatch(_) | RomError::PatchChecksumMismatch { .. } =>
+                    eprintln!("Unable to patch {}: {}", path, err),
+            }
+            std::process::exit(1);
+        }
     }
 }
 
@@ -196,13 +278,23 @@ fn run_chrdump(path: &str) {
 
 fn run_game(path: &str) {
     let mut emu = Emulator::new();
-    let rom = ROM::from_path(Path::new(path)).unwrap();
+    let rom = load_rom(path);
+    emu.run_rom(&rom);
+}
+
+// run famicom disk system game, the BIOS (disksys.rom) has to be dumped separately. The disk
+// drive and timer are emulated, the wavetable audio isn't: games play without their FDS sound.
+
+const DEFAULT_FDS_BIOS: &str = "rom/fds/disksys.rom";
+
+fn run_fds(path: &str, bios_path: &str) {
+    let mut emu = Emulator::new();
+    let rom = load_fds(path, bios_path);
     emu.run_rom(&rom);
 }
 
 // todo: test audio with different games
 //  - pacman: nothing sounds right
-//      - uses 5-Step Sequence which hasn't been implemented yet
 //  - duck hunt: is broken (also visually broken)
 //  - pinball: shouldn't have audio playing during demo
 //  - ice climber: breaking blocks (noise) doesn't sound right
@@ -217,11 +309,9 @@ fn run_game(path: &str) {
 //  - smb_dh_wctm: super mario bros can't be selected, duck hunt unplayable
 //  - teenage mutant ninja turtles: background is incorrect in sewer section
 //      - check https://www.nesdev.org/wiki/Tricky-to-emulate_games
-//  - silver surfer: crashes on pallete out of bounds bug
 //  - NEStress + oam_read + oam_stress: attempt to add with overflow
 //  - 240pee: multiple problems
 //      - overscan: completely broken compared to nestopia
-//      - cpu_clock_speed: crashes on pallete out of bounds bug
 //  - scroll: broken
 //  - smwstomp: broken (stomp thingy from smbw doesnt show up)
 
@@ -232,6 +322,24 @@ fn run_game(path: &str) {
 //  - [BUG] Fix Legend of Zelda audio to make it sound glorious
 
 fn main() {
+    // alpines <rom> runs a game, alpines <disk.fds> [bios] runs a Famicom Disk System game and
+    // alpines --ppu-viewer <rom> runs a game next to the PPU viewer
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(path) = args.first() {
+        if path == "--ppu-viewer" {
+            let Some(path) = args.get(1) else {
+                println!("usage: alpines --ppu-viewer <rom>");
+                return;
+            };
+            run_ppu_viewer(path);
+        } else if path.to_ascii_lowercase().ends_with(".fds") {
+            run_fds(path, args.get(1).map_or(DEFAULT_FDS_BIOS, |bios_path| bios_path.as_str()));
+        } else {
+            run_game(path);
+        }
+        return;
+    }
+
     // run_snake();
     // run_chrdump("rom/mapper66/super_mario_bros_duck_hunt.nes");
     // run_game("rom/test/cpu/nestest.nes");
@@ -243,6 +351,9 @@ fn main() {
     // run_game("rom/mapper2/metal_gear.nes");
     // run_game("rom/mapper3/friday_the_13th.nes");
     run_game("rom/mapper4/super_mario_bros_3.nes");
+    // run_game("rom/mapper5/castlevania_3.nes");
+    // run_game("rom/mapper9/punch_out.nes");
     // run_game("rom/mapper66/super_mario_bros_duck_hunt.nes");
     // run_game("rom/romhack/zelda_challenge_outlands.nes");
+    // run_fds("rom/fds/zelda_no_densetsu.fds", DEFAULT_FDS_BIOS);
 }
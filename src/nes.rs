@@ -6,10 +6,111 @@ pub mod rom;
 
 use crate::nes::cpu::CPU;
 use crate::nes::cpu::mem::Memory;
-use crate::nes::rom::ROM;
+use crate::nes::rom::{Timing, ROM};
+
+// Ref: https://www.nesdev.org/wiki/Cycle_reference_chart
+
+// The console's timing, which PAL and Dendy (a Russian famiclone) units don't share with NTSC ones:
+// =====================================
+//                      NTSC          PAL           Dendy
+// Master clock         21.477272MHz  26.601712MHz  26.601712MHz
+// CPU clock divisor    12            16            15
+// PPU clock divisor    4             5             5
+// Scanlines per frame  262           312           312
+// VBlank starts        241           241           291
+// Frame rate           60.0988Hz     50.0070Hz     50.0070Hz
+//
+// Only NTSC PPUs skip a dot on odd frames.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    // Multi-region games are run as NTSC, the region most of them were made for.
+    pub fn from_timing(timing: Timing) -> Self {
+        match timing {
+            Timing::Ntsc | Timing::MultiRegion => Region::Ntsc,
+            Timing::Pal => Region::Pal,
+            Timing::Dendy => Region::Dendy,
+        }
+    }
+
+    pub fn get_master_clock(&self) -> f64 {
+        match self {
+            Region::Ntsc => 21_477_272.0,
+            Region::Pal | Region::Dendy => 26_601_712.0,
+        }
+    }
+
+    #[inline]
+    pub fn get_cpu_divisor(&self) -> usize {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    #[inline]
+    pub fn get_ppu_divisor(&self) -> usize {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn get_cpu_frequency(&self) -> f64 {
+        self.get_master_clock() / self.get_cpu_divisor() as f64
+    }
+
+    #[inline]
+    pub fn get_scanline_count(&self) -> usize {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    #[inline]
+    pub fn get_vblank_scanline(&self) -> isize {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // The pre-render scanline is numbered -1, so the frame ends two lines short of the count.
+    #[inline]
+    pub fn get_last_scanline(&self) -> isize {
+        self.get_scanline_count() as isize - 2
+    }
+
+    #[inline]
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    // Average CPU cycles per frame, rounded down. Ex: 341 * 262 / 3 = 29780.67 on NTSC.
+    pub fn get_cpu_cycles_per_frame(&self) -> usize {
+        341 * self.get_scanline_count() * self.get_ppu_divisor() / self.get_cpu_divisor()
+    }
+
+    pub fn get_fps(&self) -> f64 {
+        let frame_clocks = 341.0 * self.get_scanline_count() as f64 * self.get_ppu_divisor() as f64;
+        let frame_clocks = if self.skips_odd_frame_dot() { frame_clocks - 0.5 * self.get_ppu_divisor() as f64 } else { frame_clocks };
+        self.get_master_clock() / frame_clocks
+    }
+}
 
 pub struct NES {
     pub cpu: CPU,
+    pub region: Region,
+    // cycles the last frame ran past its end, taken off the next one
+    frame_overshoot: usize,
 }
 
 impl NES {
@@ -32,13 +133,47 @@ impl NES {
     pub fn new() -> Self {
         NES {
             cpu: CPU::new(),
+            region: Region::Ntsc,
+            frame_overshoot: 0,
         }
     }
 
+    // Retimes the PPU and APU. Takes effect right away, but is normally only done when a game is
+    // loaded, see load_rom.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.cpu.memory.ppu.set_region(region);
+        self.cpu.memory.apu.set_region(region);
+    }
+
     pub fn step(&mut self) -> Result<bool, bool> {
         self.cpu.step()?;
-        self.cpu.memory.ppu.step()?;
-        self.cpu.memory.apu.step()
+        self.cpu.memory.ppu.step()
+    }
+
+    // Runs one frame's worth of CPU cycles, servicing interrupts along the way. Frames are 29780.67
+    // cycles long on average (on NTSC), so the cycles run past the end of one frame are taken off the
+    // next. Returns false if the CPU halted before the frame was over.
+    pub fn run_frame(&mut self) -> bool {
+        let frame_cycles = self.region.get_cpu_cycles_per_frame();
+        let target = frame_cycles.saturating_sub(self.frame_overshoot);
+        let start = self.cpu.cycles;
+        let mut running = true;
+        while running && self.cpu.cycles - start < target {
+            self.poll_interrupts();
+            running = self.step().is_ok();
+        }
+        self.frame_overshoot = (self.cpu.cycles - start).saturating_sub(target);
+        running
+    }
+
+    pub fn poll_interrupts(&mut self) {
+        if self.cpu.memory.ppu.poll_nmi() {
+            self.cpu.handle_nmi();
+            self.cpu.memory.ppu.clear_nmi();
+        } else if self.cpu.memory.poll_mapper_irq() || self.cpu.memory.apu.poll_irq() {
+            self.cpu.handle_irq();
+        }
     }
 
     pub fn load(&mut self, program: &Vec<u8>) {
@@ -46,24 +181,30 @@ impl NES {
     }
 
     pub fn load_at_addr(&mut self, addr: u16, program: &Vec<u8>) {
+        self.cpu.power_on();
         self.cpu.memory.load_at_addr(addr, program);
-        self.reset();
+        self.cpu.reset();
     }
 
+    // Swapping cartridges power cycles the console, so nothing from the previous game carries over.
     pub fn load_rom(&mut self, rom: &ROM) {
+        self.cpu.power_on();
+        self.set_region(Region::from_timing(rom.get_timing()));
         self.cpu.memory.load_rom(rom);
-        self.reset();
+        self.cpu.reset();
     }
 
+    // Pressing the reset button: RAM, VRAM and mapper banks survive, which some games rely on.
     pub fn reset(&mut self) {
+        self.cpu.memory.reset();
         self.cpu.reset();
-        self.cpu.program_counter = self.cpu.memory.read_addr(Memory::RESET_INT_VECTOR);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nes::rom::tests::ines_image;
 
     #[test]
     fn test_nes_load() {
@@ -73,6 +214,34 @@ mod tests {
         assert_eq!(nes.cpu.program_counter, Memory::PRG_ROM_START);
     }
 
+    #[test]
+    fn test_region_timing() {
+        assert_eq!(Region::Ntsc.get_cpu_cycles_per_frame(), 29780);
+        assert_eq!(Region::Pal.get_cpu_cycles_per_frame(), 33247);
+        assert_eq!(Region::Dendy.get_cpu_cycles_per_frame(), 35464);
+        assert!((Region::Ntsc.get_fps() - 60.0988).abs() < 1e-4);
+        assert!((Region::Pal.get_fps() - 50.0070).abs() < 1e-4);
+        assert!((Region::Ntsc.get_cpu_frequency() - 1_789_772.67).abs() < 1.0);
+        assert!((Region::Pal.get_cpu_frequency() - 1_662_607.0).abs() < 1.0);
+        assert_eq!(Region::from_timing(Timing::MultiRegion), Region::Ntsc);
+    }
+
+    #[test]
+    fn test_load_rom_region() {
+        // NES 2.0, with PAL timing in byte 12
+        let mut raw = ines_image(0, 0b0000_1000, 1, 1);
+        raw[12] = 1;
+        let mut nes = NES::new();
+        nes.load_rom(&ROM::from_bytes(&raw).unwrap());
+        assert_eq!(nes.region, Region::Pal);
+        assert_eq!(nes.cpu.memory.ppu.get_region(), Region::Pal);
+        assert!((nes.cpu.memory.apu.resampler.get_input_rate() - Region::Pal.get_cpu_frequency()).abs() < 1e-6);
+
+        // a raw program power cycles the console, but it's still the same console
+        nes.load(&vec![CPU::NOP]);
+        assert_eq!(nes.cpu.memory.ppu.get_region(), Region::Pal);
+    }
+
     #[test]
     fn test_nes_reset() {
         let mut nes = NES::new();
@@ -83,6 +252,36 @@ mod tests {
         assert_eq!(nes.cpu.program_counter, Memory::PRG_ROM_START);
     }
 
+    #[test]
+    fn test_nes_soft_reset() {
+        let mut nes = NES::new();
+        nes.load(&vec![CPU::LDA_IM, 5, CPU::LDX_IM, 7, CPU::STA_ZP, 0x10, CPU::PHA, CPU::CLI, CPU::BRK]);
+        for _ in 0..5 {
+            nes.step().unwrap();
+        }
+        nes.cpu.memory.write_byte(0x2000, 0x90);
+        nes.cpu.memory.write_byte(0x2001, 0x1E);
+        nes.cpu.memory.write_byte(0x2006, 0x21);
+        nes.cpu.memory.ppu.memory.write_byte(0x2100, 0x42);
+        assert_eq!(nes.cpu.stack, 0xfc);
+
+        nes.reset();
+        assert_eq!(nes.cpu.program_counter, Memory::PRG_ROM_START);
+        assert_eq!(nes.cpu.stack, 0xf9);
+        assert_eq!(nes.cpu.status.value & 0b0000_0100, 0b0000_0100);
+        assert_eq!((nes.cpu.register_a, nes.cpu.register_x), (5, 7));
+        assert_eq!(nes.cpu.memory.read_byte(0x10), 5);
+        assert_eq!(nes.cpu.memory.read_byte(0x1fd), 5);
+
+        // PPUCTRL, PPUMASK and the write latch are cleared, VRAM isn't
+        assert_eq!(nes.cpu.memory.ppu.ctrl.get_value(), 0x00);
+        assert_eq!(nes.cpu.memory.ppu.mask.get_value(), 0x00);
+        nes.cpu.memory.write_byte(0x2006, 0x21);
+        nes.cpu.memory.write_byte(0x2006, 0x00);
+        nes.cpu.memory.read_byte(0x2007);
+        assert_eq!(nes.cpu.memory.read_byte(0x2007), 0x42);
+    }
+
     #[test]
     fn test_nes_step() {
         let mut nes = NES::new();
@@ -128,8 +327,6 @@ mod tests {
     #[test]
     fn test_nes_read_ppu_ram() {
         let mut nes = NES::new();
-        nes.cpu.memory.ppu.memory.write_byte(0x26ab, 0xff);
-        nes.cpu.memory.ppu.data_buffer = 0xaa;
         let program = vec![
             // write addr 0x0600 to addr register
             CPU::LDA_IM, 0x26, CPU::STA_AB, 0x06, 0x20,
@@ -140,6 +337,8 @@ mod tests {
             CPU::BRK
         ];
         nes.load(&program);
+        nes.cpu.memory.ppu.memory.write_byte(0x26ab, 0xff);
+        nes.cpu.memory.ppu.data_buffer = 0xaa;
 
         nes.step().unwrap();
         nes.step().unwrap();
@@ -1,329 +1,492 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+#[cfg(feature = "sdl")]
 use sdl2::Sdl;
-use crate::nes::apu::registers::frame_counter::FrameCounterRegister;
-use crate::nes::apu::registers::dmc::DMCRegisters;
-use crate::nes::apu::registers::noise::NoiseRegisters;
-use crate::nes::apu::registers::pulse::PulseRegisters;
-use crate::nes::apu::registers::status::StatusFlag::{DmcEnable, FrameInterrupt, NoiseEnable, PulseOneEnable, PulseTwoEnable, TriangleEnable};
+use crate::nes::apu::channels::dmc::DMCChannel;
+use crate::nes::apu::channels::noise::NoiseChannel;
+use crate::nes::apu::channels::pulse::PulseChannel;
+use crate::nes::apu::channels::triangle::TriangleChannel;
+use crate::nes::apu::filters::FilterChain;
+use crate::nes::Region;
+use crate::nes::apu::resampler::Resampler;
+use crate::nes::apu::scope::{ChannelOutputs, ChannelScope};
+use crate::nes::apu::frame_counter::{FrameClock, FrameCounter};
+use crate::nes::apu::registers::status::StatusFlag::{DmcEnable, NoiseEnable, PulseOneEnable, PulseTwoEnable, TriangleEnable};
 use crate::nes::apu::registers::status::StatusRegister;
-use crate::nes::apu::registers::triangle::TriangleRegisters;
+use crate::util::audio::{APUMixer, AudioConfig, Channel, RateControl};
+#[cfg(feature = "sdl")]
 use crate::util::audio::AudioPlayer;
 use crate::util::bitvec::BitVector;
+use crate::util::wav::WavWriter;
 
 pub mod registers;
+pub mod channels;
+pub mod frame_counter;
+pub mod filters;
+pub mod resampler;
+pub mod scope;
 
 pub struct APU {
-    pub pulse_one: PulseRegisters,
-    pub pulse_two: PulseRegisters,
-    pub triangle: TriangleRegisters,
-    pub noise: NoiseRegisters,
-    pub dmc: DMCRegisters,
+    pub pulse_one: PulseChannel,
+    pub pulse_two: PulseChannel,
+    pub triangle: TriangleChannel,
+    pub noise: NoiseChannel,
+    pub dmc: DMCChannel,
     pub status: StatusRegister,
-    pub frame_counter: FrameCounterRegister,
+    pub frame_counter: FrameCounter,
 
+    pub mixer: APUMixer,
+    pub filters: FilterChain,
+    pub resampler: Resampler,
+    pub rate_control: RateControl,
+    #[cfg(feature = "sdl")]
     pub audio_player: Option<AudioPlayer>,
+    pub wav_writer: Option<WavWriter>,
+    pub sample_capture: Option<VecDeque<f32>>,
+    pub sample_capture_size: usize,
+    channel_scope: Option<ChannelScope>,
     pub cpu_cycles: usize,
+    samples: Vec<f32>,
+    // samples in the current batch from before the recording started
+    recording_start: usize,
 }
 
 impl APU {
-    const REGISTER_A: u8 = 0;
-    const REGISTER_B: u8 = 1;
-    const REGISTER_C: u8 = 2;
-    const REGISTER_D: u8 = 3;
+    pub const CPU_FREQUENCY: f64 = 1_789_773.0;
+    const SAMPLE_BATCH_SIZE: usize = 256;
+    // about one scope sample per 44.1kHz audio sample
+    const SCOPE_INTERVAL: usize = 40;
 
     pub fn new() -> Self {
         Self {
-            pulse_one: PulseRegisters::new(),
-            pulse_two: PulseRegisters::new(),
-            triangle: TriangleRegisters::new(),
-            noise: NoiseRegisters::new(),
-            dmc: DMCRegisters::new(),
+            pulse_one: PulseChannel::new(1),
+            pulse_two: PulseChannel::new(2),
+            triangle: TriangleChannel::new(),
+            noise: NoiseChannel::new(),
+            dmc: DMCChannel::new(),
 
             status: StatusRegister::new(),
-            frame_counter: FrameCounterRegister::new(),
+            frame_counter: FrameCounter::new(),
 
+            mixer: APUMixer::new(),
+            filters: FilterChain::new(AudioConfig::DEFAULT_SAMPLE_RATE as f32),
+            resampler: Resampler::new(APU::CPU_FREQUENCY, AudioConfig::DEFAULT_SAMPLE_RATE as f64),
+            rate_control: RateControl::new(),
+            #[cfg(feature = "sdl")]
             audio_player: None,
+            wav_writer: None,
+            sample_capture: None,
+            sample_capture_size: 0,
+            channel_scope: None,
             cpu_cycles: 0,
+            samples: Vec::with_capacity(APU::SAMPLE_BATCH_SIZE),
+            recording_start: 0,
         }
     }
 
-    pub fn init_audio_player(&mut self, sdl_context: &Sdl) {
+    #[cfg(feature = "sdl")]
+    pub fn init_audio_player(&mut self, sdl_context: &Sdl, config: &AudioConfig) {
         let audio_subsystem = sdl_context.audio().unwrap();
-        let audio_player = AudioPlayer::new(audio_subsystem);
-        self.audio_player = Some(audio_player)
+        let audio_player = AudioPlayer::new(audio_subsystem, config);
+        self.set_sample_rate(audio_player.get_freq());
+        self.audio_player = Some(audio_player);
+        self.rate_control.target_latency_ms = config.buffer_ms as f64;
     }
 
-    pub fn read_status_register(&self) -> u8 {
-        // todo: implement side-effects
-        self.status.get_value()
+    // The amount of audio kept buffered ahead of the device. Anything past twice that is dropped.
+    pub fn set_audio_latency(&mut self, latency_ms: f64) {
+        self.rate_control.target_latency_ms = latency_ms;
+        #[cfg(feature = "sdl")]
+        if let Some(audio_player) = &mut self.audio_player {
+            audio_player.set_buffer_ms(latency_ms);
+        }
     }
 
-    pub fn write_status_register(&mut self, value: u8) {
-        let frame_int_mask = (self.status.is_set(FrameInterrupt) as u8) << 6;
-        self.status.set_value((value & 0b0001_1111) | frame_int_mask);
+    // The APU runs off the CPU clock, so the samples have to be resampled from the region's clock rate
+    // to keep the pitch right. PAL also has its own frame counter steps and noise and DMC periods.
+    pub fn set_region(&mut self, region: Region) {
+        self.resampler.set_input_rate(region.get_cpu_frequency());
+        self.frame_counter.set_region(region);
+        self.noise.registers.set_region(region);
+        self.dmc.registers.set_region(region);
+    }
 
-        let mut guard = self.audio_player.as_mut().unwrap().device.lock();
-        if self.status.is_clear(PulseOneEnable) {
-            self.pulse_one.clear_length_counter();
-            guard.pulse_one.silence();
-        }
-        if self.status.is_clear(PulseTwoEnable) {
-            self.pulse_two.clear_length_counter();
-            guard.pulse_two.silence();
-        }
-        if self.status.is_clear(TriangleEnable) {
-            self.triangle.clear_length_counter();
-            guard.triangle.silence();
+    // Builds without SDL (ex: for the browser) have no audio device of their own.
+    #[cfg(feature = "sdl")]
+    pub fn has_audio_player(&self) -> bool {
+        self.audio_player.is_some()
+    }
+
+    #[cfg(not(feature = "sdl"))]
+    pub fn has_audio_player(&self) -> bool {
+        false
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: i32) {
+        self.resampler.set_output_rate(sample_rate as f64);
+        self.filters.set_sample_rate(sample_rate as f32);
+    }
+
+    // Records the samples handed to the audio device, at the device's sample rate. Samples are
+    // produced while recording even if there's no audio device, so headless runs can be recorded.
+    // The recording starts with the next sample, whatever is still waiting in the current batch is
+    // left out.
+    pub fn start_audio_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_audio_recording()?;
+        self.wav_writer = Some(WavWriter::create(path, self.resampler.get_output_rate() as u32)?);
+        if !self.has_audio_player() {
+            self.samples.clear();
         }
-        if self.status.is_clear(NoiseEnable) {
-            self.noise.clear_length_counter();
-            guard.noise.silence();
+        self.recording_start = self.samples.len();
+        Ok(())
+    }
+
+    // Keeps the last `size` samples for whoever asks for them, for front ends without an audio device
+    // of their own (ex: a browser). The oldest samples are dropped if they aren't taken in time.
+    pub fn start_sample_capture(&mut self, size: usize) {
+        self.sample_capture = Some(VecDeque::with_capacity(size));
+        self.sample_capture_size = size;
+    }
+
+    pub fn take_captured_samples(&mut self) -> Vec<f32> {
+        self.sample_capture.as_mut().map(|capture| capture.drain(..).collect()).unwrap_or_default()
+    }
+
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        let Some(mut wav_writer) = self.wav_writer.take() else { return Ok(()) };
+        wav_writer.write_samples(&self.samples[self.recording_start..])?;
+        self.recording_start = 0;
+        if !self.has_audio_player() {
+            self.samples.clear();
         }
-        if self.status.is_clear(DmcEnable) {
-            // self.dmc.clear_length_counter();
-            guard.dmc.silence();
+        wav_writer.finish()
+    }
+
+    pub fn channel_outputs(&self) -> ChannelOutputs {
+        ChannelOutputs {
+            pulse_one: self.pulse_one.output(),
+            pulse_two: self.pulse_two.output(),
+            triangle: self.triangle.output(),
+            noise: self.noise.output(),
+            dmc: self.dmc.get_output_level(),
         }
     }
 
+    // Starts keeping the last `capacity` outputs of every channel, sampled every 40 CPU cycles. It's
+    // off by default, so nothing is recorded unless a debug view asks for it.
+    pub fn enable_channel_scope(&mut self, capacity: usize) {
+        self.channel_scope = Some(ChannelScope::new(capacity));
+    }
+
+    pub fn disable_channel_scope(&mut self) {
+        self.channel_scope = None;
+    }
+
+    pub fn get_channel_scope(&self) -> Option<&ChannelScope> {
+        self.channel_scope.as_ref()
+    }
+
+    pub fn read_status_register(&mut self) -> u8 {
+        let status = self.peek_status_register();
+        self.frame_counter.clear_irq();
+        status
+    }
+
+    // Reads back which channels are still playing (non-zero length counter, or DMC bytes left to
+    // fetch) rather than the enable bits that were written.
+    pub fn peek_status_register(&self) -> u8 {
+        (self.pulse_one.is_active() as u8)
+            | (self.pulse_two.is_active() as u8) << 1
+            | (self.triangle.is_active() as u8) << 2
+            | (self.noise.is_active() as u8) << 3
+            | (self.dmc.is_active() as u8) << 4
+            | (self.frame_counter.poll_irq() as u8) << 6
+            | (self.dmc.poll_irq() as u8) << 7
+    }
+
+    pub fn poll_irq(&self) -> bool {
+        self.dmc.poll_irq() || self.frame_counter.poll_irq()
+    }
+
+    pub fn write_status_register(&mut self, value: u8) {
+        self.status.set_value(value & 0b0001_1111);
+        self.pulse_one.set_enabled(self.status.is_set(PulseOneEnable));
+        self.pulse_two.set_enabled(self.status.is_set(PulseTwoEnable));
+        self.triangle.set_enabled(self.status.is_set(TriangleEnable));
+        self.noise.set_enabled(self.status.is_set(NoiseEnable));
+        self.dmc.set_enabled(self.status.is_set(DmcEnable));
+    }
+
+    // Reset silences every channel as if $4015 was cleared, and restarts the frame counter in
+    // whichever mode it was last set to.
+    pub fn reset(&mut self) {
+        self.write_status_register(0);
+        self.frame_counter.clear_irq();
+        let mode = self.frame_counter.read();
+        self.frame_counter.write(mode, self.cpu_cycles as u64);
+    }
+
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.mixer.set_channel_volume(channel, volume);
+    }
+
+    pub fn mute_channel(&mut self, channel: Channel, mute: bool) {
+        self.mixer.mute_channel(channel, mute);
+    }
+
     pub fn read_frame_counter_register(&self) -> u8 {
         self.frame_counter.read()
     }
 
     pub fn write_frame_counter_register(&mut self, value: u8) {
-        self.frame_counter.write(value);
-        self.frame_counter.reset(); // todo: is this good enough? (ie. 3-4 cycle reset issue)
-        // todo: implement missing side-effects (https://www.nesdev.org/wiki/APU_Frame_Counter)
+        self.frame_counter.write(value, self.cpu_cycles as u64);
     }
 
     pub fn write_pulse_one_registers(&mut self, register_idx: u8, data: u8) {
         self.pulse_one.write(register_idx, data);
-        let mut guard = self.audio_player.as_mut().unwrap().device.lock();
-        if register_idx == APU::REGISTER_A {
-            guard.pulse_one.set_duty(self.pulse_one.get_duty());
-            guard.pulse_one.set_duration_enable(self.pulse_one.is_one_shot());
-            guard.pulse_one.set_envelope_enable(self.pulse_one.is_envelope_volume());
-            if self.pulse_one.is_envelope_volume() {
-                guard.pulse_one.set_envelope_frequency(self.pulse_one.get_envelope_frequency());
-            } else {
-                guard.pulse_one.set_volume(self.pulse_one.get_volume());
-            }
-        }
-        if register_idx == APU::REGISTER_B {
-            guard.pulse_one.set_sweep_enable(self.pulse_one.is_sweep_enabled());
-            guard.pulse_one.set_sweep_negate(self.pulse_one.is_sweep_negate());
-            guard.pulse_one.set_sweep_shift(self.pulse_one.get_sweep_shift());
-            guard.pulse_one.set_sweep_frequency(self.pulse_one.get_sweep_frequency());
-        }
-        if register_idx == APU::REGISTER_C {
-            guard.pulse_one.set_frequency_from_timer(self.pulse_one.get_timer());
-        }
-        if register_idx == APU::REGISTER_D {
-            guard.pulse_one.set_frequency_from_timer(self.pulse_one.get_timer());
-            guard.pulse_one.set_duration(self.pulse_one.get_duration());
-            guard.pulse_one.reset();
-        }
-        // if !guard.mute_pulse_one {
-        //     println!("pulse_one ({}): freq: {}, timer: {}, volume: {}, duty: {}, length_counter: {}, \
-        //       is_loop: {}, is_envelope: {}, is_sweep: {}, sweep_negate: {}, \
-        //       sweep_period: {}, sweep_shift: {}",
-        //         register_idx, self.pulse_one.get_frequency(), self.pulse_one.get_timer(),
-        //         self.pulse_one.get_volume(), self.pulse_one.get_duty(),
-        //         self.pulse_one.get_length_counter(), self.pulse_one.is_loop(),
-        //         self.pulse_one.is_envelope_volume(), self.pulse_one.is_sweep_enabled(),
-        //         self.pulse_one.is_sweep_negate(), self.pulse_one.get_sweep_period(),
-        //         self.pulse_one.get_sweep_shift()
-        //     );
-        // }
     }
 
     pub fn write_pulse_two_registers(&mut self, register_idx: u8, data: u8) {
         self.pulse_two.write(register_idx, data);
-        let mut guard = self.audio_player.as_mut().unwrap().device.lock();
-        if register_idx == APU::REGISTER_A {
-            guard.pulse_two.set_duty(self.pulse_two.get_duty());
-            guard.pulse_two.set_duration_enable(self.pulse_two.is_one_shot());
-            guard.pulse_two.set_envelope_enable(self.pulse_two.is_envelope_volume());
-            if self.pulse_two.is_envelope_volume() {
-                guard.pulse_two.set_envelope_frequency(self.pulse_two.get_envelope_frequency());
-            } else {
-                guard.pulse_two.set_volume(self.pulse_two.get_volume());
-            }
-        }
-        if register_idx == APU::REGISTER_B {
-            guard.pulse_two.set_sweep_enable(self.pulse_two.is_sweep_enabled());
-            guard.pulse_two.set_sweep_negate(self.pulse_two.is_sweep_negate());
-            guard.pulse_two.set_sweep_shift(self.pulse_two.get_sweep_shift());
-            guard.pulse_two.set_sweep_frequency(self.pulse_two.get_sweep_frequency());
-        }
-        if register_idx == APU::REGISTER_C {
-            guard.pulse_two.set_frequency_from_timer(self.pulse_two.get_timer());
-        }
-        if register_idx == APU::REGISTER_D {
-            guard.pulse_two.set_frequency_from_timer(self.pulse_two.get_timer());
-            guard.pulse_two.set_duration(self.pulse_two.get_duration());
-            guard.pulse_two.reset();
-        }
-        // if !guard.mute_pulse_two {
-        //     println!("pulse_two ({}): freq: {}, timer: {}, volume: {}, duty: {}, length_counter: {}, \
-        //       is_loop: {}, is_envelope: {}, is_sweep: {}, sweep_negate: {}, \
-        //       sweep_period: {}, sweep_shift: {}",
-        //         register_idx, self.pulse_two.get_frequency(), self.pulse_two.get_timer(),
-        //         self.pulse_two.get_volume(), self.pulse_two.get_duty(),
-        //         self.pulse_two.get_length_counter(), self.pulse_two.is_loop(),
-        //         self.pulse_two.is_envelope_volume(), self.pulse_two.is_sweep_enabled(),
-        //         self.pulse_two.is_sweep_negate(), self.pulse_two.get_sweep_period(),
-        //         self.pulse_two.get_sweep_shift()
-        //     );
-        // }
     }
 
     pub fn write_triangle_registers(&mut self, register_idx: u8, data: u8) {
         self.triangle.write(register_idx, data);
-        let mut guard = self.audio_player.as_mut().unwrap().device.lock();
-        if register_idx == APU::REGISTER_D {
-            if self.triangle.get_linear_counter() == 0 {
-                guard.triangle.silence();
-            } else {
-                let rate = AudioPlayer::FREQ as f32 / 240.0;
-                guard.triangle.set_duration(rate * self.triangle.get_linear_counter() as f32);
-            }
-        }
-        if register_idx == APU::REGISTER_C || register_idx == APU::REGISTER_D {
-            if self.triangle.get_length_counter() == 0 || self.triangle.get_timer() < 2 {
-                guard.triangle.silence();
-            } else {
-                guard.triangle.set_frequency(self.triangle.get_frequency());
-            }
-        }
-        // if !guard.mute_triangle {
-        //     println!("triangle ({}): freq: {}, timer: {}, length_counter: {}, linear_counter: {}",
-        //         register_idx, self.triangle.get_frequency(), self.triangle.get_timer(),
-        //         self.triangle.get_length_counter(), self.triangle.get_linear_counter());
-        // }
     }
 
     pub fn write_noise_registers(&mut self, register_idx: u8, data: u8) {
         self.noise.write(register_idx, data);
-        let mut guard = self.audio_player.as_mut().unwrap().device.lock();
-        if register_idx == APU::REGISTER_A {
-            guard.noise.set_volume(self.noise.get_volume());
-        }
-        if register_idx == APU::REGISTER_C {
-            guard.noise.set_is_tone_mode(self.noise.is_tone_mode());
-            guard.noise.set_frequency(self.noise.get_frequency());
-        }
-        if register_idx == APU::REGISTER_D {
-            if self.noise.get_length_counter() == 0 {
-                guard.noise.silence();
-            } else {
-                let rate = AudioPlayer::FREQ as f32 / 120.0;
-                guard.noise.set_duration(rate * self.noise.get_length_counter() as f32);
-            }
-        }
-        // if !guard.mute_noise {
-        //     println!("noise ({}): freq: {}, period: {}, volume: {}, length_counter: {}, tone-mode: {}, constant-volume: {}, one-shot: {}",
-        //         register_idx, self.noise.get_frequency(), self.noise.get_period(),
-        //         self.noise.get_volume(), self.noise.get_length_counter(),
-        //         self.noise.is_tone_mode(), self.noise.is_constant_volume(),
-        //         self.noise.is_one_shot_play());
-        // }
     }
 
     pub fn write_dmc_registers(&mut self, register_idx: u8, data: u8) {
         self.dmc.write(register_idx, data);
-        let mut guard = self.audio_player.as_mut().unwrap().device.lock();
-        if register_idx == APU::REGISTER_A {
-            guard.dmc.set_frequency(self.dmc.get_frequency());
-        }
-        if register_idx == APU::REGISTER_B {
-            guard.dmc.set_volume(self.dmc.get_volume());
-        }
-        // if !guard.mute_dmc {
-        //     println!("dmc ({}): volume: {}, rate: {}, sample_address: 0x{:x}, sample_length: {}",
-        //         register_idx, self.dmc.get_volume(), self.dmc.get_rate_idx(),
-        //         self.dmc.get_sample_address(), self.dmc.get_sample_length());
-        // }
     }
 
     pub fn tick(&mut self, cycles: u8) {
-        self.cpu_cycles += cycles as usize;
+        for _ in 0..cycles {
+            self.cpu_cycles += 1;
+            if self.cpu_cycles % 2 == 0 {
+                self.pulse_one.clock_timer();
+                self.pulse_two.clock_timer();
+            }
+            self.triangle.clock_timer();
+            self.noise.clock_timer();
+            self.dmc.clock_timer();
+
+            let clock = self.frame_counter.tick(self.cpu_cycles as u64);
+            self.clock_frame(clock);
+
+            if self.has_audio_player() || self.wav_writer.is_some() || self.sample_capture.is_some() {
+                let sample = self.mix();
+                if let Some(sample) = self.resampler.push(sample) {
+                    self.output_sample(sample);
+                }
+            }
+            if self.channel_scope.is_some() && self.cpu_cycles.is_multiple_of(APU::SCOPE_INTERVAL) {
+                let outputs = self.channel_outputs();
+                if let Some(channel_scope) = &mut self.channel_scope {
+                    channel_scope.push(outputs);
+                }
+            }
+        }
     }
 
-    pub fn step(&mut self) -> Result<bool, bool> {
-        if self.frame_counter.is_four_step_mode() {
-            self.step_four_mode();
-        } else {
-            self.step_five_mode();
-        }
-        Ok(true)
+    fn mix(&self) -> f32 {
+        let outputs = self.channel_outputs();
+        self.mixer.sample(outputs.pulse_one, outputs.pulse_two, outputs.triangle, outputs.noise, outputs.dmc)
     }
 
-    fn step_four_mode(&mut self) {
-        if self.cpu_cycles > 7457 && self.frame_counter.get_step() == 0 {
-            self.frame_counter.increment();
-            self.update_quarter_frame();
+    fn output_sample(&mut self, sample: f32) {
+        let sample = self.filters.process(sample);
+        if let Some(capture) = &mut self.sample_capture {
+            if capture.len() == self.sample_capture_size {
+                capture.pop_front();
+            }
+            capture.push_back(sample);
         }
-
-        if self.cpu_cycles > 14913 && self.frame_counter.get_step() == 1 {
-            self.frame_counter.increment();
-            self.update_quarter_frame();
-            self.update_half_frame();
+        self.samples.push(sample);
+        if self.samples.len() >= APU::SAMPLE_BATCH_SIZE {
+            #[cfg(feature = "sdl")]
+            if let Some(audio_player) = &self.audio_player {
+                let adjustment = self.rate_control.get_adjustment(audio_player.get_queued_samples(), audio_player.get_freq());
+                self.resampler.set_rate_adjustment(adjustment);
+                audio_player.play(&self.samples);
+            }
+            if let Some(wav_writer) = &mut self.wav_writer {
+                if let Err(err) = wav_writer.write_samples(&self.samples[self.recording_start..]) {
+                    println!("[WARNING] audio recording stopped: {}", err);
+                    self.wav_writer = None;
+                }
+            }
+            self.samples.clear();
+            self.recording_start = 0;
         }
+    }
 
-        if self.cpu_cycles > 22371 && self.frame_counter.get_step() == 2 {
-            self.frame_counter.increment();
+    fn clock_frame(&mut self, clock: FrameClock) {
+        if clock.quarter_frame {
             self.update_quarter_frame();
         }
-
-        if self.cpu_cycles > 29830 && self.frame_counter.get_step() == 3 {
-            self.frame_counter.increment();
-            self.update_quarter_frame();
+        if clock.half_frame {
             self.update_half_frame();
-            self.set_irq();
-            self.cpu_cycles -= 29830;
         }
     }
 
-    fn step_five_mode(&mut self) {
-        if self.cpu_cycles > 7457 && self.frame_counter.get_step() == 0 {
-            self.frame_counter.increment();
-            self.update_quarter_frame();
-        }
+    fn update_quarter_frame(&mut self) {
+        self.triangle.clock_linear_counter();
+        self.pulse_one.clock_envelope();
+        self.pulse_two.clock_envelope();
+        self.noise.clock_envelope();
+    }
 
-        if self.cpu_cycles > 14913 && self.frame_counter.get_step() == 1 {
-            self.frame_counter.increment();
-            self.update_quarter_frame();
-            self.update_half_frame();
-        }
+    fn update_half_frame(&mut self) {
+        self.pulse_one.clock_length_counter();
+        self.pulse_two.clock_length_counter();
+        self.triangle.clock_length_counter();
+        self.noise.clock_length_counter();
+        self.pulse_one.clock_sweep();
+        self.pulse_two.clock_sweep();
+    }
+}
 
-        if self.cpu_cycles > 22371 && self.frame_counter.get_step() == 2 {
-            self.frame_counter.increment();
-            self.update_quarter_frame();
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes the last register of every channel, loading length index 1 (254 half frames)
+    fn load_length_counters(apu: &mut APU) {
+        apu.write_pulse_one_registers(3, 0b0000_1000);
+        apu.write_pulse_two_registers(3, 0b0000_1000);
+        apu.write_triangle_registers(3, 0b0000_1000);
+        apu.write_noise_registers(3, 0b0000_1000);
+    }
 
-        if self.cpu_cycles > 29829 && self.frame_counter.get_step() == 3 {
-            self.frame_counter.increment();
+    #[test]
+    fn test_sample_capture() {
+        let mut apu = APU::new();
+        apu.tick(100);
+        assert!(apu.take_captured_samples().is_empty());
+
+        apu.start_sample_capture(16);
+        // ~40.6 CPU cycles per sample at 44.1kHz
+        apu.tick(200);
+        apu.tick(210);
+        assert_eq!(apu.take_captured_samples().len(), 10);
+        for _ in 0..10 {
+            apu.tick(200);
         }
+        assert_eq!(apu.take_captured_samples().len(), 16);
+    }
 
-        if self.cpu_cycles > 37282 && self.frame_counter.get_step() == 4 {
-            self.frame_counter.increment();
-            self.update_quarter_frame();
-            self.update_half_frame();
-            self.cpu_cycles -= 37282;
+    #[test]
+    fn test_recording_skips_earlier_samples() {
+        let path = std::env::temp_dir().join("alpines_test_recording.wav");
+        let mut apu = APU::new();
+        apu.tick(200);
+        apu.tick(210);
+
+        apu.start_audio_recording(&path).unwrap();
+        apu.start_sample_capture(64);
+        apu.tick(200);
+        apu.tick(210);
+        apu.stop_audio_recording().unwrap();
+        let recorded = apu.take_captured_samples().len() as u64;
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 44 + 2 * recorded);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_status_reports_length_counters() {
+        let mut apu = APU::new();
+        load_length_counters(&mut apu);
+        assert_eq!(apu.peek_status_register(), 0x00);
+
+        apu.write_status_register(0b0000_0101);
+        load_length_counters(&mut apu);
+        assert_eq!(apu.peek_status_register(), 0b0000_0101);
+
+        apu.write_status_register(0b0000_1111);
+        load_length_counters(&mut apu);
+        assert_eq!(apu.peek_status_register(), 0b0000_1111);
+        assert_eq!(apu.pulse_one.get_length_counter(), 254);
+    }
+
+    #[test]
+    fn test_status_write_clears_length_counters() {
+        let mut apu = APU::new();
+        apu.write_status_register(0b0000_1111);
+        load_length_counters(&mut apu);
+
+        for (channel, bit) in [(0, 0b0001), (1, 0b0010), (2, 0b0100), (3, 0b1000)] {
+            apu.write_status_register(0b0000_1111 & !bit);
+            assert_eq!(apu.peek_status_register() & bit, 0, "channel {}", channel);
+            apu.write_status_register(0b0000_1111);
+            assert_eq!(apu.peek_status_register() & bit, 0, "channel {}", channel);
+            load_length_counters(&mut apu);
+            assert_eq!(apu.peek_status_register() & bit, bit, "channel {}", channel);
         }
     }
 
-    fn update_quarter_frame(&mut self) {
-        // self.triangle.decrement_linear_counter();
-        // todo: update envelopes
+    #[test]
+    fn test_length_counters_count_down() {
+        let mut apu = APU::new();
+        apu.write_status_register(0b0000_1111);
+        apu.write_pulse_one_registers(0, 0b0010_0000); // halted
+        apu.write_triangle_registers(0, 0b1000_0000); // halted through the control flag
+        apu.write_pulse_two_registers(3, 0b0001_1000); // 2 half frames
+        apu.write_noise_registers(3, 0b0001_1000);
+        apu.write_pulse_one_registers(3, 0b0001_1000);
+        apu.write_triangle_registers(3, 0b0001_1000);
+
+        apu.update_half_frame();
+        assert_eq!(apu.peek_status_register(), 0b0000_1111);
+        apu.update_half_frame();
+        assert_eq!(apu.peek_status_register(), 0b0000_0101);
+        assert_eq!(apu.pulse_two.output(), 0);
+        assert_eq!(apu.noise.output(), 0);
     }
 
-    fn update_half_frame(&mut self) {
-        // todo: update length counters
-        // self.triangle.decrement_length_counter();
-        // todo: update sweep units
+    #[test]
+    fn test_status_read_clears_frame_irq_only() {
+        let mut apu = APU::new();
+        apu.write_status_register(0b0000_0001);
+        apu.write_pulse_one_registers(3, 0b0000_1000);
+        apu.tick(255);
+        while !apu.frame_counter.poll_irq() {
+            apu.tick(1);
+        }
+        assert_eq!(apu.read_status_register(), 0b0100_0001);
+        assert_eq!(apu.read_status_register(), 0b0000_0001);
     }
 
-    fn set_irq(&mut self) {
-        // todo: implement
+    #[test]
+    fn test_channel_scope() {
+        let mut apu = APU::new();
+        apu.tick(100);
+        assert!(apu.get_channel_scope().is_none());
+
+        apu.enable_channel_scope(512);
+        apu.write_status_register(0b0000_0001);
+        apu.write_pulse_one_registers(0, 0b1011_1111); // 50% duty, constant volume 15
+        apu.write_pulse_one_registers(2, 0xFD);
+        apu.write_pulse_one_registers(3, 0x00);
+        for _ in 0..1000 {
+            apu.tick(APU::SCOPE_INTERVAL as u8);
+        }
+        assert_eq!(apu.channel_outputs().pulse_two, 0);
+
+        let scope = apu.get_channel_scope().unwrap();
+        let pulse_one = scope.get_history(Channel::PulseOne);
+        assert_eq!(pulse_one.len(), 512);
+        assert!(pulse_one.contains(&15) && pulse_one.contains(&0));
+        // a silenced triangle holds whatever step it stopped on, so the others are flat rather than 0
+        for channel in [Channel::PulseTwo, Channel::Triangle, Channel::Noise, Channel::Dmc] {
+            let history = scope.get_history(channel);
+            assert!(history.iter().all(|output| *output == history[0]));
+        }
+
+        apu.disable_channel_scope();
+        assert!(apu.get_channel_scope().is_none());
     }
-}
\ No newline at end of file
+}
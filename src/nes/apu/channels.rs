@@ -0,0 +1,5 @@
+pub mod dmc;
+pub mod pulse;
+pub mod envelope;
+pub mod length_counter;pub mod triangle;
+pub mod noise;
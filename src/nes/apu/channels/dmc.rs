@@ -0,0 +1,336 @@
+use crate::nes::apu::registers::dmc::DMCRegisters;
+
+// The DMC plays 1-bit delta-encoded samples straight out of CPU memory:
+//
+//  memory reader -> sample buffer -> shift register -> output level
+//
+// Whenever the sample buffer is empty and there are bytes left to play, the memory reader fetches the
+// next byte with a DMA that halts the CPU (see CPU::dmc_dma). The output unit drains the shift
+// register one bit per timer period, moving the output level up or down by 2. When the last byte of a
+// non-looping sample is fetched, the channel raises an IRQ if enabled in $4010.
+
+pub struct DMCChannel {
+    pub registers: DMCRegisters,
+
+    timer: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    output_level: u8,
+    silence: bool,
+    dma_wait_cycles: u16,
+    irq_flag: bool,
+}
+
+impl DMCChannel {
+    pub fn new() -> Self {
+        DMCChannel {
+            registers: DMCRegisters::new(),
+
+            timer: 0,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            output_level: 0,
+            silence: true,
+            dma_wait_cycles: 0,
+            irq_flag: false,
+        }
+    }
+
+    pub fn read(&self, index: u8) -> u8 {
+        self.registers.read(index)
+    }
+
+    pub fn write(&mut self, index: u8, data: u8) {
+        self.registers.write(index, data);
+        match index {
+            0 => {
+                if !self.registers.is_irq_enable() {
+                    self.irq_flag = false;
+                }
+            },
+            1 => self.output_level = self.registers.get_volume(),
+            _ => {},
+        }
+    }
+
+    // Writing $4015 always acknowledges the DMC interrupt.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    // Clocked once per CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.registers.get_rate() - 1;
+            self.clock_output_unit();
+        } else {
+            self.timer -= 1;
+        }
+
+        if self.poll_dma().is_some() {
+            self.dma_wait_cycles = self.dma_wait_cycles.saturating_add(1);
+        }
+    }
+
+    // Address of the next sample byte, if the memory reader is waiting on a DMA to fill the buffer.
+    pub fn poll_dma(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    // Number of CPU cycles the pending DMA has been waiting to be serviced.
+    pub fn get_dma_wait_cycles(&self) -> u16 {
+        self.dma_wait_cycles
+    }
+
+    pub fn load_sample_buffer(&mut self, sample: u8) {
+        self.sample_buffer = Some(sample);
+        self.dma_wait_cycles = 0;
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.registers.is_loop() {
+                self.restart();
+            } else if self.registers.is_irq_enable() {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    pub fn poll_irq(&self) -> bool {
+        self.irq_flag
+    }
+
+    // Reported in bit 4 of $4015: the sample still has bytes left to fetch.
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn get_bytes_remaining(&self) -> u16 {
+        self.bytes_remaining
+    }
+
+    pub fn get_output_level(&self) -> u8 {
+        self.output_level
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.registers.get_sample_address();
+        self.bytes_remaining = self.registers.get_sample_length();
+    }
+
+    fn clock_output_unit(&mut self) {
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                },
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::Region;
+
+    #[test]
+    fn test_memory_reader() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(2, 0xff); // $FFC0
+        dmc.write(3, 0x04); // 65 bytes
+        assert_eq!(dmc.poll_dma(), None);
+
+        dmc.set_enabled(true);
+        assert_eq!(dmc.poll_dma(), Some(0xFFC0));
+        for _ in 0..63 {
+            dmc.load_sample_buffer(0);
+            dmc.sample_buffer = None;
+        }
+        assert_eq!(dmc.poll_dma(), Some(0xFFFF));
+        dmc.load_sample_buffer(0);
+        dmc.sample_buffer = None;
+        assert_eq!(dmc.poll_dma(), Some(0x8000));
+        dmc.load_sample_buffer(0);
+        assert_eq!(dmc.get_bytes_remaining(), 0);
+        assert_eq!(dmc.poll_dma(), None);
+    }
+
+    #[test]
+    fn test_disable_halts_sample() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(3, 0x01); // 17 bytes
+        dmc.set_enabled(true);
+        dmc.load_sample_buffer(0xFF);
+        assert!(dmc.is_active());
+
+        // the byte already in the buffer still plays out, but nothing more is fetched
+        dmc.set_enabled(false);
+        assert!(!dmc.is_active());
+        assert_eq!(dmc.poll_dma(), None);
+        assert_eq!(dmc.sample_buffer, Some(0xFF));
+
+        // enabling again restarts the sample from the top
+        dmc.set_enabled(true);
+        assert_eq!(dmc.get_bytes_remaining(), 17);
+        dmc.sample_buffer = None;
+        assert_eq!(dmc.poll_dma(), Some(0xC000));
+
+        // and doesn't restart one that's still playing
+        dmc.load_sample_buffer(0x00);
+        dmc.set_enabled(true);
+        assert_eq!(dmc.get_bytes_remaining(), 16);
+    }
+
+    #[test]
+    fn test_output_level_clamp() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(1, 0x7E);
+        dmc.write(3, 0x01);
+        dmc.set_enabled(true);
+        dmc.load_sample_buffer(0xFF);
+        for _ in 0..16 {
+            dmc.clock_output_unit();
+        }
+        assert_eq!(dmc.get_output_level(), 0x7E);
+
+        dmc.write(1, 0x01);
+        dmc.load_sample_buffer(0x00);
+        for _ in 0..16 {
+            dmc.clock_output_unit();
+        }
+        assert_eq!(dmc.get_output_level(), 0x01);
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(0, 0b0100_0000);
+        dmc.write(2, 0x01);
+        dmc.set_enabled(true);
+        dmc.load_sample_buffer(0);
+        assert_eq!(dmc.get_bytes_remaining(), 1);
+        dmc.sample_buffer = None;
+        assert_eq!(dmc.poll_dma(), Some(0xC040));
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(0, 0b1000_0000);
+        dmc.write(3, 0x00); // 1 byte
+        dmc.set_enabled(true);
+        dmc.load_sample_buffer(0);
+        assert!(dmc.poll_irq());
+
+        dmc.set_enabled(true);
+        assert!(!dmc.poll_irq());
+
+        dmc.sample_buffer = None;
+        dmc.load_sample_buffer(0);
+        assert!(dmc.poll_irq());
+        dmc.write(0, 0b0000_0000);
+        assert!(!dmc.poll_irq());
+
+        // looping samples never raise an IRQ
+        dmc.write(0, 0b1100_0000);
+        dmc.set_enabled(true);
+        dmc.sample_buffer = None;
+        dmc.load_sample_buffer(0);
+        assert!(!dmc.poll_irq());
+        assert_eq!(dmc.get_bytes_remaining(), 1);
+    }
+
+    #[test]
+    fn test_silence_cleared_on_load() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(3, 0x01);
+        dmc.set_enabled(true);
+        dmc.load_sample_buffer(0xFF);
+        for _ in 0..8 {
+            dmc.clock_output_unit();
+        }
+        assert!(!dmc.silence);
+        for _ in 0..8 {
+            dmc.clock_output_unit();
+        }
+        assert!(dmc.silence);
+    }
+
+    #[test]
+    fn test_dma_request_rate() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(0, 0b0000_1111); // 54 cycles per bit
+        dmc.write(3, 0xff);
+        dmc.set_enabled(true);
+
+        let mut dma_cycles = Vec::new();
+        for cycle in 0..2000 {
+            dmc.clock_timer();
+            if dmc.poll_dma().is_some() {
+                dma_cycles.push(cycle);
+                dmc.load_sample_buffer(0x00);
+            }
+        }
+        assert_eq!(dma_cycles, vec![0, 378, 810, 1242, 1674]);
+    }
+
+    #[test]
+    fn test_output_level() {
+        let mut dmc = DMCChannel::new();
+        dmc.write(1, 0x40);
+        assert_eq!(dmc.get_output_level(), 0x40);
+
+        dmc.write(3, 0x01);
+        dmc.set_enabled(true);
+        dmc.load_sample_buffer(0b0000_0111);
+        for _ in 0..8 {
+            dmc.clock_output_unit(); // the first byte only loads once the shift register empties
+        }
+        for _ in 0..8 {
+            dmc.clock_output_unit();
+        }
+        assert_eq!(dmc.get_output_level(), 0x40 + 3 * 2 - 5 * 2);
+    }
+
+    #[test]
+    fn test_pal_rate_lookup() {
+        let mut dmc = DMCChannel::new();
+        dmc.registers.set_region(Region::Pal);
+        let rates: Vec<u16> = (0..16).map(|index| { dmc.write(0, index); dmc.registers.get_rate() }).collect();
+        assert_eq!(rates, vec![398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50]);
+
+        dmc.registers.set_region(Region::Ntsc);
+        assert_eq!(dmc.registers.get_rate(), 54);
+    }
+}
@@ -0,0 +1,93 @@
+// The envelope generator produces either a constant volume or a sawtooth that decays from 15 to 0,
+// stepping down once every (period + 1) quarter frames and optionally looping back up to 15.
+//
+//  start flag -> divider -> decay level -> volume (or the constant volume from the register)
+
+pub struct Envelope {
+    start: bool,
+    divider: u8,
+    decay_level: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Envelope {
+            start: false,
+            divider: 0,
+            decay_level: 0,
+        }
+    }
+
+    // Called when the channel's length counter is reloaded ($4003/$4007/$400F writes).
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // Clocked every quarter frame by the frame counter.
+    pub fn clock(&mut self, period: u8, is_loop: bool) {
+        if self.start {
+            self.start = false;
+            self.decay_level = 15;
+            self.divider = period;
+        } else if self.divider == 0 {
+            self.divider = period;
+            if self.decay_level > 0 {
+                self.decay_level -= 1;
+            } else if is_loop {
+                self.decay_level = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn get_volume(&self, is_constant_volume: bool, constant_volume: u8) -> u8 {
+        if is_constant_volume { constant_volume } else { self.decay_level }
+    }
+
+    pub fn get_decay_level(&self) -> u8 {
+        self.decay_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay() {
+        let mut envelope = Envelope::new();
+        envelope.restart();
+        envelope.clock(1, false);
+        assert_eq!(envelope.get_decay_level(), 15);
+
+        // period 1 -> the decay level drops every 2 clocks
+        let levels: Vec<u8> = (0..6).map(|_| { envelope.clock(1, false); envelope.get_decay_level() }).collect();
+        assert_eq!(levels, vec![15, 14, 14, 13, 13, 12]);
+    }
+
+    #[test]
+    fn test_decay_end() {
+        let mut envelope = Envelope::new();
+        envelope.restart();
+        for _ in 0..20 {
+            envelope.clock(0, false);
+        }
+        assert_eq!(envelope.get_decay_level(), 0);
+
+        envelope.restart();
+        for _ in 0..17 {
+            envelope.clock(0, true);
+        }
+        assert_eq!(envelope.get_decay_level(), 15);
+    }
+
+    #[test]
+    fn test_constant_volume() {
+        let mut envelope = Envelope::new();
+        envelope.restart();
+        envelope.clock(3, false);
+        assert_eq!(envelope.get_volume(true, 7), 7);
+        assert_eq!(envelope.get_volume(false, 7), 15);
+    }
+}
@@ -0,0 +1,83 @@
+// The length counter silences its channel once it counts down to 0. It is loaded from a lookup table
+// through the upper 5 bits of the channel's last register and clocked every half frame, unless
+// halted. Disabling the channel through $4015 clears the counter and prevents further loads.
+
+pub struct LengthCounter {
+    enabled: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub const LENGTH_LOOKUP: [u8; 32] = [
+        10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+        12, 16,  24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30
+    ];
+
+    pub fn new() -> Self {
+        LengthCounter {
+            enabled: false,
+            counter: 0,
+        }
+    }
+
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LengthCounter::LENGTH_LOOKUP[index as usize];
+        }
+    }
+
+    // Clocked every half frame by the frame counter.
+    pub fn clock(&mut self, halt: bool) {
+        if !halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+
+    pub fn get_counter(&self) -> u8 {
+        self.counter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_clock() {
+        let mut length_counter = LengthCounter::new();
+        length_counter.load(1);
+        assert_eq!(length_counter.get_counter(), 0);
+
+        length_counter.set_enabled(true);
+        length_counter.load(3);
+        assert_eq!(length_counter.get_counter(), 2);
+        length_counter.clock(true);
+        assert_eq!(length_counter.get_counter(), 2);
+        length_counter.clock(false);
+        length_counter.clock(false);
+        length_counter.clock(false);
+        assert_eq!(length_counter.get_counter(), 0);
+        assert!(!length_counter.is_active());
+    }
+
+    #[test]
+    fn test_disable() {
+        let mut length_counter = LengthCounter::new();
+        length_counter.set_enabled(true);
+        length_counter.load(1);
+        assert_eq!(length_counter.get_counter(), 254);
+        length_counter.set_enabled(false);
+        assert_eq!(length_counter.get_counter(), 0);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::nes::apu::channels::envelope::Envelope;
+use crate::nes::apu::channels::length_counter::LengthCounter;
+use crate::nes::apu::registers::noise::NoiseRegisters;
+
+//    Timer --> Shift Register   Length Counter
+//                   |                |
+//                   v                v
+// Envelope -------> Gate ----------> Gate --> (to mixer)
+
+pub struct NoiseChannel {
+    pub registers: NoiseRegisters,
+
+    envelope: Envelope,
+    length_counter: LengthCounter,
+    shift_register: u16,
+    timer: u16,
+}
+
+impl NoiseChannel {
+    pub fn new() -> Self {
+        NoiseChannel {
+            registers: NoiseRegisters::new(),
+
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+            shift_register: 1,
+            timer: 0,
+        }
+    }
+
+    pub fn read(&self, index: u8) -> u8 {
+        self.registers.read(index)
+    }
+
+    pub fn write(&mut self, index: u8, data: u8) {
+        self.registers.write(index, data);
+        if index == 3 {
+            self.length_counter.load(self.registers.get_length_counter());
+            self.envelope.restart();
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    pub fn get_length_counter(&self) -> u8 {
+        self.length_counter.get_counter()
+    }
+
+    pub fn get_shift_register(&self) -> u16 {
+        self.shift_register
+    }
+
+    // Clocked every CPU cycle, the period lookup is in CPU cycles.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.registers.get_period() - 1;
+            self.clock();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // Shifts the 15-bit LFSR and returns its new bit 0. Feedback comes from bit 1, or from bit 6 in
+    // short mode, which gives a much shorter (metallic sounding) sequence.
+    pub fn clock(&mut self) -> u8 {
+        let mode_bit = if self.registers.is_tone_mode() { 6 } else { 1 };
+        let feedback = (self.shift_register & 1) ^ ((self.shift_register >> mode_bit) & 1);
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        (self.shift_register & 1) as u8
+    }
+
+    // Clocked every quarter frame.
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock(self.registers.get_envelope_rate(), self.registers.is_infinite_play());
+    }
+
+    // Clocked every half frame. The envelope loop flag doubles as the length counter halt flag.
+    pub fn clock_length_counter(&mut self) {
+        self.length_counter.clock(self.registers.is_infinite_play());
+    }
+
+    // The channel is muted while bit 0 of the shift register is set.
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 == 1 || !self.length_counter.is_active() {
+            return 0;
+        }
+        self.envelope.get_volume(self.registers.is_constant_volume(), self.registers.get_volume())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::Region;
+
+    fn sequence_length(tone_mode: bool) -> usize {
+        let mut noise = NoiseChannel::new();
+        noise.write(2, if tone_mode { 0b1000_0000 } else { 0 });
+        let mut steps = 1;
+        noise.clock();
+        while noise.get_shift_register() != 1 {
+            noise.clock();
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn test_lfsr() {
+        let mut noise = NoiseChannel::new();
+        let bits: Vec<u8> = (0..4).map(|_| noise.clock()).collect();
+        assert_eq!(bits, vec![0, 0, 0, 0]);
+        assert_eq!(noise.get_shift_register(), 0x0800);
+    }
+
+    #[test]
+    fn test_lfsr_sequence_length() {
+        assert_eq!(sequence_length(false), 32767);
+        assert_eq!(sequence_length(true), 93);
+    }
+
+    // shortest period the output bits repeat with, from wherever the shift register currently is
+    fn output_period(noise: &mut NoiseChannel, max_period: usize) -> usize {
+        let bits: Vec<u8> = (0..2 * max_period).map(|_| noise.clock()).collect();
+        (1..=max_period).find(|&period| (0..max_period).all(|i| bits[i] == bits[i + period])).unwrap()
+    }
+
+    #[test]
+    fn test_short_mode_output_period() {
+        let mut noise = NoiseChannel::new();
+        noise.write(2, 0b1000_0000);
+        assert_eq!(output_period(&mut noise, 93), 93);
+
+        // 31 of the register states sit on a separate, shorter loop
+        let mut noise = NoiseChannel::new();
+        noise.write(2, 0b1000_0000);
+        noise.shift_register = 0x0737;
+        assert_eq!(output_period(&mut noise, 93), 31);
+    }
+
+    #[test]
+    fn test_long_mode_output_period() {
+        let mut noise = NoiseChannel::new();
+        assert_eq!(output_period(&mut noise, 32767), 32767);
+    }
+
+    #[test]
+    fn test_period_lookup() {
+        let mut noise = NoiseChannel::new();
+        let periods: Vec<u16> = (0..16).map(|index| { noise.write(2, 0b1000_0000 | index); noise.registers.get_period() }).collect();
+        assert_eq!(periods, vec![4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068]);
+
+        // the timer reloads with the lookup value, so the LFSR shifts once per period
+        let mut noise = NoiseChannel::new();
+        noise.write(2, 0x04);
+        let mut cycles_per_shift = Vec::new();
+        let mut cycles = 0;
+        let mut previous = noise.get_shift_register();
+        while cycles_per_shift.len() < 4 {
+            noise.clock_timer();
+            cycles += 1;
+            if noise.get_shift_register() != previous {
+                cycles_per_shift.push(cycles);
+                previous = noise.get_shift_register();
+                cycles = 0;
+            }
+        }
+        assert_eq!(cycles_per_shift, vec![1, 64, 64, 64]);
+    }
+
+    #[test]
+    fn test_pal_period_lookup() {
+        let mut noise = NoiseChannel::new();
+        noise.registers.set_region(Region::Pal);
+        let periods: Vec<u16> = (0..16).map(|index| { noise.write(2, index); noise.registers.get_period() }).collect();
+        assert_eq!(periods, vec![4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778]);
+
+        noise.registers.set_region(Region::Dendy);
+        assert_eq!(noise.registers.get_period(), 4068);
+    }
+
+    #[test]
+    fn test_timer_period() {
+        let mut noise = NoiseChannel::new();
+        noise.write(2, 0x01); // period 8
+        noise.clock_timer();
+        assert_eq!(noise.get_shift_register(), 0x4000);
+        for _ in 0..7 {
+            noise.clock_timer();
+        }
+        assert_eq!(noise.get_shift_register(), 0x4000);
+        noise.clock_timer();
+        assert_eq!(noise.get_shift_register(), 0x2000);
+    }
+
+    #[test]
+    fn test_output() {
+        let mut noise = NoiseChannel::new();
+        noise.set_enabled(true);
+        noise.write(0, 0b0011_1010); // constant volume 10
+        noise.write(3, 0b0000_1000);
+        assert_eq!(noise.output(), 0);
+        noise.clock();
+        assert_eq!(noise.output(), 10);
+
+        noise.set_enabled(false);
+        assert_eq!(noise.output(), 0);
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut noise = NoiseChannel::new();
+        noise.set_enabled(true);
+        noise.write(3, 0b0001_1000); // length index 3 -> 2 half frames
+        noise.clock_length_counter();
+        assert!(noise.is_active());
+        noise.clock_length_counter();
+        assert!(!noise.is_active());
+
+        noise.write(0, 0b0010_0000); // halt
+        noise.write(3, 0b0001_1000);
+        noise.clock_length_counter();
+        assert_eq!(noise.get_length_counter(), 2);
+    }
+}
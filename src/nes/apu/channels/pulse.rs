@@ -0,0 +1,324 @@
+use crate::nes::apu::channels::envelope::Envelope;
+use crate::nes::apu::channels::length_counter::LengthCounter;
+use crate::nes::apu::registers::pulse::PulseRegisters;
+
+//                  Sweep -----> Timer
+//                    |            |
+//                    |            |
+//                    |            v
+//                    |        Sequencer   Length Counter
+//                    |            |             |
+//                    |            |             |
+//                    v            v             v
+// Envelope -------> Gate -----> Gate -------> Gate ---> (to mixer)
+
+pub struct PulseChannel {
+    pub registers: PulseRegisters,
+
+    channel: u8,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+    sweep_divider: u8,
+    sweep_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    sequence_step: u8,
+}
+
+impl PulseChannel {
+    const DUTY_LOOKUP: [[u8; 8]; 4] = [
+        [0, 1, 0, 0, 0, 0, 0, 0], // 12.5%
+        [0, 1, 1, 0, 0, 0, 0, 0], // 25%
+        [0, 1, 1, 1, 1, 0, 0, 0], // 50%
+        [1, 0, 0, 1, 1, 1, 1, 1], // 25% negated
+    ];
+
+    // Pulse 1 and pulse 2 only differ in how the sweep unit negates, so `channel` is either 1 or 2.
+    pub fn new(channel: u8) -> Self {
+        PulseChannel {
+            registers: PulseRegisters::new(),
+
+            channel,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+            sweep_divider: 0,
+            sweep_reload: false,
+            timer_period: 0,
+            timer: 0,
+            sequence_step: 0,
+        }
+    }
+
+    pub fn read(&self, index: u8) -> u8 {
+        self.registers.read(index)
+    }
+
+    pub fn write(&mut self, index: u8, data: u8) {
+        self.registers.write(index, data);
+        match index {
+            1 => self.sweep_reload = true,
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            3 => {
+                // only the high bits are replaced, so a period the sweep unit changed keeps its low byte
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length_counter.load(self.registers.get_length_counter());
+                self.envelope.restart();
+                self.sequence_step = 0;
+            },
+            _ => {},
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    pub fn get_length_counter(&self) -> u8 {
+        self.length_counter.get_counter()
+    }
+
+    pub fn get_timer_period(&self) -> u16 {
+        self.timer_period
+    }
+
+    // Clocked every APU cycle (every other CPU cycle).
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // the sequencer counts down, hence the odd ordering of the duty lookup
+            self.sequence_step = (self.sequence_step + 7) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // Clocked every quarter frame.
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock(self.registers.get_envelope(), self.registers.is_loop());
+    }
+
+    // Clocked every half frame. The envelope loop flag doubles as the length counter halt flag.
+    pub fn clock_length_counter(&mut self) {
+        self.length_counter.clock(self.registers.is_loop());
+    }
+
+    // Clocked every half frame.
+    pub fn clock_sweep(&mut self) {
+        let target_period = self.get_sweep_target_period();
+        let sweep_shift = self.registers.get_sweep_shift();
+        if self.sweep_divider == 0 && self.registers.is_sweep_enabled() && sweep_shift != 0 && !self.is_sweep_muting() {
+            self.timer_period = target_period;
+        }
+
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.registers.get_sweep_period();
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        let duty = PulseChannel::DUTY_LOOKUP[self.registers.get_duty() as usize][self.sequence_step as usize];
+        if duty == 0 || !self.length_counter.is_active() || self.is_sweep_muting() {
+            return 0;
+        }
+        self.envelope.get_volume(self.registers.is_constant_volume(), self.registers.get_volume())
+    }
+
+    // The target period is computed continuously, and mutes the channel even when the sweep unit is
+    // disabled. Pulse 1 negates with one's complement (-c - 1), pulse 2 with two's complement (-c).
+    fn get_sweep_target_period(&self) -> u16 {
+        let change = self.timer_period >> self.registers.get_sweep_shift();
+        if !self.registers.is_sweep_negate() {
+            self.timer_period + change
+        } else if self.channel == 1 {
+            self.timer_period.saturating_sub(change + 1)
+        } else {
+            self.timer_period.saturating_sub(change)
+        }
+    }
+
+    fn is_sweep_muting(&self) -> bool {
+        self.timer_period < 8 || self.get_sweep_target_period() > 0x7FF
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_pulse(channel: u8) -> PulseChannel {
+        let mut pulse = PulseChannel::new(channel);
+        pulse.set_enabled(true);
+        pulse
+    }
+
+    #[test]
+    fn test_duty_sequence() {
+        let mut pulse = enabled_pulse(1);
+        pulse.write(0, 0b0101_1111); // 25% duty, constant volume 15
+        pulse.write(2, 0x08);
+        pulse.write(3, 0b0000_1000);
+
+        let mut waveform = Vec::new();
+        for _ in 0..8 {
+            waveform.push(pulse.output());
+            for _ in 0..=8 {
+                pulse.clock_timer();
+            }
+        }
+        assert_eq!(waveform, vec![0, 0, 0, 0, 0, 0, 15, 15]);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let mut pulse = enabled_pulse(1);
+        pulse.write(0, 0b0100_0000); // envelope with period 0
+        pulse.write(2, 0x08);
+        pulse.write(3, 0b0000_1000);
+        pulse.sequence_step = 1;
+
+        pulse.clock_envelope();
+        assert_eq!(pulse.output(), 15);
+        pulse.clock_envelope();
+        pulse.clock_envelope();
+        assert_eq!(pulse.output(), 13);
+        for _ in 0..20 {
+            pulse.clock_envelope();
+        }
+        assert_eq!(pulse.output(), 0);
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut pulse = enabled_pulse(2);
+        pulse.write(0, 0b0101_1111);
+        pulse.write(2, 0x08);
+        pulse.write(3, 0b0001_1000); // length index 3 -> 2 half frames
+        pulse.sequence_step = 1;
+        assert_eq!(pulse.get_length_counter(), 2);
+        assert_eq!(pulse.output(), 15);
+
+        pulse.clock_length_counter();
+        pulse.clock_length_counter();
+        assert!(!pulse.is_active());
+        assert_eq!(pulse.output(), 0);
+
+        pulse.write(0, 0b0111_1111); // halt
+        pulse.write(3, 0b0001_1000);
+        pulse.clock_length_counter();
+        assert_eq!(pulse.get_length_counter(), 2);
+
+        pulse.set_enabled(false);
+        assert_eq!(pulse.get_length_counter(), 0);
+        pulse.write(3, 0b0001_1000);
+        assert_eq!(pulse.get_length_counter(), 0);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut pulse = enabled_pulse(2);
+        pulse.write(1, 0b1000_0001); // enabled, period 0, shift 1
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x01); // timer period 0x100
+
+        let periods: Vec<u16> = (0..4).map(|_| { pulse.clock_sweep(); pulse.get_timer_period() }).collect();
+        assert_eq!(periods, vec![0x180, 0x240, 0x360, 0x510]);
+
+        // the next target (0x798) still fits in 11 bits, after that the sweep mutes and stops
+        pulse.clock_sweep();
+        assert_eq!(pulse.get_timer_period(), 0x798);
+        assert!(pulse.is_sweep_muting());
+        pulse.clock_sweep();
+        assert_eq!(pulse.get_timer_period(), 0x798);
+    }
+
+    #[test]
+    fn test_sweep_negate() {
+        // pulse 1 subtracts one more than pulse 2 on every step
+        let mut pulse_one = enabled_pulse(1);
+        let mut pulse_two = enabled_pulse(2);
+        for pulse in [&mut pulse_one, &mut pulse_two] {
+            pulse.write(1, 0b1000_1010); // enabled, period 0, negate, shift 2
+            pulse.write(2, 0x00);
+            pulse.write(3, 0x01); // timer period 0x100
+        }
+
+        let periods: Vec<u16> = (0..3).map(|_| { pulse_one.clock_sweep(); pulse_one.get_timer_period() }).collect();
+        assert_eq!(periods, vec![0xBF, 0x8F, 0x6B]);
+        let periods: Vec<u16> = (0..3).map(|_| { pulse_two.clock_sweep(); pulse_two.get_timer_period() }).collect();
+        assert_eq!(periods, vec![0xC0, 0x90, 0x6C]);
+    }
+
+    #[test]
+    fn test_sweep_negate_low_period() {
+        let mut pulse = enabled_pulse(1);
+        pulse.write(1, 0b1000_1001); // enabled, period 0, negate, shift 1
+        pulse.write(2, 0x10);
+        pulse.write(3, 0x00);
+
+        let periods: Vec<u16> = (0..3).map(|_| { pulse.clock_sweep(); pulse.get_timer_period() }).collect();
+        assert_eq!(periods, vec![0x07, 0x07, 0x07]);
+        // below 8 the channel is muted and the period is left alone
+        assert!(pulse.is_sweep_muting());
+    }
+
+    #[test]
+    fn test_sweep_mutes_while_disabled() {
+        let mut pulse = enabled_pulse(1);
+        pulse.write(0, 0b0101_1111);
+        pulse.write(1, 0b0000_0001); // disabled, shift 1
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x06); // timer period 0x600, target 0x900
+        pulse.sequence_step = 1;
+        assert!(pulse.is_sweep_muting());
+        assert_eq!(pulse.output(), 0);
+        pulse.clock_sweep();
+        assert_eq!(pulse.get_timer_period(), 0x600);
+
+        // a negated target can't overflow, so the channel plays
+        pulse.write(1, 0b0000_1001);
+        assert_eq!(pulse.output(), 15);
+
+        // shift 0 still mutes: the target is twice the period
+        pulse.write(1, 0b1000_0000);
+        assert!(pulse.is_sweep_muting());
+        pulse.write(3, 0x03);
+        assert!(!pulse.is_sweep_muting());
+        pulse.clock_sweep();
+        assert_eq!(pulse.get_timer_period(), 0x300);
+
+        // too low a period mutes as well
+        pulse.write(2, 0x07);
+        pulse.write(3, 0x00);
+        assert!(pulse.is_sweep_muting());
+        assert_eq!(pulse.output(), 0);
+    }
+
+    #[test]
+    fn test_timer_high_write_after_sweep() {
+        let mut pulse = enabled_pulse(2);
+        pulse.write(1, 0b1000_0001);
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x01);
+        pulse.clock_sweep();
+        assert_eq!(pulse.get_timer_period(), 0x180);
+        pulse.write(3, 0x02);
+        assert_eq!(pulse.get_timer_period(), 0x280);
+    }
+
+    #[test]
+    fn test_sweep_divider() {
+        let mut pulse = enabled_pulse(2);
+        pulse.write(1, 0b1010_0001); // enabled, period 2, shift 1
+        pulse.write(3, 0x01);
+
+        let periods: Vec<u16> = (0..6).map(|_| { pulse.clock_sweep(); pulse.get_timer_period() }).collect();
+        assert_eq!(periods, vec![0x180, 0x180, 0x180, 0x240, 0x240, 0x240]);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::nes::apu::channels::length_counter::LengthCounter;
+use crate::nes::apu::registers::triangle::TriangleRegisters;
+
+//       Linear Counter   Length Counter
+//             |                |
+//             v                v
+// Timer ---> Gate ----------> Gate ---> Sequencer ---> (to mixer)
+
+pub struct TriangleChannel {
+    pub registers: TriangleRegisters,
+
+    length_counter: LengthCounter,
+    linear_counter: u8,
+    linear_counter_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    sequence_step: u8,
+}
+
+impl TriangleChannel {
+    const SEQUENCE_LOOKUP: [u8; 32] = [
+        15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+         0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15
+    ];
+
+    pub fn new() -> Self {
+        TriangleChannel {
+            registers: TriangleRegisters::new(),
+
+            length_counter: LengthCounter::new(),
+            linear_counter: 0,
+            linear_counter_reload: false,
+            timer_period: 0,
+            timer: 0,
+            sequence_step: 0,
+        }
+    }
+
+    pub fn read(&self, index: u8) -> u8 {
+        self.registers.read(index)
+    }
+
+    pub fn write(&mut self, index: u8, data: u8) {
+        self.registers.write(index, data);
+        match index {
+            2 | 3 => {
+                self.timer_period = self.registers.get_timer();
+                if index == 3 {
+                    self.length_counter.load(self.registers.get_length_counter());
+                    self.linear_counter_reload = true;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    pub fn get_length_counter(&self) -> u8 {
+        self.length_counter.get_counter()
+    }
+
+    pub fn get_linear_counter(&self) -> u8 {
+        self.linear_counter
+    }
+
+    // Clocked every CPU cycle. The sequencer only advances while both counters are non-zero.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length_counter.is_active() {
+                self.sequence_step = (self.sequence_step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // Clocked every quarter frame. The reload flag is only cleared when the control flag is clear.
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.registers.get_linear_counter();
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.registers.is_infinite_play() {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    // Clocked every half frame. The control flag doubles as the length counter halt flag.
+    pub fn clock_length_counter(&mut self) {
+        self.length_counter.clock(self.registers.is_infinite_play());
+    }
+
+    // Silencing the triangle halts the sequencer rather than zeroing its output, which avoids pops.
+    pub fn output(&self) -> u8 {
+        TriangleChannel::SEQUENCE_LOOKUP[self.sequence_step as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing_triangle(linear_counter: u8) -> TriangleChannel {
+        let mut triangle = TriangleChannel::new();
+        triangle.set_enabled(true);
+        triangle.write(0, linear_counter);
+        triangle.write(2, 0x00);
+        triangle.write(3, 0b0000_1000); // length index 1 -> 254 half frames
+        triangle.clock_linear_counter();
+        triangle
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut triangle = playing_triangle(0x7F);
+        let mut waveform = Vec::new();
+        for _ in 0..32 {
+            waveform.push(triangle.output());
+            triangle.clock_timer();
+        }
+        assert_eq!(waveform, TriangleChannel::SEQUENCE_LOOKUP.to_vec());
+        assert_eq!(triangle.output(), 15);
+    }
+
+    #[test]
+    fn test_timer_period() {
+        let mut triangle = playing_triangle(0x7F);
+        triangle.write(2, 0x02);
+        for _ in 0..3 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.output(), 14);
+        for _ in 0..3 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.output(), 13);
+    }
+
+    #[test]
+    fn test_linear_counter() {
+        let mut triangle = playing_triangle(2);
+        assert_eq!(triangle.get_linear_counter(), 2);
+        triangle.clock_linear_counter();
+        triangle.clock_linear_counter();
+        assert_eq!(triangle.get_linear_counter(), 0);
+
+        // the sequencer holds its current step once silenced
+        triangle.clock_timer();
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 15);
+    }
+
+    #[test]
+    fn test_linear_counter_control() {
+        let mut triangle = playing_triangle(0b1000_0010);
+        triangle.clock_linear_counter();
+        triangle.clock_linear_counter();
+        assert_eq!(triangle.get_linear_counter(), 2);
+
+        // clearing the control flag lets the reload flag clear on the next clock
+        triangle.write(0, 0b0000_0010);
+        triangle.clock_linear_counter();
+        triangle.clock_linear_counter();
+        assert_eq!(triangle.get_linear_counter(), 1);
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut triangle = playing_triangle(0x7F);
+        triangle.write(3, 0b0001_1000); // length index 3 -> 2 half frames
+        triangle.clock_length_counter();
+        triangle.clock_length_counter();
+        assert!(!triangle.is_active());
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 15);
+
+        triangle.write(0, 0b1111_1111); // halt
+        triangle.write(3, 0b0001_1000);
+        triangle.clock_length_counter();
+        assert_eq!(triangle.get_length_counter(), 2);
+
+        triangle.set_enabled(false);
+        assert!(!triangle.is_active());
+    }
+}
@@ -0,0 +1,206 @@
+use std::f32::consts::PI;
+
+// First-order IIR filters modelling the analog stages between the APU and the audio output. The NES
+// runs the mixed signal through two high-pass filters (~90Hz and ~440Hz), which strip the DC offset,
+// and a low-pass filter (~14kHz), which rolls off the top end.
+//
+//  high-pass: y[n] = c * (y[n-1] + x[n] - x[n-1])
+//  low-pass:  y[n] = c * y[n-1] + (1 - c) * x[n]
+
+pub struct AudioFilter {
+    prev_input: f32,
+    prev_output: f32,
+}
+
+impl AudioFilter {
+    pub fn new() -> Self {
+        AudioFilter {
+            prev_input: 0.0,
+            prev_output: 0.0,
+        }
+    }
+
+    pub fn coefficient(sample_rate: f32, cutoff: f32) -> f32 {
+        sample_rate / (2.0 * PI * cutoff + sample_rate)
+    }
+
+    pub fn high_pass(&mut self, input: f32, coefficient: f32) -> f32 {
+        let output = coefficient * (self.prev_output + input - self.prev_input);
+        self.prev_input = input;
+        self.prev_output = output;
+        output
+    }
+
+    pub fn low_pass(&mut self, input: f32, coefficient: f32) -> f32 {
+        let output = coefficient * self.prev_output + (1.0 - coefficient) * input;
+        self.prev_input = input;
+        self.prev_output = output;
+        output
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+pub struct FilterStage {
+    pub kind: FilterKind,
+    pub cutoff: f32,
+    coefficient: f32,
+    filter: AudioFilter,
+}
+
+impl FilterStage {
+    pub fn new(kind: FilterKind, cutoff: f32, sample_rate: f32) -> Self {
+        FilterStage {
+            kind,
+            cutoff,
+            coefficient: AudioFilter::coefficient(sample_rate, cutoff),
+            filter: AudioFilter::new(),
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        match self.kind {
+            FilterKind::HighPass => self.filter.high_pass(input, self.coefficient),
+            FilterKind::LowPass => self.filter.low_pass(input, self.coefficient),
+        }
+    }
+}
+
+pub struct FilterChain {
+    sample_rate: f32,
+    stages: Vec<FilterStage>,
+}
+
+impl FilterChain {
+    pub const NES_STAGES: [(FilterKind, f32); 3] = [
+        (FilterKind::HighPass, 90.0),
+        (FilterKind::HighPass, 440.0),
+        (FilterKind::LowPass, 14_000.0),
+    ];
+
+    // The NES's own filters.
+    pub fn new(sample_rate: f32) -> Self {
+        let mut chain = FilterChain::empty(sample_rate);
+        for (kind, cutoff) in FilterChain::NES_STAGES {
+            chain.add_stage(kind, cutoff);
+        }
+        chain
+    }
+
+    // Passes samples through untouched until stages are added.
+    pub fn empty(sample_rate: f32) -> Self {
+        FilterChain { sample_rate, stages: Vec::new() }
+    }
+
+    pub fn add_stage(&mut self, kind: FilterKind, cutoff: f32) {
+        self.stages.push(FilterStage::new(kind, cutoff, self.sample_rate));
+    }
+
+    pub fn clear(&mut self) {
+        self.stages.clear();
+    }
+
+    pub fn get_stages(&self) -> &[FilterStage] {
+        &self.stages
+    }
+
+    pub fn get_sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    // Recomputes every stage for a new sample rate, keeping their kinds and cutoffs.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for stage in self.stages.iter_mut() {
+            *stage = FilterStage::new(stage.kind, stage.cutoff, sample_rate);
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.stages.iter_mut().fold(input, |sample, stage| stage.process(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44_100.0;
+
+    fn sine_amplitude(chain: &mut FilterChain, freq: f32) -> f32 {
+        let mut amplitude: f32 = 0.0;
+        for n in 0..(SAMPLE_RATE as usize) {
+            let input = (2.0 * PI * freq * n as f32 / SAMPLE_RATE).sin();
+            let output = chain.process(input);
+            if n > SAMPLE_RATE as usize / 2 {
+                amplitude = amplitude.max(output.abs());
+            }
+        }
+        amplitude
+    }
+
+    #[test]
+    fn test_coefficient() {
+        assert!((AudioFilter::coefficient(SAMPLE_RATE, 90.0) - 0.98734).abs() < 1e-4);
+        assert!((AudioFilter::coefficient(SAMPLE_RATE, 14_000.0) - 0.33392).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_high_pass_removes_dc() {
+        let mut filter = AudioFilter::new();
+        let coefficient = AudioFilter::coefficient(SAMPLE_RATE, 90.0);
+        let mut output = filter.high_pass(1.0, coefficient);
+        assert!((output - coefficient).abs() < 1e-6);
+        for _ in 0..SAMPLE_RATE as usize {
+            output = filter.high_pass(1.0, coefficient);
+        }
+        assert!(output.abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_low_pass_settles() {
+        let mut filter = AudioFilter::new();
+        let coefficient = AudioFilter::coefficient(SAMPLE_RATE, 14_000.0);
+        let mut output = 0.0;
+        for _ in 0..100 {
+            output = filter.low_pass(0.5, coefficient);
+        }
+        assert!((output - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_filter_chain() {
+        let passband = sine_amplitude(&mut FilterChain::new(SAMPLE_RATE), 2_000.0);
+        let bass = sine_amplitude(&mut FilterChain::new(SAMPLE_RATE), 40.0);
+        let treble = sine_amplitude(&mut FilterChain::new(SAMPLE_RATE), 20_000.0);
+        assert!(passband > 0.75);
+        assert!(bass < 0.2);
+        assert!(treble < passband);
+    }
+
+    #[test]
+    fn test_configurable_chain() {
+        let mut chain = FilterChain::empty(SAMPLE_RATE);
+        assert_eq!(chain.process(0.75), 0.75);
+
+        chain.add_stage(FilterKind::LowPass, 1_000.0);
+        assert!(sine_amplitude(&mut chain, 10_000.0) < 0.2);
+
+        chain.clear();
+        chain.add_stage(FilterKind::HighPass, 5_000.0);
+        assert!(sine_amplitude(&mut chain, 200.0) < 0.1);
+    }
+
+    #[test]
+    fn test_set_sample_rate() {
+        let mut chain = FilterChain::new(SAMPLE_RATE);
+        chain.set_sample_rate(48_000.0);
+        let cutoffs: Vec<f32> = chain.get_stages().iter().map(|stage| stage.cutoff).collect();
+        assert_eq!(cutoffs, vec![90.0, 440.0, 14_000.0]);
+        assert_eq!(chain.get_stages()[0].coefficient, AudioFilter::coefficient(48_000.0, 90.0));
+    }
+}
@@ -0,0 +1,307 @@
+use crate::nes::apu::registers::frame_counter::FrameCounterRegister;
+use crate::nes::Region;
+
+// The frame counter divides the CPU clock down to the ~240Hz quarter frame and ~120Hz half frame
+// signals that drive the envelopes, linear counter, length counters and sweep units.
+//
+//  mode 0: 4-step   quarter   quarter+half   quarter   quarter+half+IRQ
+//  mode 1: 5-step   quarter   quarter+half   quarter   -                  quarter+half
+//
+// Step positions below are in CPU cycles since the sequence was last restarted by a $4017 write.
+// The restart lands 3 CPU cycles after a write made on an even cycle and 4 after one made on an odd
+// cycle, since the sequencer only resets on APU cycle boundaries. The IRQ inhibit takes effect at once.
+// PAL consoles run the same sequence with longer steps.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FrameClock {
+    pub quarter_frame: bool,
+    pub half_frame: bool,
+}
+
+impl FrameClock {
+    pub const NONE: FrameClock = FrameClock { quarter_frame: false, half_frame: false };
+    pub const QUARTER: FrameClock = FrameClock { quarter_frame: true, half_frame: false };
+    pub const HALF: FrameClock = FrameClock { quarter_frame: true, half_frame: true };
+}
+
+// Ref: https://www.nesdev.org/wiki/APU_Frame_Counter
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FrameTimings {
+    pub step_one: u64,
+    pub step_two: u64,
+    pub step_three: u64,
+    pub four_step_irq: u64,
+    pub four_step_last: u64,
+    pub four_step_period: u64,
+    pub five_step_last: u64,
+    pub five_step_period: u64,
+}
+
+impl FrameTimings {
+    pub const NTSC: FrameTimings = FrameTimings {
+        step_one: 7457,
+        step_two: 14913,
+        step_three: 22371,
+        four_step_irq: 29828,
+        four_step_last: 29829,
+        four_step_period: 29830,
+        five_step_last: 37281,
+        five_step_period: 37282,
+    };
+
+    pub const PAL: FrameTimings = FrameTimings {
+        step_one: 8313,
+        step_two: 16627,
+        step_three: 24939,
+        four_step_irq: 33252,
+        four_step_last: 33253,
+        four_step_period: 33254,
+        five_step_last: 41565,
+        five_step_period: 41566,
+    };
+
+    // The Dendy keeps the NTSC APU timings.
+    pub fn from_region(region: Region) -> Self {
+        match region {
+            Region::Pal => FrameTimings::PAL,
+            Region::Ntsc | Region::Dendy => FrameTimings::NTSC,
+        }
+    }
+}
+
+pub struct FrameCounter {
+    pub register: FrameCounterRegister,
+    pub timings: FrameTimings,
+
+    sequence_start: u64,
+    pending_write: Option<(u8, u64)>,
+    irq_flag: bool,
+}
+
+impl FrameCounter {
+    pub const WRITE_DELAY_EVEN: u64 = 3;
+    pub const WRITE_DELAY_ODD: u64 = 4;
+
+    pub fn new() -> Self {
+        FrameCounter {
+            register: FrameCounterRegister::new(),
+            timings: FrameTimings::NTSC,
+
+            sequence_start: 0,
+            pending_write: None,
+            irq_flag: false,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register.read()
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.timings = FrameTimings::from_region(region);
+    }
+
+    // Schedules a restart of the sequence for a write made on `cycle`. Selecting the 5-step mode
+    // clocks a half frame as soon as the restart happens.
+    pub fn write(&mut self, data: u8, cycle: u64) {
+        if data & 0b0100_0000 != 0 {
+            self.irq_flag = false;
+        }
+        let delay = if cycle.is_multiple_of(2) { FrameCounter::WRITE_DELAY_EVEN } else { FrameCounter::WRITE_DELAY_ODD };
+        self.pending_write = Some((data, cycle + delay));
+    }
+
+    // Called once per CPU cycle with the running CPU cycle count.
+    pub fn tick(&mut self, cycle: u64) -> FrameClock {
+        if let Some((data, restart_cycle)) = self.pending_write {
+            if cycle >= restart_cycle {
+                self.pending_write = None;
+                self.register.write(data);
+                self.sequence_start = cycle;
+                if self.register.is_five_step_mode() {
+                    return FrameClock::HALF;
+                }
+                return FrameClock::NONE;
+            }
+        }
+
+        let position = cycle - self.sequence_start;
+        if self.register.is_four_step_mode() {
+            self.tick_four_step(cycle, position)
+        } else {
+            self.tick_five_step(cycle, position)
+        }
+    }
+
+    pub fn poll_irq(&self) -> bool {
+        self.irq_flag
+    }
+
+    pub fn clear_irq(&mut self) {
+        self.irq_flag = false;
+    }
+
+    fn tick_four_step(&mut self, cycle: u64, position: u64) -> FrameClock {
+        let timings = self.timings;
+        if position == timings.step_one || position == timings.step_three {
+            FrameClock::QUARTER
+        } else if position == timings.step_two {
+            FrameClock::HALF
+        } else if position == timings.four_step_irq {
+            self.set_irq();
+            FrameClock::NONE
+        } else if position == timings.four_step_last {
+            self.set_irq();
+            FrameClock::HALF
+        } else if position == timings.four_step_period {
+            self.set_irq();
+            self.sequence_start = cycle;
+            FrameClock::NONE
+        } else {
+            FrameClock::NONE
+        }
+    }
+
+    fn tick_five_step(&mut self, cycle: u64, position: u64) -> FrameClock {
+        let timings = self.timings;
+        if position == timings.step_one || position == timings.step_three {
+            FrameClock::QUARTER
+        } else if position == timings.step_two || position == timings.five_step_last {
+            FrameClock::HALF
+        } else if position == timings.five_step_period {
+            self.sequence_start = cycle;
+            FrameClock::NONE
+        } else {
+            FrameClock::NONE
+        }
+    }
+
+    fn set_irq(&mut self) {
+        let inhibited = match self.pending_write {
+            Some((data, _)) => data & 0b0100_0000 != 0,
+            None => self.register.is_irq_inhibit(),
+        };
+        if !inhibited {
+            self.irq_flag = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_frame_counter(frame_counter: &mut FrameCounter, from: u64, to: u64) -> Vec<(u64, FrameClock)> {
+        let mut clocks = Vec::new();
+        for cycle in from..=to {
+            let clock = frame_counter.tick(cycle);
+            if clock != FrameClock::NONE {
+                clocks.push((cycle, clock));
+            }
+        }
+        clocks
+    }
+
+    #[test]
+    fn test_four_step_mode() {
+        let mut frame_counter = FrameCounter::new();
+        let clocks = run_frame_counter(&mut frame_counter, 1, 2 * FrameTimings::NTSC.four_step_period);
+        assert_eq!(clocks, vec![
+            (7457, FrameClock::QUARTER),
+            (14913, FrameClock::HALF),
+            (22371, FrameClock::QUARTER),
+            (29829, FrameClock::HALF),
+            (29830 + 7457, FrameClock::QUARTER),
+            (29830 + 14913, FrameClock::HALF),
+            (29830 + 22371, FrameClock::QUARTER),
+            (29830 + 29829, FrameClock::HALF),
+        ]);
+    }
+
+    #[test]
+    fn test_five_step_mode() {
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.write(0b1000_0000, 0);
+        let clocks = run_frame_counter(&mut frame_counter, 1, 3 + 2 * FrameTimings::NTSC.five_step_period);
+        assert_eq!(clocks, vec![
+            (3, FrameClock::HALF),
+            (3 + 7457, FrameClock::QUARTER),
+            (3 + 14913, FrameClock::HALF),
+            (3 + 22371, FrameClock::QUARTER),
+            (3 + 37281, FrameClock::HALF),
+            (3 + 37282 + 7457, FrameClock::QUARTER),
+            (3 + 37282 + 14913, FrameClock::HALF),
+            (3 + 37282 + 22371, FrameClock::QUARTER),
+            (3 + 37282 + 37281, FrameClock::HALF),
+        ]);
+        assert!(!frame_counter.poll_irq());
+    }
+
+    #[test]
+    fn test_write_restarts_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        run_frame_counter(&mut frame_counter, 1, 1000);
+        frame_counter.write(0x00, 1000);
+        let clocks = run_frame_counter(&mut frame_counter, 1001, 1003 + FrameTimings::NTSC.step_one);
+        assert_eq!(clocks, vec![(1003 + 7457, FrameClock::QUARTER)]);
+    }
+
+    #[test]
+    fn test_write_delay() {
+        // the old sequence keeps running until the restart
+        let mut frame_counter = FrameCounter::new();
+        run_frame_counter(&mut frame_counter, 1, FrameTimings::NTSC.step_one - 2);
+        frame_counter.write(0b1000_0000, FrameTimings::NTSC.step_one - 2);
+        let clocks = run_frame_counter(&mut frame_counter, FrameTimings::NTSC.step_one - 1, FrameTimings::NTSC.step_one + 2);
+        assert_eq!(clocks, vec![(7457, FrameClock::QUARTER), (7459, FrameClock::HALF)]);
+
+        // a write on an odd cycle waits an extra cycle
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.write(0b1000_0000, 101);
+        let clocks = run_frame_counter(&mut frame_counter, 102, 106);
+        assert_eq!(clocks, vec![(105, FrameClock::HALF)]);
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut frame_counter = FrameCounter::new();
+        run_frame_counter(&mut frame_counter, 1, FrameTimings::NTSC.four_step_irq - 1);
+        assert!(!frame_counter.poll_irq());
+        frame_counter.tick(FrameTimings::NTSC.four_step_irq);
+        assert!(frame_counter.poll_irq());
+
+        frame_counter.clear_irq();
+        frame_counter.tick(FrameTimings::NTSC.four_step_last);
+        assert!(frame_counter.poll_irq());
+
+        // setting the inhibit flag acknowledges the interrupt right away and suppresses new ones
+        frame_counter.write(0b0100_0000, 0);
+        assert!(!frame_counter.poll_irq());
+        run_frame_counter(&mut frame_counter, 1, 3 + FrameTimings::NTSC.four_step_period);
+        assert!(!frame_counter.poll_irq());
+    }
+
+    #[test]
+    fn test_pal_timings() {
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.set_region(Region::Pal);
+        let clocks = run_frame_counter(&mut frame_counter, 1, FrameTimings::PAL.four_step_period);
+        assert_eq!(clocks, vec![
+            (8313, FrameClock::QUARTER),
+            (16627, FrameClock::HALF),
+            (24939, FrameClock::QUARTER),
+            (33253, FrameClock::HALF),
+        ]);
+        assert!(frame_counter.poll_irq());
+
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.set_region(Region::Pal);
+        frame_counter.write(0b1000_0000, 0);
+        let clocks = run_frame_counter(&mut frame_counter, 1, 3 + FrameTimings::PAL.five_step_period);
+        assert_eq!(clocks.last(), Some(&(3 + 41565, FrameClock::HALF)));
+
+        // the Dendy keeps the NTSC sequence
+        frame_counter.set_region(Region::Dendy);
+        assert_eq!(frame_counter.timings, FrameTimings::NTSC);
+    }
+}
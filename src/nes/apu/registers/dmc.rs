@@ -1,14 +1,21 @@
+use crate::nes::Region;
+
 pub struct DMCRegisters {
     register_a: u8, // IL-- RRRR 	IRQ enable (I), loop (L), rate (R)
     register_b: u8, // -DDD DDDD	Load counter (D)
     register_c: u8, // AAAA AAAA	Sample address (A)
     register_d: u8, // LLLL LLLL	Sample length (L)
+
+    rate_lookup: &'static [u16; 16],
 }
 
 impl DMCRegisters {
     const RATE_LOOKUP: [u16; 16] = [
         428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54
     ];
+    const PAL_RATE_LOOKUP: [u16; 16] = [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50
+    ];
 
     pub fn new() -> Self {
         DMCRegisters {
@@ -16,6 +23,8 @@ impl DMCRegisters {
             register_b: 0,
             register_c: 0,
             register_d: 0,
+
+            rate_lookup: &DMCRegisters::RATE_LOOKUP,
         }
     }
 
@@ -60,7 +69,16 @@ impl DMCRegisters {
     }
 
     pub fn get_rate(&self) -> u16 {
-        return DMCRegisters::RATE_LOOKUP[self.get_rate_idx() as usize];
+        return self.rate_lookup[self.get_rate_idx() as usize];
+    }
+
+    // Ref: https://www.nesdev.org/wiki/APU_DMC
+    // The Dendy keeps the NTSC rates.
+    pub fn set_region(&mut self, region: Region) {
+        self.rate_lookup = match region {
+            Region::Pal => &DMCRegisters::PAL_RATE_LOOKUP,
+            Region::Ntsc | Region::Dendy => &DMCRegisters::RATE_LOOKUP,
+        };
     }
 
     pub fn get_volume(&self) -> u8 {
@@ -74,8 +92,4 @@ impl DMCRegisters {
     pub fn get_sample_length(&self) -> u16 {
         16 * self.register_d as u16 + 1
     }
-
-    pub fn get_frequency(&self) -> f32 {
-        1_789_773.0 / self.get_rate() as f32
-    }
 }
\ No newline at end of file
@@ -1,14 +1,21 @@
+use crate::nes::Region;
+
 pub struct NoiseRegisters {
     register_a: u8, // --LC VVVV	Envelope loop / length counter halt (L), constant volume (C), volume/envelope (V)
     register_b: u8, // ---- ----	Unused
     register_c: u8, // M--- PPPP	Mode (M), noise period (P)
     register_d: u8, // LLLL L---	Length counter load (L)
+
+    period_lookup: &'static [u16; 16],
 }
 
 impl NoiseRegisters {
     const PERIOD_LOOKUP: [u16; 16] = [
         4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068
     ];
+    const PAL_PERIOD_LOOKUP: [u16; 16] = [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778
+    ];
 
     pub fn new() -> Self {
         NoiseRegisters {
@@ -16,6 +23,8 @@ impl NoiseRegisters {
             register_b: 0,
             register_c: 0,
             register_d: 0,
+
+            period_lookup: &NoiseRegisters::PERIOD_LOOKUP,
         }
     }
 
@@ -72,7 +81,16 @@ impl NoiseRegisters {
     }
 
     pub fn get_period(&self) -> u16 {
-        return NoiseRegisters::PERIOD_LOOKUP[self.get_period_idx() as usize];
+        return self.period_lookup[self.get_period_idx() as usize];
+    }
+
+    // Ref: https://www.nesdev.org/wiki/APU_Noise
+    // The Dendy keeps the NTSC periods.
+    pub fn set_region(&mut self, region: Region) {
+        self.period_lookup = match region {
+            Region::Pal => &NoiseRegisters::PAL_PERIOD_LOOKUP,
+            Region::Ntsc | Region::Dendy => &NoiseRegisters::PERIOD_LOOKUP,
+        };
     }
 
     pub fn is_tone_mode(&self) -> bool {
@@ -82,12 +100,4 @@ impl NoiseRegisters {
     pub fn get_length_counter(&self) -> u8 {
         (self.register_d & 0b1111_1000) >> 3
     }
-
-    pub fn clear_length_counter(&mut self) {
-        self.register_d = self.register_d & 0b0000_0111;
-    }
-
-    pub fn get_frequency(&self) -> f32 {
-        (39_375_000.0 / 44.0) / self.get_period() as f32
-    }
 }
\ No newline at end of file
@@ -1,5 +1,3 @@
-use crate::util::audio::AudioPlayer;
-
 pub struct PulseRegisters {
     register_a: u8, // DDLC VVVV	Duty (D), envelope loop / length counter halt (L), constant volume (C), volume/envelope (V)
     register_b: u8, // EPPP NSSS	Sweep unit: enabled (E), period (P), negate (N), shift (S)
@@ -69,16 +67,12 @@ impl PulseRegisters {
         self.get_volume()
     }
 
-    pub fn get_envelope_frequency(&self) -> f32 {
-        240.0 / (self.get_envelope() as f32 + 1.0)
-    }
-
     pub fn is_sweep_enabled(&self) -> bool {
         self.register_b & 0b1000_0000 > 0
     }
 
     pub fn get_sweep_period(&self) -> u8 {
-        self.register_b & 0b0111_0000 >> 4
+        (self.register_b & 0b0111_0000) >> 4
     }
 
     pub fn is_sweep_negate(&self) -> bool {
@@ -89,10 +83,6 @@ impl PulseRegisters {
         self.register_b & 0b0000_0111
     }
 
-    pub fn get_sweep_frequency(&self) -> f32 {
-        120.0 / (self.get_sweep_period() as f32 + 1.0)
-    }
-
     pub fn get_timer(&self) -> u16 {
         ((self.register_d as u16 & 0b0000_0111) << 8) | self.register_c as u16
     }
@@ -101,19 +91,6 @@ impl PulseRegisters {
         (self.register_d & 0b1111_1000) >> 3
     }
 
-    pub fn get_length(&self) -> u16 {
-        AudioPlayer::LENGTH_LOOKUP[self.get_length_counter() as usize]
-    }
-
-    pub fn get_duration(&self) -> f32 {
-        let rate = AudioPlayer::FREQ as f32 / 120.0;
-        return rate * self.get_length() as f32;
-    }
-
-    pub fn clear_length_counter(&mut self) {
-        self.register_d = self.register_d & 0b0000_0111;
-    }
-
     pub fn get_frequency(&self) -> f32 {
         1_789_773.0 / (16.0 * (self.get_timer() as f32 + 1.0))
     }
@@ -0,0 +1,168 @@
+// Ref: https://www.nesdev.org/wiki/APU_Mixer#Emulation
+
+// The mixer produces a sample every CPU cycle (~1.79MHz), while the audio device only takes a few
+// tens of thousands per second. Picking every Nth sample lets the pulse and noise harmonics above
+// the output Nyquist frequency fold back into the audible range, which is heard as crackle and
+// out of tune whistles. Instead, every output sample is the average of all the input that falls in
+// its period, with the input samples that straddle two periods split between them:
+//
+//  input:   | a | b | c | d | e | f | g | ...          (one per CPU cycle)
+//  output:  |  (a + b + c/2) / 2.5  |  (c/2 + d + e) / 2.5  | ...    (step = 2.5 input samples)
+//
+// The box filter has nulls at every multiple of the output rate, which is exactly where the
+// frequencies that would alias down to near DC sit.
+
+pub struct Resampler {
+    input_rate: f64,
+    output_rate: f64,
+    rate_adjustment: f64,
+    step: f64,
+    phase: f64,
+    accumulator: f64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64) -> Self {
+        Resampler {
+            input_rate,
+            output_rate,
+            rate_adjustment: 0.0,
+            step: input_rate / output_rate,
+            phase: 0.0,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn get_input_rate(&self) -> f64 {
+        self.input_rate
+    }
+
+    pub fn get_output_rate(&self) -> f64 {
+        self.output_rate
+    }
+
+    pub fn set_output_rate(&mut self, output_rate: f64) {
+        *self = Resampler::new(self.input_rate, output_rate);
+    }
+
+    pub fn set_input_rate(&mut self, input_rate: f64) {
+        *self = Resampler::new(input_rate, self.output_rate);
+    }
+
+    // Produces a fraction more (or less) samples than the output rate calls for, without resetting
+    // the sample in progress. This is how the audio queue is kept from draining or piling up.
+    pub fn set_rate_adjustment(&mut self, rate_adjustment: f64) {
+        self.rate_adjustment = rate_adjustment;
+        self.step = self.input_rate / (self.output_rate * (1.0 + rate_adjustment));
+    }
+
+    pub fn get_rate_adjustment(&self) -> f64 {
+        self.rate_adjustment
+    }
+
+    // Takes one input sample, and returns an output sample whenever an output period is complete.
+    #[inline]
+    pub fn push(&mut self, input: f32) -> Option<f32> {
+        let input = input as f64;
+        self.phase += 1.0;
+        if self.phase < self.step {
+            self.accumulator += input;
+            return None;
+        }
+
+        let overshoot = self.phase - self.step;
+        let output = (self.accumulator + input * (1.0 - overshoot)) / self.step;
+        self.accumulator = input * overshoot;
+        self.phase = overshoot;
+        Some(output as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const CPU_FREQUENCY: f64 = 1_789_773.0;
+    const OUTPUT_RATE: f64 = 44_100.0;
+
+    // amplitude of a single frequency in the signal, by correlating against a sine and cosine (with a
+    // Hann window, so the loud fundamental doesn't leak into the frequencies around it)
+    fn magnitude(samples: &[f32], freq: f64, sample_rate: f64) -> f64 {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (n, sample) in samples.iter().enumerate() {
+            let window = 0.5 - 0.5 * (2.0 * PI * n as f64 / samples.len() as f64).cos();
+            let angle = 2.0 * PI * freq * n as f64 / sample_rate;
+            re += window * *sample as f64 * angle.cos();
+            im += window * *sample as f64 * angle.sin();
+        }
+        4.0 * (re * re + im * im).sqrt() / samples.len() as f64
+    }
+
+    #[test]
+    fn test_constant_input() {
+        let mut resampler = Resampler::new(10.0, 4.0);
+        let outputs: Vec<f32> = (0..100).filter_map(|_| resampler.push(0.5)).collect();
+        assert_eq!(outputs.len(), 40);
+        assert!(outputs.iter().all(|output| (output - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_fractional_step() {
+        let mut resampler = Resampler::new(5.0, 2.0);
+        let outputs: Vec<f32> = [1.0, 1.0, 2.0, 3.0, 3.0].into_iter().filter_map(|input| resampler.push(input)).collect();
+        assert_eq!(outputs, vec![1.2, 2.8]);
+    }
+
+    #[test]
+    fn test_output_rate() {
+        let mut resampler = Resampler::new(CPU_FREQUENCY, OUTPUT_RATE);
+        let outputs = (0..CPU_FREQUENCY as usize).filter_map(|_| resampler.push(0.0)).count();
+        assert!(outputs.abs_diff(44_100) <= 1);
+
+        resampler.set_output_rate(48_000.0);
+        assert_eq!(resampler.get_output_rate(), 48_000.0);
+        let outputs = (0..CPU_FREQUENCY as usize).filter_map(|_| resampler.push(0.0)).count();
+        assert!(outputs.abs_diff(48_000) <= 1);
+    }
+
+    #[test]
+    fn test_rate_adjustment() {
+        let mut resampler = Resampler::new(CPU_FREQUENCY, OUTPUT_RATE);
+        resampler.set_rate_adjustment(0.01);
+        let outputs = (0..CPU_FREQUENCY as usize).filter_map(|_| resampler.push(0.0)).count();
+        assert!(outputs.abs_diff(44_541) <= 1);
+
+        resampler.set_rate_adjustment(-0.01);
+        let outputs = (0..CPU_FREQUENCY as usize).filter_map(|_| resampler.push(0.0)).count();
+        assert!(outputs.abs_diff(43_659) <= 1);
+        assert_eq!(resampler.get_output_rate(), OUTPUT_RATE);
+    }
+
+    #[test]
+    fn test_square_wave_has_no_mirror_frequencies() {
+        // a 440Hz-ish square wave, with its period in CPU cycles like a pulse channel's timer
+        let period = 4068;
+        let fundamental = CPU_FREQUENCY / period as f64;
+        let square = |cycle: usize| if cycle % period < period / 2 { 1.0 } else { 0.0 };
+
+        let mut resampler = Resampler::new(CPU_FREQUENCY, OUTPUT_RATE);
+        let resampled: Vec<f32> = (0..CPU_FREQUENCY as usize / 2).filter_map(|cycle| resampler.push(square(cycle))).collect();
+
+        // the 103rd harmonic sits above Nyquist and folds back in between the 2nd and 3rd harmonics
+        let harmonic = 103.0 * fundamental;
+        let mirror = (harmonic - OUTPUT_RATE).abs();
+        assert!(mirror > 2.0 * fundamental && mirror < 3.0 * fundamental);
+
+        let tone = magnitude(&resampled, fundamental, OUTPUT_RATE);
+        let filtered = magnitude(&resampled, mirror, OUTPUT_RATE);
+        assert!(tone > 0.6);
+        assert!(filtered < tone / 1000.0, "mirror frequency at {}", filtered);
+
+        // picking every Nth sample instead lets the mirror frequency through
+        let step = CPU_FREQUENCY / OUTPUT_RATE;
+        let decimated: Vec<f32> = (0..resampled.len()).map(|n| square((n as f64 * step) as usize)).collect();
+        let aliased = magnitude(&decimated, mirror, OUTPUT_RATE);
+        assert!(aliased > 10.0 * filtered);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use crate::util::audio::Channel;
+
+// The raw output of every channel before mixing: 0-15 for the pulses, triangle and noise, and 0-127
+// for the DMC.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ChannelOutputs {
+    pub pulse_one: u8,
+    pub pulse_two: u8,
+    pub triangle: u8,
+    pub noise: u8,
+    pub dmc: u8,
+}
+
+impl ChannelOutputs {
+    pub fn get(&self, channel: Channel) -> u8 {
+        match channel {
+            Channel::PulseOne => self.pulse_one,
+            Channel::PulseTwo => self.pulse_two,
+            Channel::Triangle => self.triangle,
+            Channel::Noise => self.noise,
+            Channel::Dmc => self.dmc,
+        }
+    }
+}
+
+// The last `capacity` outputs of every channel, for drawing oscilloscope strips. Once full, every new
+// sample pushes the oldest one out. A scope with no capacity keeps nothing.
+pub struct ChannelScope {
+    capacity: usize,
+    history: [VecDeque<u8>; 5],
+}
+
+impl ChannelScope {
+    pub const CHANNELS: [Channel; 5] = [Channel::PulseOne, Channel::PulseTwo, Channel::Triangle, Channel::Noise, Channel::Dmc];
+
+    pub fn new(capacity: usize) -> Self {
+        ChannelScope {
+            capacity,
+            history: std::array::from_fn(|_| VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn push(&mut self, outputs: ChannelOutputs) {
+        if self.capacity == 0 {
+            return;
+        }
+        for channel in ChannelScope::CHANNELS {
+            let history = &mut self.history[channel as usize];
+            if history.len() == self.capacity {
+                history.pop_front();
+            }
+            history.push_back(outputs.get(channel));
+        }
+    }
+
+    // Oldest sample first.
+    pub fn get_history(&self, channel: Channel) -> &VecDeque<u8> {
+        &self.history[channel as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(value: u8) -> ChannelOutputs {
+        ChannelOutputs { pulse_one: value, pulse_two: 0, triangle: 2 * value, noise: 0, dmc: 100 }
+    }
+
+    #[test]
+    fn test_scope_keeps_latest_samples() {
+        let mut scope = ChannelScope::new(3);
+        scope.push(outputs(1));
+        scope.push(outputs(2));
+        assert_eq!(scope.get_history(Channel::PulseOne), &VecDeque::from([1, 2]));
+
+        scope.push(outputs(3));
+        scope.push(outputs(4));
+        assert_eq!(scope.get_history(Channel::PulseOne), &VecDeque::from([2, 3, 4]));
+        assert_eq!(scope.get_history(Channel::Triangle), &VecDeque::from([4, 6, 8]));
+        assert_eq!(scope.get_history(Channel::Dmc), &VecDeque::from([100, 100, 100]));
+        assert_eq!(scope.get_capacity(), 3);
+    }
+
+    #[test]
+    fn test_empty_scope() {
+        let mut scope = ChannelScope::new(0);
+        scope.push(outputs(1));
+        scope.push(outputs(2));
+        assert!(scope.get_history(Channel::PulseOne).is_empty());
+    }
+}
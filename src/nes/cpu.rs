@@ -1,11 +1,13 @@
+pub mod editor;
 pub mod mem;
+pub mod trace;
 mod registers;
 
-use rand::Rng;
-
+use std::fmt;
 use crate::nes::cpu::mem::Memory;
-use crate::nes::cpu::registers::status::{StatusFlag, StatusRegister};
-use crate::util::bitvec::BitVector;
+use crate::nes::cpu::registers::status::StatusRegister;
+#[cfg(feature = "trace")]
+use crate::util::logger::Logger;
 
 const ISB_PATTERN: u8 = 0b1110_0011;
 const DCP_PATTERN: u8 = 0b1100_0011;
@@ -41,6 +43,60 @@ const STY_PATTERN: u8 = 0b1000_0000;
 
 const OP_MASK: u8 = 0b1110_0011;
 
+// Cycle counts before any page crossing or taken branch. An instruction's last bus access lands on
+// its last cycle, which is how far the PPU gets caught up before a register access.
+const BASE_CYCLES: [u8; 256] = [
+//  x0 x1 x2 x3 x4 x5 x6 x7 x8 x9 xA xB xC xD xE xF
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // 0x
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 1x
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // 2x
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 3x
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // 4x
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 5x
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // 6x
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 7x
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 8x
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // 9x
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // Ax
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // Bx
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // Cx
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // Dx
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // Ex
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // Fx
+];
+
+// The stack pointer wraps around page 1, which the hardware is fine with but is almost always a
+// bug: a JSR without its RTS, or an RTI without the interrupt that pushed its return address. Debug
+// builds watch for it and keep the first one seen, along with where the program counter was.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StackError {
+    Overflow { program_counter: u16 },
+    Underflow { program_counter: u16 },
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackError::Overflow { program_counter } =>
+                write!(f, "Stack overflow at PC: 0x{:04X}, pushed past $0100", program_counter),
+            StackError::Underflow { program_counter } =>
+                write!(f, "Stack underflow at PC: 0x{:04X}, popped past $01FF", program_counter),
+        }
+    }
+}
+
+// A copy of the registers, for code that should see the CPU but not be able to change it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CpuState {
+    pub program_counter: u16,
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub stack: u8,
+    pub status: u8,
+    pub cycles: usize,
+}
+
 pub struct CPU {
     pub register_a: u8,
     pub register_x: u8,
@@ -52,9 +108,18 @@ pub struct CPU {
     pub memory: Memory,
 
     pub cycles: usize,
+    rng_seed: u64,
+    rng_state: u64,
+    ane_magic: Option<u8>,
+    #[cfg(feature = "trace")]
+    pub trace_logger: Option<Logger>,
+    #[cfg(debug_assertions)]
+    stack_error: Option<StackError>,
 }
 
 impl CPU {
+    pub const DEFAULT_RNG_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
     pub const LDA_IM: u8 = 0xa9;
     pub const LDA_ZP: u8 = 0xa5;
     pub const LDA_ZP_X: u8 = 0xb5;
@@ -355,20 +420,86 @@ impl CPU {
             memory: Memory::new(),
 
             cycles: 0,
+            rng_seed: CPU::DEFAULT_RNG_SEED,
+            rng_state: CPU::DEFAULT_RNG_SEED,
+            ane_magic: None,
+            #[cfg(feature = "trace")]
+            trace_logger: None,
+            #[cfg(debug_assertions)]
+            stack_error: None,
         }
     }
 
-    pub fn reset(&mut self) {
+    // The unstable opcodes draw from a seeded generator, so a run can be reproduced from its seed.
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        // xorshift gets stuck at 0
+        let seed = if seed == 0 { CPU::DEFAULT_RNG_SEED } else { seed };
+        self.rng_seed = seed;
+        self.rng_state = seed;
+    }
+
+    pub fn get_rng_seed(&self) -> u64 {
+        self.rng_seed
+    }
+
+    // Where the generator is in its sequence, for save states.
+    pub fn get_rng_state(&self) -> u64 {
+        self.rng_state
+    }
+
+    pub fn set_rng_state(&mut self, state: u64) {
+        self.rng_state = if state == 0 { self.rng_seed } else { state };
+    }
+
+    // Pins the "magic" constant ANE ORs into A, instead of drawing a new one every time. It depends on
+    // the chip (and its temperature) on real hardware, and $FF is a common stand-in.
+    pub fn set_ane_magic(&mut self, magic: u8) {
+        self.ane_magic = Some(magic);
+    }
+
+    // Ref: https://www.jstatsoft.org/article/view/v008i14 (xorshift64)
+    fn next_random(&mut self) -> u64 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        self.rng_state
+    }
+
+    // Power-up state. The reset sequence still has to run once a cartridge is in, which takes the
+    // stack pointer down to $FD and sets the interrupt disable flag.
+    pub fn power_on(&mut self) {
+        self.memory.power_on();
+        self.cycles = 0;
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
-        self.stack = 0xfd;
-        self.status.set_value(0b0010_0100);
+        self.stack = 0x00;
+        self.status.set_value(0b0010_0000);
         self.program_counter = 0;
+        self.rng_state = self.rng_seed;
+        #[cfg(debug_assertions)]
+        {
+            self.stack_error = None;
+        }
     }
 
-    pub fn step(&mut self) -> Result<bool, bool> {
+    // The reset sequence is an interrupt with its three stack pushes turned into reads, so all that
+    // changes is S (down by 3), the I flag and PC. RAM and the other registers are left alone.
+    pub fn reset(&mut self) {
+        self.stack = self.stack.wrapping_sub(3);
+        self.status.set_interrupt_disable(true);
+        self.program_counter = self.memory.read_addr(Memory::RESET_INT_VECTOR);
+    }
+
+    // Executes one instruction and returns the number of CPU cycles it took, including any DMA stall.
+    pub fn step(&mut self) -> Result<u16, bool> {
         let opcode = self.memory.read_byte(self.program_counter);
+        #[cfg(feature = "trace")]
+        if let Some(logger) = &mut self.trace_logger {
+            logger.trace_instruction(self.program_counter, opcode, self.register_a, self.register_x, self.register_y,
+                self.stack, self.status.get_value() & !0b0001_0000, self.cycles as u64);
+        }
+        self.memory.access_cycle = BASE_CYCLES[opcode as usize] - 1;
         let cycles: u8 = match opcode {
             CPU::TAX => self.tax(),
             CPU::TAY => self.tay(),
@@ -566,13 +697,60 @@ impl CPU {
             }
         };
         self.tick(cycles);
-        return Ok(true);
+        self.memory.access_cycle = 0;
+
+        let mut cycles = cycles as u16;
+        if let Some(page) = self.memory.oam_dma_pending.take() {
+            cycles += self.oam_dma(page);
+        }
+        if let Some(address) = self.memory.apu.dmc.poll_dma() {
+            cycles += self.dmc_dma(address);
+        }
+        return Ok(cycles);
     }
 
     pub fn tick(&mut self, cycles: u8) {
         self.cycles = self.cycles.wrapping_add(cycles as usize);
         self.memory.ppu.tick(cycles);
         self.memory.apu.tick(cycles);
+        self.memory.rom_mut().tick(cycles);
+    }
+
+    // OAM DMA halts the CPU for one cycle, plus one more to align to an even (get) cycle, and then
+    // alternates reads and writes for 256 bytes: 513 or 514 cycles in total. The PPU and APU keep
+    // running while the CPU is halted, and the PPU is kept in lockstep with the copy so that sprite
+    // evaluation only sees the bytes written so far.
+    fn oam_dma(&mut self, page: u8) -> u16 {
+        let alignment = (self.cycles % 2) as u8;
+        self.tick(1 + alignment);
+
+        let read_addr = (page as u16) << 8;
+        let write_addr = self.memory.ppu.oam_addr;
+        for i in 0..256 {
+            let value = self.memory.read_byte(read_addr.wrapping_add(i));
+            self.tick(1);
+            self.memory.ppu.step().unwrap();
+            self.memory.ppu.oam.write_byte(write_addr.wrapping_add(i as u8), value);
+            self.tick(1);
+        }
+        513 + alignment as u16
+    }
+
+    // A DMC sample fetch halts the CPU for 4 cycles. The halt lands on the first CPU read after the
+    // request, so a request raised during the last cycle of an instruction halts that instruction's
+    // final read. The CPU repeats that read once released, and if it was a controller port then the
+    // controller gets clocked twice and a button bit is lost (the DMC/joypad conflict).
+    fn dmc_dma(&mut self, address: u16) -> u16 {
+        let last_read_address = self.memory.last_read_address;
+        let is_joycon_read = last_read_address == Memory::JOYCON_ONE_REGISTER || last_read_address == Memory::JOYCON_TWO_REGISTER;
+        if self.memory.dmc_dma_glitch && is_joycon_read && self.memory.apu.dmc.get_dma_wait_cycles() <= 1 {
+            self.memory.read_byte(last_read_address);
+        }
+
+        let sample = self.memory.read_byte(address);
+        self.memory.apu.dmc.load_sample_buffer(sample);
+        self.tick(4);
+        4
     }
 
     // NMI & IRQ execution flow:
@@ -586,18 +764,18 @@ impl CPU {
         self.push_addr(self.program_counter);
         self.push_byte(self.status.get_value_interrupt());
 
-        self.status.set(StatusFlag::InterruptDisable);
+        self.status.set_interrupt_disable(true);
 
         self.tick(2);
         self.program_counter = self.memory.read_addr(Memory::NMI_INT_VECTOR);
     }
 
     pub fn handle_irq(&mut self) {
-        if self.status.is_clear(StatusFlag::InterruptDisable) {
+        if !self.status.interrupt_disable() {
             self.push_addr(self.program_counter);
             self.push_byte(self.status.get_value_interrupt());
 
-            self.status.set(StatusFlag::InterruptDisable);
+            self.status.set_interrupt_disable(true);
 
             self.tick(2);
             self.program_counter = self.memory.read_addr(Memory::IRQ_INT_VECTOR);
@@ -685,49 +863,49 @@ impl CPU {
     
     #[inline]
     fn sec(&mut self) -> u8 {
-        self.status.set(StatusFlag::Carry);
+        self.status.set_carry(true);
         self.increment_program_counter();
         return 2;
     }
 
     #[inline]
     fn clc(&mut self) -> u8 {
-        self.status.clear(StatusFlag::Carry);
+        self.status.set_carry(false);
         self.increment_program_counter();
         return 2;
     }
 
     #[inline]
     fn sed(&mut self) -> u8 {
-        self.status.set(StatusFlag::DecimalMode);
+        self.status.set_decimal(true);
         self.increment_program_counter();
         return 2;
     }
 
     #[inline]
     fn cld(&mut self) -> u8 {
-        self.status.clear(StatusFlag::DecimalMode);
+        self.status.set_decimal(false);
         self.increment_program_counter();
         return 2;
     }
 
     #[inline]
     fn sei(&mut self) -> u8 {
-        self.status.set(StatusFlag::InterruptDisable);
+        self.status.set_interrupt_disable(true);
         self.increment_program_counter();
         return 2;
     }
 
     #[inline]
     fn cli(&mut self) -> u8 {
-        self.status.clear(StatusFlag::InterruptDisable);
+        self.status.set_interrupt_disable(false);
         self.increment_program_counter();
         return 2;
     }
 
     #[inline]
     fn clv(&mut self) -> u8 {
-        self.status.clear(StatusFlag::Overflow);
+        self.status.set_overflow(false);
         self.increment_program_counter();
         return 2;
     }
@@ -786,7 +964,7 @@ impl CPU {
 
     #[inline]
     fn jmp_in(&mut self, address: u16) -> u8 {
-        let addr = self.memory.read_addr_in(address);
+        let addr = self.memory.read_addr_in_with_page_wrap(address);
         self.program_counter = addr;
         return 5;
     }
@@ -816,7 +994,7 @@ impl CPU {
     fn beq(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_set(StatusFlag::Zero) {
+        if self.status.zero() {
             cycles += self.jmp_offset(offset);
         }
         return cycles;
@@ -826,7 +1004,7 @@ impl CPU {
     fn bne(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_clear(StatusFlag::Zero) {
+        if !self.status.zero() {
             cycles += self.jmp_offset(offset);
         }
         return cycles;
@@ -836,7 +1014,7 @@ impl CPU {
     fn bcs(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_set(StatusFlag::Carry) {
+        if self.status.carry() {
             cycles += self.jmp_offset(offset);
         }
         return cycles;
@@ -846,7 +1024,7 @@ impl CPU {
     fn bcc(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_clear(StatusFlag::Carry) {
+        if !self.status.carry() {
             cycles += self.jmp_offset(offset);
         }
         return cycles;
@@ -856,7 +1034,7 @@ impl CPU {
     fn bmi(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_set(StatusFlag::Negative) {
+        if self.status.negative() {
             cycles += self.jmp_offset(offset);
         }
         cycles
@@ -866,7 +1044,7 @@ impl CPU {
     fn bpl(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_clear(StatusFlag::Negative) {
+        if !self.status.negative() {
             cycles += self.jmp_offset(offset);
         }
         cycles
@@ -876,7 +1054,7 @@ impl CPU {
     fn bvs(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_set(StatusFlag::Overflow) {
+        if self.status.overflow() {
             cycles += self.jmp_offset(offset);
         }
         cycles
@@ -886,7 +1064,7 @@ impl CPU {
     fn bvc(&mut self, offset: i8) -> u8 {
         let mut cycles = 2;
         self.increment_program_counter();
-        if self.status.is_clear(StatusFlag::Overflow) {
+        if !self.status.overflow() {
             cycles += self.jmp_offset(offset);
         }
         cycles
@@ -898,8 +1076,8 @@ impl CPU {
         self.ror_a();
         let bit_6 = (self.register_a & 0x40 > 0) as u8;
         let bit_5 = (self.register_a & 0x20 > 0) as u8;
-        self.status.update(StatusFlag::Carry, bit_6 > 0);
-        self.status.update(StatusFlag::Overflow, bit_6 ^ bit_5 > 0);
+        self.status.set_carry(bit_6 > 0);
+        self.status.set_overflow(bit_6 ^ bit_5 > 0);
         self.increment_program_counter();
         return 2;
     }
@@ -925,7 +1103,7 @@ impl CPU {
         self.register_x = self.register_x & self.register_a;
         let sum = (self.register_x as u16).wrapping_add(immediate.wrapping_neg() as u16);
         self.register_x = sum as u8;
-        self.status.update(StatusFlag::Carry, sum > 0xff);
+        self.status.set_carry(sum > 0xff);
         self.update_zero_and_negative_flag(self.register_x);
         self.increment_program_counter();
         return 2;
@@ -944,8 +1122,10 @@ impl CPU {
 
     #[inline]
     fn ane(&mut self, immediate: u8) -> u8 {
-        let magic_digit = rand::thread_rng().gen_range(0..0xf) as u8;
-        let magic = (magic_digit << 4) | magic_digit;
+        let magic = self.ane_magic.unwrap_or_else(|| {
+            let magic_digit = (self.next_random() % 0xf) as u8;
+            (magic_digit << 4) | magic_digit
+        });
         self.register_a = (self.register_a | magic) & self.register_x & immediate;
         self.update_zero_and_negative_flag(self.register_a);
         self.increment_program_counter();
@@ -999,7 +1179,7 @@ impl CPU {
     #[inline]
     fn anc(&mut self, immediate: u8) -> u8 {
         self.and_im(immediate);
-        self.status.update(StatusFlag::Carry, self.register_a & 0x80 > 0);
+        self.status.set_carry(self.register_a & 0x80 > 0);
         self.increment_program_counter();
         return 2;
     }
@@ -1090,14 +1270,14 @@ impl CPU {
     fn adc_im(&mut self, immediate: u8) -> u8 {
         let mut sum = (self.register_a as u16).wrapping_add(immediate as u16);
         let mut overflow = (self.register_a ^ (sum as u8)) & (immediate ^ (sum as u8)) & 0x80 != 0;
-        if self.status.is_set(StatusFlag::Carry) {
+        if self.status.carry() {
             let carry_sum = sum.wrapping_add(1);
             overflow = overflow || ((sum as u8) ^ (carry_sum as u8)) & (carry_sum as u8) & 0x80 != 0;
             sum = carry_sum;
         }
         self.register_a = sum as u8;
-        self.status.update(StatusFlag::Overflow, overflow);
-        self.status.update(StatusFlag::Carry, sum > 0xff);
+        self.status.set_overflow(overflow);
+        self.status.set_carry(sum > 0xff);
         self.update_zero_and_negative_flag(self.register_a);
         return 2;
     }
@@ -1563,7 +1743,7 @@ impl CPU {
 
     #[inline]
     fn lsr_a(&mut self) -> u8 {
-        self.status.update(StatusFlag::Carry, self.register_a & 1 != 0);
+        self.status.set_carry(self.register_a & 1 != 0);
         self.register_a = self.register_a >> 1;
         self.update_zero_and_negative_flag(self.register_a);
         return 2;
@@ -1572,7 +1752,7 @@ impl CPU {
     #[inline]
     fn lsr_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.zp_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -1582,7 +1762,7 @@ impl CPU {
     #[inline]
     fn lsr_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.zp_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
@@ -1592,7 +1772,7 @@ impl CPU {
     #[inline]
     fn lsr_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.ab_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -1602,7 +1782,7 @@ impl CPU {
     #[inline]
     fn lsr_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.ab_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
@@ -1648,7 +1828,7 @@ impl CPU {
     #[inline]
     fn sre_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.zp_write(address, value);
         self.eor_zp(address);
@@ -1658,7 +1838,7 @@ impl CPU {
     #[inline]
     fn sre_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.zp_x_write(address, self.register_x, value);
         self.eor_zp_x(address);
@@ -1668,7 +1848,7 @@ impl CPU {
     #[inline]
     fn sre_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.ab_write(address, value);
         self.eor_ab(address);
@@ -1678,7 +1858,7 @@ impl CPU {
     #[inline]
     fn sre_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.ab_x_write(address, self.register_x, value);
         self.eor_ab_x(address);
@@ -1688,7 +1868,7 @@ impl CPU {
     #[inline]
     fn sre_ab_y(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_y_read(address, self.register_y);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.ab_y_write(address, self.register_y, value);
         self.eor_ab_y(address);
@@ -1698,7 +1878,7 @@ impl CPU {
     #[inline]
     fn sre_in_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.in_x_write(address, self.register_x, value);
         self.eor_in_x(address);
@@ -1708,7 +1888,7 @@ impl CPU {
     #[inline]
     fn sre_in_y(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_y_read(address, self.register_y);
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        self.status.set_carry(value & 1 != 0);
         value = value >> 1;
         self.memory.in_y_write(address, self.register_y, value);
         self.eor_in_y(address);
@@ -1744,7 +1924,7 @@ impl CPU {
 
     #[inline]
     fn asl_a(&mut self) -> u8 {
-        self.status.update(StatusFlag::Carry, self.register_a & 0x80 != 0);
+        self.status.set_carry(self.register_a & 0x80 != 0);
         self.register_a = self.register_a << 1;
         self.update_zero_and_negative_flag(self.register_a);
         return 2;
@@ -1753,7 +1933,7 @@ impl CPU {
     #[inline]
     fn asl_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.zp_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -1763,17 +1943,20 @@ impl CPU {
     #[inline]
     fn asl_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.zp_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
         return 6;
     }
 
+    // todo: [BUG] read-modify-write instructions (ASL, LSR, ROL, ROR, INC, DEC and the illegal SLO, SRE,
+    //  RLA, RRA, DCP, ISB) write the unmodified value back the cycle before the result. The MMC1 relies
+    //  on that dummy write, see Mapper1::write_mapper
     #[inline]
     fn asl_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.ab_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -1783,7 +1966,7 @@ impl CPU {
     #[inline]
     fn asl_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.ab_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
@@ -1829,7 +2012,7 @@ impl CPU {
     #[inline]
     fn slo_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.zp_write(address, value);
         self.ora_zp(address);
@@ -1839,7 +2022,7 @@ impl CPU {
     #[inline]
     fn slo_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.zp_x_write(address, self.register_x, value);
         self.ora_zp_x(address);
@@ -1849,7 +2032,7 @@ impl CPU {
     #[inline]
     fn slo_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.ab_write(address, value);
         self.ora_ab(address);
@@ -1859,7 +2042,7 @@ impl CPU {
     #[inline]
     fn slo_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.ab_x_write(address, self.register_x, value);
         self.ora_ab_x(address);
@@ -1869,7 +2052,7 @@ impl CPU {
     #[inline]
     fn slo_ab_y(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_y_read(address, self.register_y);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.ab_y_write(address, self.register_y, value);
         self.ora_ab_y(address);
@@ -1879,7 +2062,7 @@ impl CPU {
     #[inline]
     fn slo_in_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_x_read(address, self.register_x);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.in_x_write(address, self.register_x, value);
         self.ora_in_x(address);
@@ -1889,7 +2072,7 @@ impl CPU {
     #[inline]
     fn slo_in_y(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_y_read(address, self.register_y);
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        self.status.set_carry(value & 0x80 != 0);
         value = value << 1;
         self.memory.in_y_write(address, self.register_y, value);
         self.ora_in_y(address);
@@ -1925,8 +2108,8 @@ impl CPU {
 
     #[inline]
     fn ror_a(&mut self) -> u8 {
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, self.register_a & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(self.register_a & 1 != 0);
         self.register_a = (self.register_a >> 1) | (old_carry << 7);
         self.update_zero_and_negative_flag(self.register_a);
         return 2;
@@ -1935,8 +2118,8 @@ impl CPU {
     #[inline]
     fn ror_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.zp_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -1946,8 +2129,8 @@ impl CPU {
     #[inline]
     fn ror_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.zp_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
@@ -1957,8 +2140,8 @@ impl CPU {
     #[inline]
     fn ror_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.ab_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -1968,8 +2151,8 @@ impl CPU {
     #[inline]
     fn ror_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.ab_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
@@ -2015,8 +2198,8 @@ impl CPU {
     #[inline]
     fn rra_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.zp_write(address, value);
         self.adc_zp(address);
@@ -2026,8 +2209,8 @@ impl CPU {
     #[inline]
     fn rra_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.zp_x_write(address, self.register_x, value);
         self.adc_zp_x(address);
@@ -2037,8 +2220,8 @@ impl CPU {
     #[inline]
     fn rra_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.ab_write(address, value);
         self.adc_ab(address);
@@ -2048,8 +2231,8 @@ impl CPU {
     #[inline]
     fn rra_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.ab_x_write(address, self.register_x, value);
         self.adc_ab_x(address);
@@ -2059,8 +2242,8 @@ impl CPU {
     #[inline]
     fn rra_ab_y(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_y_read(address, self.register_y);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.ab_y_write(address, self.register_y, value);
         self.adc_ab_y(address);
@@ -2070,8 +2253,8 @@ impl CPU {
     #[inline]
     fn rra_in_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.in_x_write(address, self.register_x, value);
         self.adc_in_x(address);
@@ -2081,8 +2264,8 @@ impl CPU {
     #[inline]
     fn rra_in_y(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_y_read(address, self.register_y);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 1 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 1 != 0);
         value = (value >> 1) | (old_carry << 7);
         self.memory.in_y_write(address, self.register_y, value);
         self.adc_in_y(address);
@@ -2118,8 +2301,8 @@ impl CPU {
 
     #[inline]
     fn rol_a(&mut self) -> u8 {
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, self.register_a & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(self.register_a & 0x80 != 0);
         self.register_a = (self.register_a << 1) | old_carry;
         self.update_zero_and_negative_flag(self.register_a);
         return 2;
@@ -2128,8 +2311,8 @@ impl CPU {
     #[inline]
     fn rol_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.zp_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -2139,8 +2322,8 @@ impl CPU {
     #[inline]
     fn rol_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.zp_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
@@ -2150,8 +2333,8 @@ impl CPU {
     #[inline]
     fn rol_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.ab_write(address, value);
         self.update_zero_and_negative_flag(value);
@@ -2161,8 +2344,8 @@ impl CPU {
     #[inline]
     fn rol_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.ab_x_write(address, self.register_x, value);
         self.update_zero_and_negative_flag(value);
@@ -2208,8 +2391,8 @@ impl CPU {
     #[inline]
     fn rla_zp(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.zp_write(address, value);
         self.and_zp(address);
@@ -2219,8 +2402,8 @@ impl CPU {
     #[inline]
     fn rla_zp_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.zp_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.zp_x_write(address, self.register_x, value);
         self.and_zp_x(address);
@@ -2230,8 +2413,8 @@ impl CPU {
     #[inline]
     fn rla_ab(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_read(address);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.ab_write(address, value);
         self.and_ab(address);
@@ -2241,8 +2424,8 @@ impl CPU {
     #[inline]
     fn rla_ab_x(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.ab_x_write(address, self.register_x, value);
         self.and_ab_x(address);
@@ -2252,8 +2435,8 @@ impl CPU {
     #[inline]
     fn rla_ab_y(&mut self, address: u16) -> u8 {
         let mut value = self.memory.ab_y_read(address, self.register_y);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.ab_y_write(address, self.register_y, value);
         self.and_ab_y(address);
@@ -2263,8 +2446,8 @@ impl CPU {
     #[inline]
     fn rla_in_x(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_x_read(address, self.register_x);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.in_x_write(address, self.register_x, value);
         self.and_in_x(address);
@@ -2274,8 +2457,8 @@ impl CPU {
     #[inline]
     fn rla_in_y(&mut self, address: u8) -> u8 {
         let mut value = self.memory.in_y_read(address, self.register_y);
-        let old_carry = self.status.is_set(StatusFlag::Carry) as u8;
-        self.status.update(StatusFlag::Carry, value & 0x80 != 0);
+        let old_carry = self.status.carry() as u8;
+        self.status.set_carry(value & 0x80 != 0);
         value = (value << 1) | old_carry;
         self.memory.in_y_write(address, self.register_y, value);
         self.and_in_y(address);
@@ -2375,7 +2558,7 @@ impl CPU {
     fn lda_in_y(&mut self, address: u8) -> u8 {
         self.register_a = self.memory.in_y_read(address, self.register_y);
         self.update_zero_and_negative_flag(self.register_a);
-        return 4 + self.in_y_page_crossed(address) as u8;
+        return 5 + self.in_y_page_crossed(address) as u8;
     }
 
     fn ldx(&mut self, opcode: u8) -> u8 {
@@ -3147,7 +3330,7 @@ impl CPU {
     #[inline]
     fn cmp_im(&mut self, immediate: u8) -> u8 {
         let cmp = self.register_a.wrapping_sub(immediate);
-        self.status.update(StatusFlag::Carry, self.register_a >= immediate);
+        self.status.set_carry(self.register_a >= immediate);
         self.update_zero_and_negative_flag(cmp);
         return 2;
     }
@@ -3224,7 +3407,7 @@ impl CPU {
     #[inline]
     fn cpx_im(&mut self, immediate: u8) -> u8 {
         let cmp = self.register_x.wrapping_sub(immediate);
-        self.status.update(StatusFlag::Carry, self.register_x >= immediate);
+        self.status.set_carry(self.register_x >= immediate);
         self.update_zero_and_negative_flag(cmp);
         return 2;
     }
@@ -3266,7 +3449,7 @@ impl CPU {
     #[inline]
     fn cpy_im(&mut self, immediate: u8) -> u8 {
         let cmp = self.register_y.wrapping_sub(immediate);
-        self.status.update(StatusFlag::Carry, self.register_y >= immediate);
+        self.status.set_carry(self.register_y >= immediate);
         self.update_zero_and_negative_flag(cmp);
         return 2;
     }
@@ -3287,16 +3470,16 @@ impl CPU {
 
     #[inline]
     fn update_zero_and_negative_flag(&mut self, value: u8) {
-        self.status.update(StatusFlag::Zero, value == 0);
-        self.status.update(StatusFlag::Negative, value & 0x80 > 0);
+        self.status.set_zero(value == 0);
+        self.status.set_negative(value & 0x80 > 0);
     }
 
     #[inline]
     fn update_bit_flags(&mut self, value: u8) {
         let test = value & self.register_a;
-        self.status.update(StatusFlag::Zero, test == 0);
-        self.status.update(StatusFlag::Negative, value & 0x80 > 0);
-        self.status.update(StatusFlag::Overflow, (value << 1) & 0x80 > 0);
+        self.status.set_zero(test == 0);
+        self.status.set_negative(value & 0x80 > 0);
+        self.status.set_overflow((value << 1) & 0x80 > 0);
     }
 
     #[inline]
@@ -3317,26 +3500,81 @@ impl CPU {
         self.memory.read_addr(self.program_counter.wrapping_sub(1))
     }
 
+    pub fn get_state(&self) -> CpuState {
+        CpuState {
+            program_counter: self.program_counter,
+            register_a: self.register_a,
+            register_x: self.register_x,
+            register_y: self.register_y,
+            stack: self.stack,
+            status: self.status.get_value(),
+            cycles: self.cycles,
+        }
+    }
+
+    // Bytes currently on the stack, most recently pushed first.
+    pub fn peek_stack(&self) -> Vec<u8> {
+        self.stack_contents(&self.memory)
+    }
+
+    // Same as peek_stack, but for a debugger holding onto its own view of memory.
+    pub fn stack_contents(&self, memory: &Memory) -> Vec<u8> {
+        let top = 0x0100 + self.stack as u16 + 1;
+        memory.dump(top, 0x0200 - top as usize)
+    }
+
+    // Number of bytes pushed, counting down from an empty stack at $01FF.
+    pub fn stack_depth(&self) -> u8 {
+        0xFF - self.stack
+    }
+
+    #[cfg(debug_assertions)]
+    pub fn get_stack_error(&self) -> Option<StackError> {
+        self.stack_error
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_stack(&mut self, pushed: u8, popped: u8) {
+        if self.stack_error.is_some() { return }
+        let error = if self.stack < pushed {
+            StackError::Overflow { program_counter: self.program_counter }
+        } else if self.stack > 0xFF - popped {
+            StackError::Underflow { program_counter: self.program_counter }
+        } else {
+            return;
+        };
+        println!("[WARNING] {}", error);
+        self.stack_error = Some(error);
+    }
+
     #[inline]
     fn push_byte(&mut self, value: u8) {
+        #[cfg(debug_assertions)]
+        self.check_stack(1, 0);
         self.memory.write_byte(0x0100 + self.stack as u16, value);
         self.stack = self.stack.wrapping_sub(1);
     }
 
     #[inline]
     fn pop_byte(&mut self) -> u8 {
+        #[cfg(debug_assertions)]
+        self.check_stack(0, 1);
         self.stack = self.stack.wrapping_add(1);
         self.memory.read_byte(0x0100 + self.stack as u16)
     }
 
     #[inline]
     fn push_addr(&mut self, value: u16) {
+        #[cfg(debug_assertions)]
+        self.check_stack(2, 0);
         self.memory.write_addr(0x0100 + self.stack.wrapping_sub(1) as u16, value);
         self.stack = self.stack.wrapping_sub(2);
     }
 
     #[inline]
     fn pop_addr(&mut self) -> u16 {
+        #[cfg(debug_assertions)]
+        self.check_stack(0, 2);
         self.stack = self.stack.wrapping_add(2);
         self.memory.read_addr(0x0100 + self.stack.wrapping_sub(1) as u16)
     }
@@ -3373,6 +3611,7 @@ impl CPU {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nes::rom::ROM;
 
     const BYTE_A: u8 = 0x0a;
     const BYTE_B: u8 = 0x0b;
@@ -3385,22 +3624,34 @@ mod tests {
         assert_eq!(cpu.register_y, 0);
         assert_eq!(cpu.program_counter, 0);
         assert_eq!(cpu.stack, 0xff);
-        assert_eq!(cpu.status.is_set(StatusFlag::Unused), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::BreakCommand), true);
+        assert_eq!(cpu.status.unused(), true);
+        assert_eq!(cpu.status.break_command(), true);
     }
 
     #[test]
     fn test_reset() {
         let mut cpu = CPU::new();
+        cpu.power_on();
+        cpu.memory.load_at_addr(0x8123, &vec![]);
         cpu.reset();
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.register_x, 0);
         assert_eq!(cpu.register_y, 0);
-        assert_eq!(cpu.program_counter, 0);
+        assert_eq!(cpu.program_counter, 0x8123);
         assert_eq!(cpu.stack, 0xfd);
-        assert_eq!(cpu.status.is_set(StatusFlag::Unused), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::BreakCommand), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::InterruptDisable), true);
+        assert_eq!(cpu.status.unused(), true);
+        assert_eq!(cpu.status.break_command(), false);
+        assert_eq!(cpu.status.interrupt_disable(), true);
+
+        // pressing reset again keeps the registers and only walks the stack pointer down
+        cpu.register_a = 0x12;
+        cpu.status.set_interrupt_disable(false);
+        cpu.status.set_carry(true);
+        cpu.reset();
+        assert_eq!(cpu.register_a, 0x12);
+        assert_eq!(cpu.stack, 0xfa);
+        assert_eq!(cpu.status.interrupt_disable(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     /* NOP */
@@ -3448,14 +3699,155 @@ mod tests {
         cpu.step().unwrap();
         assert_eq!(cpu.program_counter, 0);
     }
-    
+
+    #[test]
+    #[cfg(feature = "trace")]
+    fn test_trace_logger() {
+        let path = std::env::temp_dir().join("alpines_test_cpu_trace.log");
+        let mut cpu = CPU::new();
+        cpu.memory.write_byte(0, CPU::LDA_IM);
+        cpu.memory.write_byte(1, BYTE_A);
+        cpu.memory.write_byte(2, CPU::JAM_1);
+        cpu.trace_logger = Some(Logger::to_file(&path).unwrap());
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.trace_logger.as_mut().unwrap().flush().unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("0000  A9        LDA"));
+        assert!(lines[1].starts_with("0002  02       *JAM"));
+        assert!(lines[1].ends_with(&format!("A:{:02X} X:00 Y:00 P:20 SP:FF CYC:2", BYTE_A)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /* Cycle Counts */
+
+    #[test]
+    fn test_base_cycles() {
+        for opcode in 0..=255u8 {
+            let info = trace::OPCODES[opcode as usize];
+            if info.mnemonic == "JAM" || info.mnemonic == "BRK" || info.mode == trace::AddressingMode::Relative {
+                continue;
+            }
+            let mut cpu = CPU::new();
+            cpu.memory.write_byte(0x0600, opcode);
+            cpu.program_counter = 0x0600;
+            assert_eq!(cpu.step(), Ok(BASE_CYCLES[opcode as usize] as u16), "opcode {:02X} {}", opcode, info.mnemonic);
+        }
+    }
+
+    #[test]
+    fn test_ppu_catch_up_on_register_write() {
+        let mut cpu = CPU::new();
+        cpu.memory.ppu.memory.rom.is_chr_ram = true;
+        cpu.memory.ppu.memory.rom.chr_rom = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        cpu.memory.write_bulk(0x0000, &[CPU::STA_AB, 0x01, 0x20]);
+        cpu.register_a = 0b0001_1110;
+        cpu.memory.ppu.scanline = 10;
+        cpu.memory.ppu.dot = 100;
+        cpu.memory.ppu.tick(10);
+
+        // the 10 pending cycles plus the 3 of STA that come before its write run first
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory.ppu.dot, 100 + 3 * 13);
+        assert_eq!(cpu.memory.ppu.cycles, 3);
+        cpu.memory.ppu.step().unwrap();
+        assert_eq!(cpu.memory.ppu.dot, 100 + 3 * 14);
+    }
+
+    #[test]
+    fn test_ppu_catch_up_twice_in_one_instruction() {
+        let mut cpu = CPU::new();
+        // INC $2001 reads, writes back the old value, then writes the incremented one
+        cpu.memory.write_bulk(0x0000, &[CPU::INC_AB, 0x01, 0x20]);
+        cpu.memory.ppu.scanline = 241;
+        cpu.memory.ppu.dot = 10;
+        cpu.step().unwrap();
+        cpu.memory.ppu.step().unwrap();
+        assert_eq!(cpu.memory.ppu.dot, 10 + 3 * 6);
+    }
+
+    /* OAM DMA */
+
+    #[test]
+    fn test_step_oam_dma() {
+        let mut cpu = CPU::new();
+        for i in 0..256 {
+            cpu.memory.write_byte(0x0200 + i, i as u8);
+        }
+        cpu.memory.write_bulk(0x0000, &[CPU::STA_AB, 0x14, 0x40]);
+        cpu.register_a = 0x02;
+        cpu.memory.ppu.oam_addr = 0x10;
+
+        assert_eq!(cpu.step(), Ok(4 + 513));
+        assert_eq!(cpu.cycles, 4 + 513);
+        assert_eq!(cpu.memory.ppu.oam.read_byte(0x10), 0x00);
+        assert_eq!(cpu.memory.ppu.oam.read_byte(0xff), 0xef);
+        assert_eq!(cpu.memory.ppu.oam.read_byte(0x0f), 0xff);
+        assert_eq!(cpu.memory.oam_dma_pending, None);
+    }
+
+    #[test]
+    fn test_step_oam_dma_odd_cycle() {
+        let mut cpu = CPU::new();
+        cpu.memory.write_bulk(0x0000, &[CPU::NOP, CPU::STA_AB, 0x14, 0x40, CPU::STX_ZP, 0x00]);
+        cpu.cycles = 1;
+
+        assert_eq!(cpu.step(), Ok(2));
+        assert_eq!(cpu.step(), Ok(4 + 514));
+        assert_eq!(cpu.cycles, 1 + 2 + 4 + 514);
+        assert_eq!(cpu.step(), Ok(3));
+    }
+
+    #[test]
+    fn test_step_oam_dma_ppu_runs() {
+        let mut cpu = CPU::new();
+        cpu.memory.write_bulk(0x0000, &[CPU::STA_AB, 0x14, 0x40]);
+        cpu.memory.ppu.scanline = 241;
+        cpu.step().unwrap();
+        // everything up to the last write has already been drawn, the final cycle is still pending
+        assert_eq!(cpu.memory.ppu.cycles, 3);
+        cpu.memory.ppu.step().unwrap();
+        assert_eq!(cpu.memory.ppu.scanline, 245);
+        assert_eq!(cpu.memory.ppu.dot, 3 * (4 + 513) - 4 * 341);
+        assert_eq!(cpu.memory.ppu.cycles, 0);
+    }
+
+    #[test]
+    fn test_step_oam_dma_sprite_evaluation() {
+        let mut cpu = CPU::new();
+        cpu.memory.ppu.memory.rom.is_chr_ram = true;
+        cpu.memory.ppu.memory.rom.chr_rom = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        cpu.memory.ppu.mask.set_value(0b0001_1000);
+        cpu.memory.ppu.oam.memory = [0xFF; 256];
+        cpu.memory.ppu.scanline = 0;
+        // with OAMADDR at 4, the last byte copied lands on sprite 0's Y
+        cpu.memory.ppu.oam_addr = 0x04;
+        cpu.memory.write_byte(0x02FC, 0x00);
+        cpu.memory.write_bulk(0x0000, &[CPU::STA_AB, 0x14, 0x40]);
+        cpu.register_a = 0x02;
+
+        cpu.step().unwrap();
+        cpu.memory.ppu.step().unwrap();
+        assert_eq!(cpu.memory.ppu.scanline, 4);
+        assert_eq!(cpu.memory.ppu.oam.memory[0], 0x00);
+        // scanlines 0-3 were evaluated before sprite 0 was copied in
+        assert!(!cpu.memory.ppu.sprite_zero_in_range);
+
+        cpu.memory.ppu.tick(100);
+        cpu.memory.ppu.step().unwrap();
+        assert!(cpu.memory.ppu.sprite_zero_in_range);
+    }
+
     /* Set & Clear Flags */
 
     #[test]
     fn test_sec() {
         let mut cpu = CPU::new();
         cpu.sec();
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -3463,14 +3855,14 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.status.value = 0b1111_1111;
         cpu.clc();
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), false);
+        assert_eq!(cpu.status.carry(), false);
     }
 
     #[test]
     fn test_sed() {
         let mut cpu = CPU::new();
         cpu.sed();
-        assert_eq!(cpu.status.is_set(StatusFlag::DecimalMode), true);
+        assert_eq!(cpu.status.decimal(), true);
     }
 
     #[test]
@@ -3478,14 +3870,14 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.status.value = 0b1111_1111;
         cpu.cld();
-        assert_eq!(cpu.status.is_set(StatusFlag::DecimalMode), false);
+        assert_eq!(cpu.status.decimal(), false);
     }
 
     #[test]
     fn test_sei() {
         let mut cpu = CPU::new();
         cpu.sei();
-        assert_eq!(cpu.status.is_set(StatusFlag::InterruptDisable), true);
+        assert_eq!(cpu.status.interrupt_disable(), true);
     }
 
     #[test]
@@ -3493,7 +3885,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.status.value = 0b1111_1111;
         cpu.cli();
-        assert_eq!(cpu.status.is_set(StatusFlag::InterruptDisable), false);
+        assert_eq!(cpu.status.interrupt_disable(), false);
     }
 
     #[test]
@@ -3501,7 +3893,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.status.value = 0b1111_1111;
         cpu.clv();
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
+        assert_eq!(cpu.status.overflow(), false);
     }
 
     /* Stack */
@@ -3515,6 +3907,54 @@ mod tests {
         assert_eq!(cpu.memory.read_byte(0x01ff), BYTE_A);
     }
 
+    #[test]
+    fn test_stack_depth() {
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.stack_depth(), 0);
+        cpu.register_a = BYTE_A;
+        cpu.pha();
+        cpu.register_a = BYTE_B;
+        cpu.pha();
+        assert_eq!(cpu.stack_depth(), 2);
+        assert_eq!(cpu.stack_contents(&cpu.memory), vec![BYTE_B, BYTE_A]);
+        assert_eq!(cpu.peek_stack(), vec![BYTE_B, BYTE_A]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_stack_overflow() {
+        let mut cpu = CPU::new();
+        cpu.stack = 0x01;
+        cpu.program_counter = 0x8123;
+        cpu.jsr(0x9000);
+        assert_eq!(cpu.get_stack_error(), Some(StackError::Overflow { program_counter: 0x8123 }));
+        assert_eq!(cpu.stack, 0xFF);
+
+        // only the first one is kept, it's the one that points at the bug
+        cpu.pla();
+        assert_eq!(cpu.get_stack_error(), Some(StackError::Overflow { program_counter: 0x8123 }));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_stack_underflow() {
+        let mut cpu = CPU::new();
+        cpu.power_on();
+        cpu.memory.load_at_addr(0x8000, &vec![]);
+        cpu.reset();
+        assert_eq!(cpu.get_stack_error(), None);
+        cpu.rts();
+        cpu.program_counter = 0x8040;
+        assert_eq!(cpu.get_stack_error(), None);
+        cpu.pla();
+        assert_eq!(cpu.get_stack_error(), Some(StackError::Underflow { program_counter: 0x8040 }));
+        assert_eq!(StackError::Underflow { program_counter: 0x8040 }.to_string(),
+            "Stack underflow at PC: 0x8040, popped past $01FF");
+
+        cpu.power_on();
+        assert_eq!(cpu.get_stack_error(), None);
+    }
+
     #[test]
     fn test_pla() {
         let mut cpu = CPU::new();
@@ -3524,8 +3964,8 @@ mod tests {
         assert_eq!(cpu.stack, 0xff);
         assert_eq!(cpu.register_a, 0b0111_1010);
         assert_eq!(cpu.memory.read_byte(0x01ff), 0b0111_1010);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), false);
     }
 
     #[test]
@@ -3536,8 +3976,8 @@ mod tests {
         assert_eq!(cpu.stack, 0xff);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x01ff), 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.negative(), false);
     }
 
     #[test]
@@ -3549,8 +3989,8 @@ mod tests {
         assert_eq!(cpu.stack, 0xff);
         assert_eq!(cpu.register_a, 0b1011_1010);
         assert_eq!(cpu.memory.read_byte(0x01ff), 0b1011_1010);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     #[test]
@@ -3611,9 +4051,9 @@ mod tests {
         cpu.memory.write_byte(0x10, 0b0011_1111);
         cpu.register_a = 0b0110_0011;
         cpu.bit_zp(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), false);
+        assert_eq!(cpu.status.overflow(), false);
     }
 
     #[test]
@@ -3622,9 +4062,9 @@ mod tests {
         cpu.memory.write_byte(0x1400, 0b0011_1111);
         cpu.register_a = 0b0110_0011;
         cpu.bit_ab(0x1400);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), false);
+        assert_eq!(cpu.status.overflow(), false);
     }
 
     #[test]
@@ -3633,9 +4073,9 @@ mod tests {
         cpu.memory.write_byte(0x10, 0b0011_1100);
         cpu.register_a = 0b1100_0011;
         cpu.bit_zp(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.negative(), false);
+        assert_eq!(cpu.status.overflow(), false);
     }
 
     #[test]
@@ -3644,9 +4084,9 @@ mod tests {
         cpu.memory.write_byte(0x10, 0b1000_0000);
         cpu.register_a = 0b1111_1111;
         cpu.bit_zp(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.overflow(), false);
     }
 
     #[test]
@@ -3655,9 +4095,9 @@ mod tests {
         cpu.memory.write_byte(0x10, 0b0100_0000);
         cpu.register_a = 0b1111_1111;
         cpu.bit_zp(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), false);
+        assert_eq!(cpu.status.overflow(), true);
     }
     
     /* Add */
@@ -3746,7 +4186,7 @@ mod tests {
         cpu.register_a = 0xff;
         cpu.adc_im(0x01);
         assert_eq!(cpu.register_a, 0x00);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
+        assert_eq!(cpu.status.zero(), true);
     }
 
     #[test]
@@ -3755,7 +4195,7 @@ mod tests {
         cpu.register_a = 0xfe;
         cpu.adc_im(0x01);
         assert_eq!(cpu.register_a, 0xff);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     #[test]
@@ -3764,7 +4204,7 @@ mod tests {
         cpu.register_a = 0xff;
         cpu.adc_im(0xff);
         assert_eq!(cpu.register_a, 0xfe);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -3773,17 +4213,17 @@ mod tests {
         cpu.register_a = 0x64;
         cpu.adc_im(0x64);
         assert_eq!(cpu.register_a, 0xc8);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
     fn test_adc_add_positives_overflow_with_carry() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x64;
         cpu.adc_im(0x64);
         assert_eq!(cpu.register_a, 0xc9);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
@@ -3792,49 +4232,49 @@ mod tests {
         cpu.register_a = 0x9C;
         cpu.adc_im(0x9C);
         assert_eq!(cpu.register_a, 0x38);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
     fn test_adc_add_negatives_overflow_with_carry() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x9C;
         cpu.adc_im(0x9C);
         assert_eq!(cpu.register_a, 0x39);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
     fn test_adc_carry_overflow() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x0f;
         cpu.adc_im(0x70);
         assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
     fn test_adc_add_zero_carry_overflow() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x7f;
         cpu.adc_im(0x00);
         assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
     
     #[test]
     fn test_adc_carry_wraparound() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x0f;
         cpu.adc_im(0xf0);
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
+        assert_eq!(cpu.status.overflow(), false);
     }
 
     /* Subtract */
@@ -3842,7 +4282,7 @@ mod tests {
     #[test]
     fn test_sbc_im() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.sbc_im(0x01);
         assert_eq!(cpu.register_a, BYTE_A);
@@ -3851,7 +4291,7 @@ mod tests {
     #[test]
     fn test_sbc_zp() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.memory.write_byte(0x10, 0x01);
         cpu.sbc_zp(0x10);
@@ -3861,7 +4301,7 @@ mod tests {
     #[test]
     fn test_sbc_zp_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.memory.write_byte(0x10, 0x01);
         cpu.register_x = 0x08;
@@ -3872,7 +4312,7 @@ mod tests {
     #[test]
     fn test_sbc_ab() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.memory.write_byte(0x1400, 0x01);
         cpu.sbc_ab(0x1400);
@@ -3882,7 +4322,7 @@ mod tests {
     #[test]
     fn test_sbc_ab_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.memory.write_byte(0x1410, 0x01);
         cpu.register_x = 0x10;
@@ -3893,7 +4333,7 @@ mod tests {
     #[test]
     fn test_sbc_ab_y() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.memory.write_byte(0x1410, 0x01);
         cpu.register_y = 0x10;
@@ -3904,7 +4344,7 @@ mod tests {
     #[test]
     fn test_sbc_in_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.memory.write_byte(0x1400, 0x01);
         cpu.memory.write_addr(0x10, 0x1400);
@@ -3916,7 +4356,7 @@ mod tests {
     #[test]
     fn test_sbc_in_y() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.memory.write_byte(0x1410, 0x01);
         cpu.memory.write_addr(0x10, 0x1400);
@@ -3928,41 +4368,41 @@ mod tests {
     #[test]
     fn test_sbc_zero() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x01;
         cpu.sbc_im(0x01);
         assert_eq!(cpu.register_a, 0x00);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
+        assert_eq!(cpu.status.zero(), true);
     }
 
     #[test]
     fn test_sbc_negative() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0xff;
         cpu.sbc_im(0x01);
         assert_eq!(cpu.register_a, 0xfe);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     #[test]
     fn test_sbc_carry() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x10;
         cpu.sbc_im(0x01);
         assert_eq!(cpu.register_a, 0x0f);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_sbc_sub_negatives_carry() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0xff;
         cpu.sbc_im(0xff);
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -3971,17 +4411,17 @@ mod tests {
         cpu.register_a = 0xff;
         cpu.sbc_im(0xff);
         assert_eq!(cpu.register_a, 0xff);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), false);
+        assert_eq!(cpu.status.carry(), false);
     }
 
     #[test]
     fn test_sbc_sub_positive_overflow() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x9C;
         cpu.sbc_im(0x64);
         assert_eq!(cpu.register_a, 0x38);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
@@ -3990,17 +4430,17 @@ mod tests {
         cpu.register_a = 0x9C;
         cpu.sbc_im(0x64);
         assert_eq!(cpu.register_a, 0x37);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
     fn test_sbc_sub_negative_overflow() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0x64;
         cpu.sbc_im(0x9C);
         assert_eq!(cpu.register_a, 0xc8);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
@@ -4009,7 +4449,7 @@ mod tests {
         cpu.register_a = 0x64;
         cpu.sbc_im(0x9C);
         assert_eq!(cpu.register_a, 0xc7);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
@@ -4018,7 +4458,7 @@ mod tests {
         cpu.register_a = 0x80;
         cpu.sbc_im(0x0f);
         assert_eq!(cpu.register_a, 0x70);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
@@ -4027,7 +4467,7 @@ mod tests {
         cpu.register_a = 0x80;
         cpu.sbc_im(0x00);
         assert_eq!(cpu.register_a, 0x7f);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
@@ -4036,9 +4476,9 @@ mod tests {
         cpu.register_a = 0x00;
         cpu.sbc_im(0x00);
         assert_eq!(cpu.register_a, 0xff);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.carry(), false);
+        assert_eq!(cpu.status.overflow(), false);
     }
 
     /* Bitwise */
@@ -4127,7 +4567,7 @@ mod tests {
         cpu.register_a = 0b0101_1010;
         cpu.eor_im(0b0101_1010);
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
+        assert_eq!(cpu.status.zero(), true);
     }
 
     #[test]
@@ -4136,7 +4576,7 @@ mod tests {
         cpu.register_a = 0b0101_1010;
         cpu.eor_im(0b1101_1010);
         assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     #[test]
@@ -4232,7 +4672,7 @@ mod tests {
         cpu.register_a = 0b0101_1010;
         cpu.and_im(0b1010_0101);
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
+        assert_eq!(cpu.status.zero(), true);
     }
 
     #[test]
@@ -4241,7 +4681,7 @@ mod tests {
         cpu.register_a = 0b1101_1010;
         cpu.and_im(0b1010_0101);
         assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     #[test]
@@ -4250,59 +4690,59 @@ mod tests {
         cpu.register_a = 0x9b;
         cpu.anc(0xf1);
         assert_eq!(cpu.register_a, 0x91);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.carry(), true);
+        assert_eq!(cpu.status.zero(), false);
     }
 
     #[test]
     fn test_arr() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b1110_0000;
         cpu.arr(0b1110_1010);
         assert_eq!(cpu.register_a, 0b1111_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.overflow(), false);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_arr_overflow() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b1011_0000;
         cpu.arr(0b1110_1010);
         assert_eq!(cpu.register_a, 0b1101_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Overflow), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.carry(), true);
+        assert_eq!(cpu.status.overflow(), true);
     }
 
     #[test]
     fn test_alr() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b1110_0001;
         cpu.alr(0b1110_1011);
         assert_eq!(cpu.register_a, 0b0111_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), false);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_lxa() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b1110_0001;
         cpu.lxa(0b1110_1011);
         assert_eq!(cpu.register_a, 0b1110_0001);
         assert_eq!(cpu.register_x, 0b1110_0001);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     #[test]
@@ -4315,8 +4755,8 @@ mod tests {
         assert_eq!(cpu.register_a, 0b1010_0010);
         assert_eq!(cpu.register_x, 0b1010_0010);
         assert_eq!(cpu.stack, 0b1010_0010);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     #[test]
@@ -4383,9 +4823,9 @@ mod tests {
         cpu.register_x = 0b1110_1101;
         cpu.sbx(0x04);
         assert_eq!(cpu.register_x, 0b1010_0001);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4395,8 +4835,8 @@ mod tests {
         cpu.register_x = BYTE_B;
         cpu.ane(0);
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.negative(), false);
     }
 
     #[test]
@@ -4406,8 +4846,8 @@ mod tests {
         cpu.register_x = BYTE_B;
         cpu.ane(BYTE_A);
         assert_eq!(cpu.register_a, BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
+        assert_eq!(cpu.status.zero(), false);
+        assert_eq!(cpu.status.negative(), false);
     }
 
     #[test]
@@ -4417,7 +4857,58 @@ mod tests {
         cpu.register_x = BYTE_B;
         cpu.ane(BYTE_A);
         assert_eq!(cpu.register_a == 0x11, false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
+        assert_eq!(cpu.status.negative(), false);
+    }
+
+    #[test]
+    fn test_ane_magic() {
+        let mut cpu = CPU::new();
+        cpu.set_ane_magic(0xFF);
+        for _ in 0..16 {
+            cpu.register_a = 0x11;
+            cpu.register_x = 0xF0;
+            cpu.ane(0xBF);
+            assert_eq!(cpu.register_a, 0xB0);
+            assert_eq!(cpu.status.negative(), true);
+        }
+
+        cpu.set_ane_magic(0xEE);
+        cpu.register_a = 0x01;
+        cpu.register_x = 0xFF;
+        cpu.ane(0xFF);
+        assert_eq!(cpu.register_a, 0xEF);
+    }
+
+    #[test]
+    fn test_ane_seeded() {
+        let run = |seed: u64| {
+            let mut cpu = CPU::new();
+            cpu.set_rng_seed(seed);
+            (0..16).map(|_| {
+                cpu.register_a = 0x00;
+                cpu.register_x = 0xFF;
+                cpu.ane(0xFF);
+                cpu.register_a
+            }).collect::<Vec<u8>>()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+        assert_eq!(run(0), run(CPU::DEFAULT_RNG_SEED));
+    }
+
+    #[test]
+    fn test_rng_restarts_on_power_on() {
+        let mut cpu = CPU::new();
+        cpu.set_rng_seed(7);
+        let first = cpu.next_random();
+        cpu.next_random();
+        let state = cpu.get_rng_state();
+        let third = cpu.next_random();
+
+        cpu.set_rng_state(state);
+        assert_eq!(cpu.next_random(), third);
+        cpu.power_on();
+        assert_eq!(cpu.next_random(), first);
     }
 
     #[test]
@@ -4503,7 +4994,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.ora_im(0);
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
+        assert_eq!(cpu.status.zero(), true);
     }
 
     #[test]
@@ -4512,7 +5003,7 @@ mod tests {
         cpu.register_a = 0b0101_1010;
         cpu.ora_im(0b1010_0101);
         assert_eq!(cpu.register_a, 0xff);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     /* Shift */
@@ -4523,49 +5014,49 @@ mod tests {
         cpu.register_a = 0b0000_1111;
         cpu.lsr_a();
         assert_eq!(cpu.register_a, 0b0000_0111);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
     
     #[test]
     fn test_lsr_zp() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x10, 0b0000_1111);
         cpu.lsr_zp(0x10);
         assert_eq!(cpu.memory.read_byte(0x10), 0b0000_0111);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_lsr_zp_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x20, 0b0000_1111);
         cpu.register_x = 0x10;
         cpu.lsr_zp_x(0x10);
         assert_eq!(cpu.memory.read_byte(0x20), 0b0000_0111);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_lsr_ab() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1400, 0b0000_1111);
         cpu.lsr_ab(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1400), 0b0000_0111);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_lsr_ab_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1410, 0b0000_1111);
         cpu.register_x = 0x10;
         cpu.lsr_ab_x(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1410), 0b0000_0111);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4574,8 +5065,8 @@ mod tests {
         cpu.register_a = 0x01;
         cpu.lsr_a();
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4584,8 +5075,8 @@ mod tests {
         cpu.register_a = 0xff;
         cpu.lsr_a();
         assert_eq!(cpu.register_a, 0x7F);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), false);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.negative(), false);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4596,8 +5087,8 @@ mod tests {
             cpu.lsr_a();
         }
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4683,49 +5174,49 @@ mod tests {
         cpu.register_a = 0b1111_0000;
         cpu.asl_a();
         assert_eq!(cpu.register_a, 0b1110_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
     
     #[test]
     fn test_asl_zp() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x10, 0b1111_0000);
         cpu.asl_zp(0x10);
         assert_eq!(cpu.memory.read_byte(0x10), 0b1110_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_asl_zp_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x20, 0b1111_0000);
         cpu.register_x = 0x10;
         cpu.asl_zp_x(0x10);
         assert_eq!(cpu.memory.read_byte(0x20), 0b1110_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_asl_ab() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1400, 0b1111_0000);
         cpu.asl_ab(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1400), 0b1110_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_asl_ab_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1410, 0b1111_0000);
         cpu.register_x = 0x10;
         cpu.asl_ab_x(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1410), 0b1110_0000);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4734,8 +5225,8 @@ mod tests {
         cpu.register_a = 0x80;
         cpu.asl_a();
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4744,8 +5235,8 @@ mod tests {
         cpu.register_a = 0x40;
         cpu.asl_a();
         assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.carry(), false);
     }
 
     #[test]
@@ -4756,8 +5247,8 @@ mod tests {
             cpu.asl_a();
         }
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4842,7 +5333,7 @@ mod tests {
     #[test]
     fn test_ror_a() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b0000_1111;
         cpu.ror_a();
         assert_eq!(cpu.register_a, 0b1000_0111);
@@ -4851,7 +5342,7 @@ mod tests {
     #[test]
     fn test_ror_zp() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x10, 0b0000_1111);
         cpu.ror_zp(0x10);
         assert_eq!(cpu.memory.read_byte(0x10), 0b1000_0111);
@@ -4860,7 +5351,7 @@ mod tests {
     #[test]
     fn test_ror_zp_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x20, 0b0000_1111);
         cpu.register_x = 0x10;
         cpu.ror_zp_x(0x10);
@@ -4870,7 +5361,7 @@ mod tests {
     #[test]
     fn test_ror_ab() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1400, 0b0000_1111);
         cpu.ror_ab(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1400), 0b1000_0111);
@@ -4879,7 +5370,7 @@ mod tests {
     #[test]
     fn test_ror_ab_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1410, 0b0000_1111);
         cpu.register_x = 0x10;
         cpu.ror_ab_x(0x1400);
@@ -4892,30 +5383,30 @@ mod tests {
         cpu.register_a = 1;
         cpu.ror_a();
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_ror_negative() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.ror_a();
         assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.carry(), false);
     }
 
     #[test]
     fn test_ror_wraparound() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b0000_1111;
         for _i in 0..9 {
             cpu.ror_a();
         }
         assert_eq!(cpu.register_a, 0b0000_1111);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -4998,7 +5489,7 @@ mod tests {
     #[test]
     fn test_rol_a() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b0000_1111;
         cpu.rol_a();
         assert_eq!(cpu.register_a, 0b0001_1111);
@@ -5007,7 +5498,7 @@ mod tests {
     #[test]
     fn test_rol_zp() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x10, 0b0000_1111);
         cpu.rol_zp(0x10);
         assert_eq!(cpu.memory.read_byte(0x10), 0b0001_1111);
@@ -5016,7 +5507,7 @@ mod tests {
     #[test]
     fn test_rol_zp_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x20, 0b0000_1111);
         cpu.register_x = 0x10;
         cpu.rol_zp_x(0x10);
@@ -5026,7 +5517,7 @@ mod tests {
     #[test]
     fn test_rol_ab() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1400, 0b0000_1111);
         cpu.rol_ab(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1400), 0b0001_1111);
@@ -5035,7 +5526,7 @@ mod tests {
     #[test]
     fn test_rol_ab_x() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.memory.write_byte(0x1410, 0b0000_1111);
         cpu.register_x = 0x10;
         cpu.rol_ab_x(0x1400);
@@ -5048,8 +5539,8 @@ mod tests {
         cpu.register_a = 0x80;
         cpu.rol_a();
         assert_eq!(cpu.register_a, 0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5058,20 +5549,20 @@ mod tests {
         cpu.register_a = 0x40;
         cpu.rol_a();
         assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), false);
+        assert_eq!(cpu.status.negative(), true);
+        assert_eq!(cpu.status.carry(), false);
     }
 
     #[test]
     fn test_rol_wraparound() {
         let mut cpu = CPU::new();
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = 0b0000_1111;
         for _i in 0..9 {
             cpu.rol_a();
         }
         assert_eq!(cpu.register_a, 0b0000_1111);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5223,6 +5714,41 @@ mod tests {
         assert_eq!(cpu.register_a, BYTE_A);
     }
 
+    #[test]
+    fn test_lda_in_y_cycles() {
+        // 2 to fetch the opcode and operand, 2 to read the pointer, 1 to read the value, and 1 more to
+        // fix up the high byte when adding Y crosses a page
+        let mut cpu = CPU::new();
+        cpu.memory.write_addr(0x10, 0x0040);
+        cpu.register_y = 0x10;
+        assert_eq!(cpu.lda_in_y(0x10), 5);
+
+        cpu.memory.write_addr(0x10, 0x00F8);
+        assert_eq!(cpu.lda_in_y(0x10), 6);
+    }
+
+    #[test]
+    fn test_lda_in_x_zero_page_wrap() {
+        let mut cpu = CPU::new();
+        cpu.memory.write_byte(0x1400, BYTE_A);
+        cpu.memory.write_byte(0x00FF, 0x00);
+        cpu.memory.write_byte(0x0000, 0x14);
+        cpu.register_x = 0x0F;
+        cpu.lda_in_x(0xF0);
+        assert_eq!(cpu.register_a, BYTE_A);
+    }
+
+    #[test]
+    fn test_lda_in_y_zero_page_wrap() {
+        let mut cpu = CPU::new();
+        cpu.memory.write_byte(0x1410, BYTE_A);
+        cpu.memory.write_byte(0x00FF, 0x00);
+        cpu.memory.write_byte(0x0000, 0x14);
+        cpu.register_y = 0x10;
+        cpu.lda_in_y(0xFF);
+        assert_eq!(cpu.register_a, BYTE_A);
+    }
+
     #[test]
     fn test_ldx_im() {
         let mut cpu = CPU::new();
@@ -5369,14 +5895,14 @@ mod tests {
     fn test_load_zero() {
         let mut cpu = CPU::new();
         cpu.lda_im(0);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true)
+        assert_eq!(cpu.status.zero(), true)
     }
 
     #[test]
     fn test_load_negative() {
         let mut cpu = CPU::new();
         cpu.lda_im(0xff);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true)
+        assert_eq!(cpu.status.negative(), true)
     }
 
     /* Store */
@@ -5606,7 +6132,7 @@ mod tests {
     fn test_transfer_zero() {
         let mut cpu = CPU::new();
         cpu.tax();
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true)
+        assert_eq!(cpu.status.zero(), true)
     }
 
     #[test]
@@ -5614,7 +6140,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_a = 0xff;
         cpu.tax();
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true)
+        assert_eq!(cpu.status.negative(), true)
     }
 
     /* Increment */
@@ -5675,68 +6201,68 @@ mod tests {
     fn test_isb_zp() {
         let mut cpu = CPU::new();
         cpu.memory.write_byte(0x10, BYTE_A);
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.isb_zp(0x10);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x10), BYTE_B);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_isb_zp_x() {
         let mut cpu = CPU::new();
         cpu.memory.write_byte(0x20, BYTE_A);
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.register_x = 0x10;
         cpu.isb_zp_x(0x10);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x20), BYTE_B);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_isb_ab() {
         let mut cpu = CPU::new();
         cpu.memory.write_byte(0x1400, BYTE_A);
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.isb_ab(0x1400);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x1400), BYTE_B);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_isb_ab_x() {
         let mut cpu = CPU::new();
         cpu.memory.write_byte(0x1410, BYTE_A);
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.register_x = 0x10;
         cpu.isb_ab_x(0x1400);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x1410), BYTE_B);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
     fn test_isb_ab_y() {
         let mut cpu = CPU::new();
         cpu.memory.write_byte(0x1410, BYTE_A);
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.register_y = 0x10;
         cpu.isb_ab_y(0x1400);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x1410), BYTE_B);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5744,14 +6270,14 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.memory.write_byte(0x1400, BYTE_A);
         cpu.memory.write_addr(0x10, 0x1400);
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.register_x = 0x08;
         cpu.isb_in_x(0x08);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x1400), BYTE_B);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5759,14 +6285,14 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.memory.write_byte(0x1410, BYTE_A);
         cpu.memory.write_addr(0x10, 0x1400);
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.register_a = BYTE_B;
         cpu.register_y = 0x10;
         cpu.isb_in_y(0x10);
         assert_eq!(cpu.register_a, 0);
         assert_eq!(cpu.memory.read_byte(0x1410), BYTE_B);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5774,7 +6300,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_x = 0xff;
         cpu.inx();
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true)
+        assert_eq!(cpu.status.zero(), true)
     }
 
     #[test]
@@ -5782,7 +6308,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_x = 0xfe;
         cpu.inx();
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true)
+        assert_eq!(cpu.status.negative(), true)
     }
 
     /* Decrement */
@@ -5848,8 +6374,8 @@ mod tests {
         cpu.register_a = BYTE_A;
         cpu.dcp_zp(0x10);
         assert_eq!(cpu.memory.read_byte(0x10), BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5860,8 +6386,8 @@ mod tests {
         cpu.register_x = 0x10;
         cpu.dcp_zp_x(0x10);
         assert_eq!(cpu.memory.read_byte(0x20), BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5871,8 +6397,8 @@ mod tests {
         cpu.register_a = BYTE_A;
         cpu.dcp_ab(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1400), BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5883,8 +6409,8 @@ mod tests {
         cpu.register_x = 0x10;
         cpu.dcp_ab_x(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1410), BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5895,8 +6421,8 @@ mod tests {
         cpu.register_y = 0x10;
         cpu.dcp_ab_y(0x1400);
         assert_eq!(cpu.memory.read_byte(0x1410), BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5908,8 +6434,8 @@ mod tests {
         cpu.register_x = 0x08;
         cpu.dcp_in_x(0x08);
         assert_eq!(cpu.memory.read_byte(0x1400), BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5921,8 +6447,8 @@ mod tests {
         cpu.register_y = 0x10;
         cpu.dcp_in_y(0x10);
         assert_eq!(cpu.memory.read_byte(0x1410), BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5930,7 +6456,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_x = 1;
         cpu.dex();
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true)
+        assert_eq!(cpu.status.zero(), true)
     }
 
     #[test]
@@ -5938,7 +6464,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_x = 0xff;
         cpu.dex();
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true)
+        assert_eq!(cpu.status.negative(), true)
     }
 
     /* Compare */
@@ -5948,8 +6474,8 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_a = BYTE_A;
         cpu.cmp_im(BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5958,8 +6484,8 @@ mod tests {
         cpu.memory.write_byte(0x10, BYTE_A);
         cpu.register_a = BYTE_A;
         cpu.cmp_zp(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5969,8 +6495,8 @@ mod tests {
         cpu.register_a = BYTE_A;
         cpu.register_x = 0x08;
         cpu.cmp_zp_x(0x08);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5979,8 +6505,8 @@ mod tests {
         cpu.memory.write_byte(0x1400, BYTE_A);
         cpu.register_a = BYTE_A;
         cpu.cmp_ab(0x1400);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -5990,8 +6516,8 @@ mod tests {
         cpu.register_a = BYTE_A;
         cpu.register_x = 0x10;
         cpu.cmp_ab_x(0x1400);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6001,8 +6527,8 @@ mod tests {
         cpu.register_a = BYTE_A;
         cpu.register_y = 0x10;
         cpu.cmp_ab_y(0x1400);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6013,8 +6539,8 @@ mod tests {
         cpu.register_a = BYTE_A;
         cpu.register_x = 0x08;
         cpu.cmp_in_x(0x08);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6025,8 +6551,8 @@ mod tests {
         cpu.register_a = BYTE_A;
         cpu.register_y = 0x10;
         cpu.cmp_in_y(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6034,8 +6560,8 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_x = BYTE_A;
         cpu.cpx_im(BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6044,8 +6570,8 @@ mod tests {
         cpu.memory.write_byte(0x10, BYTE_A);
         cpu.register_x = BYTE_A;
         cpu.cpx_zp(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6054,8 +6580,8 @@ mod tests {
         cpu.memory.write_byte(0x1400, BYTE_A);
         cpu.register_x = BYTE_A;
         cpu.cpx_ab(0x1400);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6063,8 +6589,8 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_y = BYTE_A;
         cpu.cpy_im(BYTE_A);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6073,8 +6599,8 @@ mod tests {
         cpu.memory.write_byte(0x10, BYTE_A);
         cpu.register_y = BYTE_A;
         cpu.cpy_zp(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6083,8 +6609,8 @@ mod tests {
         cpu.memory.write_byte(0x1400, BYTE_A);
         cpu.register_y = BYTE_A;
         cpu.cpy_ab(0x1400);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6092,8 +6618,8 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_a = 0x20;
         cpu.cmp_im(0x20);
-        assert_eq!(cpu.status.is_set(StatusFlag::Zero), true);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.zero(), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6101,7 +6627,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_a = 0x20;
         cpu.cmp_im(0x10);
-        assert_eq!(cpu.status.is_set(StatusFlag::Carry), true);
+        assert_eq!(cpu.status.carry(), true);
     }
 
     #[test]
@@ -6109,7 +6635,7 @@ mod tests {
         let mut cpu = CPU::new();
         cpu.register_a = 0x20;
         cpu.cmp_im(0x30);
-        assert_eq!(cpu.status.is_set(StatusFlag::Negative), true);
+        assert_eq!(cpu.status.negative(), true);
     }
 
     /* Jump & Branch */
@@ -6129,6 +6655,17 @@ mod tests {
         assert_eq!(cpu.program_counter, 0x2000);
     }
 
+    #[test]
+    fn test_step_jmp_in_page_wrap() {
+        let mut cpu = CPU::new();
+        cpu.memory.write_bulk(0x0000, &[CPU::JMP_IN, 0xFF, 0x01]);
+        cpu.memory.write_byte(0x01FF, 0x00);
+        cpu.memory.write_byte(0x0100, 0x14);
+        cpu.memory.write_byte(0x0200, 0x15);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x1400);
+    }
+
     #[test]
     fn test_jsr() {
         let mut cpu = CPU::new();
@@ -6169,7 +6706,7 @@ mod tests {
     fn test_beq() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.set(StatusFlag::Zero);
+        cpu.status.set_zero(true);
         cpu.beq(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6178,7 +6715,7 @@ mod tests {
     fn test_bne() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.clear(StatusFlag::Zero);
+        cpu.status.set_zero(false);
         cpu.bne(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6187,7 +6724,7 @@ mod tests {
     fn test_bcs() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.set(StatusFlag::Carry);
+        cpu.status.set_carry(true);
         cpu.bcs(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6196,7 +6733,7 @@ mod tests {
     fn test_bcc() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.clear(StatusFlag::Carry);
+        cpu.status.set_carry(false);
         cpu.bcc(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6205,7 +6742,7 @@ mod tests {
     fn test_bmi() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.set(StatusFlag::Negative);
+        cpu.status.set_negative(true);
         cpu.bmi(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6214,7 +6751,7 @@ mod tests {
     fn test_bpl() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.clear(StatusFlag::Negative);
+        cpu.status.set_negative(false);
         cpu.bpl(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6223,7 +6760,7 @@ mod tests {
     fn test_bvs() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.set(StatusFlag::Overflow);
+        cpu.status.set_overflow(true);
         cpu.bvs(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6232,7 +6769,7 @@ mod tests {
     fn test_bvc() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.clear(StatusFlag::Overflow);
+        cpu.status.set_overflow(false);
         cpu.bvc(0x10);
         assert_eq!(cpu.program_counter, 0x90 + 1);
     }
@@ -6241,7 +6778,7 @@ mod tests {
     fn test_branch_zero_offset() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.set(StatusFlag::Zero);
+        cpu.status.set_zero(true);
         cpu.beq(0);
         assert_eq!(cpu.program_counter, 0x80 + 1);
     }
@@ -6250,7 +6787,7 @@ mod tests {
     fn test_branch_negative_offset() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0x80;
-        cpu.status.set(StatusFlag::Zero);
+        cpu.status.set_zero(true);
         cpu.beq(-0x10);
         assert_eq!(cpu.program_counter, 0x70 + 1);
     }
@@ -6261,7 +6798,7 @@ mod tests {
     fn test_branch_cycles_when_no_branch() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0xc0;
-        cpu.status.clear(StatusFlag::Zero);
+        cpu.status.set_zero(false);
         let cycles = cpu.beq(0x10);
         assert_eq!(cycles, 2);
     }
@@ -6270,7 +6807,7 @@ mod tests {
     fn test_branch_cycles_when_branch() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0xc0;
-        cpu.status.set(StatusFlag::Zero);
+        cpu.status.set_zero(true);
         let cycles = cpu.beq(0x10);
         assert_eq!(cycles, 3);
     }
@@ -6279,7 +6816,7 @@ mod tests {
     fn test_branch_cycles_when_branch_page_cross() {
         let mut cpu = CPU::new();
         cpu.program_counter = 0xc0;
-        cpu.status.set(StatusFlag::Zero);
+        cpu.status.set_zero(true);
         assert_eq!(cpu.beq(0x70), 4);
     }
 
@@ -0,0 +1,237 @@
+// Ref: https://www.nesdev.org/wiki/CPU_memory_map
+
+// Memory editor layout:
+// =====================================
+// $0000: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00    <- 16 bytes a row, 32 rows
+// $0010: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+// ...
+//
+// > $80__                                                   <- jump prompt, while typing an address
+//
+// Only the parts of the address space worth looking at are listed, without their mirrors: internal
+// RAM ($0000-$07FF), the PPU registers ($2000-$2007), the APU and I/O registers ($4000-$401F) and
+// cartridge PRG ($8000-$FFFF). Bytes are read with peek_byte, so the view never disturbs the
+// registers, and the ones written since the last refresh are highlighted.
+
+use crate::nes::cpu::mem::Memory;
+use crate::nes::io::frame::Frame;
+use crate::util::font::{draw_text, CELL_HEIGHT, CELL_WIDTH};
+
+pub const MEMORY_EDITOR_REGIONS: [(u16, usize); 4] = [(0x0000, 0x0800), (0x2000, 0x0008), (0x4000, 0x0020), (0x8000, 0x8000)];
+pub const MEMORY_EDITOR_WIDTH: usize = 2 * MARGIN + CELL_WIDTH * ROW_CHARS;
+pub const MEMORY_EDITOR_HEIGHT: usize = 2 * MARGIN + CELL_HEIGHT * (VISIBLE_ROWS + 2);
+
+pub const ADDRESS_COLOR: (u8, u8, u8) = (0x80, 0x80, 0x80);
+pub const BYTE_COLOR: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+pub const WRITTEN_COLOR: (u8, u8, u8) = (0xFF, 0xC0, 0x00);
+
+const BYTES_PER_ROW: usize = 16;
+const VISIBLE_ROWS: usize = 32;
+const ROW_CHARS: usize = 7 + 3 * BYTES_PER_ROW - 1; // "$XXXX: " and the bytes
+const MARGIN: usize = 4;
+const REFRESH_FRAMES: u32 = 60;
+
+pub struct MemoryEditor {
+    rows: Vec<(u16, usize)>, // start address and length of every row
+    top_row: usize,
+    written: Vec<u16>,
+    jump_input: Option<String>,
+    refresh_timer: u32,
+    dirty: bool,
+}
+
+impl MemoryEditor {
+    pub fn new() -> Self {
+        let mut rows = Vec::new();
+        for (start, len) in MEMORY_EDITOR_REGIONS {
+            for offset in (0..len).step_by(BYTES_PER_ROW) {
+                rows.push((start + offset as u16, usize::min(BYTES_PER_ROW, len - offset)));
+            }
+        }
+        MemoryEditor {
+            rows,
+            top_row: 0,
+            written: Vec::new(),
+            jump_input: None,
+            refresh_timer: REFRESH_FRAMES,
+            dirty: true,
+        }
+    }
+
+    pub fn get_top_address(&self) -> u16 {
+        self.rows[self.top_row].0
+    }
+
+    pub fn get_written(&self) -> &Vec<u16> {
+        &self.written
+    }
+
+    // Called once a frame. Every 60 frames the write highlights are refreshed, and whenever the view
+    // needs redrawing (a refresh, or the user moved around) this returns true.
+    pub fn tick(&mut self, memory: &mut Memory) -> bool {
+        self.refresh_timer += 1;
+        if self.refresh_timer >= REFRESH_FRAMES {
+            self.refresh_timer = 0;
+            self.written = MEMORY_EDITOR_REGIONS.iter()
+                .flat_map(|(start, len)| memory.watch_writes(*start, *len))
+                .collect();
+            self.dirty = true;
+        }
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_to(self.top_row.saturating_sub(VISIBLE_ROWS));
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_to(self.top_row + VISIBLE_ROWS);
+    }
+
+    pub fn home(&mut self) {
+        self.scroll_to(0);
+    }
+
+    pub fn end(&mut self) {
+        self.scroll_to(self.rows.len());
+    }
+
+    // Scrolls to the row holding the address, or the next listed row if the address is in one of
+    // the unlisted areas (ie. $6000 goes to $8000).
+    pub fn jump_to(&mut self, address: u16) {
+        let row = self.rows.iter()
+            .position(|(start, len)| *start as usize + len > address as usize)
+            .unwrap_or(self.rows.len());
+        self.scroll_to(row);
+    }
+
+    fn scroll_to(&mut self, row: usize) {
+        self.top_row = usize::min(row, self.rows.len() - VISIBLE_ROWS);
+        self.dirty = true;
+    }
+
+    // The "G" dialog: hex digits are typed in one at a time, up to 4 of them.
+
+    pub fn is_jumping(&self) -> bool {
+        self.jump_input.is_some()
+    }
+
+    pub fn begin_jump(&mut self) {
+        self.jump_input = Some(String::new());
+        self.dirty = true;
+    }
+
+    pub fn input_digit(&mut self, digit: char) {
+        if let Some(input) = &mut self.jump_input {
+            if digit.is_ascii_hexdigit() && input.len() < 4 {
+                input.push(digit.to_ascii_uppercase());
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub fn delete_digit(&mut self) {
+        if let Some(input) = &mut self.jump_input {
+            input.pop();
+            self.dirty = true;
+        }
+    }
+
+    pub fn confirm_jump(&mut self) {
+        if let Some(input) = self.jump_input.take() {
+            if let Ok(address) = u16::from_str_radix(&input, 16) {
+                self.jump_to(address);
+            }
+            self.dirty = true;
+        }
+    }
+
+    pub fn cancel_jump(&mut self) {
+        self.jump_input = None;
+        self.dirty = true;
+    }
+
+    pub fn render(&self, memory: &Memory, frame: &mut Frame) {
+        frame.clear();
+        for (i, (start, len)) in self.rows[self.top_row..self.top_row + VISIBLE_ROWS].iter().enumerate() {
+            let y = MARGIN + CELL_HEIGHT * i;
+            draw_text(frame, MARGIN, y, &format!("${:04X}:", start), ADDRESS_COLOR);
+            for (col, byte) in memory.dump(*start, *len).iter().enumerate() {
+                let address = *start + col as u16;
+                let color = if self.written.contains(&address) { WRITTEN_COLOR } else { BYTE_COLOR };
+                draw_text(frame, MARGIN + CELL_WIDTH * (7 + 3 * col), y, &format!("{:02X}", byte), color);
+            }
+        }
+
+        if let Some(input) = &self.jump_input {
+            let y = MARGIN + CELL_HEIGHT * (VISIBLE_ROWS + 1);
+            draw_text(frame, MARGIN, y, &format!("> ${:_<4}", input), BYTE_COLOR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows() {
+        let mut editor = MemoryEditor::new();
+        assert_eq!(editor.rows.len(), 0x80 + 1 + 2 + 0x800);
+        assert_eq!(editor.rows[0x80], (0x2000, 8));
+
+        editor.page_down();
+        assert_eq!(editor.get_top_address(), 0x0200);
+        editor.page_up();
+        editor.page_up();
+        assert_eq!(editor.get_top_address(), 0x0000);
+        editor.end();
+        assert_eq!(editor.get_top_address(), 0xFE00);
+        editor.home();
+        assert_eq!(editor.get_top_address(), 0x0000);
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut editor = MemoryEditor::new();
+        editor.begin_jump();
+        for digit in "4g01x5".chars() {
+            editor.input_digit(digit);
+        }
+        assert_eq!(editor.jump_input.as_deref(), Some("4015"));
+        editor.confirm_jump();
+        assert!(!editor.is_jumping());
+        assert_eq!(editor.get_top_address(), 0x4010);
+
+        editor.jump_to(0x6000);
+        assert_eq!(editor.get_top_address(), 0x8000);
+        editor.jump_to(0xFFFC);
+        assert_eq!(editor.get_top_address(), 0xFE00);
+
+        editor.begin_jump();
+        editor.input_digit('1');
+        editor.delete_digit();
+        editor.confirm_jump();
+        assert_eq!(editor.get_top_address(), 0xFE00);
+    }
+
+    #[test]
+    fn test_write_highlights() {
+        let mut memory = Memory::new();
+        let mut editor = MemoryEditor::new();
+        let mut frame = Frame::with_size(MEMORY_EDITOR_WIDTH, MEMORY_EDITOR_HEIGHT);
+        assert!(editor.tick(&mut memory));
+        memory.write_byte(0x0001, 0xFF);
+        memory.write_byte(0x4015, 0x0F);
+
+        assert!((1..REFRESH_FRAMES).all(|_| !editor.tick(&mut memory)));
+        assert!(editor.tick(&mut memory));
+        assert_eq!(editor.get_written(), &vec![0x0001, 0x4015]);
+
+        // the second byte of the first row, drawn as "FF" in the highlight color
+        editor.render(&memory, &mut frame);
+        let x = MARGIN + CELL_WIDTH * 10;
+        assert_eq!(frame.get_background_color(x, MARGIN), WRITTEN_COLOR);
+        assert_eq!(frame.get_background_color(x - 3 * CELL_WIDTH, MARGIN + 1), BYTE_COLOR);
+    }
+}
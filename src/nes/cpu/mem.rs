@@ -1,11 +1,11 @@
 use std::fs;
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io;
+use std::ops::Range;
 use std::path::Path;
 use crate::nes::apu::APU;
 use crate::nes::io::joycon::Joycon;
 use crate::nes::ppu::PPU;
-use crate::nes::rom::ROM;
+use crate::nes::rom::{Mirroring, ROM};
 
 // CPU memory map
 #[macro_export] macro_rules! ram_range { () => {0x0000..=0x1FFF} }
@@ -15,14 +15,49 @@ use crate::nes::rom::ROM;
 #[macro_export] macro_rules! prg_ram_range { () => {0x6000..=0x7FFF} }
 #[macro_export] macro_rules! prg_rom_range { () => {0x8000..=0xFFFF} }
 
+// The regions of the CPU address space, by who answers on the bus. The handlers for each region are
+// Memory::read_* and Memory::write_*.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemoryRegion {
+    Ram,
+    PpuRegisters,
+    ApuIoRegisters,
+    Expansion,
+    PrgRam,
+    PrgRom,
+}
+
+impl MemoryRegion {
+    #[inline]
+    pub fn of(address: u16) -> MemoryRegion {
+        match address {
+            ram_range!() => MemoryRegion::Ram,
+            ppu_registers_range!() => MemoryRegion::PpuRegisters,
+            apu_io_registers_range!() => MemoryRegion::ApuIoRegisters,
+            custom_ram_range!() => MemoryRegion::Expansion,
+            prg_ram_range!() => MemoryRegion::PrgRam,
+            prg_rom_range!() => MemoryRegion::PrgRom,
+        }
+    }
+}
+
 pub struct Memory {
     pub memory: [u8; Memory::MEM_SIZE],
     pub ppu: PPU,
     pub apu: APU,
-    pub rom: ROM, // todo: should this be Option<ROM>?
-    pub save_ram: Option<File>,
+    pub prg_ram_dirty: bool,
     pub joycon1: Joycon,
     pub joycon2: Joycon,
+    pub oam_dma_pending: Option<u8>,
+    pub last_read_address: u16,
+    pub data_bus: u8,
+    pub dmc_dma_glitch: bool,
+    pub access_cycle: u8, // cycles of the current instruction that come before its last bus access
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+    prg_ram_banks: Vec<u8>, // all of the cartridge's PRG RAM, $6000-$7FFF holds the mapped bank
+    prg_ram_bank: usize,
+    write_watch: Option<Box<[u64; Memory::MEM_SIZE / 64]>>, // one bit per address, set on CPU writes
 }
 
 impl Memory {
@@ -73,51 +108,159 @@ impl Memory {
             memory: [0; Memory::MEM_SIZE],
             ppu: PPU::new(),
             apu: APU::new(),
-            rom: ROM::new(),
-            save_ram: None,
+            prg_ram_dirty: false,
             joycon1: Joycon::new(),
             joycon2: Joycon::new(),
+            oam_dma_pending: None,
+            last_read_address: 0,
+            data_bus: 0,
+            dmc_dma_glitch: true,
+            access_cycle: 0,
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+            prg_ram_banks: vec![0; ROM::PRG_RAM_PAGE_SIZE],
+            prg_ram_bank: 0,
+            write_watch: None,
+        }
+    }
+
+    // Puts the console back in its power-on state, as if the cartridge was swapped with the power
+    // off. Host-side settings (the audio device, channel mutes, master palette) are kept.
+    pub fn power_on(&mut self) {
+        let mut memory = Memory::new();
+        #[cfg(feature = "sdl")]
+        {
+            memory.apu.audio_player = self.apu.audio_player.take();
         }
+        memory.apu.wav_writer = self.apu.wav_writer.take();
+        memory.apu.sample_capture = self.apu.sample_capture.take();
+        memory.apu.sample_capture_size = self.apu.sample_capture_size;
+        std::mem::swap(&mut memory.apu.mixer, &mut self.apu.mixer);
+        std::mem::swap(&mut memory.apu.filters, &mut self.apu.filters);
+        std::mem::swap(&mut memory.apu.resampler, &mut self.apu.resampler);
+        std::mem::swap(&mut memory.apu.rate_control, &mut self.apu.rate_control);
+        memory.ppu.emphasis_palettes = self.ppu.emphasis_palettes;
+        memory.ppu.emphasis_attenuation = self.ppu.emphasis_attenuation;
+        let region = self.ppu.get_region();
+        memory.ppu.set_region(region);
+        memory.apu.set_region(region);
+        memory.dmc_dma_glitch = self.dmc_dma_glitch;
+        *self = memory;
     }
 
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+        self.oam_dma_pending = None;
+    }
+
+    // The cartridge sits on both buses, but there's only one of it: it's kept by the PPU's memory,
+    // which fetches from it every few dots, and reached from here for everything on the CPU side.
     pub fn load_rom(&mut self, rom: &ROM) {
-        self.rom = rom.clone();
         self.ppu.memory.load_rom(rom);
-        if rom.has_save_ram {
-            self.init_save_ram();
-        }
-    }
-
-    fn init_save_ram(&mut self) {
-        let save_path = format!("Saves/{}", self.rom.game_title);
-        fs::create_dir_all(&save_path).unwrap();
-        let save_path = format!("{}/battery.sav", save_path);
-        if Path::new(save_path.as_str()).exists() {
-            let mut save_file = fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(save_path)
-                .unwrap();
-            save_file.read(&mut self.memory[prg_ram_range!()]).expect("unable to load save file");
-            self.save_ram = Some(save_file);
-        } else {
-            let mut save_file = File::create(save_path).expect("unable to create save file");
-            save_file.write(vec![0; 0x2000].as_slice()).expect("unable to init save file");
-            self.save_ram = Some(save_file);
+        let prg_ram_size = (rom.get_prg_ram_size() + rom.get_prg_nvram_size()).next_multiple_of(ROM::PRG_RAM_PAGE_SIZE);
+        self.prg_ram_banks = vec![0; usize::max(prg_ram_size, ROM::PRG_RAM_PAGE_SIZE)];
+        self.prg_ram_bank = 0;
+        self.sync_prg_ram_control();
+        if let Some(trainer) = &rom.trainer {
+            let trainer_start = ROM::TRAINER_START as usize;
+            self.memory[trainer_start..trainer_start + trainer.len()].copy_from_slice(trainer);
         }
     }
 
+    // Battery-backed PRG RAM is kept in memory and marked dirty on writes, so the emulator can flush
+    // it to disk every so often instead of on every write.
+    pub fn load_battery_ram(&mut self, path: &Path) -> io::Result<()> {
+        let save_data = fs::read(path)?;
+        let mut prg_ram = self.get_prg_ram();
+        let battery_ram = &mut prg_ram[self.battery_ram_range()];
+        let len = usize::min(save_data.len(), battery_ram.len());
+        battery_ram[..len].copy_from_slice(&save_data[..len]);
+        self.load_prg_ram(&prg_ram);
+        self.prg_ram_dirty = false;
+        Ok(())
+    }
+
+    pub fn save_battery_ram(&mut self, path: &Path) -> io::Result<()> {
+        fs::write(path, &self.get_prg_ram()[self.battery_ram_range()])?;
+        self.prg_ram_dirty = false;
+        Ok(())
+    }
+
+    // NES 2.0 headers can give a cartridge both plain and battery-backed PRG RAM (ie. SOROM's 8 KB
+    // of each), in which case the plain RAM comes first and only the rest is saved.
+    fn battery_ram_range(&self) -> Range<usize> {
+        let prg_ram_size = self.prg_ram_banks.len();
+        if self.rom().get_prg_nvram_size() == 0 {
+            return 0..prg_ram_size;
+        }
+        let start = usize::min(self.rom().get_prg_ram_size(), prg_ram_size);
+        start..usize::min(start + self.rom().get_prg_nvram_size(), prg_ram_size)
+    }
+
+    // All of the PRG RAM, not just the bank mapped at $6000.
+    pub fn get_prg_ram(&self) -> Vec<u8> {
+        let mut prg_ram = self.prg_ram_banks.clone();
+        let bank_start = self.prg_ram_bank * ROM::PRG_RAM_PAGE_SIZE;
+        prg_ram[bank_start..bank_start + ROM::PRG_RAM_PAGE_SIZE].copy_from_slice(&self.memory[prg_ram_range!()]);
+        prg_ram
+    }
+
+    pub fn load_prg_ram(&mut self, prg_ram: &[u8]) {
+        let len = usize::min(prg_ram.len(), self.prg_ram_banks.len());
+        self.prg_ram_banks[..len].copy_from_slice(&prg_ram[..len]);
+        let bank_start = self.prg_ram_bank * ROM::PRG_RAM_PAGE_SIZE;
+        self.memory[prg_ram_range!()].copy_from_slice(&self.prg_ram_banks[bank_start..bank_start + ROM::PRG_RAM_PAGE_SIZE]);
+    }
+
+    // Loads a bare program by wrapping it in a 32kB NROM cartridge whose reset vector points at it.
+    // Programs below PRG ROM space are copied into RAM instead.
     pub fn load_at_addr(&mut self, address: u16, program: &Vec<u8>) {
-        for i in 0..program.len() {
-            self.memory[address.wrapping_add(i as u16) as usize] = program[i];
+        let mut prg_rom = vec![0; 2 * ROM::PRG_ROM_PAGE_SIZE];
+        if address >= Memory::PRG_ROM_START {
+            let start = (address - Memory::PRG_ROM_START) as usize;
+            let len = usize::min(program.len(), prg_rom.len() - start);
+            prg_rom[start..start + len].copy_from_slice(&program[..len]);
+        } else {
+            let start = address as usize;
+            let len = usize::min(program.len(), Memory::MEM_SIZE - start);
+            self.memory[start..start + len].copy_from_slice(&program[..len]);
         }
-        let addr_bytes = &u16::to_le_bytes(address);
-        self.memory[Memory::RESET_INT_VECTOR as usize] = addr_bytes[0];
-        self.memory[Memory::RESET_INT_VECTOR.wrapping_add(1) as usize] = addr_bytes[1];
+        let reset_vector = (Memory::RESET_INT_VECTOR - Memory::PRG_ROM_START) as usize;
+        prg_rom[reset_vector..reset_vector + 2].copy_from_slice(&address.to_le_bytes());
+
+        self.load_rom(&ROM::from_raw_prg(&prg_rom, 0, Mirroring::Horizontal).unwrap());
     }
 
-    #[inline]
-    pub fn read_byte(&mut self, address: u16) -> u8 {
+    // Snapshot of the CPU address space, read through the memory map with peek_byte so there are no
+    // side effects. Unlike peek_slice, it stops at $FFFF instead of wrapping around.
+    pub fn dump(&self, start: u16, len: usize) -> Vec<u8> {
+        let end = usize::min(start as usize + len, Memory::MEM_SIZE);
+        (start as usize..end).map(|address| self.peek_byte(address as u16)).collect()
+    }
+
+    // Addresses in the range the CPU has written to since the last call, in ascending order. Writes
+    // are only tracked once this has been called, so the first call always comes back empty.
+    pub fn watch_writes(&mut self, start: u16, len: usize) -> Vec<u16> {
+        let Some(write_watch) = &mut self.write_watch else {
+            self.write_watch = Some(Box::new([0; Memory::MEM_SIZE / 64]));
+            return Vec::new();
+        };
+        let end = usize::min(start as usize + len, Memory::MEM_SIZE);
+        let mut written = Vec::new();
+        for address in start as usize..end {
+            let (word, bit) = (address / 64, 1 << (address % 64));
+            if write_watch[word] & bit != 0 {
+                write_watch[word] &= !bit;
+                written.push(address as u16);
+            }
+        }
+        written
+    }
+
+    // Reads through the same memory map as the CPU, but without any side effects: registers aren't
+    // acknowledged, buffers and shift registers don't advance and the data bus is left alone.
+    pub fn peek_byte(&self, address: u16) -> u8 {
         match address {
             ram_range!() => {
                 let mirror_addr = address & 0b0000_0111_1111_1111;
@@ -126,33 +269,16 @@ impl Memory {
             ppu_registers_range!() => {
                 let mirror_addr = address & 0b0010_0000_0000_0111;
                 match mirror_addr {
-                    Memory::PPU_CTRL_REGISTER | Memory::PPU_MASK_REGISTER |
-                    Memory::PPU_OAM_ADDR_REGISTER | Memory::PPU_SCROLL_REGISTER |
-                    Memory::PPU_ADDR_REGISTER => {
-                        return 0 // todo: simulate ppu open bus here
-                    },
-                    Memory::PPU_STAT_REGISTER => {
-                        self.ppu.read_status_register()
-                    },
-                    Memory::PPU_DATA_REGISTER => {
-                        self.ppu.read_data_register()
-                    },
-                    Memory::PPU_OAM_DATA_REGISTER => {
-                        self.ppu.read_oam_data_register()
-                    },
-                    _ => {
-                        panic!("Attempt to read from write-only PPU address memory: 0x{:0>4X}", mirror_addr);
-                    }
+                    Memory::PPU_STAT_REGISTER => self.ppu.peek_status_register(),
+                    Memory::PPU_DATA_REGISTER => self.ppu.peek_data_register(),
+                    Memory::PPU_OAM_DATA_REGISTER => self.ppu.peek_oam_data_register(),
+                    _ => self.ppu.read_bus_latch(),
                 }
             },
             apu_io_registers_range!() => {
                 match address {
-                    Memory::JOYCON_ONE_REGISTER => {
-                        self.joycon1.read()
-                    },
-                    Memory::JOYCON_TWO_REGISTER => {
-                        self.joycon2.read()
-                    },
+                    Memory::JOYCON_ONE_REGISTER => self.joycon1.peek(),
+                    Memory::JOYCON_TWO_REGISTER => self.joycon2.peek(),
                     Memory::APU_PULSE_ONE_REGISTER_A..=Memory::APU_PULSE_ONE_REGISTER_D => {
                         self.apu.pulse_one.read(address as u8 % 4)
                     },
@@ -168,137 +294,375 @@ impl Memory {
                     Memory::APU_DMC_REGISTER_A..=Memory::APU_DMC_REGISTER_D => {
                         self.apu.dmc.read(address as u8 % 4)
                     },
-                    Memory::APU_STATUS_REGISTER => {
-                        self.apu.read_status_register()
-                    },
-                    _ => {
-                        panic!("Attempt to read from unmapped APU/IO address memory: 0x{:0>4X}", address);
-                    }
+                    Memory::APU_STATUS_REGISTER => self.apu.peek_status_register(),
+                    _ => self.data_bus,
                 }
             },
             custom_ram_range!() => {
-                println!("[WARNING] Read from custom ram range: 0x{:0>4X}", address);
                 self.memory[address as usize]
             },
             prg_ram_range!() => {
-                self.memory[address as usize]
+                if self.prg_ram_enabled { self.memory[address as usize] } else { self.data_bus }
             },
             prg_rom_range!() => {
-                self.rom.read_prg_byte(address)
+                self.rom().read_prg_byte(address)
             }
         }
     }
 
-    #[inline]
-    pub fn write_byte(&mut self, address: u16, data: u8) {
+    // Writes through the same memory map as the CPU, but without any side effects: registers are set
+    // directly (ie. no NMI on a PPUCTRL poke, no length counter reload on an APU poke), PRG RAM write
+    // protection and the save file are bypassed, and writes to PRG ROM are dropped instead of being
+    // interpreted as mapper commands.
+    pub fn poke_byte(&mut self, address: u16, data: u8) {
         match address {
             ram_range!() => {
                 let mirror_addr = address & 0b0000_0111_1111_1111;
                 self.memory[mirror_addr as usize] = data;
-            }
+            },
             ppu_registers_range!() => {
                 let mirror_addr = address & 0b0010_0000_0000_0111;
                 match mirror_addr {
-                    Memory::PPU_CTRL_REGISTER => {
-                        self.ppu.write_ctrl_register(data);
-                    },
-                    Memory::PPU_MASK_REGISTER => {
-                        self.ppu.write_mask_register(data);
-                    },
-                    Memory::PPU_ADDR_REGISTER => {
-                        self.ppu.write_addr_register(data);
-                    },
-                    Memory::PPU_DATA_REGISTER => {
-                        self.ppu.write_data_register(data);
-                    },
-                    Memory::PPU_OAM_ADDR_REGISTER => {
-                        self.ppu.write_oam_addr_register(data);
-                    },
-                    Memory::PPU_OAM_DATA_REGISTER => {
-                        self.ppu.write_oam_data_register(data);
-                    },
-                    Memory::PPU_SCROLL_REGISTER => {
-                        self.ppu.write_scroll_register(data);
-                    },
-                    _ => {
-                        println!("[WARNING] Attempt to write to read-only PPU register: 0x{:0>4X}", mirror_addr);
-                    }
+                    Memory::PPU_CTRL_REGISTER => self.ppu.ctrl.set_value(data),
+                    Memory::PPU_MASK_REGISTER => self.ppu.mask.set_value(data),
+                    Memory::PPU_STAT_REGISTER => self.ppu.status.set_value(data),
+                    Memory::PPU_OAM_ADDR_REGISTER => self.ppu.oam_addr = data,
+                    Memory::PPU_OAM_DATA_REGISTER => self.ppu.oam.write_byte(self.ppu.oam_addr, data),
+                    Memory::PPU_DATA_REGISTER => self.ppu.memory.write_byte(self.ppu.get_vram_addr(), data),
+                    _ => {}, // PPUSCROLL and PPUADDR are two-write registers, with no single byte to set
                 }
-            }
+            },
             apu_io_registers_range!() => {
                 match address {
-                    Memory::PPU_OAM_DMA_REGISTER => {
-                        let read_addr = (data as u16) << 8;
-                        let write_addr = self.ppu.oam_addr;
-                        for i in 0..256 {
-                            let value = self.read_byte(read_addr.wrapping_add(i));
-                            self.ppu.oam.write_byte(write_addr.wrapping_add(i as u8), value);
-                        }
-                        // todo: this op takes between 513 - 514 CPU cycles to execute
-                    },
-                    Memory::JOYCON_ONE_REGISTER => {
-                        self.joycon1.write(data);
-                        self.joycon2.write(data);
-                    },
                     Memory::APU_PULSE_ONE_REGISTER_A..=Memory::APU_PULSE_ONE_REGISTER_D => {
-                        self.apu.write_pulse_one_registers(address as u8 % 4, data);
+                        self.apu.pulse_one.registers.write(address as u8 % 4, data);
                     },
                     Memory::APU_PULSE_TWO_REGISTER_A..=Memory::APU_PULSE_TWO_REGISTER_D => {
-                        self.apu.write_pulse_two_registers(address as u8 % 4, data);
+                        self.apu.pulse_two.registers.write(address as u8 % 4, data);
                     },
                     Memory::APU_TRIANGLE_REGISTER_A..=Memory::APU_TRIANGLE_REGISTER_D => {
-                        self.apu.write_triangle_registers(address as u8 % 4, data);
+                        self.apu.triangle.registers.write(address as u8 % 4, data);
                     },
                     Memory::APU_NOISE_REGISTER_A..=Memory::APU_NOISE_REGISTER_D => {
-                        self.apu.write_noise_registers(address as u8 % 4, data);
+                        self.apu.noise.registers.write(address as u8 % 4, data);
                     },
                     Memory::APU_DMC_REGISTER_A..=Memory::APU_DMC_REGISTER_D => {
-                        self.apu.write_dmc_registers(address as u8 % 4, data);
-                        if address == Memory::APU_DMC_REGISTER_C || address == Memory::APU_DMC_REGISTER_D {
-                            // println!("DMC HIT");
-                            // let sample_addr = self.apu.dmc.get_sample_address();
-                            // let sample_length = self.apu.dmc.get_sample_length();
-                            // for addr in sample_addr..(sample_addr + sample_length) {
-                            //     let sample = self.read_byte(addr);
-                            //     // todo: don't lock in a loop...
-                            //     let mut guard = self.apu.audio_player.as_mut().unwrap().device.lock(); // todo: pulling the guard out like this sucks. Write a helper method
-                            //     guard.dmc.add_dpcm_sample(sample);
-                            // }
-                        }
+                        self.apu.dmc.registers.write(address as u8 % 4, data);
                     },
-                    Memory::APU_STATUS_REGISTER => {
-                        self.apu.write_status_register(data);
-                    },
-                    Memory::APU_FRAME_COUNTER_REGISTER => {
-                        // todo: implement
-                        // println!("APU_FRAME_COUNTER_REGISTER write -> 5-step: {}, IRQ enabled: {}",
-                        //     data & 0b1000_0000 != 0, data & 0b0100_0000 == 0)
-                    },
-                    _ => {
-                        panic!("Attempt to write to unmapped APU/IO address memory: 0x{:0>4X}", address);
-                    }
+                    _ => {},
                 }
-            }
+            },
             custom_ram_range!() => {
-                println!("[WARNING] Write to custom ram range: 0x{:0>4X}", address);
                 self.memory[address as usize] = data;
             },
             prg_ram_range!() => {
                 self.memory[address as usize] = data;
-                if self.rom.has_save_ram {
-                    let pos = (address - 0x6000) as u64;
-                    let save_file = self.save_ram.as_mut().unwrap();
-                    save_file.seek(SeekFrom::Start(pos)).expect("unable to seek in save file");
-                    save_file.write(&[data]).expect("unable to write to save file");
-                }
             },
-            prg_rom_range!() => {
-                self.rom.write_prg_byte(address, data);
-                self.ppu.memory.rom.write_prg_byte(address, data);
+            prg_rom_range!() => {},
+        }
+    }
+
+    pub fn peek_slice(&self, start: u16, len: usize) -> Vec<u8> {
+        (0..len).map(|i| self.peek_byte(start.wrapping_add(i as u16))).collect()
+    }
+
+    pub fn poke_slice(&mut self, start: u16, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.poke_byte(start.wrapping_add(i as u16), *byte);
+        }
+    }
+
+    // Mappers with PRG RAM (ie. MMC1, MMC3) can switch it off entirely, in which case reads return
+    // open bus, or write-protect it while leaving it readable.
+    pub fn set_prg_ram_enabled(&mut self, enabled: bool) {
+        self.prg_ram_enabled = enabled;
+    }
+
+    pub fn set_prg_ram_write_protect(&mut self, write_protect: bool) {
+        self.prg_ram_write_protect = write_protect;
+    }
+
+    #[inline]
+    pub fn rom(&self) -> &ROM {
+        &self.ppu.memory.rom
+    }
+
+    #[inline]
+    pub fn rom_mut(&mut self) -> &mut ROM {
+        &mut self.ppu.memory.rom
+    }
+
+    pub fn poll_mapper_irq(&self) -> bool {
+        self.rom().poll_irq()
+    }
+
+    // Carries the mapper's PRG RAM bits over to the bus, after a write to its registers.
+    pub fn sync_prg_ram_control(&mut self) {
+        let Some(control) = self.rom().mapper.get_prg_ram_control() else { return };
+        self.prg_ram_enabled = control.enabled;
+        self.prg_ram_write_protect = control.write_protect;
+        self.switch_prg_ram_bank(control.bank);
+    }
+
+    // SOROM and SXROM have more PRG RAM than fits at $6000, so the mapped 8 KB are swapped out.
+    fn switch_prg_ram_bank(&mut self, bank: usize) {
+        let bank = bank % (self.prg_ram_banks.len() / ROM::PRG_RAM_PAGE_SIZE);
+        if bank == self.prg_ram_bank {
+            return;
+        }
+        let old_start = self.prg_ram_bank * ROM::PRG_RAM_PAGE_SIZE;
+        self.prg_ram_banks[old_start..old_start + ROM::PRG_RAM_PAGE_SIZE].copy_from_slice(&self.memory[prg_ram_range!()]);
+        let new_start = bank * ROM::PRG_RAM_PAGE_SIZE;
+        self.memory[prg_ram_range!()].copy_from_slice(&self.prg_ram_banks[new_start..new_start + ROM::PRG_RAM_PAGE_SIZE]);
+        self.prg_ram_bank = bank;
+    }
+
+    pub fn is_prg_ram_enabled(&self) -> bool {
+        self.prg_ram_enabled
+    }
+
+    pub fn is_prg_ram_write_protected(&self) -> bool {
+        self.prg_ram_write_protect
+    }
+
+    #[inline]
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        self.last_read_address = address;
+        let data = match MemoryRegion::of(address) {
+            MemoryRegion::Ram => self.read_ram(address),
+            MemoryRegion::PpuRegisters => self.read_ppu_register(address),
+            MemoryRegion::ApuIoRegisters => self.read_apu_io_register(address),
+            MemoryRegion::Expansion => self.read_expansion(address),
+            MemoryRegion::PrgRam => self.read_prg_ram(address),
+            MemoryRegion::PrgRom => self.rom().read_prg_byte(address),
+        };
+        self.data_bus = data;
+        data
+    }
+
+    #[inline]
+    pub fn write_byte(&mut self, address: u16, data: u8) {
+        self.data_bus = data;
+        if let Some(write_watch) = &mut self.write_watch {
+            write_watch[address as usize / 64] |= 1 << (address % 64);
+        }
+        match MemoryRegion::of(address) {
+            MemoryRegion::Ram => self.write_ram(address, data),
+            MemoryRegion::PpuRegisters => self.write_ppu_register(address, data),
+            MemoryRegion::ApuIoRegisters => self.write_apu_io_register(address, data),
+            MemoryRegion::Expansion => self.write_expansion(address, data),
+            MemoryRegion::PrgRam => self.write_prg_ram(address, data),
+            MemoryRegion::PrgRom => self.write_prg_rom(address, data),
+        }
+    }
+
+    /* Region handlers */
+
+    #[inline]
+    fn read_ram(&mut self, address: u16) -> u8 {
+        let mirror_addr = address & 0b0000_0111_1111_1111;
+        self.memory[mirror_addr as usize]
+    }
+
+    #[inline]
+    fn write_ram(&mut self, address: u16, data: u8) {
+        let mirror_addr = address & 0b0000_0111_1111_1111;
+        self.memory[mirror_addr as usize] = data;
+    }
+
+    #[inline]
+    fn read_ppu_register(&mut self, address: u16) -> u8 {
+        let mirror_addr = address & 0b0010_0000_0000_0111;
+        self.catch_up_ppu();
+        match mirror_addr {
+            Memory::PPU_CTRL_REGISTER | Memory::PPU_MASK_REGISTER |
+            Memory::PPU_OAM_ADDR_REGISTER | Memory::PPU_SCROLL_REGISTER |
+            Memory::PPU_ADDR_REGISTER => {
+                self.ppu.read_bus_latch()
+            },
+            Memory::PPU_STAT_REGISTER => {
+                self.ppu.read_status_register()
+            },
+            Memory::PPU_DATA_REGISTER => {
+                let data = self.ppu.read_data_register();
+                self.ppu.refresh_bus_latch(data);
+                data
+            },
+            Memory::PPU_OAM_DATA_REGISTER => {
+                let data = self.ppu.read_oam_data_register();
+                self.ppu.refresh_bus_latch(data);
+                data
+            },
+            _ => {
+                panic!("Attempt to read from unmapped PPU address memory: 0x{:0>4X}", mirror_addr);
+            }
+        }
+    }
+
+    #[inline]
+    fn write_ppu_register(&mut self, address: u16, data: u8) {
+        let mirror_addr = address & 0b0010_0000_0000_0111;
+        self.catch_up_ppu();
+        self.ppu.refresh_bus_latch(data);
+        match mirror_addr {
+            Memory::PPU_CTRL_REGISTER => {
+                self.ppu.write_ctrl_register(data);
+            },
+            Memory::PPU_MASK_REGISTER => {
+                self.ppu.write_mask_register(data);
+            },
+            Memory::PPU_ADDR_REGISTER => {
+                self.ppu.write_addr_register(data);
+            },
+            Memory::PPU_DATA_REGISTER => {
+                self.ppu.write_data_register(data);
+            },
+            Memory::PPU_OAM_ADDR_REGISTER => {
+                self.ppu.write_oam_addr_register(data);
+            },
+            Memory::PPU_OAM_DATA_REGISTER => {
+                self.ppu.write_oam_data_register(data);
+            },
+            Memory::PPU_SCROLL_REGISTER => {
+                self.ppu.write_scroll_register(data);
+            },
+            _ => {
+                println!("[WARNING] Attempt to write to read-only PPU register: 0x{:0>4X}", mirror_addr);
             }
         }
     }
 
+    #[inline]
+    fn read_apu_io_register(&mut self, address: u16) -> u8 {
+        match address {
+            Memory::JOYCON_ONE_REGISTER => {
+                self.joycon1.read()
+            },
+            Memory::JOYCON_TWO_REGISTER => {
+                self.joycon2.read()
+            },
+            Memory::APU_PULSE_ONE_REGISTER_A..=Memory::APU_PULSE_ONE_REGISTER_D => {
+                self.apu.pulse_one.read(address as u8 % 4)
+            },
+            Memory::APU_PULSE_TWO_REGISTER_A..=Memory::APU_PULSE_TWO_REGISTER_D => {
+                self.apu.pulse_two.read(address as u8 % 4)
+            },
+            Memory::APU_TRIANGLE_REGISTER_A..=Memory::APU_TRIANGLE_REGISTER_D => {
+                self.apu.triangle.read(address as u8 % 4)
+            },
+            Memory::APU_NOISE_REGISTER_A..=Memory::APU_NOISE_REGISTER_D => {
+                self.apu.noise.read(address as u8 % 4)
+            },
+            Memory::APU_DMC_REGISTER_A..=Memory::APU_DMC_REGISTER_D => {
+                self.apu.dmc.read(address as u8 % 4)
+            },
+            Memory::APU_STATUS_REGISTER => {
+                self.apu.read_status_register()
+            },
+            _ => {
+                panic!("Attempt to read from unmapped APU/IO address memory: 0x{:0>4X}", address);
+            }
+        }
+    }
+
+    #[inline]
+    fn write_apu_io_register(&mut self, address: u16, data: u8) {
+        match address {
+            Memory::PPU_OAM_DMA_REGISTER => {
+                // the transfer is carried out by the CPU once this write completes (see CPU::oam_dma)
+                self.oam_dma_pending = Some(data);
+            },
+            Memory::JOYCON_ONE_REGISTER => {
+                self.joycon1.write(data);
+                self.joycon2.write(data);
+            },
+            Memory::APU_PULSE_ONE_REGISTER_A..=Memory::APU_PULSE_ONE_REGISTER_D => {
+                self.apu.write_pulse_one_registers(address as u8 % 4, data);
+            },
+            Memory::APU_PULSE_TWO_REGISTER_A..=Memory::APU_PULSE_TWO_REGISTER_D => {
+                self.apu.write_pulse_two_registers(address as u8 % 4, data);
+            },
+            Memory::APU_TRIANGLE_REGISTER_A..=Memory::APU_TRIANGLE_REGISTER_D => {
+                self.apu.write_triangle_registers(address as u8 % 4, data);
+            },
+            Memory::APU_NOISE_REGISTER_A..=Memory::APU_NOISE_REGISTER_D => {
+                self.apu.write_noise_registers(address as u8 % 4, data);
+            },
+            Memory::APU_DMC_REGISTER_A..=Memory::APU_DMC_REGISTER_D => {
+                self.apu.write_dmc_registers(address as u8 % 4, data);
+            },
+            Memory::APU_STATUS_REGISTER => {
+                self.apu.write_status_register(data);
+            },
+            Memory::APU_FRAME_COUNTER_REGISTER => {
+                self.apu.write_frame_counter_register(data);
+            },
+            _ => {
+                panic!("Attempt to write to unmapped APU/IO address memory: 0x{:0>4X}", address);
+            }
+        }
+    }
+
+    // Cartridge expansion area. Boards with registers here (ie. MMC5, FDS) answer for it, for
+    // anything else it's backed by plain RAM.
+    #[inline]
+    fn read_expansion(&mut self, address: u16) -> u8 {
+        if self.rom().mapper.has_expansion() {
+            // the registers can depend on the PPU's fetches (ie. the MMC5's IRQ status)
+            self.catch_up_ppu();
+            let ppu_dot = self.ppu.get_dot_count();
+            return self.rom_mut().mapper.read_expansion(address, ppu_dot).unwrap_or(self.data_bus);
+        }
+        println!("[WARNING] Read from custom ram range: 0x{:0>4X}", address);
+        self.memory[address as usize]
+    }
+
+    #[inline]
+    fn write_expansion(&mut self, address: u16, data: u8) {
+        if self.rom().mapper.has_expansion() {
+            self.catch_up_ppu();
+            self.rom_mut().mapper.write_expansion(address, data);
+            return;
+        }
+        println!("[WARNING] Write to custom ram range: 0x{:0>4X}", address);
+        self.memory[address as usize] = data;
+    }
+
+    #[inline]
+    fn read_prg_ram(&mut self, address: u16) -> u8 {
+        if !self.prg_ram_enabled {
+            // nothing drives the bus, so the last value on it is read back
+            return self.data_bus;
+        }
+        self.memory[address as usize]
+    }
+
+    #[inline]
+    fn write_prg_ram(&mut self, address: u16, data: u8) {
+        if !self.prg_ram_enabled || self.prg_ram_write_protect {
+            return;
+        }
+        self.memory[address as usize] = data;
+        if self.rom().has_battery() {
+            self.prg_ram_dirty = true;
+        }
+    }
+
+    #[inline]
+    fn write_prg_rom(&mut self, address: u16, data: u8) {
+        // bank switches and IRQ writes take effect from the current dot on
+        self.catch_up_ppu();
+        let cycle = self.apu.cpu_cycles + self.access_cycle as usize;
+        self.rom_mut().set_cpu_cycle(cycle);
+        self.rom_mut().write_prg_byte(address, data);
+        self.sync_prg_ram_control();
+    }
+
+    #[inline]
+    fn catch_up_ppu(&mut self) {
+        self.ppu.catch_up(self.access_cycle);
+    }
+
     #[inline]
     pub fn write_bulk(&mut self, address: u16, data: &[u8]) {
         for i in 0..data.len() {
@@ -314,6 +678,8 @@ impl Memory {
         ])
     }
 
+    // Pointers in zero page wrap within it, so a pointer at $FF has its high byte at $00. Both
+    // (indirect,X) and (indirect),Y read their pointer this way.
     #[inline]
     pub fn read_addr_zp(&mut self, address: u8) -> u16 {
         u16::from_le_bytes([
@@ -322,8 +688,10 @@ impl Memory {
         ])
     }
 
+    // JMP ($xxFF) doesn't carry into the high byte of the pointer, so the target's high byte is read
+    // from $xx00 instead of the start of the next page. Real hardware does this and some games rely on it.
     #[inline]
-    pub fn read_addr_in(&mut self, address: u16) -> u16 {
+    pub fn read_addr_in_with_page_wrap(&mut self, address: u16) -> u16 {
         let upper_addr = address & 0xff00;
         let lower_addr = (address & 0x00ff) as u8;
         u16::from_le_bytes([
@@ -427,6 +795,13 @@ impl Memory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nes::apu::frame_counter::FrameTimings;
+    use crate::nes::ppu::oam::SpriteInfo;
+    use crate::nes::ppu::registers::status::StatusFlag::VerticalBlank;
+    use crate::nes::io::joycon::joycon_status::JoyconButton;
+    use crate::util::bitvec::BitVector;
+    use crate::nes::rom::mappers::create_mapper;
+    use crate::nes::rom::tests::ines_image;
 
     const BYTE_A: u8 = 0x0a;
     const BYTE_B: u8 = 0x0b;
@@ -442,6 +817,497 @@ mod tests {
         assert_eq!(mem.read_byte(0x0002), BYTE_B);
     }
 
+    #[test]
+    fn test_ppu_register_mirroring() {
+        let mut mem = Memory::new();
+        mem.ppu.status.set(VerticalBlank);
+        mem.write_byte(0x2000, 0b0001_0101);
+        assert_eq!(mem.read_byte(0x2002), 0b1001_0101);
+        mem.ppu.status.set(VerticalBlank);
+        assert_eq!(mem.read_byte(0x200A), 0b1001_0101);
+        mem.ppu.status.set(VerticalBlank);
+        assert_eq!(mem.read_byte(0x3FFA), 0b1001_0101);
+        assert_eq!(mem.read_byte(0x3FFA), 0b0001_0101);
+
+        mem.write_byte(0x3FFE, 0x21);
+        mem.write_byte(0x200E, 0x08);
+        assert_eq!(mem.ppu.addr.get(), 0x2108);
+    }
+
+    #[test]
+    fn test_ppu_bus_latch() {
+        let mut mem = Memory::new();
+        for address in [0x2000, 0x2001, 0x2003, 0x2005, 0x2006, 0x3FF8, 0x2FFD] {
+            mem.write_byte(0x2002, 0xA5);
+            assert_eq!(mem.read_byte(address), 0xA5);
+        }
+
+        mem.write_byte(0x2003, 0x10);
+        mem.write_byte(0x2004, 0x5A);
+        mem.write_byte(0x2003, 0x10);
+        assert_eq!(mem.read_byte(0x2004), 0x5A);
+        assert_eq!(mem.read_byte(0x2001), 0x5A);
+    }
+
+    #[test]
+    fn test_ppu_bus_latch_oam_attribute() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x2003, 0x06);
+        mem.write_byte(0x2004, 0xFF);
+        mem.write_byte(0x2004, 0xFF);
+        mem.write_byte(0x2003, 0x06);
+        assert_eq!(mem.read_byte(0x2004), 0xE3);
+        assert_eq!(mem.read_byte(0x2001), 0xE3);
+        mem.write_byte(0x2003, 0x07);
+        assert_eq!(mem.read_byte(0x2004), 0xFF);
+    }
+
+    #[test]
+    fn test_ppu_bus_latch_status_low_bits() {
+        let mut mem = Memory::new();
+        mem.ppu.status.set(VerticalBlank);
+        mem.write_byte(0x2000, 0x1F);
+        mem.ppu.ctrl.set_value(0x00);
+        assert_eq!(mem.read_byte(0x2002), 0x9F);
+        assert_eq!(mem.read_byte(0x2005), 0x9F);
+    }
+
+    #[test]
+    fn test_load_rom_trainer() {
+        let mut mem = Memory::new();
+        let mut raw = ines_image(0b0000_0100, 0, 1, 0);
+        for (i, byte) in raw[16..16 + ROM::TRAINER_SIZE].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        mem.load_rom(&ROM::from_bytes(&raw).unwrap());
+        assert_eq!(mem.read_byte(0x6FFF), 0x00);
+        assert_eq!(mem.read_byte(0x7000), 0x00);
+        assert_eq!(mem.read_byte(0x7001), 0x01);
+        assert_eq!(mem.read_byte(0x71FF), 0xFF);
+        assert_eq!(mem.read_byte(0x7200), 0x00);
+    }
+
+    #[test]
+    fn test_oam_sprite_infos() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x2003, 0x04);
+        for byte in [0x20, 0x33, 0b0110_0001, 0x48, 0xEF, 0x34, 0b1000_0010, 0x00] {
+            mem.write_byte(0x2004, byte);
+        }
+
+        let sprites = mem.ppu.get_sprite_infos();
+        assert_eq!(sprites.len(), 64);
+        assert_eq!(sprites[1], SpriteInfo {
+            index: 1, x: 0x48, y: 0x20, tile: 0x33, palette: 1,
+            behind_background: true, flip_horizontal: true, flip_vertical: false,
+        });
+        assert_eq!(sprites[2], SpriteInfo {
+            index: 2, x: 0x00, y: 0xEF, tile: 0x34, palette: 2,
+            behind_background: false, flip_horizontal: false, flip_vertical: true,
+        });
+        assert_eq!(sprites[0].y, 0x00);
+    }
+
+    #[test]
+    fn test_ppu_bus_latch_decay() {
+        let mut mem = Memory::new();
+        mem.ppu.scanline = 241;
+        mem.write_byte(0x2001, 0xFF);
+        for _ in 0..35 {
+            mem.ppu.tick(114);
+            mem.ppu.step().unwrap();
+            assert_eq!(mem.read_byte(0x2003), 0xFF);
+            mem.ppu.scanline = 241;
+            mem.ppu.dot = 0;
+        }
+        mem.ppu.tick(114);
+        mem.ppu.step().unwrap();
+        assert_eq!(mem.read_byte(0x2003), 0x00);
+    }
+
+    #[test]
+    fn test_peek_ppu_status() {
+        let mut mem = Memory::new();
+        mem.ppu.status.set(VerticalBlank);
+        assert_eq!(mem.peek_byte(0x2002) & 0x80, 0x80);
+        assert_eq!(mem.peek_byte(0x2002) & 0x80, 0x80);
+        assert!(mem.ppu.status.is_set(VerticalBlank));
+
+        assert_eq!(mem.read_byte(0x2002) & 0x80, 0x80);
+        assert_eq!(mem.peek_byte(0x2002) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn test_peek_ppu_data() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x2006, 0x20);
+        mem.write_byte(0x2006, 0x00);
+        mem.write_byte(0x2007, BYTE_A);
+        mem.write_byte(0x2006, 0x20);
+        mem.write_byte(0x2006, 0x00);
+        mem.read_byte(0x2007); // prime the read buffer
+        assert_eq!(mem.peek_byte(0x2007), BYTE_A);
+        assert_eq!(mem.peek_byte(0x2007), BYTE_A);
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2001);
+    }
+
+    #[test]
+    fn test_ppu_scroll_addr_shared_latch() {
+        let mut mem = Memory::new();
+        // $2005 then $2006 share the same write toggle, so the $2006 write acts as a second write
+        mem.write_byte(0x2005, 0x00);
+        mem.write_byte(0x2006, 0x00);
+        mem.write_byte(0x2006, 0x24);
+        mem.write_byte(0x2006, 0x40);
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2440);
+
+        // the $2007 address is v, so a later $2005 write only reaches it through t
+        mem.write_byte(0x2005, 0xFF);
+        mem.write_byte(0x2005, 0xFF);
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2440);
+        mem.write_byte(0x2007, BYTE_A);
+        assert_eq!(mem.ppu.memory.read_byte(0x2440), BYTE_A);
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2441);
+    }
+
+    #[test]
+    fn test_ppu_data_read_buffer() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x2006, 0x21);
+        mem.write_byte(0x2006, 0x00);
+        mem.write_byte(0x2007, BYTE_A);
+        mem.write_byte(0x2007, BYTE_B);
+
+        mem.write_byte(0x2006, 0x21);
+        mem.write_byte(0x2006, 0x00);
+        mem.ppu.data_buffer = 0x77;
+        assert_eq!(mem.read_byte(0x2007), 0x77); // stale buffer contents
+        assert_eq!(mem.read_byte(0x2007), BYTE_A);
+        assert_eq!(mem.read_byte(0x2007), BYTE_B);
+    }
+
+    fn set_ppu_addr(mem: &mut Memory, addr: u16) {
+        mem.write_byte(0x2006, (addr >> 8) as u8);
+        mem.write_byte(0x2006, addr as u8);
+    }
+
+    #[test]
+    fn test_ppu_data_read_back_pattern() {
+        let mut mem = Memory::new();
+        set_ppu_addr(&mut mem, 0x2080);
+        for i in 0..16 {
+            mem.write_byte(0x2007, i);
+        }
+
+        set_ppu_addr(&mut mem, 0x2080);
+        mem.read_byte(0x2007); // dummy read fills the buffer
+        for i in 0..16 {
+            assert_eq!(mem.read_byte(0x2007), i);
+        }
+    }
+
+    #[test]
+    fn test_ppu_data_increment_32() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x2000, 0b0000_0100);
+        set_ppu_addr(&mut mem, 0x2000);
+        for i in 0..4 {
+            mem.write_byte(0x2007, i);
+        }
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2080);
+        for i in 0..4 {
+            assert_eq!(mem.ppu.memory.read_byte(0x2000 + 32 * i as u16), i);
+        }
+
+        set_ppu_addr(&mut mem, 0x2000);
+        mem.read_byte(0x2007);
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2020);
+        assert_eq!(mem.read_byte(0x2007), 0);
+        assert_eq!(mem.read_byte(0x2007), 1);
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2060);
+
+        mem.write_byte(0x2000, 0b0000_0000);
+        mem.read_byte(0x2007);
+        assert_eq!(mem.ppu.get_vram_addr(), 0x2061);
+    }
+
+    #[test]
+    fn test_ppu_data_palette_mirrors() {
+        let mut mem = Memory::new();
+        for (addr, mirror) in [(0x3F10, 0x3F00), (0x3F14, 0x3F04), (0x3F18, 0x3F08), (0x3F1C, 0x3F0C)] {
+            set_ppu_addr(&mut mem, addr);
+            mem.write_byte(0x2007, (addr & 0x3F) as u8);
+            set_ppu_addr(&mut mem, mirror);
+            assert_eq!(mem.read_byte(0x2007), (addr & 0x3F) as u8);
+
+            set_ppu_addr(&mut mem, mirror);
+            mem.write_byte(0x2007, 0x2A);
+            set_ppu_addr(&mut mem, addr);
+            assert_eq!(mem.read_byte(0x2007), 0x2A);
+        }
+
+        // $3F20-$3FFF mirror the 32 palette entries
+        set_ppu_addr(&mut mem, 0x3F05);
+        mem.write_byte(0x2007, 0x15);
+        set_ppu_addr(&mut mem, 0x3FE5);
+        assert_eq!(mem.read_byte(0x2007) & 0x3F, 0x15); // top bits are the $E5 left on the bus
+    }
+
+    #[test]
+    fn test_ppu_data_read_palette() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x2006, 0x2F);
+        mem.write_byte(0x2006, 0x05);
+        mem.write_byte(0x2007, BYTE_A); // nametable byte underneath $3F05
+        mem.write_byte(0x2006, 0x3F);
+        mem.write_byte(0x2006, 0x05);
+        mem.write_byte(0x2007, 0x2C);
+
+        mem.write_byte(0x2006, 0x3F);
+        mem.write_byte(0x2006, 0x05);
+        assert_eq!(mem.read_byte(0x2007), 0x2C);
+        assert_eq!(mem.ppu.data_buffer, BYTE_A);
+    }
+
+    #[test]
+    fn test_ppu_data_read_palette_open_bus() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x2006, 0x3F);
+        mem.write_byte(0x2006, 0x00);
+        mem.write_byte(0x2007, 0xFF);
+        mem.write_byte(0x2006, 0x3F);
+        mem.write_byte(0x2006, 0x00);
+        assert_eq!(mem.peek_byte(0x2007), 0x3F);
+        assert_eq!(mem.read_byte(0x2007), 0x3F);
+
+        mem.write_byte(0x2006, 0x3F);
+        mem.write_byte(0x2006, 0x00);
+        mem.write_byte(0x2001, 0x80); // leaves $80 on the PPU bus
+        assert_eq!(mem.read_byte(0x2007), 0xBF);
+    }
+
+    #[test]
+    fn test_peek_joycon() {
+        let mut mem = Memory::new();
+        mem.joycon1.set_button(JoyconButton::A);
+        mem.write_byte(0x4016, 1);
+        mem.write_byte(0x4016, 0);
+        assert_eq!(mem.peek_byte(0x4016) & 1, 1);
+        assert_eq!(mem.peek_byte(0x4016) & 1, 1);
+        assert_eq!(mem.read_byte(0x4016) & 1, 1);
+        assert_eq!(mem.read_byte(0x4016) & 1, 0);
+    }
+
+    #[test]
+    fn test_peek_poke_slice() {
+        let mut mem = Memory::new();
+        mem.poke_slice(0x07FE, &[BYTE_A, BYTE_B, BYTE_A]);
+        assert_eq!(mem.peek_slice(0x0FFE, 3), vec![BYTE_A, BYTE_B, BYTE_A]);
+
+        mem.set_prg_ram_write_protect(true);
+        mem.poke_slice(0x6000, &[BYTE_A, BYTE_B]);
+        assert_eq!(mem.peek_slice(0x6000, 2), vec![BYTE_A, BYTE_B]);
+    }
+
+    #[test]
+    fn test_memory_regions() {
+        assert_eq!(MemoryRegion::of(0x0000), MemoryRegion::Ram);
+        assert_eq!(MemoryRegion::of(0x1FFF), MemoryRegion::Ram);
+        assert_eq!(MemoryRegion::of(0x2000), MemoryRegion::PpuRegisters);
+        assert_eq!(MemoryRegion::of(0x3FFF), MemoryRegion::PpuRegisters);
+        assert_eq!(MemoryRegion::of(0x4017), MemoryRegion::ApuIoRegisters);
+        assert_eq!(MemoryRegion::of(0x4020), MemoryRegion::Expansion);
+        assert_eq!(MemoryRegion::of(0x5FFF), MemoryRegion::Expansion);
+        assert_eq!(MemoryRegion::of(0x6000), MemoryRegion::PrgRam);
+        assert_eq!(MemoryRegion::of(0x7FFF), MemoryRegion::PrgRam);
+        assert_eq!(MemoryRegion::of(0x8000), MemoryRegion::PrgRom);
+        assert_eq!(MemoryRegion::of(0xFFFF), MemoryRegion::PrgRom);
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x0001, BYTE_A);
+        mem.ppu.status.set(VerticalBlank);
+        assert_eq!(mem.dump(0x0800, 2), vec![0x00, BYTE_A]);
+        assert_eq!(mem.dump(0x2002, 1)[0] & 0x80, 0x80);
+        assert!(mem.ppu.status.is_set(VerticalBlank));
+        mem.load_at_addr(0x8000, &vec![BYTE_A]);
+        assert_eq!(mem.dump(0xFFFC, 8), vec![0x00, 0x80, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_watch_writes() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x0010, BYTE_A);
+        assert!(mem.watch_writes(0x0000, 0x0800).is_empty());
+
+        mem.write_byte(0x0010, BYTE_A);
+        mem.write_byte(0x0045, BYTE_B);
+        mem.write_byte(0x2000, 0x00);
+        mem.poke_byte(0x0020, BYTE_B);
+        assert_eq!(mem.watch_writes(0x0040, 0x10), vec![0x0045]);
+        assert_eq!(mem.watch_writes(0x0000, 0x10000), vec![0x0010, 0x2000]);
+        assert!(mem.watch_writes(0x0000, 0x10000).is_empty());
+    }
+
+    #[test]
+    fn test_poke_ppu_ctrl() {
+        let mut mem = Memory::new();
+        mem.ppu.status.set(VerticalBlank);
+        mem.poke_byte(0x2000, 0x80);
+        assert_eq!(mem.ppu.ctrl.get_value(), 0x80);
+        assert!(!mem.ppu.poll_nmi());
+
+        mem.poke_byte(0x4003, 0b1111_1000);
+        assert_eq!(mem.apu.pulse_one.read(3), 0b1111_1000);
+        assert_eq!(mem.apu.pulse_one.get_length_counter(), 0);
+    }
+
+    #[test]
+    fn test_apu_frame_irq() {
+        let mut mem = Memory::new();
+        mem.apu.tick(255);
+        while !mem.apu.poll_irq() {
+            mem.apu.tick(1);
+        }
+        assert_eq!(mem.apu.cpu_cycles, FrameTimings::NTSC.four_step_irq as usize);
+
+        // peeking leaves the flag alone, reading $4015 acknowledges it
+        assert_eq!(mem.peek_byte(0x4015) & 0x40, 0x40);
+        assert_eq!(mem.read_byte(0x4015) & 0x40, 0x40);
+        assert_eq!(mem.read_byte(0x4015) & 0x40, 0x00);
+        assert!(!mem.apu.poll_irq());
+
+        // inhibiting through $4017 acknowledges it too
+        mem.apu.tick(1);
+        assert!(mem.apu.poll_irq());
+        mem.write_byte(0x4017, 0x40);
+        assert!(!mem.apu.poll_irq());
+    }
+
+    #[test]
+    fn test_prg_ram_write_protect() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x6000, BYTE_A);
+        mem.set_prg_ram_write_protect(true);
+        mem.write_byte(0x6000, BYTE_B);
+        mem.write_byte(0x7FFF, BYTE_B);
+        assert_eq!(mem.read_byte(0x6000), BYTE_A);
+        assert_eq!(mem.read_byte(0x7FFF), 0);
+
+        mem.set_prg_ram_write_protect(false);
+        mem.write_byte(0x6000, BYTE_B);
+        assert_eq!(mem.read_byte(0x6000), BYTE_B);
+    }
+
+    #[test]
+    fn test_prg_ram_disabled() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x6000, BYTE_A);
+        mem.set_prg_ram_enabled(false);
+        mem.write_byte(0x6001, BYTE_B);
+
+        // reads return whatever was last on the data bus
+        mem.write_byte(0x0000, 0x60);
+        assert_eq!(mem.read_byte(0x6000), 0x60);
+        assert_eq!(mem.read_byte(0x0001), 0);
+        assert_eq!(mem.read_byte(0x6001), 0);
+
+        mem.set_prg_ram_enabled(true);
+        assert_eq!(mem.read_byte(0x6000), BYTE_A);
+        assert_eq!(mem.read_byte(0x6001), 0);
+    }
+
+    #[test]
+    fn test_mmc3_prg_ram_protect() {
+        let mut rom = ROM::new();
+        rom.mapper_id = 4;
+        rom.mapper = create_mapper(&rom).unwrap();
+        rom.prg_rom = vec![0; 2 * ROM::PRG_ROM_PAGE_SIZE];
+        let mut mem = Memory::new();
+        mem.load_rom(&rom);
+        mem.write_byte(0xA001, 0b1000_0000);
+        mem.write_byte(0x6000, BYTE_A);
+        assert_eq!(mem.read_byte(0x6000), BYTE_A);
+
+        mem.write_byte(0xA001, 0b1100_0000);
+        mem.write_byte(0x6000, BYTE_B);
+        assert_eq!(mem.read_byte(0x6000), BYTE_A);
+
+        mem.write_byte(0xA001, 0b0000_0000);
+        assert!(!mem.is_prg_ram_enabled());
+        mem.write_byte(0xA001, 0b1000_0000);
+        mem.write_byte(0x6000, BYTE_B);
+        assert_eq!(mem.read_byte(0x6000), BYTE_B);
+    }
+
+    #[test]
+    fn test_sorom_prg_ram_banks() {
+        let path = std::env::temp_dir().join("alpines_test_sorom_battery_ram.sav");
+        let mut rom = ROM::new();
+        rom.mapper_id = 1;
+        rom.prg_rom = vec![0; 2 * ROM::PRG_ROM_PAGE_SIZE];
+        rom.has_save_ram = true;
+        rom.prg_ram_size = ROM::PRG_RAM_PAGE_SIZE;
+        rom.prg_nvram_size = ROM::PRG_RAM_PAGE_SIZE;
+        rom.mapper = create_mapper(&rom).unwrap();
+        let mut mem = Memory::new();
+        mem.load_rom(&rom);
+        // bit 3 of the CHR bank register picks the 8 KB bank, written a bit at a time
+        let write_chr_bank0 = |mem: &mut Memory, value: u8| {
+            for bit in 0..5 {
+                mem.write_byte(0xA000, (value >> bit) & 1);
+            }
+        };
+        mem.write_byte(0x6000, BYTE_A);
+        write_chr_bank0(&mut mem, 0b0_1000);
+        assert_eq!(mem.read_byte(0x6000), 0);
+        mem.write_byte(0x6000, BYTE_B);
+        write_chr_bank0(&mut mem, 0b0_0000);
+        assert_eq!(mem.read_byte(0x6000), BYTE_A);
+
+        // only the battery-backed second bank is saved
+        mem.save_battery_ram(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap().len(), ROM::PRG_RAM_PAGE_SIZE);
+        let mut mem = Memory::new();
+        mem.load_rom(&rom);
+        mem.load_battery_ram(&path).unwrap();
+        assert_eq!(mem.get_prg_ram()[ROM::PRG_RAM_PAGE_SIZE], BYTE_B);
+        assert_eq!(mem.read_byte(0x6000), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_battery_ram() {
+        let path = std::env::temp_dir().join("alpines_test_battery_ram.sav");
+        let mut mem = Memory::new();
+        mem.rom_mut().has_save_ram = true;
+        mem.write_byte(0x6000, BYTE_A);
+        mem.write_byte(0x7FFF, BYTE_B);
+        assert!(mem.prg_ram_dirty);
+        mem.save_battery_ram(&path).unwrap();
+        assert!(!mem.prg_ram_dirty);
+
+        let mut mem = Memory::new();
+        mem.load_battery_ram(&path).unwrap();
+        assert_eq!(mem.read_byte(0x6000), BYTE_A);
+        assert_eq!(mem.read_byte(0x7FFF), BYTE_B);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_battery_ram_dirty() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x6000, BYTE_A);
+        assert!(!mem.prg_ram_dirty);
+        mem.rom_mut().has_save_ram = true;
+        mem.write_byte(0x0000, BYTE_A);
+        assert!(!mem.prg_ram_dirty);
+        mem.write_byte(0x6000, BYTE_A);
+        assert!(mem.prg_ram_dirty);
+    }
+
     #[test]
     fn test_write_bulk() {
         let mut mem = Memory::new();
@@ -458,4 +1324,48 @@ mod tests {
         assert_eq!(mem.read_addr(0x0101), 0x0a);
         assert_eq!(mem.read_addr(0x0100), 0x0a0b);
     }
+
+    #[test]
+    fn test_read_addr_in_with_page_wrap() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x01FF, 0x00);
+        mem.write_byte(0x0100, 0x14);
+        mem.write_byte(0x0200, 0x15);
+        assert_eq!(mem.read_addr_in_with_page_wrap(0x01FF), 0x1400);
+        assert_eq!(mem.read_addr(0x01FF), 0x1500);
+        mem.write_addr(0x0180, 0x2000);
+        assert_eq!(mem.read_addr_in_with_page_wrap(0x0180), 0x2000);
+    }
+
+    #[test]
+    fn test_in_x_zero_page_wrap() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x00FF, 0x00);
+        mem.write_byte(0x0000, 0x14);
+        mem.write_byte(0x0100, 0x15);
+        mem.write_byte(0x1400, BYTE_A);
+        mem.write_byte(0x1500, BYTE_B);
+        // $80 + $7F lands on $FF, and the pointer's high byte comes from $00 rather than $100
+        assert_eq!(mem.in_x_read(0x80, 0x7F), BYTE_A);
+        // the zero page address itself wraps too: $F0 + $20 = $10
+        mem.write_addr(0x0010, 0x1500);
+        assert_eq!(mem.in_x_read(0xF0, 0x20), BYTE_B);
+
+        mem.in_x_write(0x80, 0x7F, BYTE_B);
+        assert_eq!(mem.read_byte(0x1400), BYTE_B);
+    }
+
+    #[test]
+    fn test_in_y_zero_page_wrap() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x00FF, 0x00);
+        mem.write_byte(0x0000, 0x14);
+        mem.write_byte(0x0100, 0x15);
+        mem.write_byte(0x1410, BYTE_A);
+        assert_eq!(mem.in_y_read(0xFF, 0x10), BYTE_A);
+
+        mem.in_y_write(0xFF, 0x20, BYTE_B);
+        assert_eq!(mem.read_byte(0x1420), BYTE_B);
+        assert_eq!(mem.read_byte(0x1520), 0x00);
+    }
 }
\ No newline at end of file
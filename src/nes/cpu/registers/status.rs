@@ -1,5 +1,3 @@
-use crate::util::bitvec::BitVector;
-
 // 7  bit  0
 // ---- ----
 // NVss DIZC
@@ -12,41 +10,20 @@ use crate::util::bitvec::BitVector;
 // |+-------- Overflow
 // +--------- Negative
 
-pub enum StatusFlag {
-    Carry,
-    Zero,
-    InterruptDisable,
-    DecimalMode,
-    BreakCommand,
-    Unused,
-    Overflow,
-    Negative,
-}
-
 pub struct StatusRegister {
     pub value: u8,
 }
 
-impl BitVector for StatusRegister {
-    type Flag = StatusFlag;
-
-    #[inline]
-    fn is_set(&self, flag: Self::Flag) -> bool {
-        self.value & 1 << (flag as u8) != 0
-    }
-
-    #[inline]
-    fn set(&mut self, flag: Self::Flag) {
-        self.value |= 1 << (flag as u8)
-    }
-
-    #[inline]
-    fn clear(&mut self, flag: Self::Flag) {
-        self.value &= !(1 << (flag as u8))
-    }
-}
-
 impl StatusRegister {
+    pub const CARRY: u8 = 0b0000_0001;
+    pub const ZERO: u8 = 0b0000_0010;
+    pub const INTERRUPT_DISABLE: u8 = 0b0000_0100;
+    pub const DECIMAL: u8 = 0b0000_1000;
+    pub const BREAK_COMMAND: u8 = 0b0001_0000;
+    pub const UNUSED: u8 = 0b0010_0000;
+    pub const OVERFLOW: u8 = 0b0100_0000;
+    pub const NEGATIVE: u8 = 0b1000_0000;
+
     const B_FLAG_MASK: u8 = 0b0011_0000;
     const B_FLAG_INTERRUPT_SET_MASK: u8 = 0b0010_0000;
     const B_FLAG_INTERRUPT_CLEAR_MASK: u8 = 0b1110_1111;
@@ -78,4 +55,112 @@ impl StatusRegister {
     pub fn set_value_interrupt(&mut self, value: u8) {
         self.value = (value | Self::B_FLAG_INTERRUPT_SET_MASK) & Self::B_FLAG_INTERRUPT_CLEAR_MASK
     }
-}
\ No newline at end of file
+
+    // Named accessors for the flags the CPU reads and writes on every instruction.
+
+    #[inline]
+    pub fn carry(&self) -> bool {
+        self.value & Self::CARRY != 0
+    }
+
+    #[inline]
+    pub fn set_carry(&mut self, value: bool) {
+        self.set_mask(Self::CARRY, value)
+    }
+
+    #[inline]
+    pub fn zero(&self) -> bool {
+        self.value & Self::ZERO != 0
+    }
+
+    #[inline]
+    pub fn set_zero(&mut self, value: bool) {
+        self.set_mask(Self::ZERO, value)
+    }
+
+    #[inline]
+    pub fn interrupt_disable(&self) -> bool {
+        self.value & Self::INTERRUPT_DISABLE != 0
+    }
+
+    #[inline]
+    pub fn set_interrupt_disable(&mut self, value: bool) {
+        self.set_mask(Self::INTERRUPT_DISABLE, value)
+    }
+
+    #[inline]
+    pub fn decimal(&self) -> bool {
+        self.value & Self::DECIMAL != 0
+    }
+
+    #[inline]
+    pub fn set_decimal(&mut self, value: bool) {
+        self.set_mask(Self::DECIMAL, value)
+    }
+
+    #[inline]
+    pub fn break_command(&self) -> bool {
+        self.value & Self::BREAK_COMMAND != 0
+    }
+
+    #[inline]
+    pub fn set_break_command(&mut self, value: bool) {
+        self.set_mask(Self::BREAK_COMMAND, value)
+    }
+
+    // Reads back as 1 whenever the flags are pushed.
+    #[inline]
+    pub fn unused(&self) -> bool {
+        self.value & Self::UNUSED != 0
+    }
+
+    #[inline]
+    pub fn overflow(&self) -> bool {
+        self.value & Self::OVERFLOW != 0
+    }
+
+    #[inline]
+    pub fn set_overflow(&mut self, value: bool) {
+        self.set_mask(Self::OVERFLOW, value)
+    }
+
+    #[inline]
+    pub fn negative(&self) -> bool {
+        self.value & Self::NEGATIVE != 0
+    }
+
+    #[inline]
+    pub fn set_negative(&mut self, value: bool) {
+        self.set_mask(Self::NEGATIVE, value)
+    }
+
+    #[inline]
+    fn set_mask(&mut self, mask: u8, value: bool) {
+        if value {
+            self.value |= mask;
+        } else {
+            self.value &= !mask;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_accessors() {
+        let mut status = StatusRegister::from(0);
+        status.set_carry(true);
+        status.set_negative(true);
+        status.set_overflow(true);
+        assert_eq!(status.value, StatusRegister::CARRY | StatusRegister::OVERFLOW | StatusRegister::NEGATIVE);
+        assert!(status.carry() && status.negative() && status.overflow());
+        assert!(!status.zero() && !status.decimal() && !status.interrupt_disable() && !status.break_command());
+
+        status.set_overflow(false);
+        status.set_zero(true);
+        assert_eq!(status.value, 0b1000_0011);
+        assert!(status.zero() && !status.unused());
+    }
+}
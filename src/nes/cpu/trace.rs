@@ -0,0 +1,350 @@
+use std::fmt;
+use crate::nes::cpu::CPU;
+
+// Trace lines in the format of Nintendulator's nestest.log:
+//
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+// |     |        ||                               |
+// |     |        |+- mnemonic and operand         +- registers and CPU cycle count
+// |     |        +-- '*' for unofficial opcodes
+// |     +----------- instruction bytes
+// +----------------- program counter
+//
+// The reference log also annotates operands with the memory they touch (ex: "LDA $00 = 00") and
+// reports the PPU position; neither is produced here, and both are ignored when comparing logs.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl AddressingMode {
+    pub fn operand_len(&self) -> usize {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 0,
+            AddressingMode::Absolute | AddressingMode::AbsoluteX |
+            AddressingMode::AbsoluteY | AddressingMode::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub unofficial: bool,
+}
+
+const fn op(mnemonic: &'static str, mode: AddressingMode, unofficial: bool) -> Opcode {
+    Opcode { mnemonic, mode, unofficial }
+}
+
+use AddressingMode::*;
+
+pub const OPCODES: [Opcode; 256] = [
+    op("BRK", Implied, false), op("ORA", IndirectX, false), op("JAM", Implied, true), op("SLO", IndirectX, true),
+    op("NOP", ZeroPage, true), op("ORA", ZeroPage, false), op("ASL", ZeroPage, false), op("SLO", ZeroPage, true),
+    op("PHP", Implied, false), op("ORA", Immediate, false), op("ASL", Accumulator, false), op("ANC", Immediate, true),
+    op("NOP", Absolute, true), op("ORA", Absolute, false), op("ASL", Absolute, false), op("SLO", Absolute, true),
+    op("BPL", Relative, false), op("ORA", IndirectY, false), op("JAM", Implied, true), op("SLO", IndirectY, true),
+    op("NOP", ZeroPageX, true), op("ORA", ZeroPageX, false), op("ASL", ZeroPageX, false), op("SLO", ZeroPageX, true),
+    op("CLC", Implied, false), op("ORA", AbsoluteY, false), op("NOP", Implied, true), op("SLO", AbsoluteY, true),
+    op("NOP", AbsoluteX, true), op("ORA", AbsoluteX, false), op("ASL", AbsoluteX, false), op("SLO", AbsoluteX, true),
+    op("JSR", Absolute, false), op("AND", IndirectX, false), op("JAM", Implied, true), op("RLA", IndirectX, true),
+    op("BIT", ZeroPage, false), op("AND", ZeroPage, false), op("ROL", ZeroPage, false), op("RLA", ZeroPage, true),
+    op("PLP", Implied, false), op("AND", Immediate, false), op("ROL", Accumulator, false), op("ANC", Immediate, true),
+    op("BIT", Absolute, false), op("AND", Absolute, false), op("ROL", Absolute, false), op("RLA", Absolute, true),
+    op("BMI", Relative, false), op("AND", IndirectY, false), op("JAM", Implied, true), op("RLA", IndirectY, true),
+    op("NOP", ZeroPageX, true), op("AND", ZeroPageX, false), op("ROL", ZeroPageX, false), op("RLA", ZeroPageX, true),
+    op("SEC", Implied, false), op("AND", AbsoluteY, false), op("NOP", Implied, true), op("RLA", AbsoluteY, true),
+    op("NOP", AbsoluteX, true), op("AND", AbsoluteX, false), op("ROL", AbsoluteX, false), op("RLA", AbsoluteX, true),
+    op("RTI", Implied, false), op("EOR", IndirectX, false), op("JAM", Implied, true), op("SRE", IndirectX, true),
+    op("NOP", ZeroPage, true), op("EOR", ZeroPage, false), op("LSR", ZeroPage, false), op("SRE", ZeroPage, true),
+    op("PHA", Implied, false), op("EOR", Immediate, false), op("LSR", Accumulator, false), op("ALR", Immediate, true),
+    op("JMP", Absolute, false), op("EOR", Absolute, false), op("LSR", Absolute, false), op("SRE", Absolute, true),
+    op("BVC", Relative, false), op("EOR", IndirectY, false), op("JAM", Implied, true), op("SRE", IndirectY, true),
+    op("NOP", ZeroPageX, true), op("EOR", ZeroPageX, false), op("LSR", ZeroPageX, false), op("SRE", ZeroPageX, true),
+    op("CLI", Implied, false), op("EOR", AbsoluteY, false), op("NOP", Implied, true), op("SRE", AbsoluteY, true),
+    op("NOP", AbsoluteX, true), op("EOR", AbsoluteX, false), op("LSR", AbsoluteX, false), op("SRE", AbsoluteX, true),
+    op("RTS", Implied, false), op("ADC", IndirectX, false), op("JAM", Implied, true), op("RRA", IndirectX, true),
+    op("NOP", ZeroPage, true), op("ADC", ZeroPage, false), op("ROR", ZeroPage, false), op("RRA", ZeroPage, true),
+    op("PLA", Implied, false), op("ADC", Immediate, false), op("ROR", Accumulator, false), op("ARR", Immediate, true),
+    op("JMP", Indirect, false), op("ADC", Absolute, false), op("ROR", Absolute, false), op("RRA", Absolute, true),
+    op("BVS", Relative, false), op("ADC", IndirectY, false), op("JAM", Implied, true), op("RRA", IndirectY, true),
+    op("NOP", ZeroPageX, true), op("ADC", ZeroPageX, false), op("ROR", ZeroPageX, false), op("RRA", ZeroPageX, true),
+    op("SEI", Implied, false), op("ADC", AbsoluteY, false), op("NOP", Implied, true), op("RRA", AbsoluteY, true),
+    op("NOP", AbsoluteX, true), op("ADC", AbsoluteX, false), op("ROR", AbsoluteX, false), op("RRA", AbsoluteX, true),
+    op("NOP", Immediate, true), op("STA", IndirectX, false), op("NOP", Immediate, true), op("SAX", IndirectX, true),
+    op("STY", ZeroPage, false), op("STA", ZeroPage, false), op("STX", ZeroPage, false), op("SAX", ZeroPage, true),
+    op("DEY", Implied, false), op("NOP", Immediate, true), op("TXA", Implied, false), op("ANE", Immediate, true),
+    op("STY", Absolute, false), op("STA", Absolute, false), op("STX", Absolute, false), op("SAX", Absolute, true),
+    op("BCC", Relative, false), op("STA", IndirectY, false), op("JAM", Implied, true), op("SHA", IndirectY, true),
+    op("STY", ZeroPageX, false), op("STA", ZeroPageX, false), op("STX", ZeroPageY, false), op("SAX", ZeroPageY, true),
+    op("TYA", Implied, false), op("STA", AbsoluteY, false), op("TXS", Implied, false), op("SHS", AbsoluteY, true),
+    op("SHY", AbsoluteX, true), op("STA", AbsoluteX, false), op("SHX", AbsoluteY, true), op("SHA", AbsoluteY, true),
+    op("LDY", Immediate, false), op("LDA", IndirectX, false), op("LDX", Immediate, false), op("LAX", IndirectX, true),
+    op("LDY", ZeroPage, false), op("LDA", ZeroPage, false), op("LDX", ZeroPage, false), op("LAX", ZeroPage, true),
+    op("TAY", Implied, false), op("LDA", Immediate, false), op("TAX", Implied, false), op("LXA", Immediate, true),
+    op("LDY", Absolute, false), op("LDA", Absolute, false), op("LDX", Absolute, false), op("LAX", Absolute, true),
+    op("BCS", Relative, false), op("LDA", IndirectY, false), op("JAM", Implied, true), op("LAX", IndirectY, true),
+    op("LDY", ZeroPageX, false), op("LDA", ZeroPageX, false), op("LDX", ZeroPageY, false), op("LAX", ZeroPageY, true),
+    op("CLV", Implied, false), op("LDA", AbsoluteY, false), op("TSX", Implied, false), op("LAS", AbsoluteY, true),
+    op("LDY", AbsoluteX, false), op("LDA", AbsoluteX, false), op("LDX", AbsoluteY, false), op("LAX", AbsoluteY, true),
+    op("CPY", Immediate, false), op("CMP", IndirectX, false), op("NOP", Immediate, true), op("DCP", IndirectX, true),
+    op("CPY", ZeroPage, false), op("CMP", ZeroPage, false), op("DEC", ZeroPage, false), op("DCP", ZeroPage, true),
+    op("INY", Implied, false), op("CMP", Immediate, false), op("DEX", Implied, false), op("SBX", Immediate, true),
+    op("CPY", Absolute, false), op("CMP", Absolute, false), op("DEC", Absolute, false), op("DCP", Absolute, true),
+    op("BNE", Relative, false), op("CMP", IndirectY, false), op("JAM", Implied, true), op("DCP", IndirectY, true),
+    op("NOP", ZeroPageX, true), op("CMP", ZeroPageX, false), op("DEC", ZeroPageX, false), op("DCP", ZeroPageX, true),
+    op("CLD", Implied, false), op("CMP", AbsoluteY, false), op("NOP", Implied, true), op("DCP", AbsoluteY, true),
+    op("NOP", AbsoluteX, true), op("CMP", AbsoluteX, false), op("DEC", AbsoluteX, false), op("DCP", AbsoluteX, true),
+    op("CPX", Immediate, false), op("SBC", IndirectX, false), op("NOP", Immediate, true), op("ISB", IndirectX, true),
+    op("CPX", ZeroPage, false), op("SBC", ZeroPage, false), op("INC", ZeroPage, false), op("ISB", ZeroPage, true),
+    op("INX", Implied, false), op("SBC", Immediate, false), op("NOP", Implied, false), op("SBC", Immediate, true),
+    op("CPX", Absolute, false), op("SBC", Absolute, false), op("INC", Absolute, false), op("ISB", Absolute, true),
+    op("BEQ", Relative, false), op("SBC", IndirectY, false), op("JAM", Implied, true), op("ISB", IndirectY, true),
+    op("NOP", ZeroPageX, true), op("SBC", ZeroPageX, false), op("INC", ZeroPageX, false), op("ISB", ZeroPageX, true),
+    op("SED", Implied, false), op("SBC", AbsoluteY, false), op("NOP", Implied, true), op("ISB", AbsoluteY, true),
+    op("NOP", AbsoluteX, true), op("SBC", AbsoluteX, false), op("INC", AbsoluteX, false), op("ISB", AbsoluteX, true),
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CpuLogLine {
+    pub pc: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub operand: String,
+    pub unofficial: bool,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub cycles: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Mismatch {
+    pub line: usize,
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl CpuLogLine {
+    // Captures the state of the CPU right before it executes the instruction at the program counter.
+    pub fn capture(cpu: &mut CPU) -> Self {
+        let pc = cpu.program_counter;
+        let opcode = OPCODES[cpu.memory.read_byte(pc) as usize];
+        let bytes: Vec<u8> = (0..=opcode.mode.operand_len())
+            .map(|i| cpu.memory.read_byte(pc.wrapping_add(i as u16)))
+            .collect();
+        CpuLogLine {
+            pc,
+            operand: CpuLogLine::format_operand(pc, opcode.mode, &bytes),
+            bytes,
+            mnemonic: opcode.mnemonic.to_string(),
+            unofficial: opcode.unofficial,
+            a: cpu.register_a,
+            x: cpu.register_x,
+            y: cpu.register_y,
+            p: cpu.status.get_value() & !0b0001_0000,
+            sp: cpu.stack,
+            cycles: cpu.cycles,
+        }
+    }
+
+    // Parses a line of a Nintendulator log. Memory annotations and the PPU position are dropped.
+    pub fn parse(line: &str) -> Option<Self> {
+        let pc = u16::from_str_radix(line.get(0..4)?, 16).ok()?;
+        let bytes = line.get(6..14)?.split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let unofficial = line.get(15..16)? == "*";
+        let registers_start = line.find("A:")?;
+        let mut disassembly = line.get(16..registers_start)?.split_whitespace();
+        let mnemonic = disassembly.next()?.to_string();
+        let operand = disassembly.next().unwrap_or("").to_string();
+
+        let mut registers = [0; 5];
+        let mut cycles = None;
+        for token in line[registers_start..].split_whitespace() {
+            let Some((name, value)) = token.split_once(':') else { continue };
+            match name {
+                "A" => registers[0] = u8::from_str_radix(value, 16).ok()?,
+                "X" => registers[1] = u8::from_str_radix(value, 16).ok()?,
+                "Y" => registers[2] = u8::from_str_radix(value, 16).ok()?,
+                "P" => registers[3] = u8::from_str_radix(value, 16).ok()?,
+                "SP" => registers[4] = u8::from_str_radix(value, 16).ok()?,
+                "CYC" => cycles = value.parse().ok(),
+                _ => {},
+            }
+        }
+
+        Some(CpuLogLine {
+            pc,
+            bytes,
+            mnemonic,
+            operand,
+            unofficial,
+            a: registers[0],
+            x: registers[1],
+            y: registers[2],
+            p: registers[3],
+            sp: registers[4],
+            cycles: cycles?,
+        })
+    }
+
+    fn format_operand(pc: u16, mode: AddressingMode, bytes: &[u8]) -> String {
+        let byte = || bytes[1];
+        let addr = || u16::from_le_bytes([bytes[1], bytes[2]]);
+        match mode {
+            Implied => String::new(),
+            Accumulator => "A".to_string(),
+            Immediate => format!("#${:02X}", byte()),
+            ZeroPage => format!("${:02X}", byte()),
+            ZeroPageX => format!("${:02X},X", byte()),
+            ZeroPageY => format!("${:02X},Y", byte()),
+            Absolute => format!("${:04X}", addr()),
+            AbsoluteX => format!("${:04X},X", addr()),
+            AbsoluteY => format!("${:04X},Y", addr()),
+            Indirect => format!("(${:04X})", addr()),
+            IndirectX => format!("(${:02X},X)", byte()),
+            IndirectY => format!("(${:02X}),Y", byte()),
+            Relative => format!("${:04X}", pc.wrapping_add(2).wrapping_add_signed(byte() as i8 as i16)),
+        }
+    }
+
+    fn diff(&self, line: usize, expected: &CpuLogLine) -> Vec<Mismatch> {
+        let hex = |bytes: &Vec<u8>| bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" ");
+        let fields = [
+            ("PC", format!("{:04X}", expected.pc), format!("{:04X}", self.pc)),
+            ("bytes", hex(&expected.bytes), hex(&self.bytes)),
+            ("mnemonic", expected.mnemonic.clone(), self.mnemonic.clone()),
+            ("A", format!("{:02X}", expected.a), format!("{:02X}", self.a)),
+            ("X", format!("{:02X}", expected.x), format!("{:02X}", self.x)),
+            ("Y", format!("{:02X}", expected.y), format!("{:02X}", self.y)),
+            ("P", format!("{:02X}", expected.p), format!("{:02X}", self.p)),
+            ("SP", format!("{:02X}", expected.sp), format!("{:02X}", self.sp)),
+            ("CYC", expected.cycles.to_string(), self.cycles.to_string()),
+        ];
+        fields.into_iter()
+            .filter(|(_, expected, actual)| expected != actual)
+            .map(|(field, expected, actual)| Mismatch { line, field, expected, actual })
+            .collect()
+    }
+}
+
+impl fmt::Display for CpuLogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" ");
+        let disassembly = format!("{} {}", self.mnemonic, self.operand);
+        write!(f, "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc, bytes, if self.unofficial { '*' } else { ' ' }, disassembly.trim_end(),
+            self.a, self.x, self.y, self.p, self.sp, self.cycles)
+    }
+}
+
+// Diffs a trace against a reference log (one line per instruction) and reports every field that
+// differs on the first diverging line. An empty result means the traces agree.
+pub fn compare_log(actual: &[CpuLogLine], expected: &str) -> Vec<Mismatch> {
+    let expected_lines = expected.lines().filter(|line| !line.trim().is_empty());
+    for (idx, expected_line) in expected_lines.enumerate() {
+        let line = idx + 1;
+        let Some(expected) = CpuLogLine::parse(expected_line) else {
+            return vec![Mismatch { line, field: "line", expected: expected_line.to_string(), actual: "unparseable".to_string() }];
+        };
+        let Some(actual) = actual.get(idx) else {
+            return vec![Mismatch { line, field: "line", expected: expected_line.to_string(), actual: "end of trace".to_string() }];
+        };
+        let mismatches = actual.diff(line, &expected);
+        if !mismatches.is_empty() {
+            return mismatches;
+        }
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESTEST_LINES: &str = "\
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 36 CYC:12
+C72A  04 A9    *NOP $A9 = 00                    A:AA X:97 Y:4E P:EF SP:F5 PPU: 14, 58 CYC:1654";
+
+    #[test]
+    fn test_parse() {
+        let lines: Vec<CpuLogLine> = NESTEST_LINES.lines().map(|line| CpuLogLine::parse(line).unwrap()).collect();
+        assert_eq!(lines[0].pc, 0xC000);
+        assert_eq!(lines[0].bytes, vec![0x4C, 0xF5, 0xC5]);
+        assert_eq!(lines[0].mnemonic, "JMP");
+        assert_eq!(lines[0].operand, "$C5F5");
+        assert_eq!((lines[0].a, lines[0].p, lines[0].sp, lines[0].cycles), (0x00, 0x24, 0xFD, 7));
+        assert_eq!((lines[2].p, lines[2].cycles), (0x26, 12));
+        assert!(lines[3].unofficial);
+        assert_eq!(lines[3].mnemonic, "NOP");
+        assert_eq!((lines[3].a, lines[3].x, lines[3].y, lines[3].sp), (0xAA, 0x97, 0x4E, 0xF5));
+    }
+
+    #[test]
+    fn test_display() {
+        let line = CpuLogLine::parse(NESTEST_LINES.lines().next().unwrap()).unwrap();
+        assert_eq!(line.to_string(), "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7");
+        let line = CpuLogLine::parse(NESTEST_LINES.lines().nth(3).unwrap()).unwrap();
+        assert_eq!(line.to_string(), "C72A  04 A9    *NOP $A9                         A:AA X:97 Y:4E P:EF SP:F5 CYC:1654");
+        assert_eq!(CpuLogLine::parse(&line.to_string()), Some(line));
+    }
+
+    #[test]
+    fn test_format_operand() {
+        assert_eq!(CpuLogLine::format_operand(0xC000, Relative, &[0xD0, 0xFE]), "$C000");
+        assert_eq!(CpuLogLine::format_operand(0xC000, IndirectY, &[0xB1, 0x80]), "($80),Y");
+        assert_eq!(CpuLogLine::format_operand(0xC000, Indirect, &[0x6C, 0xFF, 0x02]), "($02FF)");
+        assert_eq!(CpuLogLine::format_operand(0xC000, Accumulator, &[0x0A]), "A");
+    }
+
+    #[test]
+    fn test_opcodes() {
+        assert_eq!(OPCODES[CPU::LDA_IN_Y as usize], op("LDA", IndirectY, false));
+        assert_eq!(OPCODES[CPU::JMP_IN as usize], op("JMP", Indirect, false));
+        assert_eq!(OPCODES[CPU::SBC_IM_U as usize], op("SBC", Immediate, true));
+        assert_eq!(OPCODES[CPU::LAX_ZP_Y as usize], op("LAX", ZeroPageY, true));
+        assert_eq!(OPCODES.iter().filter(|opcode| !opcode.unofficial).count(), 151);
+    }
+
+    #[test]
+    fn test_compare_log() {
+        let expected: Vec<CpuLogLine> = NESTEST_LINES.lines().map(|line| CpuLogLine::parse(line).unwrap()).collect();
+        assert!(compare_log(&expected, NESTEST_LINES).is_empty());
+
+        let mut actual = expected.clone();
+        actual[2].p = 0x24;
+        actual[2].cycles = 13;
+        actual[3].a = 0x00;
+        assert_eq!(compare_log(&actual, NESTEST_LINES), vec![
+            Mismatch { line: 3, field: "P", expected: "26".to_string(), actual: "24".to_string() },
+            Mismatch { line: 3, field: "CYC", expected: "12".to_string(), actual: "13".to_string() },
+        ]);
+
+        let mismatches = compare_log(&expected[..2], NESTEST_LINES);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!((mismatches[0].line, mismatches[0].actual.as_str()), (3, "end of trace"));
+    }
+}
@@ -1,4 +1,12 @@
+use std::io;
+use std::path::Path;
+use image::{ExtendedColorType, ImageEncoder, ImageFormat};
+use image::codecs::png::PngEncoder;
+
+#[derive(Clone)]
 pub struct Frame {
+    pub width: usize,
+    pub height: usize,
     pub background: Vec<u8>,
     pub background_priority: Vec<u8>,
     pub sprite: Vec<u8>,
@@ -14,11 +22,18 @@ impl Frame {
     pub const FG_PRIORITY: u8 = 2;
 
     pub fn new() -> Self {
+        Frame::with_size(Frame::WIDTH, Frame::HEIGHT)
+    }
+
+    // debug views (nametables, pattern tables, ...) don't share the NES screen's dimensions
+    pub fn with_size(width: usize, height: usize) -> Self {
         Frame {
-            background: vec![0; 3 * Frame::WIDTH * Frame::HEIGHT],
-            background_priority: vec![0; Frame::WIDTH * Frame::HEIGHT],
-            sprite: vec![0; 3 * Frame::WIDTH * Frame::HEIGHT],
-            sprite_priority: vec![0; Frame::WIDTH * Frame::HEIGHT],
+            width,
+            height,
+            background: vec![0; 3 * width * height],
+            background_priority: vec![0; width * height],
+            sprite: vec![0; 3 * width * height],
+            sprite_priority: vec![0; width * height],
         }
     }
 
@@ -26,10 +41,10 @@ impl Frame {
     pub fn clear(&mut self) {
         // todo: hold on... do we even need to clear the screen?
         //  Frame gets redrawn each time anyways
-        self.background = vec![0; 3 * Frame::WIDTH * Frame::HEIGHT];
-        self.background_priority = vec![0; Frame::WIDTH * Frame::HEIGHT];
-        self.sprite = vec![0; 3 * Frame::WIDTH * Frame::HEIGHT];
-        self.sprite_priority = vec![0; Frame::WIDTH * Frame::HEIGHT];
+        self.background = vec![0; 3 * self.width * self.height];
+        self.background_priority = vec![0; self.width * self.height];
+        self.sprite = vec![0; 3 * self.width * self.height];
+        self.sprite_priority = vec![0; self.width * self.height];
     }
 
     // #[inline]
@@ -43,8 +58,8 @@ impl Frame {
 
     #[inline]
     pub fn get_background_color(&self, x: usize, y: usize) -> (u8, u8, u8) {
-        if x < Frame::WIDTH && y < Frame::HEIGHT {
-            let base = 3 * Frame::WIDTH * y + 3 * x;
+        if x < self.width && y < self.height {
+            let base = 3 * self.width * y + 3 * x;
             return (self.background[base], self.background[base + 1], self.background[base + 2]);
         }
         return (0, 0, 0);
@@ -52,8 +67,8 @@ impl Frame {
 
     #[inline]
     pub fn get_sprite_color(&self, x: usize, y: usize) -> (u8, u8, u8) {
-        if x < Frame::WIDTH && y < Frame::HEIGHT {
-            let base = 3 * Frame::WIDTH * y + 3 * x;
+        if x < self.width && y < self.height {
+            let base = 3 * self.width * y + 3 * x;
             return (self.sprite[base], self.sprite[base + 1], self.sprite[base + 2]);
         }
         return (0, 0, 0);
@@ -61,24 +76,24 @@ impl Frame {
 
     #[inline]
     pub fn get_background_priority(&self, x: usize, y: usize) -> u8 {
-        if x < Frame::WIDTH && y < Frame::HEIGHT {
-            return self.background_priority[Frame::WIDTH * y + x];
+        if x < self.width && y < self.height {
+            return self.background_priority[self.width * y + x];
         }
         return 0;
     }
 
     #[inline]
     pub fn get_sprite_priority(&self, x: usize, y: usize) -> u8 {
-        if x < Frame::WIDTH && y < Frame::HEIGHT {
-            return self.sprite_priority[Frame::WIDTH * y + x];
+        if x < self.width && y < self.height {
+            return self.sprite_priority[self.width * y + x];
         }
         return 0;
     }
 
     #[inline]
     pub fn set_background_color(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
-        if x < Frame::WIDTH && y < Frame::HEIGHT {
-            let base = 3 * Frame::WIDTH * y + 3 * x;
+        if x < self.width && y < self.height {
+            let base = 3 * self.width * y + 3 * x;
             self.background[base] = rgb.0;
             self.background[base + 1] = rgb.1;
             self.background[base + 2] = rgb.2;
@@ -94,8 +109,8 @@ impl Frame {
 
     #[inline]
     pub fn set_background_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8), priority: u8) {
-        if x < Frame::WIDTH && y < Frame::HEIGHT {
-            let base_pri = Frame::WIDTH * y + x;
+        if x < self.width && y < self.height {
+            let base_pri = self.width * y + x;
             let base_rgb = 3 * base_pri;
             self.background[base_rgb] = rgb.0;
             self.background[base_rgb + 1] = rgb.1;
@@ -106,9 +121,9 @@ impl Frame {
 
     #[inline]
     pub fn set_sprite_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8), priority: u8) {
-        if x < Frame::WIDTH && y < Frame::HEIGHT {
-            let base_pri = Frame::WIDTH * y + x;
-            let base_rgb = 3 * (Frame::WIDTH * y + x);
+        if x < self.width && y < self.height {
+            let base_pri = self.width * y + x;
+            let base_rgb = 3 * (self.width * y + x);
             self.sprite[base_rgb] = rgb.0;
             self.sprite[base_rgb + 1] = rgb.1;
             self.sprite[base_rgb + 2] = rgb.2;
@@ -116,18 +131,53 @@ impl Frame {
         }
     }
 
+    // Ref: https://www.nesdev.org/wiki/PPU_rendering#Preface
+    // Which layer ends up on screen, going by the priorities left at a pixel:
+    //
+    //  sprite                 background               shown
+    //  EMPTY (transparent)    any                      background
+    //  FG (in front)          any                      sprite
+    //  BG (behind)            BG (transparent)         sprite
+    //  BG (behind)            FG (opaque)              background
+    //
+    // A transparent background pixel is drawn in the universal background color ($3F00), so that's
+    // what shows through around a sprite that's behind the background.
+    #[inline]
+    pub fn is_sprite_shown(sprite_priority: u8, background_priority: u8) -> bool {
+        match sprite_priority {
+            Frame::FG_PRIORITY => true,
+            Frame::BG_PRIORITY => background_priority == Frame::BG_PRIORITY,
+            _ => false,
+        }
+    }
+
+    // Draws the sprite layer over the background layer, so the background holds the finished
+    // picture afterwards. Compose a clone when the layers are still needed.
     #[inline]
     pub fn compose(&mut self) -> &Vec<u8> {
-        for y in 0..Frame::HEIGHT {
-            for x in 0..Frame::WIDTH {
+        for y in 0..self.height {
+            for x in 0..self.width {
                 let sp = self.get_sprite_priority(x, y);
                 let bp = self.get_background_priority(x, y);
-                let is_foreground = sp == Frame::FG_PRIORITY || bp == Frame::BG_PRIORITY;
-                if sp != Frame::EMPTY_PRIORITY && is_foreground {
+                if Frame::is_sprite_shown(sp, bp) {
                     self.set_background_color(x, y, self.get_sprite_color(x, y));
                 }
             }
         }
         return &self.background;
     }
-}
\ No newline at end of file
+
+    // encodes the background layer, which holds the finished picture once compose() has run
+    pub fn to_png_bytes(&self) -> Vec<u8> {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(&self.background, self.width as u32, self.height as u32, ExtendedColorType::Rgb8)
+            .expect("unable to encode PNG");
+        png
+    }
+
+    pub fn export_png(&self, path: &Path) -> io::Result<()> {
+        image::save_buffer_with_format(path, &self.background, self.width as u32, self.height as u32, ExtendedColorType::Rgb8, ImageFormat::Png)
+            .map_err(io::Error::other)
+    }
+}
@@ -4,6 +4,20 @@ use crate::util::bitvec::BitVector;
 
 pub mod joycon_status;
 
+// The buttons held on both controllers, one bit per button in the order they're shifted out (A in
+// bit 0, Right in bit 7), see JoyconButton.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ControllerInputs {
+    pub player1: u8,
+    pub player2: u8,
+}
+
+impl ControllerInputs {
+    pub fn new() -> Self {
+        ControllerInputs { player1: 0, player2: 0 }
+    }
+}
+
 pub struct Joycon {
     strobe: bool,
     button_index: u8,
@@ -11,6 +25,10 @@ pub struct Joycon {
 }
 
 impl Joycon {
+    // Only D0 is driven by a standard controller. The upper bits float and read back the last value
+    // on the data bus, which for `LDA $4016` is the high byte of the address ($40).
+    pub const OPEN_BUS_BITS: u8 = 0x40;
+
     pub fn new() -> Self {
         Joycon {
             strobe: false,
@@ -27,17 +45,28 @@ impl Joycon {
     }
 
     pub fn read(&mut self) -> u8 {
-        if self.button_index > 7 {
-            return 1;
+        // while strobe is held high the shift register keeps reloading, so we keep reporting A
+        if self.strobe {
+            self.button_index = 0;
         }
-        let button = JoyconButton::from_value(self.button_index);
-        let response = self.button_status.is_set(button) as u8;
-        if !self.strobe {
+        let response = self.peek();
+        if !self.strobe && self.button_index <= 7 {
             self.button_index += 1;
         }
         response
     }
 
+    // The bit the next read would return, without shifting the register.
+    pub fn peek(&self) -> u8 {
+        let button_index = if self.strobe { 0 } else { self.button_index };
+        // a standard controller shifts in 1s after all eight buttons have been reported
+        if button_index > 7 {
+            return 1 | Joycon::OPEN_BUS_BITS;
+        }
+        let button = JoyconButton::from_value(button_index);
+        self.button_status.is_set(button) as u8 | Joycon::OPEN_BUS_BITS
+    }
+
     pub fn set_button(&mut self, button: JoyconButton) {
         self.button_status.set(button);
     }
@@ -45,4 +74,84 @@ impl Joycon {
     pub fn clear_button(&mut self, button: JoyconButton) {
         self.button_status.clear(button);
     }
+
+    // All eight buttons at once, in the order they're shifted out (A in bit 0, Right in bit 7).
+    pub fn get_buttons(&self) -> u8 {
+        self.button_status.get_value()
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.button_status.set_value(buttons);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strobe(joycon: &mut Joycon) {
+        joycon.write(1);
+        joycon.write(0);
+    }
+
+    #[test]
+    fn test_read_buttons() {
+        let mut joycon = Joycon::new();
+        joycon.set_button(JoyconButton::A);
+        joycon.set_button(JoyconButton::Start);
+        joycon.set_button(JoyconButton::Right);
+        strobe(&mut joycon);
+        let bits: Vec<u8> = (0..8).map(|_| joycon.read() & 1).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut joycon = Joycon::new();
+        joycon.set_button(JoyconButton::A);
+        strobe(&mut joycon);
+        assert_eq!(joycon.peek(), 1 | Joycon::OPEN_BUS_BITS);
+        assert_eq!(joycon.peek(), 1 | Joycon::OPEN_BUS_BITS);
+        joycon.read();
+        assert_eq!(joycon.peek(), Joycon::OPEN_BUS_BITS);
+    }
+
+    #[test]
+    fn test_read_open_bus_bits() {
+        let mut joycon = Joycon::new();
+        strobe(&mut joycon);
+        for _ in 0..8 {
+            assert_eq!(joycon.read(), Joycon::OPEN_BUS_BITS);
+        }
+    }
+
+    #[test]
+    fn test_read_after_eight_reads() {
+        let mut joycon = Joycon::new();
+        strobe(&mut joycon);
+        for _ in 0..8 {
+            joycon.read();
+        }
+        for _ in 0..16 {
+            assert_eq!(joycon.read(), 1 | Joycon::OPEN_BUS_BITS);
+        }
+        strobe(&mut joycon);
+        assert_eq!(joycon.read(), Joycon::OPEN_BUS_BITS);
+    }
+
+    #[test]
+    fn test_read_strobe_high() {
+        let mut joycon = Joycon::new();
+        joycon.set_button(JoyconButton::A);
+        joycon.write(1);
+        for _ in 0..10 {
+            assert_eq!(joycon.read(), 1 | Joycon::OPEN_BUS_BITS);
+        }
+        joycon.clear_button(JoyconButton::A);
+        joycon.set_button(JoyconButton::B);
+        assert_eq!(joycon.read(), Joycon::OPEN_BUS_BITS);
+        joycon.write(0);
+        assert_eq!(joycon.read(), Joycon::OPEN_BUS_BITS);
+        assert_eq!(joycon.read(), 1 | Joycon::OPEN_BUS_BITS);
+    }
 }
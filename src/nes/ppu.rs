@@ -1,12 +1,17 @@
 pub mod mem;
 pub mod oam;
+pub mod palette;
+pub mod pipeline;
 pub mod registers;
+pub mod viewer;
 
+use crate::nes::Region;
 use crate::nes::io::frame::Frame;
-use crate::nes::NES;
 use crate::util::bitvec::BitVector;
 use crate::nes::ppu::mem::PPUMemory;
-use crate::nes::ppu::oam::OAM;
+use crate::nes::ppu::oam::{SpriteInfo, OAM};
+use crate::nes::ppu::palette::{build_emphasis_palettes, MasterPalette, Palette, DEFAULT_PALETTE, EMPHASIS_ATTENUATION};
+use crate::nes::ppu::pipeline::{BackgroundShifter, SpriteUnit};
 use crate::nes::ppu::registers::addr::AddressRegister;
 use crate::nes::ppu::registers::scroll::ScrollRegister;
 use crate::nes::ppu::registers::ctrl::ControlRegister;
@@ -15,7 +20,7 @@ use crate::nes::ppu::registers::mask::{MaskFlag, MaskRegister};
 use crate::nes::ppu::registers::mask::MaskFlag::{ShowBackground, ShowSprites};
 use crate::nes::ppu::registers::scrollctx::ScrollContext;
 use crate::nes::ppu::registers::status::StatusRegister;
-use crate::nes::ppu::registers::status::StatusFlag::{SpriteZeroHit, VerticalBlank};
+use crate::nes::ppu::registers::status::StatusFlag::{SpriteOverflow, SpriteZeroHit, VerticalBlank};
 
 pub struct PPU {
     pub addr: AddressRegister,
@@ -38,11 +43,26 @@ pub struct PPU {
     pub frame: Frame,
     pub oam: OAM,
     pub scroll_ctx: ScrollContext,
+    pub emphasis_palettes: [MasterPalette; 8],
+    pub emphasis_attenuation: f64,
     pub data_buffer: u8,
+    pub ppu_bus_latch: u8,
+    ppu_bus_latch_age: usize,
+    pub scanline_sprites: Vec<usize>,
+    pub sprite_zero_in_range: bool,
+    pub bg_shifter: BackgroundShifter,
+    pub sprite_units: Vec<SpriteUnit>,
 
     pub cycles: usize,
+    dots_ahead: usize,
+    dot_count: u64,
     pub scanline: isize,
+    pub dot: usize,
+    pub odd_frame: bool,
     pub nmi_flag: bool,
+    vblank_suppressed: bool,
+    region: Region,
+    clock_remainder: usize,
 }
 
 impl PPU {
@@ -50,11 +70,16 @@ impl PPU {
     const VISIBLE_SCANLINE_START: isize = 0;
     const VISIBLE_SCANLINE_END: isize = 239;
     const POST_RENDER_SCANLINE: isize = 240;
-    const VBLANK_SCANLINE_START: isize = 241;
-    const VBLANK_SCANLINE_END: isize = 260;
     const SCANLINE_CYCLES: usize = 341;
+    const OAM_SPRITES: usize = 64;
+    const MAX_SPRITES_PER_SCANLINE: usize = 8;
+    const PPU_BUS_LATCH_DECAY_FRAMES: usize = 36; // ~600ms
 
     pub fn new() -> Self {
+        PPU::with_palette(&DEFAULT_PALETTE)
+    }
+
+    pub fn with_palette(palette: &Palette) -> Self {
         Self {
             addr: AddressRegister::new(),
             data: 0,
@@ -69,199 +94,455 @@ impl PPU {
             frame: Frame::new(),
             oam: OAM::new(),
             scroll_ctx: ScrollContext::new(),
+            emphasis_palettes: build_emphasis_palettes(palette.get_colors(), EMPHASIS_ATTENUATION),
+            emphasis_attenuation: EMPHASIS_ATTENUATION,
             data_buffer: 0,
+            ppu_bus_latch: 0,
+            ppu_bus_latch_age: 0,
+            scanline_sprites: Vec::with_capacity(PPU::MAX_SPRITES_PER_SCANLINE),
+            sprite_zero_in_range: false,
+            bg_shifter: BackgroundShifter::new(),
+            sprite_units: Vec::with_capacity(PPU::MAX_SPRITES_PER_SCANLINE),
 
             scanline: -1,
+            dot: 0,
+            odd_frame: false,
             cycles: 0,
+            dots_ahead: 0,
+            dot_count: 0,
             nmi_flag: false,
+            vblank_suppressed: false,
+            region: Region::Ntsc,
+            clock_remainder: 0,
         }
     }
 
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.clock_remainder = 0;
+    }
+
+    // Dots run since power on, the clock mappers watching the PPU bus time things by.
+    pub fn get_dot_count(&self) -> u64 {
+        self.dot_count
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
+
+    // Three dots per CPU cycle on NTSC and Dendy, 3.2 on PAL, where the leftover master clocks are
+    // carried over to the next call.
+    #[inline]
+    fn cpu_cycles_to_dots(&self, cycles: usize) -> (usize, usize) {
+        let master_clocks = cycles * self.region.get_cpu_divisor() + self.clock_remainder;
+        let ppu_divisor = self.region.get_ppu_divisor();
+        (master_clocks / ppu_divisor, master_clocks % ppu_divisor)
+    }
+
     pub fn tick(&mut self, cycles: u8) {
-        self.cycles += 3 * cycles as usize;
+        // dots already run by a catch-up are paid back first
+        let (dots, remainder) = self.cpu_cycles_to_dots(cycles as usize);
+        self.clock_remainder = remainder;
+        let paid = usize::min(dots, self.dots_ahead);
+        self.dots_ahead -= paid;
+        self.cycles += dots - paid;
     }
 
     pub fn step(&mut self) -> Result<bool, bool> {
-        if self.cycles >= PPU::SCANLINE_CYCLES {
-            self.cycles = self.cycles - PPU::SCANLINE_CYCLES;
+        // a DMA stall can leave several scanlines to catch up on
+        while self.cycles > 0 {
+            if self.is_idle_scanline() {
+                // nothing but the dot counter moves here, so skip ahead to the end of the line
+                let dots = usize::min(self.cycles, PPU::SCANLINE_CYCLES - 1 - self.dot);
+                if dots > 0 {
+                    self.dot += dots;
+                    self.dot_count += dots as u64;
+                    self.cycles -= dots;
+                    continue;
+                }
+            }
+            self.cycles -= 1;
+            self.tick_dot();
+        }
+
+        Ok(true)
+    }
+
+    // Called by the bus before a PPU register or mapper access, so that everything up to the access
+    // is drawn with the old state. The CPU ticks an instruction's cycles once it has finished, so
+    // the part of the current instruction before the access (cpu_cycles) is run ahead of time.
+    pub fn catch_up(&mut self, cpu_cycles: u8) {
+        self.step().unwrap();
+        let (target, _) = self.cpu_cycles_to_dots(cpu_cycles as usize);
+        while self.dots_ahead < target {
+            self.tick_dot();
+            self.dots_ahead += 1;
+        }
+    }
+
+    #[inline]
+    fn is_idle_scanline(&self) -> bool {
+        self.scanline == PPU::POST_RENDER_SCANLINE
+            || (self.scanline == self.region.get_vblank_scanline() && self.dot > 1)
+            || (self.scanline > PPU::POST_RENDER_SCANLINE && self.scanline != self.region.get_vblank_scanline())
+    }
 
-            if self.scanline == PPU::PRE_RENDER_SCANLINE {
+    // Advances the PPU by one dot (one PPU clock, three per CPU cycle on NTSC, see Region).
+    pub fn tick_dot(&mut self) {
+        if self.scanline <= PPU::VISIBLE_SCANLINE_END {
+            if self.scanline == PPU::PRE_RENDER_SCANLINE && self.dot == 1 {
                 self.clear_nmi();
                 self.status.clear(VerticalBlank);
                 self.status.clear(SpriteZeroHit);
+                self.status.clear(SpriteOverflow);
                 self.frame.clear();
             }
 
-            if self.scanline >= PPU::VISIBLE_SCANLINE_START && self.scanline <= PPU::VISIBLE_SCANLINE_END {
-                self.update_mapper4();
-                self.render_scanline();
+            if self.is_rendering_enabled() {
+                self.fetch_dot();
+            } else if self.dot == 257 {
+                self.scanline_sprites.clear();
+                self.sprite_units.clear();
             }
 
-            if self.scanline == PPU::VBLANK_SCANLINE_START {
-                self.update_mapper4();
+            if self.scanline >= PPU::VISIBLE_SCANLINE_START && self.dot >= 1 && self.dot <= Frame::WIDTH {
+                self.render_pixel(self.dot - 1);
+            }
+        } else if self.scanline == self.region.get_vblank_scanline() && self.dot == 1 {
+            self.decay_bus_latch();
+            if self.vblank_suppressed {
+                self.vblank_suppressed = false;
+            } else {
                 self.status.set(VerticalBlank);
                 if self.ctrl.is_set(GenerateNmi) {
                     // NMI is triggered when PPU enters VBLANK state
                     self.set_nmi();
                 }
             }
+        }
 
-            if self.scanline == PPU::VBLANK_SCANLINE_END {
-                self.scanline = -1;
+        self.dot += 1;
+        self.dot_count += 1;
+        // with rendering on, odd frames skip the last dot of the pre-render scanline (NTSC only)
+        let skip_dot = self.odd_frame && self.is_rendering_enabled() && self.region.skips_odd_frame_dot();
+        if self.scanline == PPU::PRE_RENDER_SCANLINE && self.dot == PPU::SCANLINE_CYCLES - 1 && skip_dot {
+            self.dot += 1;
+        }
+        if self.dot == PPU::SCANLINE_CYCLES {
+            self.dot = 0;
+            if self.scanline == self.region.get_last_scanline() {
+                self.scanline = PPU::PRE_RENDER_SCANLINE;
+                self.odd_frame = !self.odd_frame;
             } else {
                 self.scanline += 1;
             }
         }
-
-        Ok(true)
     }
 
+    // The memory accesses of the visible and pre-render scanlines: two tiles are prefetched at the
+    // end of each line, then every 8 dots fetch the tile 16 pixels ahead of the one being drawn.
     #[inline]
-    fn update_mapper4(&mut self) {
-        if self.memory.rom.mapper_id != 4 { return }
+    fn fetch_dot(&mut self) {
+        let dot = self.dot;
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.bg_shifter.shift();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.bg_shifter.load();
+                    self.bg_shifter.next_tile = self.fetch_byte(self.scroll_ctx.get_tile_address());
+                },
+                2 => self.bg_shifter.next_palette = self.fetch_attribute(),
+                4 => self.bg_shifter.next_lower = self.fetch_byte(self.bg_pattern_address()),
+                6 => self.bg_shifter.next_upper = self.fetch_byte(self.bg_pattern_address() + 8),
+                7 => self.scroll_ctx.scroll_x_increment(),
+                _ => {},
+            }
+        }
+
+        if (257..=320).contains(&dot) {
+            self.fetch_sprite_dot();
+        }
 
-        if self.mask.is_set(ShowBackground) && self.mask.is_set(ShowSprites) {
-            self.memory.rom.mapper4.decrement_irq_counter();
+        match dot {
+            // the nametable fetches at the start and end of a line that the pipeline above has no use
+            // for still go out on the bus, the MMC5 detects scanlines by them
+            1 | 339 => self.memory.rom.watch_ppu_bus(self.scroll_ctx.get_tile_address(), self.dot_count),
+            256 => self.scroll_ctx.scroll_y_increment(),
+            257 => {
+                self.scroll_ctx.copy_horizontal_bits();
+                self.evaluate_sprites_for_scanline();
+                self.sprite_units.clear();
+            },
+            280..=304 if self.scanline == PPU::PRE_RENDER_SCANLINE => self.scroll_ctx.copy_vertical_bits(),
+            _ => {},
         }
     }
 
+    // Every rendering fetch puts its address on the PPU bus, where mappers like the MMC3 watch it.
     #[inline]
-    pub fn render_scanline(&mut self) {
-        self.render_background_scanline();
-        self.render_sprites_scanline();
+    fn fetch_byte(&mut self, address: u16) -> u8 {
+        let data = self.memory.read_byte(address);
+        self.memory.rom.watch_ppu_bus(address, self.dot_count);
+        data
     }
 
+    // The sprite patterns for the next scanline are fetched over dots 257-320, 8 dots per slot:
+    // two nametable fetches that go unused, then the two pattern bytes. Empty slots fetch tile $FF,
+    // which mappers watching the bus (MMC2, MMC3, MMC5) still see.
     #[inline]
-    pub fn render_background_scanline(&mut self) {
-        self.scroll_ctx.handle_scanline_start(self.scanline);
+    fn fetch_sprite_dot(&mut self) {
+        let slot = (self.dot - 257) / 8;
+        let sprite_idx = self.scanline_sprites.get(slot).copied();
+        match ((self.dot - 1) % 8, sprite_idx) {
+            (0 | 2, _) => self.memory.rom.watch_ppu_bus(self.scroll_ctx.get_tile_address(), self.dot_count),
+            (4, Some(sprite_idx)) => {
+                let attributes = self.oam.memory[sprite_idx + 2];
+                let lower = self.fetch_byte(self.sprite_row_address(sprite_idx));
+                self.sprite_units.push(SpriteUnit {
+                    index: sprite_idx / 4,
+                    x: self.oam.memory[sprite_idx + 3],
+                    attributes,
+                    lower: if attributes & 0b0100_0000 != 0 { lower.reverse_bits() } else { lower },
+                    upper: 0,
+                });
+            },
+            (6, Some(sprite_idx)) => {
+                let upper = self.fetch_byte(self.sprite_row_address(sprite_idx) + 8);
+                if let Some(unit) = self.sprite_units.last_mut() {
+                    unit.upper = if unit.attributes & 0b0100_0000 != 0 { upper.reverse_bits() } else { upper };
+                }
+            },
+            (4, None) => self.memory.rom.watch_ppu_bus(self.sprite_pattern_address(0xFF, 0).0, self.dot_count),
+            (6, None) => self.memory.rom.watch_ppu_bus(self.sprite_pattern_address(0xFF, 0).0 + 8, self.dot_count),
+            _ => {},
+        }
+    }
+
+    #[inline]
+    fn fetch_attribute(&mut self) -> u8 {
+        let attr_byte = self.fetch_byte(self.scroll_ctx.get_attribute_address());
+        let v = self.scroll_ctx.v as usize;
+        PPU::attribute_palette(attr_byte, v & 0b1_1111, (v >> 5) & 0b1_1111)
+    }
 
-        let mut tile_lower_chr = 0;
-        let mut tile_upper_chr = 0;
-        let mut pallete = [0, 0, 0, 0];
+    // Ref: https://www.nesdev.org/wiki/PPU_attribute_tables
+    // Each attribute byte covers 4x4 tiles (32x32 pixels), one 2-bit palette per 2x2 quadrant:
+    //
+    //  7654 3210
+    //  |||| ||++- top left
+    //  |||| ++--- top right
+    //  ||++------ bottom left
+    //  ++-------- bottom right
+    #[inline]
+    pub fn attribute_palette(attr_byte: u8, tile_x: usize, tile_y: usize) -> u8 {
+        let shift = 4 * ((tile_y / 2) & 1) + 2 * ((tile_x / 2) & 1);
+        (attr_byte >> shift) & 0b11
+    }
 
+    #[inline]
+    fn bg_pattern_address(&self) -> u16 {
         let background_bank = self.ctrl.get_background_chrtable_address();
-        let screen_y = self.scanline as usize;
-        let pixel_y = 8 * self.scroll_ctx.get_coarse_scroll_y() + self.scroll_ctx.get_fine_scroll_y();
-        for screen_x in 0..Frame::WIDTH {
-            let pixel_x = screen_x + self.scroll_ctx.get_fine_scroll_x() as usize;
-            if screen_x == 0 || pixel_x % 8 == 0 {
-                let tile_address = self.scroll_ctx.get_tile_address();
-                let tile_value = self.memory.read_byte(tile_address) as u16;
-                let chr_address = background_bank + 16 * tile_value;
-                let chr_y = (pixel_y % 8) as u16;
-                tile_lower_chr = self.memory.read_byte(chr_address + chr_y);
-                tile_upper_chr = self.memory.read_byte(chr_address + chr_y + 8);
-                pallete = self.bg_palette();
-            }
+        background_bank + 16 * self.bg_shifter.next_tile as u16 + self.scroll_ctx.get_fine_scroll_y() as u16
+    }
 
-            if self.mask.is_set(MaskFlag::ShowBackgroundLeftmostEight) || screen_x >= 8 {
-                let chr_x = 7 - (pixel_x % 8);
-                let lower = tile_lower_chr >> chr_x;
-                let upper = tile_upper_chr >> chr_x;
-                let palette_value = (1 & upper) << 1 | (1 & lower);
-                let palette_index = pallete[palette_value as usize];
-                let rgb = NES::SYSTEM_PALLETE[palette_index as usize];
-                let priority = if palette_value == 0 { Frame::BG_PRIORITY } else { Frame::FG_PRIORITY };
-                self.frame.set_background_pixel(screen_x, screen_y, rgb, priority);
-            } else {
-                let rgb = NES::SYSTEM_PALLETE[pallete[0] as usize];
-                self.frame.set_background_pixel(screen_x, screen_y, rgb, Frame::BG_PRIORITY);
-            }
+    #[inline]
+    pub fn is_rendering_enabled(&self) -> bool {
+        self.mask.is_set(ShowBackground) || self.mask.is_set(ShowSprites)
+    }
+
+    #[inline]
+    pub fn is_rendering(&self) -> bool {
+        self.is_rendering_enabled() && self.scanline >= PPU::PRE_RENDER_SCANLINE && self.scanline <= PPU::VISIBLE_SCANLINE_END
+    }
 
-            if pixel_x % 8 == 7 {
-                self.scroll_ctx.scroll_x_increment();
+    #[inline]
+    fn render_pixel(&mut self, x: usize) {
+        let y = self.scanline as usize;
+
+        let (bg_value, bg_palette) = if self.is_background_visible(x) {
+            self.bg_shifter.get_pixel(self.scroll_ctx.get_fine_scroll_x())
+        } else {
+            (0, 0)
+        };
+        let bg_address = if bg_value == 0 {
+            self.backdrop_address()
+        } else {
+            PPUMemory::PALLETES_START + 4 * bg_palette as u16 + bg_value as u16
+        };
+        let rgb = self.apply_post_processing(self.memory.read_byte(bg_address));
+        let priority = if bg_value == 0 { Frame::BG_PRIORITY } else { Frame::FG_PRIORITY };
+        self.frame.set_background_pixel(x, y, rgb, priority);
+
+        if !self.is_sprite_visible(x) { return }
+
+        // the first opaque sprite in OAM order wins, even if it ends up behind the background
+        for slot in 0..self.sprite_units.len() {
+            let sprite = self.sprite_units[slot];
+            let value = sprite.get_pixel(x);
+            if value == 0 { continue }
+
+            let address = PPUMemory::PALLETES_START + 0x10 + 4 * sprite.get_palette() as u16 + value as u16;
+            let rgb = self.apply_post_processing(self.memory.read_byte(address));
+            let priority = if sprite.is_behind_background() { Frame::BG_PRIORITY } else { Frame::FG_PRIORITY };
+            self.frame.set_sprite_pixel(x, y, rgb, priority);
+            if sprite.index == 0 && self.sprite_zero_in_range && self.is_sprite_zero_hit(x, y) {
+                self.status.set(SpriteZeroHit);
             }
+            break;
         }
+    }
 
-        self.scroll_ctx.scroll_y_increment();
+    // Ref: https://www.nesdev.org/wiki/PPU_palettes#The_background_palette_hack
+    // The backdrop is the universal background color at $3F00, except with rendering off: nothing
+    // drives the PPU address bus then but the VRAM address, and if that points into palette RAM
+    // the entry it points to is shown instead.
+    #[inline]
+    fn backdrop_address(&self) -> u16 {
+        let vram_addr = self.get_vram_addr();
+        if !self.is_rendering_enabled() && vram_addr >= PPUMemory::PALLETES_START {
+            vram_addr
+        } else {
+            PPUMemory::PALLETES_START
+        }
     }
 
     #[inline]
-    pub fn render_sprites_scanline(&mut self) {
-        let sprites_bank = self.ctrl.get_sprite_chrtable_address();
-        let sprite_size = if self.ctrl.is_set(SpriteSize) { 16 } else { 8 };
-
-        let screen_y = if self.scanline == 0 { 0 } else { self.scanline - 1 } as usize;
-        for sprite_idx in (0..self.oam.memory.len()).step_by(4).rev() {
-            let sprite_x = self.oam.memory[sprite_idx + 3] as usize;
-            let sprite_y = self.oam.memory[sprite_idx] as usize;
-
-            if screen_y < sprite_y || screen_y >= sprite_y + sprite_size { continue }
-
-            let priority = if self.oam.memory[sprite_idx + 2] >> 5 & 1 == 0 { Frame::FG_PRIORITY } else { Frame::BG_PRIORITY } ;
-            let mut tile_value = self.oam.memory[sprite_idx + 1] as u16;
-
-            let flip_vertical = self.oam.memory[sprite_idx + 2] >> 7 & 1 == 1;
-            let flip_horizontal = self.oam.memory[sprite_idx + 2] >> 6 & 1 == 1;
-            let palette_idx = self.oam.memory[sprite_idx + 2] & 0b0000_0011;
-            let sprite_palette = self.sprite_palette(palette_idx);
-
-            let y = screen_y - sprite_y;
-            let mut chr_y = if flip_vertical { sprite_size - 1 - y } else { y } as u16;
-            let mut tile_addr = sprites_bank + 16 * tile_value;
-            if sprite_size == 16 {
-                let sprites_bank = if tile_value & 1 == 1 { 0x1000 } else { 0x0000 };
-                tile_value = if tile_value % 2 == 1 { tile_value - 1 } else { tile_value };
-                tile_value = if chr_y >= 8 { tile_value + 1 } else { tile_value };
-                tile_addr = sprites_bank + 16 * tile_value;
-                chr_y = chr_y % 8;
+    pub fn evaluate_sprites_for_scanline(&mut self) {
+        self.scanline_sprites.clear();
+        self.sprite_zero_in_range = false;
+
+        // evaluation happens on the scanline before the sprites are drawn, and never on pre-render
+        if self.scanline < PPU::VISIBLE_SCANLINE_START { return }
+
+        let sprite_size = self.ctrl.get_sprite_height();
+        let screen_y = self.scanline as usize;
+        let in_range = |y: u8| screen_y >= y as usize && screen_y < y as usize + sprite_size;
+
+        let mut n = 0;
+        while n < PPU::OAM_SPRITES {
+            let sprite_idx = 4 * n;
+            n += 1;
+            if !in_range(self.oam.memory[sprite_idx]) { continue }
+
+            if sprite_idx == 0 {
+                self.sprite_zero_in_range = true;
             }
+            self.scanline_sprites.push(sprite_idx);
+            if self.scanline_sprites.len() == PPU::MAX_SPRITES_PER_SCANLINE { break }
+        }
 
-            let lower_chr = self.memory.read_byte(tile_addr + chr_y);
-            let upper_chr = self.memory.read_byte(tile_addr + chr_y + 8);
-            for x in 0..8 {
-                let screen_x = sprite_x + x;
-                let chr_x = if flip_horizontal { x } else { 7 - x };
-                let lower = lower_chr >> chr_x;
-                let upper = upper_chr >> chr_x;
-                let value = (1 & upper) << 1 | (1 & lower);
-                let show_leftmost = self.mask.is_set(MaskFlag::ShowSpritesLeftmostEight) || screen_x >= 8;
-                if value != 0 && show_leftmost {
-                    let rgb = NES::SYSTEM_PALLETE[sprite_palette[value as usize] as usize];
-                    self.frame.set_sprite_pixel(screen_x, screen_y + 1, rgb, priority);
-                    if sprite_idx == 0 {
-                        // todo: more sprite zero debugging required
-                        //  - https://www.nesdev.org/wiki/PPU_registers - Status Register
-                        self.status.set(SpriteZeroHit);
-                    }
-                }
+        if !self.mask.is_set(ShowBackground) && !self.mask.is_set(ShowSprites) { return }
+
+        // Once eight sprites are found, the hardware keeps scanning for overflow but buggily
+        // increments the byte offset (m) along with the sprite index (n), so it compares tile,
+        // attribute and X bytes against the scanline as if they were Y coordinates.
+        let mut m = 0;
+        while n < PPU::OAM_SPRITES {
+            if in_range(self.oam.memory[4 * n + m]) {
+                self.status.set(SpriteOverflow);
+                break;
             }
+            n += 1;
+            m = (m + 1) % 4;
         }
     }
 
+    // The address of the low pattern byte of the evaluated sprite's row on the next scanline.
     #[inline]
-    fn bg_palette(&mut self) -> [u8; 4] {
-        let attribute_address = self.scroll_ctx.get_attribute_address();
-        let attr_byte = self.memory.read_byte(attribute_address);
-        let tile_x = self.scroll_ctx.get_coarse_scroll_x();
-        let tile_y = self.scroll_ctx.get_coarse_scroll_y();
-        let pallete_val = match ((tile_x % 4) / 2, (tile_y % 4) / 2) {
-            (0, 0) => attr_byte & 0b0000_0011,
-            (1, 0) => (attr_byte >> 2) & 0b0000_0011,
-            (0, 1) => (attr_byte >> 4) & 0b0000_0011,
-            (1, 1) => (attr_byte >> 6) & 0b0000_0011,
-            (_, _) => panic!("can't be"),
-        };
-        let pallete_idx = 4 * pallete_val as u16;
-        [
-            self.memory.read_byte(PPUMemory::PALLETES_START),
-            self.memory.read_byte(PPUMemory::BACKGROUND_PALLETES_START + pallete_idx),
-            self.memory.read_byte(PPUMemory::BACKGROUND_PALLETES_START + pallete_idx + 1),
-            self.memory.read_byte(PPUMemory::BACKGROUND_PALLETES_START + pallete_idx + 2),
-        ]
+    fn sprite_row_address(&self, sprite_idx: usize) -> u16 {
+        let sprite_size = self.ctrl.get_sprite_height();
+        let sprite_y = self.oam.memory[sprite_idx] as usize;
+        let tile_index = self.oam.memory[sprite_idx + 1];
+        let flip_vertical = self.oam.memory[sprite_idx + 2] & 0b1000_0000 != 0;
+
+        let y = self.scanline as usize - sprite_y;
+        let row = if flip_vertical { sprite_size - 1 - y } else { y } as u16;
+        let (tile_addr, chr_y) = self.sprite_pattern_address(tile_index, row);
+        tile_addr + chr_y
     }
 
+    // Returns the pattern address of the tile holding the given sprite row, along with the row
+    // within that tile. 8x16 sprites ignore PPUCTRL bit 3: bit 0 of the tile index selects the
+    // pattern table, and the top and bottom halves are the even tile and the one after it.
     #[inline]
-    fn sprite_palette(&self, pallete: u8) -> [u8; 4] {
-        let pallete_idx = 4 * pallete as u16;
-        [
-            0,
-            self.memory.read_byte(PPUMemory::SPRITE_PALLETES_START + pallete_idx),
-            self.memory.read_byte(PPUMemory::SPRITE_PALLETES_START + pallete_idx + 1),
-            self.memory.read_byte(PPUMemory::SPRITE_PALLETES_START + pallete_idx + 2),
-        ]
+    pub fn sprite_pattern_address(&self, tile_index: u8, row: u16) -> (u16, u16) {
+        if self.ctrl.is_set(SpriteSize) {
+            let bank = if tile_index & 1 == 1 { 0x1000 } else { 0x0000 };
+            let tile_value = (tile_index & 0b1111_1110) as u16 + row / 8;
+            (bank + 16 * tile_value, row % 8)
+        } else {
+            let bank = self.ctrl.get_sprite_chrtable_address();
+            (bank + 16 * tile_index as u16, row)
+        }
+    }
+
+    // Sprite 0 hits on the first dot where one of its opaque pixels lands on an opaque background
+    // pixel. Both layers must be visible at that dot, and x=255 never hits.
+    #[inline]
+    fn is_sprite_zero_hit(&self, x: usize, y: usize) -> bool {
+        if !self.is_background_visible(x) || !self.is_sprite_visible(x) || x >= Frame::WIDTH - 1 {
+            return false;
+        }
+        self.frame.get_background_priority(x, y) == Frame::FG_PRIORITY
+    }
+
+    // PPUMASK is sampled per pixel, so writes between scanlines apply to the very next one
+    #[inline]
+    fn is_background_visible(&self, x: usize) -> bool {
+        self.mask.is_set(ShowBackground) && (x >= 8 || self.mask.is_set(MaskFlag::ShowBackgroundLeftmostEight))
+    }
+
+    #[inline]
+    fn is_sprite_visible(&self, x: usize) -> bool {
+        self.mask.is_set(ShowSprites) && (x >= 8 || self.mask.is_set(MaskFlag::ShowSpritesLeftmostEight))
+    }
+
+    // Turns a palette RAM entry into the color on screen, going by PPUMASK. Entries are only 6 bits
+    // wide, greyscale mode forces every palette index into the grey column ($x0), and the emphasis
+    // bits pick one of the precomputed tinted palettes.
+    #[inline]
+    pub fn apply_post_processing(&self, palette_index: u8) -> (u8, u8, u8) {
+        let palette_index = if self.mask.is_set(MaskFlag::Greyscale) { palette_index & 0x30 } else { palette_index & 0x3F };
+        self.emphasis_palettes[self.mask.get_emphasis() as usize][palette_index as usize]
+    }
+
+    pub fn get_sprite_infos(&self) -> Vec<SpriteInfo> {
+        self.oam.get_sprite_infos()
     }
 
+    // swaps the colors the palette RAM's 6-bit indices map to, e.g. for an FCEUX or Nestopia .pal
+    // The reset button clears PPUCTRL, PPUMASK, the write latch and the read buffer. VRAM, OAM,
+    // palettes and the VRAM address are left as they were.
+    pub fn reset(&mut self) {
+        self.ctrl.set_value(0);
+        self.mask.set_value(0);
+        self.clear_address_latch();
+        self.data_buffer = 0;
+        self.odd_frame = false;
+        self.nmi_flag = false;
+    }
+
+    fn set_master_palette(&mut self, palette: &MasterPalette) {
+        self.emphasis_palettes = build_emphasis_palettes(palette, self.emphasis_attenuation);
+    }
+
+    // How much each emphasis bit darkens the other two channels, to match a given PPU revision. The
+    // unemphasized palette is kept as is.
+    pub fn set_emphasis_attenuation(&mut self, attenuation: f64) {
+        let master = self.emphasis_palettes[0];
+        self.emphasis_attenuation = attenuation;
+        self.set_master_palette(&master);
+    }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.set_master_palette(palette.get_colors());
+    }
+
+    // Scroll writes land in t, which the renderer only copies into v at dot 257 (horizontal bits) and
+    // over dots 280-304 of the pre-render line (vertical bits). That's the latch a mid-scanline write
+    // waits in, so a raster split shows up from the next scanline on. Fine X is the exception, and
+    // takes effect right away.
     pub fn write_scroll_register(&mut self, value: u8) {
         self.scroll.write(value);
         self.scroll_ctx.handle_scroll_reg_write(value);
@@ -272,25 +553,59 @@ impl PPU {
         self.addr.write(value);
         self.scroll_ctx.handle_addr_reg_write(value);
         self.flip_address_latch();
+        // the VRAM address drives the PPU bus outside of rendering
+        self.memory.rom.watch_ppu_bus(self.get_vram_addr(), self.dot_count);
+    }
+
+    // Side-effect free views of the readable registers, for debuggers and other tooling.
+    pub fn peek_status_register(&self) -> u8 {
+        (self.status.get_value() & 0b1110_0000) | (self.ppu_bus_latch & 0b0001_1111)
+    }
+
+    pub fn peek_data_register(&self) -> u8 {
+        let addr = self.get_vram_addr();
+        if addr >= PPUMemory::PALLETES_START {
+            self.read_palette_data(addr)
+        } else {
+            self.data_buffer
+        }
+    }
+
+    pub fn peek_oam_data_register(&self) -> u8 {
+        PPU::mask_oam_byte(self.oam_addr, self.oam.read_byte(self.oam_addr))
     }
 
     pub fn read_data_register(&mut self) -> u8 {
-        let addr = self.addr.get();
-        self.increment_vram_addr();
+        let addr = self.get_vram_addr();
 
-        let result = self.data_buffer;
-        self.data_buffer = self.memory.read_byte(addr);
-        self.scroll_ctx.handle_data_reg_read_write();
+        // $2007 reads go through a one byte buffer, so the fetched value only shows up on the next
+        // read. Palette reads skip the buffer, which is instead filled from the nametable underneath.
+        let result = if addr >= PPUMemory::PALLETES_START {
+            self.data_buffer = self.memory.read_byte(addr & 0x2FFF);
+            self.read_palette_data(addr)
+        } else {
+            let result = self.data_buffer;
+            self.data_buffer = self.memory.read_byte(addr);
+            result
+        };
+        self.scroll_ctx.handle_data_reg_read_write(self.ctrl.get_vram_addr_increment(), self.is_rendering());
+        self.memory.rom.watch_ppu_bus(self.get_vram_addr(), self.dot_count);
         result
     }
 
+    // palette entries are 6 bits wide, the top two bits come from the PPU bus latch
+    #[inline]
+    fn read_palette_data(&self, addr: u16) -> u8 {
+        (self.memory.read_byte(addr) & 0b0011_1111) | (self.ppu_bus_latch & 0b1100_0000)
+    }
+
     pub fn write_data_register(&mut self, value: u8) {
-        let addr = self.addr.get();
-        self.increment_vram_addr();
+        let addr = self.get_vram_addr();
 
         self.data = value;
         self.memory.write_byte(addr, value);
-        self.scroll_ctx.handle_data_reg_read_write();
+        self.scroll_ctx.handle_data_reg_read_write(self.ctrl.get_vram_addr_increment(), self.is_rendering());
+        self.memory.rom.watch_ppu_bus(self.get_vram_addr(), self.dot_count);
     }
 
     pub fn write_oam_addr_register(&mut self, value: u8) {
@@ -304,7 +619,13 @@ impl PPU {
         //     self.oam_addr += 1;
         // }
 
-        self.oam.read_byte(addr)
+        PPU::mask_oam_byte(addr, self.oam.read_byte(addr))
+    }
+
+    // bits 2-4 of a sprite's attribute byte aren't backed by OAM and always read back as 0
+    #[inline]
+    fn mask_oam_byte(addr: u8, value: u8) -> u8 {
+        if addr & 0b11 == 2 { value & 0b1110_0011 } else { value }
     }
 
     pub fn write_oam_data_register(&mut self, value: u8) {
@@ -314,6 +635,8 @@ impl PPU {
         self.oam.write_byte(addr, value);
     }
 
+    // The nametable select goes into t like a scroll write, so it waits for dot 257 as well. The other
+    // bits (pattern tables, sprite size, NMI) take effect on the dot they're written.
     pub fn write_ctrl_register(&mut self, value: u8) {
         // NMI is triggered if:
         //  1. PPU is in VBLANK state
@@ -321,22 +644,74 @@ impl PPU {
         let before_nmi_status = self.ctrl.is_set(GenerateNmi);
         self.ctrl.set_value(value);
         self.scroll_ctx.handle_cntl_reg_write(value);
+        self.memory.rom.watch_ppu_ctrl(value);
         if !before_nmi_status && self.ctrl.is_set(GenerateNmi) && self.status.is_set(VerticalBlank) {
             self.set_nmi();
         }
     }
 
+    // Takes effect from the next pixel drawn, which is how games change emphasis or hide the background
+    // partway through a scanline.
     pub fn write_mask_register(&mut self, value: u8) {
         self.mask.set_value(value);
     }
 
+    // Only bits 7-5 of PPUSTATUS are driven; bits 4-0 come from the I/O latch.
+    // Ref: https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
+    //
+    // Reading one dot before VBlank starts reads the flag clear, and the flag and its NMI are skipped
+    // for the whole frame. Reading on the dot it's set, or the one after, reads it set but still
+    // cancels the NMI.
     pub fn read_status_register(&mut self) -> u8 {
-        let status = self.status.get_value();
+        let status = self.peek_status_register();
+        if self.scanline == self.region.get_vblank_scanline() {
+            match self.dot {
+                1 => self.vblank_suppressed = true,
+                2 | 3 => self.clear_nmi(),
+                _ => {},
+            }
+        }
         self.status.clear(VerticalBlank);
         self.clear_address_latch();
+        self.refresh_bus_latch(status);
         status
     }
 
+    // Reading a write-only register returns whatever was last left on the PPU's I/O bus.
+    pub fn read_bus_latch(&self) -> u8 {
+        self.ppu_bus_latch
+    }
+
+    // Every write to a PPU register, and every read of a readable one, charges the I/O latch.
+    pub fn refresh_bus_latch(&mut self, value: u8) {
+        self.ppu_bus_latch = value;
+        self.ppu_bus_latch_age = 0;
+    }
+
+    // The latch isn't refreshed by anything else, so its value fades once it goes unused long enough.
+    fn decay_bus_latch(&mut self) {
+        self.ppu_bus_latch_age += 1;
+        if self.ppu_bus_latch_age >= PPU::PPU_BUS_LATCH_DECAY_FRAMES {
+            self.ppu_bus_latch = 0;
+        }
+    }
+
+    // Look up a register by its conventional name without any of the side effects of reading it.
+    // PPUSCROLL and PPUADDR are double-write registers, so the full 16-bit value is reported.
+    pub fn peek_register(&self, name: &str) -> Option<u16> {
+        match name {
+            "PPUCTRL" => Some(self.ctrl.get_value() as u16),
+            "PPUMASK" => Some(self.mask.get_value() as u16),
+            "PPUSTATUS" => Some(self.status.get_value() as u16),
+            "OAMADDR" => Some(self.oam_addr as u16),
+            "OAMDATA" => Some(self.oam.read_byte(self.oam_addr) as u16),
+            "PPUSCROLL" => Some(self.scroll.get()),
+            "PPUADDR" => Some(self.get_vram_addr()),
+            "PPUDATA" => Some(self.data_buffer as u16),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn get_address_latch(&self) -> bool {
         self.scroll_ctx.w
@@ -366,6 +741,11 @@ impl PPU {
     }
 
     #[inline]
+    // A single nametable as it would be drawn with no scroll, see viewer::render_nametable.
+    pub fn render_nametable(&self, which: u8) -> Frame {
+        viewer::render_nametable(self, which)
+    }
+
     pub fn poll_nmi(&self) -> bool {
         return self.nmi_flag;
     }
@@ -380,18 +760,923 @@ impl PPU {
         self.nmi_flag = false;
     }
 
+    // $2006 and $2007 share v with the renderer, which is what makes mid-frame scroll splits work
     #[inline]
-    fn increment_vram_addr(&mut self) {
-        self.addr.increment(self.ctrl.get_vram_addr_increment());
+    pub fn get_vram_addr(&self) -> u16 {
+        self.scroll_ctx.v & 0x3FFF
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nes::ppu::palette::{Palette, PAL_FILE_SIZE};
+    use crate::nes::NES;
+    use crate::nes::rom::{Mirroring, ROM};
+    use crate::nes::rom::mappers::create_mapper;
+    use crate::nes::rom::mappers::mapper4::Mapper4;
+    use crate::nes::rom::mappers::mapper5::Mapper5;
+
+    const VBLANK_SCANLINE_START: isize = 241;
+    use crate::nes::ppu::registers::ctrl::ControlFlag;
 
     #[test]
     fn test_() {
         let mut ppu = PPU::new();
     }
+
+    const OPAQUE: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+
+    fn test_ppu() -> PPU {
+        let mut ppu = PPU::new();
+        ppu.memory.rom.is_chr_ram = true;
+        ppu.memory.rom.chr_rom = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        ppu.mask.set(ShowBackground);
+        ppu.mask.set(ShowSprites);
+        ppu.mask.set(MaskFlag::ShowBackgroundLeftmostEight);
+        ppu.mask.set(MaskFlag::ShowSpritesLeftmostEight);
+        ppu
+    }
+
+    // tile 1 is a solid block, tile 0 is transparent
+    fn load_solid_tile(ppu: &mut PPU) {
+        for row in 0..8 {
+            ppu.memory.write_byte(0x0010 + row, 0xFF);
+        }
+    }
+
+    fn place_sprite_zero(ppu: &mut PPU, x: u8, y: u8) {
+        ppu.oam.memory[0] = y;
+        ppu.oam.memory[1] = 0x01;
+        ppu.oam.memory[2] = 0x00;
+        ppu.oam.memory[3] = x;
+    }
+
+    fn run_until(ppu: &mut PPU, scanline: isize, dot: usize) {
+        while ppu.scanline != scanline || ppu.dot != dot {
+            ppu.tick_dot();
+        }
+    }
+
+    // renders a frame from the pre-render scanline up to and including the given scanline
+    fn render_until(ppu: &mut PPU, scanline: isize) {
+        ppu.scanline = PPU::PRE_RENDER_SCANLINE;
+        ppu.dot = 0;
+        run_until(ppu, scanline + 1, 0);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_opaque_overlap() {
+        let mut ppu = test_ppu();
+        ppu.frame.set_background_pixel(100, 50, OPAQUE, Frame::FG_PRIORITY);
+        assert!(ppu.is_sprite_zero_hit(100, 50));
+        assert!(!ppu.is_sprite_zero_hit(101, 50));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_transparent_background() {
+        let mut ppu = test_ppu();
+        ppu.frame.set_background_pixel(100, 50, OPAQUE, Frame::BG_PRIORITY);
+        assert!(!ppu.is_sprite_zero_hit(100, 50));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_never_at_x_255() {
+        let mut ppu = test_ppu();
+        ppu.frame.set_background_pixel(254, 50, OPAQUE, Frame::FG_PRIORITY);
+        ppu.frame.set_background_pixel(255, 50, OPAQUE, Frame::FG_PRIORITY);
+        assert!(ppu.is_sprite_zero_hit(254, 50));
+        assert!(!ppu.is_sprite_zero_hit(255, 50));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_left_clip() {
+        let mut ppu = test_ppu();
+        ppu.frame.set_background_pixel(7, 50, OPAQUE, Frame::FG_PRIORITY);
+        ppu.frame.set_background_pixel(8, 50, OPAQUE, Frame::FG_PRIORITY);
+        assert!(ppu.is_sprite_zero_hit(7, 50));
+
+        ppu.mask.clear(MaskFlag::ShowSpritesLeftmostEight);
+        assert!(!ppu.is_sprite_zero_hit(7, 50));
+        assert!(ppu.is_sprite_zero_hit(8, 50));
+
+        ppu.mask.set(MaskFlag::ShowSpritesLeftmostEight);
+        ppu.mask.clear(MaskFlag::ShowBackgroundLeftmostEight);
+        assert!(!ppu.is_sprite_zero_hit(7, 50));
+        assert!(ppu.is_sprite_zero_hit(8, 50));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_requires_rendering() {
+        let mut ppu = test_ppu();
+        ppu.frame.set_background_pixel(100, 50, OPAQUE, Frame::FG_PRIORITY);
+        ppu.mask.clear(ShowBackground);
+        assert!(!ppu.is_sprite_zero_hit(100, 50));
+        ppu.mask.set(ShowBackground);
+        ppu.mask.clear(ShowSprites);
+        assert!(!ppu.is_sprite_zero_hit(100, 50));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_rendered_scanline() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        // solid background tile at nametable row 4, column 12
+        ppu.memory.write_byte(0x2000 + 4 * 32 + 12, 0x01);
+
+        // sprite 0 sits left of the solid tile
+        place_sprite_zero(&mut ppu, 80, 31);
+        render_until(&mut ppu, 40);
+        assert!(!ppu.status.is_set(SpriteZeroHit));
+
+        // sprite 0 overlaps the solid tile by a single pixel
+        place_sprite_zero(&mut ppu, 89, 31);
+        render_until(&mut ppu, 31);
+        assert!(!ppu.status.is_set(SpriteZeroHit));
+        render_until(&mut ppu, 32);
+        assert!(ppu.status.is_set(SpriteZeroHit));
+    }
+
+    #[test]
+    fn test_attribute_palette() {
+        let attr_byte = 0b11_10_01_00;
+        assert_eq!(PPU::attribute_palette(attr_byte, 0, 0), 0);
+        assert_eq!(PPU::attribute_palette(attr_byte, 3, 1), 1);
+        assert_eq!(PPU::attribute_palette(attr_byte, 1, 2), 2);
+        assert_eq!(PPU::attribute_palette(attr_byte, 2, 3), 3);
+        // the next block over starts back at the top left quadrant
+        assert_eq!(PPU::attribute_palette(attr_byte, 4, 4), 0);
+        assert_eq!(PPU::attribute_palette(attr_byte, 30, 26), 3);
+    }
+
+    #[test]
+    fn test_attribute_quadrant_colors() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        for tile in 0..32 * 30 {
+            ppu.memory.write_byte(0x2000 + tile, 0x01);
+        }
+        // palette 0 top left, 1 top right, 2 bottom left, 3 bottom right, for the block at $23C9
+        ppu.memory.write_byte(0x23C9, 0b11_10_01_00);
+        let colors = [0x16, 0x2A, 0x12, 0x30];
+        for (palette, color) in colors.iter().enumerate() {
+            ppu.memory.write_byte(0x3F01 + 4 * palette as u16, *color);
+        }
+        render_until(&mut ppu, 63);
+
+        // the block covers pixels (32, 32) to (63, 63)
+        let quadrant_color = |x: usize, y: usize| ppu.frame.get_background_color(x, y);
+        assert_eq!(quadrant_color(32, 32), ppu.apply_post_processing(colors[0]));
+        assert_eq!(quadrant_color(47, 47), ppu.apply_post_processing(colors[0]));
+        assert_eq!(quadrant_color(48, 32), ppu.apply_post_processing(colors[1]));
+        assert_eq!(quadrant_color(63, 47), ppu.apply_post_processing(colors[1]));
+        assert_eq!(quadrant_color(32, 48), ppu.apply_post_processing(colors[2]));
+        assert_eq!(quadrant_color(47, 63), ppu.apply_post_processing(colors[2]));
+        assert_eq!(quadrant_color(48, 48), ppu.apply_post_processing(colors[3]));
+        assert_eq!(quadrant_color(63, 63), ppu.apply_post_processing(colors[3]));
+        // the neighboring blocks have an attribute byte of 0
+        assert_eq!(quadrant_color(64, 48), ppu.apply_post_processing(colors[0]));
+        assert_eq!(quadrant_color(31, 63), ppu.apply_post_processing(colors[0]));
+    }
+
+    #[test]
+    fn test_mmc3_irq_counts_scanlines() {
+        let mut ppu = test_ppu();
+        ppu.memory.rom.mapper_id = 4;
+        ppu.memory.rom.mapper = create_mapper(&ppu.memory.rom).unwrap();
+        ppu.ctrl.set(ControlFlag::SpritePatternAddr);
+        ppu.memory.rom.write_prg_byte(0xC000, 8);
+        ppu.memory.rom.write_prg_byte(0xC001, 0);
+        ppu.memory.rom.write_prg_byte(0xE001, 0);
+
+        // the pre-render scanline reloads the counter, then each scanline counts down one, on the
+        // first sprite pattern fetch from $1000
+        ppu.scanline = PPU::PRE_RENDER_SCANLINE;
+        ppu.dot = 0;
+        run_until(&mut ppu, 7, 261);
+        assert!(!ppu.memory.rom.poll_irq());
+        assert_eq!(ppu.memory.rom.mapper_as::<Mapper4>().unwrap().irq_counter, 1);
+        run_until(&mut ppu, 7, 262);
+        assert!(ppu.memory.rom.poll_irq());
+
+        // with everything fetched from $0000, A12 never rises
+        ppu.memory.rom.write_prg_byte(0xE000, 0);
+        ppu.ctrl.set_value(0);
+        run_until(&mut ppu, 200, 0);
+        assert_eq!(ppu.memory.rom.mapper_as::<Mapper4>().unwrap().irq_counter, 0);
+    }
+
+    #[test]
+    fn test_mmc5_irq_counts_scanlines() {
+        let mut ppu = test_ppu();
+        ppu.memory.rom.mapper_id = 5;
+        ppu.memory.rom.mapper = create_mapper(&ppu.memory.rom).unwrap();
+        ppu.memory.rom.mapper.write_expansion(0x5203, 8);
+        ppu.memory.rom.mapper.write_expansion(0x5204, 0b1000_0000);
+
+        // the frame starts with scanline 0, then each scanline is detected at its first fetch
+        ppu.scanline = PPU::PRE_RENDER_SCANLINE;
+        ppu.dot = 0;
+        run_until(&mut ppu, 8, 1);
+        assert!(ppu.memory.rom.mapper_as::<Mapper5>().unwrap().in_frame);
+        assert!(!ppu.memory.rom.poll_irq());
+        run_until(&mut ppu, 8, 2);
+        assert!(ppu.memory.rom.poll_irq());
+
+        // reading the status acknowledges the IRQ, and the frame is over once the fetches stop
+        run_until(&mut ppu, 241, 0);
+        let status = ppu.memory.rom.mapper.read_expansion(0x5204, ppu.dot_count);
+        assert_eq!(status, Some(0b1000_0000));
+        assert!(!ppu.memory.rom.poll_irq());
+    }
+
+    #[test]
+    fn test_evaluate_sprites_for_scanline() {
+        let mut ppu = test_ppu();
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        for sprite in [0, 3, 5] {
+            ppu.oam.memory[4 * sprite] = 20;
+        }
+        ppu.scanline = 25;
+        ppu.evaluate_sprites_for_scanline();
+        assert_eq!(ppu.scanline_sprites, vec![0, 12, 20]);
+        assert!(ppu.sprite_zero_in_range);
+
+        ppu.scanline = 30;
+        ppu.evaluate_sprites_for_scanline();
+        assert!(ppu.scanline_sprites.is_empty());
+        assert!(!ppu.sprite_zero_in_range);
+
+        ppu.ctrl.set(SpriteSize);
+        ppu.evaluate_sprites_for_scanline();
+        assert_eq!(ppu.scanline_sprites, vec![0, 12, 20]);
+    }
+
+    #[test]
+    fn test_evaluate_sprites_limit() {
+        let mut ppu = test_ppu();
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        for sprite in 1..=10 {
+            ppu.oam.memory[4 * sprite] = 100;
+        }
+        ppu.scanline = 101;
+        ppu.evaluate_sprites_for_scanline();
+        assert_eq!(ppu.scanline_sprites, (1..=8).map(|sprite| 4 * sprite).collect::<Vec<usize>>());
+        assert!(!ppu.sprite_zero_in_range);
+    }
+
+    // 8x16 sprite using tiles $02 (top, left half opaque) and $03 (bottom, right half opaque) in
+    // the $1000 pattern table
+    fn render_tall_sprite(attributes: u8) -> Vec<Vec<bool>> {
+        let mut ppu = test_ppu();
+        ppu.ctrl.set(SpriteSize);
+        for row in 0..8 {
+            ppu.memory.write_byte(0x1020 + row, 0b1111_0000);
+            ppu.memory.write_byte(0x1030 + row, 0b0000_1111);
+        }
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        ppu.oam.memory[0..4].copy_from_slice(&[50, 0x03, attributes, 100]);
+
+        render_until(&mut ppu, 66);
+        (51..67).map(|y| {
+            (100..108).map(|x| ppu.frame.get_sprite_priority(x, y) != Frame::EMPTY_PRIORITY).collect()
+        }).collect()
+    }
+
+    fn tall_sprite_rows(top_left: bool, bottom_left: bool) -> Vec<Vec<bool>> {
+        let half = |left: bool| (0..8).map(|x| (x < 4) == left).collect::<Vec<bool>>();
+        (0..16).map(|row| if row < 8 { half(top_left) } else { half(bottom_left) }).collect()
+    }
+
+    #[test]
+    fn test_8x16_sprite() {
+        assert_eq!(render_tall_sprite(0b0000_0000), tall_sprite_rows(true, false));
+    }
+
+    #[test]
+    fn test_8x16_sprite_flip_vertical() {
+        assert_eq!(render_tall_sprite(0b1000_0000), tall_sprite_rows(false, true));
+    }
+
+    #[test]
+    fn test_8x16_sprite_flip_horizontal() {
+        assert_eq!(render_tall_sprite(0b0100_0000), tall_sprite_rows(false, true));
+    }
+
+    #[test]
+    fn test_8x16_sprite_flip_both() {
+        assert_eq!(render_tall_sprite(0b1100_0000), tall_sprite_rows(true, false));
+    }
+
+    #[test]
+    fn test_8x16_sprite_pattern_address() {
+        let mut ppu = test_ppu();
+        ppu.ctrl.set(SpriteSize);
+        ppu.ctrl.set(ControlFlag::SpritePatternAddr);
+        assert_eq!(ppu.sprite_pattern_address(0x02, 3), (0x0020, 3));
+        assert_eq!(ppu.sprite_pattern_address(0x02, 11), (0x0030, 3));
+        assert_eq!(ppu.sprite_pattern_address(0x03, 0), (0x1020, 0));
+        assert_eq!(ppu.sprite_pattern_address(0xFF, 15), (0x1FF0, 7));
+
+        ppu.ctrl.clear(SpriteSize);
+        assert_eq!(ppu.sprite_pattern_address(0x03, 5), (0x1030, 5));
+    }
+
+    // places sprites at the given OAM indices on scanline 100, everything else is hidden
+    fn overflow_ppu(sprites: &[usize]) -> PPU {
+        let mut ppu = test_ppu();
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        for sprite in sprites {
+            ppu.oam.memory[4 * sprite] = 100;
+        }
+        ppu.scanline = 101;
+        ppu
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        let mut ppu = overflow_ppu(&[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        ppu.evaluate_sprites_for_scanline();
+        assert!(ppu.status.is_set(SpriteOverflow));
+
+        let mut ppu = overflow_ppu(&[10, 20, 30, 40, 50, 60, 61, 62, 63]);
+        ppu.evaluate_sprites_for_scanline();
+        assert!(ppu.status.is_set(SpriteOverflow));
+    }
+
+    #[test]
+    fn test_sprite_overflow_eight_sprites() {
+        let mut ppu = overflow_ppu(&[0, 1, 2, 3, 4, 5, 6, 7]);
+        ppu.evaluate_sprites_for_scanline();
+        assert_eq!(ppu.scanline_sprites.len(), 8);
+        assert!(!ppu.status.is_set(SpriteOverflow));
+    }
+
+    #[test]
+    fn test_sprite_overflow_false_negative() {
+        // the ninth in-range sprite is read at m=1, so its tile index is compared instead of its Y
+        let mut ppu = overflow_ppu(&[0, 1, 2, 3, 4, 5, 6, 7, 9]);
+        ppu.evaluate_sprites_for_scanline();
+        assert!(!ppu.status.is_set(SpriteOverflow));
+    }
+
+    #[test]
+    fn test_sprite_overflow_false_positive() {
+        // only eight sprites are in range, but sprite 9's tile index ($64) looks like a Y on this line
+        let mut ppu = overflow_ppu(&[0, 1, 2, 3, 4, 5, 6, 7]);
+        ppu.oam.memory[4 * 9 + 1] = 100;
+        ppu.evaluate_sprites_for_scanline();
+        assert!(ppu.status.is_set(SpriteOverflow));
+    }
+
+    #[test]
+    fn test_sprite_overflow_m_wraps() {
+        // n=8..11 read bytes 0..3, then n=12 reads byte 0 again
+        let mut ppu = overflow_ppu(&[0, 1, 2, 3, 4, 5, 6, 7, 12]);
+        ppu.evaluate_sprites_for_scanline();
+        assert!(ppu.status.is_set(SpriteOverflow));
+    }
+
+    #[test]
+    fn test_sprite_overflow_rendering_disabled() {
+        let mut ppu = overflow_ppu(&[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        ppu.mask.clear(ShowBackground);
+        ppu.mask.clear(ShowSprites);
+        ppu.evaluate_sprites_for_scanline();
+        assert!(!ppu.status.is_set(SpriteOverflow));
+    }
+
+    #[test]
+    fn test_background_left_clip() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        for column in 0..2 {
+            ppu.memory.write_byte(0x2000 + column, 0x01);
+        }
+        ppu.mask.clear(MaskFlag::ShowBackgroundLeftmostEight);
+        render_until(&mut ppu, 0);
+        assert_eq!(ppu.frame.get_background_priority(7, 0), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(8, 0), Frame::FG_PRIORITY);
+
+        ppu.mask.set(MaskFlag::ShowBackgroundLeftmostEight);
+        render_until(&mut ppu, 0);
+        assert_eq!(ppu.frame.get_background_priority(0, 0), Frame::FG_PRIORITY);
+    }
+
+    #[test]
+    fn test_sprite_priority() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        ppu.memory.write_byte(0x2000 + 4, 0x01);
+        ppu.memory.write_byte(0x3F00, 0x0F);
+        ppu.memory.write_byte(0x3F01, 0x16);
+        ppu.memory.write_byte(0x3F11, 0x2A);
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        // a sprite behind the background, straddling the right edge of the solid tile at x = 32..40
+        place_sprite_zero(&mut ppu, 36, 0);
+        ppu.oam.memory[2] = 0x20;
+        // and one in front of it, in the same spot a few scanlines down
+        ppu.oam.memory[4..8].copy_from_slice(&[4, 0x01, 0x00, 36]);
+        render_until(&mut ppu, 12);
+        ppu.frame.compose();
+
+        let (universal, background, sprite) = (ppu.apply_post_processing(0x0F), ppu.apply_post_processing(0x16), ppu.apply_post_processing(0x2A));
+        let colors = |y: usize| [34, 38, 42, 50].map(|x| ppu.frame.get_background_color(x, y));
+        assert_eq!(colors(2), [background, background, sprite, universal]);
+        // where they overlap the first sprite still wins, hiding the second one behind the background
+        assert_eq!(colors(6), [background, background, sprite, universal]);
+        assert_eq!(colors(10), [universal, sprite, sprite, universal]);
+    }
+
+    #[test]
+    fn test_sprite_left_clip() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        place_sprite_zero(&mut ppu, 4, 10);
+        ppu.mask.clear(MaskFlag::ShowSpritesLeftmostEight);
+        render_until(&mut ppu, 11);
+        assert_eq!(ppu.frame.get_sprite_priority(7, 11), Frame::EMPTY_PRIORITY);
+        assert_eq!(ppu.frame.get_sprite_priority(8, 11), Frame::FG_PRIORITY);
+    }
+
+    #[test]
+    fn test_mask_applies_per_scanline() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        ppu.memory.write_byte(0x2000 + 4, 0x01);
+        ppu.memory.write_byte(0x2000 + 32 + 4, 0x01);
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        place_sprite_zero(&mut ppu, 64, 0);
+
+        // switched on in the hblank of scanline 3, in time for the tile prefetch of scanline 4
+        ppu.mask.clear(ShowBackground);
+        ppu.mask.clear(ShowSprites);
+        render_until(&mut ppu, 2);
+        run_until(&mut ppu, 3, 257);
+        ppu.mask.set(ShowBackground);
+        ppu.mask.set(ShowSprites);
+        run_until(&mut ppu, 9, 0);
+        assert_eq!(ppu.frame.get_background_priority(32, 3), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(32, 4), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_sprite_priority(64, 3), Frame::EMPTY_PRIORITY);
+        assert_eq!(ppu.frame.get_sprite_priority(64, 4), Frame::FG_PRIORITY);
+    }
+
+    #[test]
+    fn test_layer_masks_apply_per_scanline() {
+        // each layer switches on its own scanline
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        ppu.memory.write_byte(0x2000 + 4, 0x01);
+        ppu.memory.write_byte(0x2000 + 32 + 4, 0x01);
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        place_sprite_zero(&mut ppu, 64, 0);
+
+        ppu.mask.clear(ShowBackground);
+        render_until(&mut ppu, 3);
+        ppu.mask.set(ShowBackground);
+        ppu.mask.clear(ShowSprites);
+        run_until(&mut ppu, 9, 0);
+        assert_eq!(ppu.frame.get_background_priority(32, 3), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(32, 4), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_sprite_priority(64, 3), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_sprite_priority(64, 4), Frame::EMPTY_PRIORITY);
+    }
+
+    #[test]
+    fn test_mask_applies_mid_scanline() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        for column in 0..32 {
+            ppu.memory.write_byte(0x2020 + column, 0x01);
+        }
+        render_until(&mut ppu, 9);
+        run_until(&mut ppu, 10, 129);
+        ppu.mask.clear(ShowBackground);
+        run_until(&mut ppu, 11, 0);
+        assert_eq!(ppu.frame.get_background_priority(127, 10), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(128, 10), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(128, 9), Frame::FG_PRIORITY);
+    }
+
+    #[test]
+    fn test_palette_write_mid_scanline() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        for column in 0..32 {
+            ppu.memory.write_byte(0x2000 + column, 0x01);
+        }
+        ppu.memory.write_byte(0x3F01, 0x16);
+        render_until(&mut ppu, -1);
+        run_until(&mut ppu, 0, 65);
+        ppu.memory.write_byte(0x3F01, 0x2A);
+        run_until(&mut ppu, 1, 0);
+        assert_eq!(ppu.frame.get_background_color(63, 0), NES::SYSTEM_PALLETE[0x16]);
+        assert_eq!(ppu.frame.get_background_color(64, 0), NES::SYSTEM_PALLETE[0x2A]);
+    }
+
+    #[test]
+    fn test_scroll_fine_x() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        ppu.memory.write_byte(0x2002, 0x01); // solid tile covers x=16..23 unscrolled
+        ppu.scroll_ctx.handle_scroll_reg_write(0x0B); // coarse X = 1, fine X = 3
+        render_until(&mut ppu, 0);
+        assert_eq!(ppu.frame.get_background_priority(4, 0), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(5, 0), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(12, 0), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(13, 0), Frame::BG_PRIORITY);
+    }
+
+    #[test]
+    fn test_scroll_split_mid_scanline() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        ppu.memory.write_byte(0x2004, 0x01); // solid tile covers x=32..39 unscrolled
+        render_until(&mut ppu, 1);
+        run_until(&mut ppu, 2, 100);
+        ppu.write_scroll_register(0x08); // coarse X = 1
+        ppu.write_scroll_register(0x00);
+        run_until(&mut ppu, 4, 0);
+        // the rest of scanline 2 is drawn with the old scroll, scanline 3 with the new one
+        assert_eq!(ppu.frame.get_background_priority(32, 2), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(24, 2), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(24, 3), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(32, 3), Frame::BG_PRIORITY);
+    }
+
+    #[test]
+    fn test_ctrl_write_mid_scanline() {
+        let mut ppu = test_ppu();
+        ppu.memory.rom.screen_mirroring = Mirroring::Vertical;
+        load_solid_tile(&mut ppu);
+        for tile in 0..0x3C0 {
+            ppu.memory.write_byte(0x2400 + tile, 0x01);
+        }
+        render_until(&mut ppu, 1);
+        run_until(&mut ppu, 2, 100);
+        ppu.write_ctrl_register(0x01); // nametable $2400
+        run_until(&mut ppu, 4, 0);
+        // like a scroll write, the nametable select waits in t until dot 257
+        assert_eq!(ppu.frame.get_background_priority(200, 2), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(0, 3), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(255, 3), Frame::FG_PRIORITY);
+    }
+
+    #[test]
+    fn test_mask_write_mid_scanline() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        for column in 0..32 {
+            ppu.memory.write_byte(0x2020 + column, 0x01);
+        }
+        render_until(&mut ppu, 9);
+        run_until(&mut ppu, 10, 129);
+        ppu.write_mask_register(0b0001_0110); // background off, sprites and both left columns on
+        run_until(&mut ppu, 11, 0);
+        assert_eq!(ppu.frame.get_background_priority(127, 10), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(128, 10), Frame::BG_PRIORITY);
+    }
+
+    #[test]
+    fn test_palette_data_write_mid_scanline() {
+        let mut ppu = test_ppu();
+        ppu.memory.rom.screen_mirroring = Mirroring::Vertical;
+        load_solid_tile(&mut ppu);
+        // every nametable is solid, so moving v to the palette doesn't change what's drawn
+        for tile in 0..0x3C0 {
+            ppu.memory.write_byte(0x2000 + tile, 0x01);
+            ppu.memory.write_byte(0x2400 + tile, 0x01);
+        }
+        ppu.memory.write_byte(0x3F01, 0x16);
+        render_until(&mut ppu, -1);
+        run_until(&mut ppu, 0, 65);
+        ppu.write_addr_register(0x3F);
+        ppu.write_addr_register(0x01);
+        ppu.write_data_register(0x2A);
+        run_until(&mut ppu, 1, 0);
+        assert_eq!(ppu.frame.get_background_color(63, 0), NES::SYSTEM_PALLETE[0x16]);
+        assert_eq!(ppu.frame.get_background_color(64, 0), NES::SYSTEM_PALLETE[0x2A]);
+    }
+
+    #[test]
+    fn test_odd_frame_skips_dot() {
+        let mut ppu = test_ppu();
+        let frame_dots = |ppu: &mut PPU| {
+            let mut dots = 0;
+            ppu.tick_dot();
+            dots += 1;
+            while ppu.scanline != PPU::PRE_RENDER_SCANLINE || ppu.dot != 0 {
+                ppu.tick_dot();
+                dots += 1;
+            }
+            dots
+        };
+        ppu.odd_frame = false;
+        assert_eq!(frame_dots(&mut ppu), 262 * 341);
+        assert_eq!(frame_dots(&mut ppu), 262 * 341 - 1);
+
+        ppu.mask.set_value(0);
+        assert_eq!(frame_dots(&mut ppu), 262 * 341);
+        assert_eq!(frame_dots(&mut ppu), 262 * 341);
+    }
+
+    #[test]
+    fn test_pal_timing() {
+        let mut ppu = test_ppu();
+        ppu.set_region(Region::Pal);
+        // 16 master clocks per CPU cycle, 5 per dot: 3.2 dots per cycle
+        ppu.tick(5);
+        assert_eq!(ppu.cycles, 16);
+        ppu.tick(1);
+        assert_eq!(ppu.cycles, 19);
+
+        // 312 scanlines, no dot is skipped on odd frames, and VBlank still starts on line 241
+        ppu.cycles = 0;
+        ppu.scanline = PPU::PRE_RENDER_SCANLINE;
+        ppu.dot = 0;
+        for frame in 0..2 {
+            ppu.cycles = 312 * 341;
+            ppu.step().unwrap();
+            assert_eq!((ppu.scanline, ppu.dot), (PPU::PRE_RENDER_SCANLINE, 0), "frame {}", frame);
+        }
+        run_until(&mut ppu, VBLANK_SCANLINE_START, 2);
+        assert!(ppu.status.is_set(VerticalBlank));
+
+        ppu.set_region(Region::Dendy);
+        run_until(&mut ppu, PPU::PRE_RENDER_SCANLINE, 2);
+        run_until(&mut ppu, VBLANK_SCANLINE_START, 2);
+        assert!(!ppu.status.is_set(VerticalBlank));
+        run_until(&mut ppu, 291, 2);
+        assert!(ppu.status.is_set(VerticalBlank));
+    }
+
+    #[test]
+    fn test_vblank_set_at_dot_1() {
+        let mut ppu = test_ppu();
+        ppu.ctrl.set(GenerateNmi);
+        ppu.scanline = VBLANK_SCANLINE_START;
+        ppu.dot = 0;
+        ppu.tick_dot();
+        assert!(!ppu.status.is_set(VerticalBlank));
+        ppu.tick_dot();
+        assert!(ppu.status.is_set(VerticalBlank));
+        assert!(ppu.poll_nmi());
+
+        run_until(&mut ppu, PPU::PRE_RENDER_SCANLINE, 1);
+        assert!(ppu.status.is_set(VerticalBlank));
+        ppu.tick_dot();
+        assert!(!ppu.status.is_set(VerticalBlank));
+    }
+
+    #[test]
+    fn test_status_read_before_vblank() {
+        let mut ppu = test_ppu();
+        ppu.ctrl.set(GenerateNmi);
+        run_until(&mut ppu, VBLANK_SCANLINE_START, 0);
+        assert_eq!(ppu.read_status_register() & 0x80, 0);
+        ppu.tick_dot();
+        ppu.tick_dot();
+        assert!(ppu.status.is_set(VerticalBlank));
+        assert!(ppu.poll_nmi());
+
+        // one dot later and neither the flag nor NMI happen this frame
+        let mut ppu = test_ppu();
+        ppu.ctrl.set(GenerateNmi);
+        run_until(&mut ppu, VBLANK_SCANLINE_START, 1);
+        assert_eq!(ppu.read_status_register() & 0x80, 0);
+        run_until(&mut ppu, VBLANK_SCANLINE_START + 1, 0);
+        assert!(!ppu.status.is_set(VerticalBlank));
+        assert!(!ppu.poll_nmi());
+
+        // and the next frame is back to normal
+        run_until(&mut ppu, VBLANK_SCANLINE_START, 2);
+        assert!(ppu.status.is_set(VerticalBlank));
+        assert!(ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_status_read_at_vblank_suppresses_nmi() {
+        for dot in [2, 3] {
+            let mut ppu = test_ppu();
+            ppu.ctrl.set(GenerateNmi);
+            run_until(&mut ppu, VBLANK_SCANLINE_START, dot);
+            assert!(ppu.poll_nmi());
+            assert_eq!(ppu.read_status_register() & 0x80, 0x80);
+            assert!(!ppu.poll_nmi(), "read at dot {}", dot);
+            assert!(!ppu.status.is_set(VerticalBlank));
+        }
+
+        // two dots later the read is an ordinary one
+        let mut ppu = test_ppu();
+        ppu.ctrl.set(GenerateNmi);
+        run_until(&mut ppu, VBLANK_SCANLINE_START, 4);
+        assert_eq!(ppu.read_status_register() & 0x80, 0x80);
+        assert!(ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_nmi_retriggered_by_ctrl_toggle() {
+        let mut ppu = test_ppu();
+        run_until(&mut ppu, VBLANK_SCANLINE_START, 10);
+        assert!(!ppu.poll_nmi());
+
+        ppu.write_ctrl_register(0x80);
+        assert!(ppu.poll_nmi());
+        ppu.clear_nmi();
+        ppu.write_ctrl_register(0x80);
+        assert!(!ppu.poll_nmi());
+        ppu.write_ctrl_register(0x00);
+        ppu.write_ctrl_register(0x80);
+        assert!(ppu.poll_nmi());
+        ppu.clear_nmi();
+
+        // not once the flag has been read
+        ppu.read_status_register();
+        ppu.write_ctrl_register(0x00);
+        ppu.write_ctrl_register(0x80);
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_greyscale() {
+        let mut ppu = test_ppu();
+        assert_eq!(ppu.apply_post_processing(0x16), NES::SYSTEM_PALLETE[0x16]);
+        ppu.mask.set(MaskFlag::Greyscale);
+        assert_eq!(ppu.apply_post_processing(0x16), NES::SYSTEM_PALLETE[0x10]);
+        assert_eq!(ppu.apply_post_processing(0x2D), NES::SYSTEM_PALLETE[0x20]);
+        assert_eq!(ppu.apply_post_processing(0x0F), NES::SYSTEM_PALLETE[0x00]);
+    }
+
+    #[test]
+    fn test_palette_color_clamped() {
+        let mut ppu = test_ppu();
+        assert_eq!(ppu.apply_post_processing(0x56), NES::SYSTEM_PALLETE[0x16]);
+        assert_eq!(ppu.apply_post_processing(0xFF), NES::SYSTEM_PALLETE[0x3F]);
+
+        ppu.memory.write_byte(PPUMemory::PALLETES_START, 0xF0);
+        render_until(&mut ppu, 0);
+        assert_eq!(ppu.frame.get_background_color(0, 0), NES::SYSTEM_PALLETE[0x30]);
+    }
+
+    #[test]
+    fn test_backdrop_color() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        ppu.memory.write_byte(0x2000, 0x00); // transparent tile...
+        ppu.memory.write_byte(0x23C0, 0xFF); // ...with palette 3
+        ppu.memory.write_byte(PPUMemory::PALLETES_START, 0x16);
+        ppu.memory.write_byte(PPUMemory::PALLETES_START + 0x0C, 0x2A);
+        ppu.memory.write_byte(PPUMemory::PALLETES_START + 0x1C, 0x2A);
+        ppu.memory.write_byte(PPUMemory::PALLETES_START + 0x1D, 0x30);
+        ppu.oam.memory[..4].copy_from_slice(&[0, 0x00, 0b11, 0]); // a transparent sprite at (0, 1)
+        render_until(&mut ppu, 1);
+
+        // pixel value 0 always shows the universal backdrop at $3F00, whatever the palette
+        assert_eq!(ppu.frame.get_background_color(0, 0), NES::SYSTEM_PALLETE[0x16]);
+        assert_eq!(ppu.frame.get_background_color(0, 1), NES::SYSTEM_PALLETE[0x16]);
+        assert_eq!(ppu.frame.get_sprite_priority(0, 1), Frame::EMPTY_PRIORITY);
+    }
+
+    #[test]
+    fn test_rendering_disabled_mid_frame() {
+        let mut ppu = test_ppu();
+        load_solid_tile(&mut ppu);
+        for column in 0..32 {
+            ppu.memory.write_byte(0x2000 + column, 0x01);
+        }
+        ppu.memory.write_byte(PPUMemory::PALLETES_START, 0x16);
+        ppu.memory.write_byte(PPUMemory::PALLETES_START + 0x05, 0x2A);
+        ppu.oam.memory = [0xFF; OAM::MEM_SIZE];
+        place_sprite_zero(&mut ppu, 64, 0);
+
+        render_until(&mut ppu, 1);
+        ppu.mask.clear(ShowBackground);
+        ppu.mask.clear(ShowSprites);
+        run_until(&mut ppu, 3, 0);
+        // with rendering off nothing is fetched, only the backdrop is drawn
+        assert_eq!(ppu.frame.get_background_priority(64, 1), Frame::FG_PRIORITY);
+        assert_eq!(ppu.frame.get_background_priority(64, 2), Frame::BG_PRIORITY);
+        assert_eq!(ppu.frame.get_sprite_priority(64, 2), Frame::EMPTY_PRIORITY);
+        assert_eq!(ppu.frame.get_background_color(64, 2), NES::SYSTEM_PALLETE[0x16]);
+
+        // unless the VRAM address points into palette RAM
+        ppu.scroll_ctx.v = 0x3F05;
+        run_until(&mut ppu, 4, 0);
+        assert_eq!(ppu.frame.get_background_color(64, 3), NES::SYSTEM_PALLETE[0x2A]);
+    }
+
+    #[test]
+    fn test_idle_scanline_fast_path() {
+        let mut fast = test_ppu();
+        let mut slow = test_ppu();
+        for ppu in [&mut fast, &mut slow] {
+            ppu.scanline = 239;
+            ppu.dot = 200;
+            ppu.ctrl.set(GenerateNmi);
+        }
+
+        fast.tick(255);
+        fast.tick(255);
+        fast.step().unwrap();
+        for _ in 0..3 * 510 {
+            slow.tick_dot();
+        }
+        assert_eq!((fast.scanline, fast.dot), (slow.scanline, slow.dot));
+        assert!(fast.status.is_set(VerticalBlank));
+        assert!(fast.poll_nmi());
+
+        for _ in 0..30 {
+            fast.tick(255);
+            fast.step().unwrap();
+            for _ in 0..3 * 255 {
+                slow.tick_dot();
+            }
+            assert_eq!((fast.scanline, fast.dot), (slow.scanline, slow.dot));
+        }
+        // well into the next frame by now
+        assert_eq!(fast.odd_frame, slow.odd_frame);
+        assert!(!fast.status.is_set(VerticalBlank));
+    }
+
+    #[test]
+    fn test_set_palette() {
+        let mut ppu = test_ppu();
+        let mut pal_file = vec![0x80; PAL_FILE_SIZE];
+        pal_file[0..3].copy_from_slice(&[0xFF, 0x00, 0x00]);
+        ppu.set_palette(&Palette::from_bytes(&pal_file).unwrap());
+        ppu.memory.write_byte(PPUMemory::PALLETES_START, 0x00);
+        render_until(&mut ppu, 0);
+        for x in 0..Frame::WIDTH {
+            assert_eq!(ppu.frame.get_background_color(x, 0), (0xFF, 0x00, 0x00));
+        }
+
+        // emphasis is derived from the new palette too
+        ppu.mask.set_value(0b0011_1110);
+        assert_eq!(ppu.apply_post_processing(0x00), (0xFF, 0x00, 0x00));
+        ppu.mask.set_value(0b0101_1110);
+        assert!(ppu.apply_post_processing(0x00).0 < 0xFF);
+    }
+
+    #[test]
+    fn test_with_palette() {
+        let mut colors = NES::SYSTEM_PALLETE;
+        colors[0x16] = (0x01, 0x02, 0x03);
+        let palette = Palette::new(colors);
+        let mut ppu = PPU::with_palette(&palette);
+        assert_eq!(ppu.apply_post_processing(0x16), (0x01, 0x02, 0x03));
+        ppu.set_palette(&DEFAULT_PALETTE);
+        assert_eq!(ppu.apply_post_processing(0x16), NES::SYSTEM_PALLETE[0x16]);
+    }
+
+    #[test]
+    fn test_emphasis() {
+        let mut ppu = test_ppu();
+        ppu.memory.write_byte(PPUMemory::PALLETES_START, 0x30);
+        let white = NES::SYSTEM_PALLETE[0x30];
+
+        for emphasis in 0..8u8 {
+            ppu.mask.set_value(0b0001_1110 | emphasis << 5);
+            render_until(&mut ppu, 0);
+            let (r, g, b) = ppu.frame.get_background_color(0, 0);
+            let darkened = |bit: u8| emphasis == 0b111 || (emphasis != 0 && emphasis & bit == 0);
+            for (channel, base, bit) in [(r, white.0, 0b001), (g, white.1, 0b010), (b, white.2, 0b100)] {
+                if darkened(bit) {
+                    assert!(channel < base, "emphasis {:03b}", emphasis);
+                } else {
+                    assert_eq!(channel, base, "emphasis {:03b}", emphasis);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_emphasis_attenuation() {
+        let mut ppu = test_ppu();
+        ppu.mask.set_value(0b0010_0001); // greyscale, emphasize red
+        let white = NES::SYSTEM_PALLETE[0x30];
+        let emphasized = ppu.apply_post_processing(0x3A);
+        assert_eq!(emphasized.0, white.0);
+        assert!(emphasized.1 < white.1);
+
+        ppu.set_emphasis_attenuation(1.0);
+        assert_eq!(ppu.apply_post_processing(0x3A), white);
+        ppu.set_emphasis_attenuation(0.5);
+        assert_eq!(ppu.apply_post_processing(0x3A), (white.0, (white.1 as f64 * 0.5).round() as u8, (white.2 as f64 * 0.5).round() as u8));
+        ppu.mask.set_value(0);
+        assert_eq!(ppu.apply_post_processing(0x3A), NES::SYSTEM_PALLETE[0x3A]);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_cleared_on_pre_render() {
+        let mut ppu = test_ppu();
+        ppu.status.set(SpriteZeroHit);
+        ppu.status.set(SpriteOverflow);
+        ppu.scanline = PPU::PRE_RENDER_SCANLINE;
+        ppu.cycles = PPU::SCANLINE_CYCLES;
+        ppu.step().unwrap();
+        assert!(!ppu.status.is_set(SpriteZeroHit));
+        assert!(!ppu.status.is_set(SpriteOverflow));
+    }
 }
\ No newline at end of file
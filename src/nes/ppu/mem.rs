@@ -32,6 +32,15 @@ impl PPUMemory {
         self.rom = rom.clone();
     }
 
+    // Mappers like MMC1, MMC3 and AxROM switch the nametable layout at runtime.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.rom.set_mirroring(mirroring);
+    }
+
+    pub fn get_mirroring(&self) -> Mirroring {
+        self.rom.get_mirroring()
+    }
+
     #[inline]
     pub fn read_byte(&self, address: u16) -> u8 {
         let ppu_addr = address % PPUMemory::MEM_SIZE as u16;
@@ -40,7 +49,10 @@ impl PPUMemory {
                 self.rom.read_chr_byte(ppu_addr)
             },
             vram_range!() => {
-                let mirror_addr = self.mirror_vram_addr(ppu_addr);
+                if let Some(data) = self.rom.mapper.read_nametable_byte(ppu_addr, &self.memory) {
+                    return data;
+                }
+                let mirror_addr = PPUMemory::mirror_vram_addr(&self.rom.get_mirroring(), ppu_addr);
                 self.memory[mirror_addr as usize]
             },
             palletes_ram_range!() => {
@@ -62,7 +74,10 @@ impl PPUMemory {
                 self.rom.write_chr_byte(ppu_addr, data)
             },
             vram_range!() => {
-                let mirror_addr = self.mirror_vram_addr(ppu_addr);
+                if self.rom.mapper.write_nametable_byte(ppu_addr, data, &mut self.memory) {
+                    return;
+                }
+                let mirror_addr = PPUMemory::mirror_vram_addr(&self.rom.get_mirroring(), ppu_addr);
                 self.memory[mirror_addr as usize] = data;
             },
             palletes_ram_range!() => {
@@ -75,15 +90,23 @@ impl PPUMemory {
         }
     }
 
+    // The console only has 2kB of nametable RAM, but the backing array spans all of $2000-$2FFF, so
+    // the extra 2kB a four-screen cartridge provides is already there and left unmirrored.
+    //
+    //   Horizontal     Vertical      OneScreenLower  OneScreenUpper  FourScreen
+    //   [ A ] [ A ]    [ A ] [ B ]   [ A ] [ A ]     [ B ] [ B ]     [ A ] [ B ]
+    //   [ B ] [ B ]    [ A ] [ B ]   [ A ] [ A ]     [ B ] [ B ]     [ C ] [ D ]
     #[inline]
-    fn mirror_vram_addr(&self, addr: u16) -> u16 {
-        // todo: does this need to be changed with the introduction of SingleScreen?
+    pub fn mirror_vram_addr(mirroring: &Mirroring, addr: u16) -> u16 {
         let mirrored_addr = addr & 0b0010_1111_1111_1111; // mirror down 0x3000-0x3eff to 0x2000-0x2eff
         let name_table = (mirrored_addr - PPUMemory::VRAM_START) / 0x400; // to the name table index
-        match (&self.rom.screen_mirroring, name_table) {
+        let offset = mirrored_addr & 0x03FF;
+        match (mirroring, name_table) {
             (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => mirrored_addr - 0x800,
             (Mirroring::Horizontal, 1) | (Mirroring::Horizontal, 2) => mirrored_addr - 0x400,
             (Mirroring::Horizontal, 3) => mirrored_addr - 0x800,
+            (Mirroring::OneScreenLower, _) => PPUMemory::VRAM_START + offset,
+            (Mirroring::OneScreenUpper, _) => PPUMemory::VRAM_START + 0x400 + offset,
             _ => mirrored_addr,
         }
     }
@@ -104,6 +127,7 @@ impl PPUMemory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nes::rom::mappers::create_mapper;
 
     const BYTE_A: u8 = 0x0a;
     const BYTE_B: u8 = 0x0b;
@@ -114,4 +138,129 @@ mod tests {
     fn test_read_write() {
         let memory = PPUMemory::new();
     }
+
+    fn mirror_all(mirroring: Mirroring) -> Vec<u16> {
+        [0x2000, 0x2400, 0x2800, 0x2C00, 0x2C05, 0x3005]
+            .iter()
+            .map(|addr| PPUMemory::mirror_vram_addr(&mirroring, *addr))
+            .collect()
+    }
+
+    #[test]
+    fn test_mirror_horizontal() {
+        assert_eq!(mirror_all(Mirroring::Horizontal), vec![0x2000, 0x2000, 0x2400, 0x2400, 0x2405, 0x2005]);
+    }
+
+    #[test]
+    fn test_mirror_vertical() {
+        assert_eq!(mirror_all(Mirroring::Vertical), vec![0x2000, 0x2400, 0x2000, 0x2400, 0x2405, 0x2005]);
+    }
+
+    #[test]
+    fn test_mirror_one_screen() {
+        assert_eq!(mirror_all(Mirroring::OneScreenLower), vec![0x2000, 0x2000, 0x2000, 0x2000, 0x2005, 0x2005]);
+        assert_eq!(mirror_all(Mirroring::OneScreenUpper), vec![0x2400, 0x2400, 0x2400, 0x2400, 0x2405, 0x2405]);
+    }
+
+    #[test]
+    fn test_mirror_four_screen() {
+        assert_eq!(mirror_all(Mirroring::FourScreen), vec![0x2000, 0x2400, 0x2800, 0x2C00, 0x2C05, 0x2005]);
+    }
+
+    // writes one byte per logical nametable and reports which physical table each read comes from
+    fn nametable_aliases(memory: &mut PPUMemory) -> Vec<u8> {
+        for (idx, addr) in [0x2000, 0x2400, 0x2800, 0x2C00].iter().enumerate() {
+            memory.write_byte(addr + 0x123, idx as u8);
+        }
+        [0x2000, 0x2400, 0x2800, 0x2C00, 0x3000, 0x3C00].iter()
+            .map(|addr| memory.read_byte(addr + 0x123))
+            .collect()
+    }
+
+    #[test]
+    fn test_aliases_horizontal() {
+        let mut memory = PPUMemory::new();
+        memory.set_mirroring(Mirroring::Horizontal);
+        assert_eq!(nametable_aliases(&mut memory), vec![1, 1, 3, 3, 1, 3]);
+    }
+
+    #[test]
+    fn test_aliases_vertical() {
+        let mut memory = PPUMemory::new();
+        memory.set_mirroring(Mirroring::Vertical);
+        assert_eq!(nametable_aliases(&mut memory), vec![2, 3, 2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn test_aliases_one_screen() {
+        let mut memory = PPUMemory::new();
+        memory.set_mirroring(Mirroring::OneScreenLower);
+        assert_eq!(nametable_aliases(&mut memory), vec![3, 3, 3, 3, 3, 3]);
+        assert_eq!(memory.memory[0x2123], 3);
+
+        let mut memory = PPUMemory::new();
+        memory.set_mirroring(Mirroring::OneScreenUpper);
+        assert_eq!(nametable_aliases(&mut memory), vec![3, 3, 3, 3, 3, 3]);
+        assert_eq!(memory.memory[0x2523], 3);
+    }
+
+    #[test]
+    fn test_aliases_four_screen() {
+        let mut memory = PPUMemory::new();
+        memory.set_mirroring(Mirroring::FourScreen);
+        assert_eq!(nametable_aliases(&mut memory), vec![0, 1, 2, 3, 0, 3]);
+    }
+
+    #[test]
+    fn test_mapper_mirroring() {
+        let mut memory = PPUMemory::new();
+        memory.rom.mapper_id = 1;
+        memory.rom.mapper = create_mapper(&memory.rom).unwrap();
+        memory.rom.screen_mirroring = Mirroring::Horizontal;
+        // MMC1 control register, written serially: %00010 selects vertical mirroring
+        for bit in [0, 1, 0, 0, 0] {
+            memory.rom.write_prg_byte(0x8000, bit);
+        }
+        assert_eq!(memory.get_mirroring(), Mirroring::Vertical);
+        assert_eq!(nametable_aliases(&mut memory), vec![2, 3, 2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn test_set_mirroring() {
+        let mut memory = PPUMemory::new();
+        memory.set_mirroring(Mirroring::Vertical);
+        memory.write_byte(0x2C00, BYTE_A);
+        assert_eq!(memory.read_byte(0x2400), BYTE_A);
+
+        memory.set_mirroring(Mirroring::FourScreen);
+        memory.write_byte(0x2C00, BYTE_B);
+        assert_eq!(memory.read_byte(0x2400), BYTE_A);
+        assert_eq!(memory.read_byte(0x2C00), BYTE_B);
+        assert_eq!(memory.get_mirroring(), Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn test_sprite_backdrop_mirrors() {
+        let mut memory = PPUMemory::new();
+        for (sprite_addr, bg_addr) in [(0x3F10, 0x3F00), (0x3F14, 0x3F04), (0x3F18, 0x3F08), (0x3F1C, 0x3F0C)] {
+            memory.write_byte(sprite_addr, BYTE_A);
+            assert_eq!(memory.read_byte(bg_addr), BYTE_A);
+            memory.write_byte(bg_addr, BYTE_B);
+            assert_eq!(memory.read_byte(sprite_addr), BYTE_B);
+        }
+    }
+
+    #[test]
+    fn test_sprite_palettes_not_mirrored() {
+        let mut memory = PPUMemory::new();
+        for offset in [0x01, 0x02, 0x03, 0x05, 0x0D, 0x0F] {
+            memory.write_byte(0x3F00 + offset, BYTE_A);
+            memory.write_byte(0x3F10 + offset, BYTE_B);
+            assert_eq!(memory.read_byte(0x3F00 + offset), BYTE_A);
+            assert_eq!(memory.read_byte(0x3F10 + offset), BYTE_B);
+        }
+        // the whole $3F00-$3F1F block repeats up to $3FFF
+        memory.write_byte(0x3FF0, BYTE_A);
+        assert_eq!(memory.read_byte(0x3F00), BYTE_A);
+    }
 }
\ No newline at end of file
@@ -1,9 +1,34 @@
+// Ref: https://www.nesdev.org/wiki/PPU_OAM
+
+// Sprite attributes (byte 2):
+// =====================================
+// 76543210
+// ||||||||
+// ||||||++- Palette (4 to 7) of sprite
+// |||+++--- Unimplemented (read 0)
+// ||+------ Priority (0: in front of background; 1: behind background)
+// |+------- Flip sprite horizontally
+// +-------- Flip sprite vertically
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SpriteInfo {
+    pub index: u8,
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
 pub struct OAM {
     pub memory: [u8; OAM::MEM_SIZE],
 }
 
 impl OAM {
     pub const MEM_SIZE: usize = 0x100 as usize; // 256 bytes
+    pub const SPRITE_COUNT: usize = OAM::MEM_SIZE / 4;
 
     pub fn new() -> Self {
         OAM {
@@ -21,6 +46,24 @@ impl OAM {
         ]
     }
 
+    pub fn get_sprite_info(&self, sprite_idx: u8) -> SpriteInfo {
+        let [y, tile, attributes, x] = self.get_sprite(sprite_idx);
+        SpriteInfo {
+            index: sprite_idx,
+            x,
+            y,
+            tile,
+            palette: attributes & 0b0000_0011,
+            behind_background: attributes & 0b0010_0000 != 0,
+            flip_horizontal: attributes & 0b0100_0000 != 0,
+            flip_vertical: attributes & 0b1000_0000 != 0,
+        }
+    }
+
+    pub fn get_sprite_infos(&self) -> Vec<SpriteInfo> {
+        (0..OAM::SPRITE_COUNT as u8).map(|sprite_idx| self.get_sprite_info(sprite_idx)).collect()
+    }
+
     #[inline]
     pub fn read_byte(&self, addr: u8) -> u8 {
         self.memory[addr as usize]
@@ -45,4 +88,26 @@ mod tests {
     fn test_read_write() {
         let memory = OAM::new();
     }
+
+    #[test]
+    fn test_get_sprite_info() {
+        let mut memory = OAM::new();
+        memory.memory[8..12].copy_from_slice(&[0x40, 0x12, 0b1110_0010, 0x80]);
+        assert_eq!(memory.get_sprite_info(2), SpriteInfo {
+            index: 2,
+            x: 0x80,
+            y: 0x40,
+            tile: 0x12,
+            palette: 2,
+            behind_background: true,
+            flip_horizontal: true,
+            flip_vertical: true,
+        });
+
+        memory.memory[8..12].copy_from_slice(&[BYTE_A, BYTE_B, 0b0001_1101, BYTE_A]);
+        let sprite = memory.get_sprite_info(2);
+        assert_eq!(sprite.palette, 1);
+        assert!(!sprite.behind_background && !sprite.flip_horizontal && !sprite.flip_vertical);
+        assert_eq!(memory.get_sprite_infos().len(), OAM::SPRITE_COUNT);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,198 @@
+// Ref: https://www.nesdev.org/wiki/NTSC_video#Color_Tint_Bits
+
+// PPUMASK emphasis bits:
+// =====================================
+// BGR
+// ||+- Emphasize red
+// |+-- Emphasize green
+// +--- Emphasize blue
+
+use std::{fmt, fs, io};
+use std::path::Path;
+use crate::nes::NES;
+
+pub type Color = (u8, u8, u8);
+pub type MasterPalette = [Color; 64];
+
+// Setting an emphasis bit darkens the two other color channels. Setting all three darkens every
+// channel. How much differs a little between PPU revisions (ex: 2C02E vs 2C02G), so this is only the
+// default, see PPU::set_emphasis_attenuation.
+pub const EMPHASIS_ATTENUATION: f64 = 0.816328;
+
+// A .pal file is just the 64 master colors as consecutive RGB triples.
+pub const PAL_FILE_SIZE: usize = 3 * 64;
+
+// The "classic" NTSC palette, used until another one is loaded.
+pub const DEFAULT_PALETTE: Palette = Palette { colors: NES::SYSTEM_PALLETE };
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    WrongSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Io(err) => write!(f, "Unable to read palette file: {}", err),
+            PaletteError::WrongSize(len) => write!(f, "Palette must be {} bytes, got {}", PAL_FILE_SIZE, len),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PaletteError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(err: io::Error) -> Self {
+        PaletteError::Io(err)
+    }
+}
+
+// The 64 colors the PPU's 6-bit palette indices map to. Different TVs (and PAL consoles) show
+// noticeably different colors, so emulators ship several, ex: FCEUX's or Nestopia's .pal files.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Palette {
+    colors: MasterPalette,
+}
+
+impl Palette {
+    pub fn new(colors: MasterPalette) -> Self {
+        Palette { colors }
+    }
+
+    pub fn from_pal_file(path: &Path) -> Result<Palette, PaletteError> {
+        Palette::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Palette, PaletteError> {
+        if bytes.len() != PAL_FILE_SIZE {
+            return Err(PaletteError::WrongSize(bytes.len()));
+        }
+
+        let mut colors = [(0, 0, 0); 64];
+        for (color, rgb) in colors.iter_mut().zip(bytes.chunks_exact(3)) {
+            *color = (rgb[0], rgb[1], rgb[2]);
+        }
+        Ok(Palette { colors })
+    }
+
+    #[inline]
+    pub fn get_color(&self, palette_index: u8) -> Color {
+        self.colors[(palette_index & 0x3F) as usize]
+    }
+
+    pub fn get_colors(&self) -> &MasterPalette {
+        &self.colors
+    }
+}
+
+pub fn build_emphasis_palettes(master: &MasterPalette, attenuation: f64) -> [MasterPalette; 8] {
+    let mut palettes = [*master; 8];
+    for (emphasis, palette) in palettes.iter_mut().enumerate() {
+        for color in palette.iter_mut() {
+            *color = emphasize(*color, emphasis as u8, attenuation);
+        }
+    }
+    palettes
+}
+
+pub fn emphasize(color: Color, emphasis: u8, attenuation: f64) -> Color {
+    if emphasis == 0 { return color }
+
+    let all = emphasis == 0b111;
+    let attenuate = |channel: u8, bit: u8| {
+        if all || emphasis & bit == 0 {
+            (channel as f64 * attenuation).round() as u8
+        } else {
+            channel
+        }
+    };
+    (attenuate(color.0, 0b001), attenuate(color.1, 0b010), attenuate(color.2, 0b100))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: Color = (0xAD, 0xAD, 0xAD);
+
+    #[test]
+    fn test_palette_from_bytes() {
+        let mut bytes = vec![0; PAL_FILE_SIZE];
+        bytes[0..3].copy_from_slice(&[0xFF, 0x00, 0x00]);
+        bytes[189..192].copy_from_slice(&[0x01, 0x02, 0x03]);
+        let palette = Palette::from_bytes(&bytes).unwrap();
+        assert_eq!(palette.get_color(0), (0xFF, 0x00, 0x00));
+        assert_eq!(palette.get_color(1), (0x00, 0x00, 0x00));
+        assert_eq!(palette.get_color(63), (0x01, 0x02, 0x03));
+    }
+
+    #[test]
+    fn test_palette_wrong_size() {
+        assert!(matches!(Palette::from_bytes(&[0; PAL_FILE_SIZE - 1]), Err(PaletteError::WrongSize(_))));
+        assert!(matches!(Palette::from_bytes(&[0; 8 * PAL_FILE_SIZE]), Err(PaletteError::WrongSize(_))));
+        assert!(matches!(Palette::from_bytes(&[]), Err(PaletteError::WrongSize(0))));
+    }
+
+    #[test]
+    fn test_palette_from_pal_file() {
+        let path = std::env::temp_dir().join("alpines_test_palette.pal");
+        let mut bytes = vec![0; PAL_FILE_SIZE];
+        bytes[0x16 * 3..0x16 * 3 + 3].copy_from_slice(&[0x12, 0x34, 0x56]);
+        fs::write(&path, &bytes).unwrap();
+        let palette = Palette::from_pal_file(&path).unwrap();
+        assert_eq!(palette.get_color(0x16), (0x12, 0x34, 0x56));
+        assert_eq!(palette.get_color(0x56), (0x12, 0x34, 0x56));
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(Palette::from_pal_file(&path), Err(PaletteError::Io(_))));
+        assert!(matches!(Palette::from_bytes(&bytes[1..]), Err(PaletteError::WrongSize(191))));
+        assert_eq!(DEFAULT_PALETTE.get_colors(), &NES::SYSTEM_PALLETE);
+    }
+
+    #[test]
+    fn test_no_emphasis() {
+        assert_eq!(emphasize(GREY, 0b000, EMPHASIS_ATTENUATION), GREY);
+    }
+
+    #[test]
+    fn test_single_emphasis() {
+        assert_eq!(emphasize(GREY, 0b001, EMPHASIS_ATTENUATION), (0xAD, 0x8D, 0x8D));
+        assert_eq!(emphasize(GREY, 0b010, EMPHASIS_ATTENUATION), (0x8D, 0xAD, 0x8D));
+        assert_eq!(emphasize(GREY, 0b100, EMPHASIS_ATTENUATION), (0x8D, 0x8D, 0xAD));
+    }
+
+    #[test]
+    fn test_double_emphasis() {
+        assert_eq!(emphasize(GREY, 0b011, EMPHASIS_ATTENUATION), (0xAD, 0xAD, 0x8D));
+        assert_eq!(emphasize(GREY, 0b110, EMPHASIS_ATTENUATION), (0x8D, 0xAD, 0xAD));
+    }
+
+    #[test]
+    fn test_full_emphasis() {
+        assert_eq!(emphasize(GREY, 0b111, EMPHASIS_ATTENUATION), (0x8D, 0x8D, 0x8D));
+    }
+
+    #[test]
+    fn test_emphasis_attenuation() {
+        assert_eq!(emphasize(GREY, 0b001, 0.5), (0xAD, 0x57, 0x57));
+        assert_eq!(emphasize(GREY, 0b111, 1.0), GREY);
+    }
+
+    #[test]
+    fn test_build_emphasis_palettes() {
+        let master = [GREY; 64];
+        let palettes = build_emphasis_palettes(&master, EMPHASIS_ATTENUATION);
+        assert_eq!(palettes[0], master);
+        for emphasis in 0..8 {
+            assert!(palettes[emphasis].iter().all(|color| *color == emphasize(GREY, emphasis as u8, EMPHASIS_ATTENUATION)));
+        }
+    }
+}
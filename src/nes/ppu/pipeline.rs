@@ -0,0 +1,154 @@
+// Ref: https://www.nesdev.org/wiki/PPU_rendering
+
+// Background shift registers:
+// =====================================
+// pattern: [ tile being drawn | next tile ]  <- shifted left once per dot, 16 bits each
+// palette: [ tile being drawn | next tile ]  <- attribute bits, expanded to 8 bits per tile
+//
+// The next tile's nametable, attribute and pattern bytes are fetched over 8 dots and loaded into
+// the low byte, so the pixel under fine X is always read from bit (15 - x).
+
+pub struct BackgroundShifter {
+    pub next_tile: u8,
+    pub next_palette: u8,
+    pub next_lower: u8,
+    pub next_upper: u8,
+    pattern_lower: u16,
+    pattern_upper: u16,
+    palette_lower: u16,
+    palette_upper: u16,
+}
+
+impl BackgroundShifter {
+    pub fn new() -> Self {
+        BackgroundShifter {
+            next_tile: 0,
+            next_palette: 0,
+            next_lower: 0,
+            next_upper: 0,
+            pattern_lower: 0,
+            pattern_upper: 0,
+            palette_lower: 0,
+            palette_upper: 0,
+        }
+    }
+
+    #[inline]
+    pub fn load(&mut self) {
+        self.pattern_lower = (self.pattern_lower & 0xFF00) | self.next_lower as u16;
+        self.pattern_upper = (self.pattern_upper & 0xFF00) | self.next_upper as u16;
+        self.palette_lower = (self.palette_lower & 0xFF00) | if self.next_palette & 0b01 != 0 { 0xFF } else { 0x00 };
+        self.palette_upper = (self.palette_upper & 0xFF00) | if self.next_palette & 0b10 != 0 { 0xFF } else { 0x00 };
+    }
+
+    #[inline]
+    pub fn shift(&mut self) {
+        self.pattern_lower <<= 1;
+        self.pattern_upper <<= 1;
+        self.palette_lower <<= 1;
+        self.palette_upper <<= 1;
+    }
+
+    // returns the 2-bit pattern value and the 2-bit palette number under fine X
+    #[inline]
+    pub fn get_pixel(&self, fine_x: u8) -> (u8, u8) {
+        let mux = 0x8000 >> fine_x;
+        let bit = |register: u16| (register & mux != 0) as u8;
+        (bit(self.pattern_upper) << 1 | bit(self.pattern_lower), bit(self.palette_upper) << 1 | bit(self.palette_lower))
+    }
+}
+
+// One of the eight sprite output units, loaded at the end of a scanline for the next one. The
+// pattern bytes are stored already flipped horizontally when the sprite asks for it.
+#[derive(Clone, Copy)]
+pub struct SpriteUnit {
+    pub index: usize,
+    pub x: u8,
+    pub attributes: u8,
+    pub lower: u8,
+    pub upper: u8,
+}
+
+impl SpriteUnit {
+    #[inline]
+    pub fn get_pixel(&self, x: usize) -> u8 {
+        let sprite_x = self.x as usize;
+        if x < sprite_x || x >= sprite_x + 8 { return 0 }
+
+        let shift = 7 - (x - sprite_x);
+        ((self.upper >> shift) & 1) << 1 | ((self.lower >> shift) & 1)
+    }
+
+    #[inline]
+    pub fn get_palette(&self) -> u8 {
+        self.attributes & 0b0000_0011
+    }
+
+    #[inline]
+    pub fn is_behind_background(&self) -> bool {
+        self.attributes & 0b0010_0000 != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_background_shifter_load() {
+        let mut shifter = BackgroundShifter::new();
+        shifter.next_lower = 0b1010_0000;
+        shifter.next_upper = 0b1100_0000;
+        shifter.next_palette = 0b10;
+        shifter.load();
+        for _ in 0..8 {
+            shifter.shift();
+        }
+        assert_eq!(shifter.get_pixel(0), (0b11, 0b10));
+        assert_eq!(shifter.get_pixel(1), (0b10, 0b10));
+        assert_eq!(shifter.get_pixel(2), (0b01, 0b10));
+        assert_eq!(shifter.get_pixel(3), (0b00, 0b10));
+    }
+
+    #[test]
+    fn test_background_shifter_two_tiles() {
+        let mut shifter = BackgroundShifter::new();
+        shifter.next_lower = 0xFF;
+        shifter.next_palette = 0b01;
+        shifter.load();
+        for _ in 0..8 {
+            shifter.shift();
+        }
+        shifter.next_lower = 0x00;
+        shifter.next_upper = 0xFF;
+        shifter.next_palette = 0b11;
+        shifter.load();
+
+        // fine X reaches into the next tile once it passes the current one
+        for _ in 0..5 {
+            shifter.shift();
+        }
+        assert_eq!(shifter.get_pixel(0), (0b01, 0b01));
+        assert_eq!(shifter.get_pixel(2), (0b01, 0b01));
+        assert_eq!(shifter.get_pixel(3), (0b10, 0b11));
+    }
+
+    #[test]
+    fn test_sprite_unit_pixel() {
+        let sprite = SpriteUnit { index: 0, x: 10, attributes: 0b0010_0011, lower: 0b1000_0001, upper: 0b0000_0001 };
+        assert_eq!(sprite.get_pixel(9), 0);
+        assert_eq!(sprite.get_pixel(10), 0b01);
+        assert_eq!(sprite.get_pixel(11), 0);
+        assert_eq!(sprite.get_pixel(17), 0b11);
+        assert_eq!(sprite.get_pixel(18), 0);
+        assert_eq!(sprite.get_palette(), 3);
+        assert!(sprite.is_behind_background());
+    }
+
+    #[test]
+    fn test_sprite_unit_right_edge() {
+        let sprite = SpriteUnit { index: 0, x: 252, attributes: 0, lower: 0xFF, upper: 0x00 };
+        assert_eq!(sprite.get_pixel(255), 0b01);
+        assert_eq!(sprite.get_pixel(256), 0b01);
+    }
+}
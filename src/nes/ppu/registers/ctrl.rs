@@ -70,6 +70,12 @@ impl ControlRegister {
         if self.is_set(ControlFlag::SpritePatternAddr) { 0x1000 } else { 0x0000 }
     }
 
+    // 8x16 sprites are two stacked tiles: the even tile of the pair on top, the next one below
+    #[inline]
+    pub fn get_sprite_height(&self) -> usize {
+        if self.is_set(ControlFlag::SpriteSize) { 16 } else { 8 }
+    }
+
     #[inline]
     pub fn get_background_chrtable_address(&self) -> u16 {
         if self.is_set(ControlFlag::BackgroundPatternAddr) { 0x1000 } else { 0x0000 }
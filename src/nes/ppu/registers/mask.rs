@@ -56,6 +56,12 @@ impl MaskRegister {
         MaskRegister { value }
     }
 
+    // bits 5-7 as a 0-7 index: red in bit 0, green in bit 1, blue in bit 2
+    #[inline]
+    pub fn get_emphasis(&self) -> u8 {
+        self.value >> 5
+    }
+
     #[inline]
     pub fn get_value(&self) -> u8 {
         self.value
@@ -16,6 +16,9 @@ pub struct ScrollContext {
 }
 
 impl ScrollContext {
+    const HORIZONTAL_BITS: u16 = 0b0000_0100_0001_1111;
+    const VERTICAL_BITS: u16 = 0b0111_1011_1110_0000;
+
     pub fn new() -> Self {
         ScrollContext {
             v: 0,
@@ -59,20 +62,29 @@ impl ScrollContext {
         }
     }
 
-    pub fn handle_data_reg_read_write(&mut self) {
-        self.scroll_x_increment();
-        self.scroll_y_increment();
-    }
-
-    pub fn handle_scanline_start(&mut self, scanline: isize) {
-        if scanline == 0 {
-            self.v = self.t;
+    // outside of rendering, $2007 accesses step v by the $2000 increment (1 or 32); during
+    // rendering, they instead trigger a coarse X and a fine Y increment at the same time
+    pub fn handle_data_reg_read_write(&mut self, increment: u8, rendering: bool) {
+        if rendering {
+            self.scroll_x_increment();
+            self.scroll_y_increment();
         } else {
-            self.v &= 0b1111_1011_1110_0000;
-            self.v |= self.t & 0b0000_0100_0001_1111;
+            self.v = self.v.wrapping_add(increment as u16) & 0x7FFF;
         }
     }
 
+    // dot 257 of each visible and pre-render scanline: v: ....A.. ...BCDEF <- t: ....A.. ...BCDEF
+    pub fn copy_horizontal_bits(&mut self) {
+        self.v &= !ScrollContext::HORIZONTAL_BITS;
+        self.v |= self.t & ScrollContext::HORIZONTAL_BITS;
+    }
+
+    // dots 280 to 304 of the pre-render scanline: v: GHIA.BC DEF..... <- t: GHIA.BC DEF.....
+    pub fn copy_vertical_bits(&mut self) {
+        self.v &= !ScrollContext::VERTICAL_BITS;
+        self.v |= self.t & ScrollContext::VERTICAL_BITS;
+    }
+
     // coarse X is incremented when the next tile is reached
     pub fn scroll_x_increment(&mut self) {
         if (self.v & 0x001F) == 31 { // if coarse X == 31
@@ -136,4 +148,155 @@ impl ScrollContext {
     pub fn get_fine_scroll_y(&self) -> u8 {
         ((self.v & 0b0111_0000_0000_0000) >> 12) as u8
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_scroll(ctx: &mut ScrollContext, value: u8) {
+        ctx.handle_scroll_reg_write(value);
+        ctx.w = !ctx.w;
+    }
+
+    fn write_addr(ctx: &mut ScrollContext, value: u8) {
+        ctx.handle_addr_reg_write(value);
+        ctx.w = !ctx.w;
+    }
+
+    #[test]
+    fn test_nesdev_register_sequence() {
+        // Ref: https://www.nesdev.org/wiki/PPU_scrolling#Summary
+        let mut ctx = ScrollContext::new();
+        ctx.t = 0x7FFF;
+
+        ctx.handle_cntl_reg_write(0b0000_0000);
+        assert_eq!(ctx.t, 0b0111_0011_1111_1111);
+
+        ctx.w = false; // $2002 read
+        write_scroll(&mut ctx, 0b0111_1101);
+        assert_eq!(ctx.t, 0b0111_0011_1110_1111);
+        assert_eq!(ctx.x, 0b101);
+        assert!(ctx.w);
+
+        write_scroll(&mut ctx, 0b0101_1110);
+        assert_eq!(ctx.t, 0b0110_0001_0110_1111);
+        assert!(!ctx.w);
+
+        write_addr(&mut ctx, 0b0011_1101);
+        assert_eq!(ctx.t, 0b0011_1101_0110_1111);
+
+        write_addr(&mut ctx, 0b1111_0000);
+        assert_eq!(ctx.t, 0b0011_1101_1111_0000);
+        assert_eq!(ctx.v, ctx.t);
+    }
+
+    #[test]
+    fn test_scroll_split_across_writes() {
+        for scroll_x in 0..=255u8 {
+            for scroll_y in (0..240u8).step_by(7) {
+                let mut ctx = ScrollContext::new();
+                ctx.handle_cntl_reg_write(0b0000_0011);
+                write_scroll(&mut ctx, scroll_x);
+                write_scroll(&mut ctx, scroll_y);
+                ctx.copy_horizontal_bits();
+                ctx.copy_vertical_bits();
+
+                assert_eq!(ctx.get_coarse_scroll_x(), scroll_x >> 3);
+                assert_eq!(ctx.get_fine_scroll_x(), scroll_x & 0b111);
+                assert_eq!(ctx.get_coarse_scroll_y(), scroll_y >> 3);
+                assert_eq!(ctx.get_fine_scroll_y(), scroll_y & 0b111);
+                assert_eq!(ctx.get_nametable_address(), 0x2C00);
+            }
+        }
+    }
+
+    #[test]
+    fn test_copy_horizontal_bits() {
+        let mut ctx = ScrollContext::new();
+        ctx.v = 0b0101_1010_1010_1010;
+        ctx.t = 0b0010_0101_0101_0101;
+        ctx.copy_horizontal_bits();
+        assert_eq!(ctx.v, 0b0101_1110_1011_0101);
+    }
+
+    #[test]
+    fn test_copy_vertical_bits() {
+        let mut ctx = ScrollContext::new();
+        ctx.v = 0b0101_1010_1010_1010;
+        ctx.t = 0b0010_0101_0101_0101;
+        ctx.copy_vertical_bits();
+        assert_eq!(ctx.v, 0b0010_0001_0100_1010);
+    }
+
+    #[test]
+    fn test_mid_frame_split() {
+        // status bar at the top scrolled to 0, the playfield below it scrolled by $2005 mid-frame
+        let mut ctx = ScrollContext::new();
+        write_scroll(&mut ctx, 0);
+        write_scroll(&mut ctx, 0);
+        ctx.copy_horizontal_bits();
+        ctx.copy_vertical_bits();
+        for _ in 0..32 {
+            ctx.scroll_y_increment();
+            ctx.copy_horizontal_bits();
+        }
+        assert_eq!(ctx.get_coarse_scroll_y(), 4);
+
+        ctx.handle_cntl_reg_write(0b0000_0001);
+        write_scroll(&mut ctx, 0x85);
+        write_scroll(&mut ctx, 0x40); // vertical bits only take effect on the next frame
+        ctx.scroll_y_increment();
+        ctx.copy_horizontal_bits();
+        assert_eq!(ctx.get_coarse_scroll_x(), 0x10);
+        assert_eq!(ctx.get_fine_scroll_x(), 0x05);
+        assert_eq!(ctx.get_nametable_address(), 0x2400);
+        assert_eq!(ctx.get_coarse_scroll_y(), 4);
+        assert_eq!(ctx.get_fine_scroll_y(), 1);
+    }
+
+    #[test]
+    fn test_scroll_x_increment_wraps_nametable() {
+        let mut ctx = ScrollContext::new();
+        ctx.v = 0x001F;
+        ctx.scroll_x_increment();
+        assert_eq!(ctx.v, 0x0400);
+        ctx.v = 0x041F;
+        ctx.scroll_x_increment();
+        assert_eq!(ctx.v, 0x0000);
+    }
+
+    #[test]
+    fn test_scroll_y_increment_wraps() {
+        let mut ctx = ScrollContext::new();
+        ctx.v = 0x7000 | (29 << 5);
+        ctx.scroll_y_increment();
+        assert_eq!(ctx.v, 0x0800);
+
+        // coarse Y 30 and 31 read attribute data and wrap without switching nametables
+        ctx.v = 0x7000 | (31 << 5);
+        ctx.scroll_y_increment();
+        assert_eq!(ctx.v, 0x0000);
+
+        ctx.v = 0x6000 | (5 << 5);
+        ctx.scroll_y_increment();
+        assert_eq!(ctx.v, 0x7000 | (5 << 5));
+    }
+
+    #[test]
+    fn test_data_reg_increment() {
+        let mut ctx = ScrollContext::new();
+        ctx.v = 0x2000;
+        ctx.handle_data_reg_read_write(1, false);
+        assert_eq!(ctx.v, 0x2001);
+        ctx.handle_data_reg_read_write(32, false);
+        assert_eq!(ctx.v, 0x2021);
+
+        ctx.v = 0x7FFF;
+        ctx.handle_data_reg_read_write(1, false);
+        assert_eq!(ctx.v, 0x0000);
+
+        ctx.v = 0x001F;
+        ctx.handle_data_reg_read_write(1, true);
+        assert_eq!(ctx.v, 0x1400);
+    }
+}
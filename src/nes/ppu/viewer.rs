@@ -0,0 +1,406 @@
+// Ref: https://www.nesdev.org/wiki/PPU_nametables
+
+// Nametable viewer layout:
+// =====================================
+// +-----------+-----------+
+// |           |           |
+// |  $2000    |  $2400    |
+// |           |           |
+// +-----------+-----------+  480
+// |           |           |
+// |  $2800    |  $2C00    |
+// |           |           |
+// +-----------+-----------+
+//            512
+//
+// Each logical nametable is read through the cartridge's mirroring, so mirrored quadrants show
+// the same tiles. The scroll window is drawn as a 256x240 outline that wraps around the edges.
+
+// PPU viewer layout:
+// =====================================
+// +-----------------------+-----------------------+
+// |                       | [ $0000 ][ $1000 ]    |  128
+// |      nametables       +-----------------------+
+// |                       | palettes              |  32
+// |                       |                       |
+// +-----------------------+-----------------------+  480
+//          512                      256
+//
+// Everything the other viewers show at once, except for the sprites.
+
+// Palette RAM viewer layout:
+// =====================================
+//        palette 0     palette 1     palette 2     palette 3
+// BG:  [$3F00-$3F03] [$3F04-$3F07] [$3F08-$3F0B] [$3F0C-$3F0F]
+// SPR: [$3F10-$3F13] [$3F14-$3F17] [$3F18-$3F1B] [$3F1C-$3F1F]
+//
+// Every entry is a 16x16 swatch, so the view is 256x32.
+
+// Pattern table viewer layout:
+// =====================================
+// [ $0000-$0FFF ][ $1000-$1FFF ]  <- 16x16 tiles each, 256x128 in total
+//
+// Tiles are read through the PPU's CHR mapping, so the view follows the mapper's bank switches
+// and CHR RAM, and they're colored with one of the 8 palette groups (0-3 background, 4-7 sprites).
+// Pixel value 0 shows the backdrop, like it does on screen.
+
+// Sprite viewer layout:
+// =====================================
+// The 64 OAM entries in an 8x8 grid, in OAM order. Each 16x24 cell fits an 8x16 sprite with a
+// 4 pixel margin, drawn with its own palette and flips. Transparent pixels show the backdrop.
+
+use crate::nes::io::frame::Frame;
+use crate::nes::ppu::mem::PPUMemory;
+use crate::nes::ppu::PPU;
+
+pub const NAMETABLES_WIDTH: usize = 2 * Frame::WIDTH;
+pub const NAMETABLES_HEIGHT: usize = 2 * Frame::HEIGHT;
+pub const SCROLL_OVERLAY_COLOR: (u8, u8, u8) = (0xFF, 0x00, 0x00);
+
+pub const PALETTES_WIDTH: usize = PALETTE_SWATCH_SIZE * 16;
+pub const PALETTES_HEIGHT: usize = PALETTE_SWATCH_SIZE * 2;
+
+pub const PATTERN_TABLES_WIDTH: usize = 2 * PATTERN_TABLE_SIZE;
+pub const PATTERN_TABLES_HEIGHT: usize = PATTERN_TABLE_SIZE;
+pub const PALETTE_GROUPS: u8 = 8;
+
+pub const SPRITES_WIDTH: usize = SPRITE_CELL_WIDTH * 8;
+pub const SPRITES_HEIGHT: usize = SPRITE_CELL_HEIGHT * 8;
+
+pub const PPU_VIEWER_WIDTH: usize = NAMETABLES_WIDTH + PATTERN_TABLES_WIDTH;
+pub const PPU_VIEWER_HEIGHT: usize = NAMETABLES_HEIGHT;
+
+const PALETTE_SWATCH_SIZE: usize = 16;
+const PATTERN_TABLE_SIZE: usize = 128;
+const SPRITE_CELL_WIDTH: usize = 16;
+const SPRITE_CELL_HEIGHT: usize = 24;
+const SPRITE_CELL_MARGIN: usize = 4;
+const NAMETABLE_TILES_X: usize = 32;
+const NAMETABLE_TILES_Y: usize = 30;
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x3C0;
+
+pub fn render_nametables(ppu: &PPU, frame: &mut Frame) {
+    for nametable in 0..4 {
+        let origin_x = (nametable & 1) * Frame::WIDTH;
+        let origin_y = (nametable >> 1) * Frame::HEIGHT;
+        render_nametable_at(ppu, frame, nametable as u8, origin_x, origin_y);
+    }
+    render_scroll_overlay(ppu, frame);
+}
+
+// A single logical nametable (0-3 for $2000, $2400, $2800 and $2C00), without the scroll overlay.
+pub fn render_nametable(ppu: &PPU, which: u8) -> Frame {
+    let mut frame = Frame::new();
+    render_nametable_at(ppu, &mut frame, which, 0, 0);
+    frame
+}
+
+fn render_nametable_at(ppu: &PPU, frame: &mut Frame, which: u8, origin_x: usize, origin_y: usize) {
+    let base = 0x2000 + 0x400 * (which & 0b11) as u16;
+    for tile_y in 0..NAMETABLE_TILES_Y {
+        for tile_x in 0..NAMETABLE_TILES_X {
+            render_nametable_tile(ppu, frame, base, tile_x, tile_y, origin_x, origin_y);
+        }
+    }
+}
+
+fn render_nametable_tile(ppu: &PPU, frame: &mut Frame, base: u16, tile_x: usize, tile_y: usize, origin_x: usize, origin_y: usize) {
+    let tile_index = ppu.memory.read_byte(base + (NAMETABLE_TILES_X * tile_y + tile_x) as u16);
+    let attr_address = base + ATTRIBUTE_TABLE_OFFSET + (8 * (tile_y / 4) + tile_x / 4) as u16;
+    let palette = PPU::attribute_palette(ppu.memory.read_byte(attr_address), tile_x, tile_y);
+
+    let pattern_address = ppu.ctrl.get_background_chrtable_address() + 16 * tile_index as u16;
+    for row in 0..8 {
+        let lower = ppu.memory.read_byte(pattern_address + row as u16);
+        let upper = ppu.memory.read_byte(pattern_address + row as u16 + 8);
+        for col in 0..8 {
+            let shift = 7 - col;
+            let value = ((upper >> shift) & 1) << 1 | ((lower >> shift) & 1);
+            let palette_address = if value == 0 {
+                PPUMemory::PALLETES_START
+            } else {
+                PPUMemory::PALLETES_START + 4 * palette as u16 + value as u16
+            };
+            let rgb = ppu.apply_post_processing(ppu.memory.read_byte(palette_address));
+            frame.set_background_color(origin_x + 8 * tile_x + col, origin_y + 8 * tile_y + row, rgb);
+        }
+    }
+}
+
+// The top-left corner of the next frame's scroll, taken from t since v has already been walked
+// across the screen by the time the viewer runs.
+fn get_scroll_origin(ppu: &PPU) -> (usize, usize) {
+    let t = ppu.scroll_ctx.t as usize;
+    let x = Frame::WIDTH * ((t >> 10) & 1) + 8 * (t & 0b1_1111) + ppu.scroll_ctx.get_fine_scroll_x() as usize;
+    let y = Frame::HEIGHT * ((t >> 11) & 1) + 8 * ((t >> 5) & 0b1_1111) + ((t >> 12) & 0b111);
+    (x % NAMETABLES_WIDTH, y % NAMETABLES_HEIGHT)
+}
+
+fn render_scroll_overlay(ppu: &PPU, frame: &mut Frame) {
+    let (scroll_x, scroll_y) = get_scroll_origin(ppu);
+    let right = scroll_x + Frame::WIDTH - 1;
+    let bottom = scroll_y + Frame::HEIGHT - 1;
+    for x in scroll_x..=right {
+        frame.set_background_color(x % NAMETABLES_WIDTH, scroll_y, SCROLL_OVERLAY_COLOR);
+        frame.set_background_color(x % NAMETABLES_WIDTH, bottom % NAMETABLES_HEIGHT, SCROLL_OVERLAY_COLOR);
+    }
+    for y in scroll_y..=bottom {
+        frame.set_background_color(scroll_x, y % NAMETABLES_HEIGHT, SCROLL_OVERLAY_COLOR);
+        frame.set_background_color(right % NAMETABLES_WIDTH, y % NAMETABLES_HEIGHT, SCROLL_OVERLAY_COLOR);
+    }
+}
+
+pub fn render_palettes(ppu: &PPU, frame: &mut Frame) {
+    for entry in 0..32 {
+        let rgb = ppu.apply_post_processing(ppu.memory.read_byte(PPUMemory::PALLETES_START + entry as u16));
+        let origin_x = PALETTE_SWATCH_SIZE * (entry % 16);
+        let origin_y = PALETTE_SWATCH_SIZE * (entry / 16);
+        for y in origin_y..origin_y + PALETTE_SWATCH_SIZE {
+            for x in origin_x..origin_x + PALETTE_SWATCH_SIZE {
+                frame.set_background_color(x, y, rgb);
+            }
+        }
+    }
+}
+
+pub fn render_pattern_tables(ppu: &PPU, frame: &mut Frame, palette_group: u8) {
+    let palette_start = PPUMemory::PALLETES_START + 4 * (palette_group % PALETTE_GROUPS) as u16;
+    for table in 0..2 {
+        for tile in 0..256 {
+            let pattern_address = 0x1000 * table as u16 + 16 * tile as u16;
+            let origin_x = PATTERN_TABLE_SIZE * table + 8 * (tile % 16);
+            let origin_y = 8 * (tile / 16);
+            for row in 0..8 {
+                let lower = ppu.memory.read_byte(pattern_address + row as u16);
+                let upper = ppu.memory.read_byte(pattern_address + row as u16 + 8);
+                for col in 0..8 {
+                    let shift = 7 - col;
+                    let value = ((upper >> shift) & 1) << 1 | ((lower >> shift) & 1);
+                    let palette_address = if value == 0 { PPUMemory::PALLETES_START } else { palette_start + value as u16 };
+                    let rgb = ppu.apply_post_processing(ppu.memory.read_byte(palette_address));
+                    frame.set_background_color(origin_x + col, origin_y + row, rgb);
+                }
+            }
+        }
+    }
+}
+
+pub fn render_ppu_viewer(ppu: &PPU, frame: &mut Frame, palette_group: u8) {
+    let mut nametables = Frame::with_size(NAMETABLES_WIDTH, NAMETABLES_HEIGHT);
+    let mut pattern_tables = Frame::with_size(PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT);
+    let mut palettes = Frame::with_size(PALETTES_WIDTH, PALETTES_HEIGHT);
+    render_nametables(ppu, &mut nametables);
+    render_pattern_tables(ppu, &mut pattern_tables, palette_group);
+    render_palettes(ppu, &mut palettes);
+
+    blit(frame, &nametables, 0, 0);
+    blit(frame, &pattern_tables, NAMETABLES_WIDTH, 0);
+    blit(frame, &palettes, NAMETABLES_WIDTH, PATTERN_TABLES_HEIGHT);
+}
+
+fn blit(frame: &mut Frame, source: &Frame, origin_x: usize, origin_y: usize) {
+    for y in 0..source.height {
+        for x in 0..source.width {
+            frame.set_background_color(origin_x + x, origin_y + y, source.get_background_color(x, y));
+        }
+    }
+}
+
+pub fn render_sprites(ppu: &PPU, frame: &mut Frame) {
+    let backdrop = ppu.apply_post_processing(ppu.memory.read_byte(PPUMemory::PALLETES_START));
+    for y in 0..SPRITES_HEIGHT {
+        for x in 0..SPRITES_WIDTH {
+            frame.set_background_color(x, y, backdrop);
+        }
+    }
+
+    let sprite_height = ppu.ctrl.get_sprite_height();
+    for sprite in ppu.get_sprite_infos() {
+        let origin_x = SPRITE_CELL_WIDTH * (sprite.index as usize % 8) + SPRITE_CELL_MARGIN;
+        let origin_y = SPRITE_CELL_HEIGHT * (sprite.index as usize / 8) + SPRITE_CELL_MARGIN;
+        for row in 0..sprite_height {
+            let sprite_row = if sprite.flip_vertical { sprite_height - 1 - row } else { row };
+            let (pattern_address, chr_y) = ppu.sprite_pattern_address(sprite.tile, sprite_row as u16);
+            let lower = ppu.memory.read_byte(pattern_address + chr_y);
+            let upper = ppu.memory.read_byte(pattern_address + chr_y + 8);
+            for col in 0..8 {
+                let shift = if sprite.flip_horizontal { col } else { 7 - col };
+                let value = ((upper >> shift) & 1) << 1 | ((lower >> shift) & 1);
+                if value == 0 { continue }
+
+                let palette_address = PPUMemory::PALLETES_START + 0x10 + 4 * sprite.palette as u16 + value as u16;
+                let rgb = ppu.apply_post_processing(ppu.memory.read_byte(palette_address));
+                frame.set_background_color(origin_x + col, origin_y + row, rgb);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::rom::{Mirroring, ROM};
+    use crate::nes::rom::mappers::create_mapper;
+
+    fn viewer_ppu() -> (PPU, Frame) {
+        let mut ppu = PPU::new();
+        ppu.memory.rom.is_chr_ram = true;
+        ppu.memory.rom.chr_rom = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        for row in 0..8 {
+            ppu.memory.write_byte(0x0010 + row, 0xFF);
+        }
+        ppu.memory.write_byte(0x3F00, 0x0F);
+        ppu.memory.write_byte(0x3F05, 0x30);
+        (ppu, Frame::with_size(NAMETABLES_WIDTH, NAMETABLES_HEIGHT))
+    }
+
+    #[test]
+    fn test_render_nametables_mirroring() {
+        let (mut ppu, mut frame) = viewer_ppu();
+        ppu.memory.rom.set_mirroring(Mirroring::Horizontal);
+        ppu.memory.write_byte(0x2000 + 32 * 2 + 2, 0x01); // tile (2, 2)
+        ppu.memory.write_byte(0x23C0, 0b0101_0101); // palette 1 for every quadrant
+        ppu.scroll_ctx.t = 0x0400 | 16; // keep the overlay away from the tile
+        render_nametables(&ppu, &mut frame);
+
+        let white = ppu.apply_post_processing(0x30);
+        let black = ppu.apply_post_processing(0x0F);
+        assert_eq!(frame.get_background_color(16, 16), white);
+        assert_eq!(frame.get_background_color(23, 23), white);
+        assert_eq!(frame.get_background_color(24, 16), black);
+        // $2400 mirrors $2000 horizontally, $2800 is the second physical table
+        assert_eq!(frame.get_background_color(Frame::WIDTH + 16, 16), white);
+        assert_eq!(frame.get_background_color(16, Frame::HEIGHT + 16), black);
+    }
+
+    #[test]
+    fn test_render_nametable() {
+        let (mut ppu, _) = viewer_ppu();
+        ppu.memory.rom.set_mirroring(Mirroring::Vertical);
+        ppu.memory.write_byte(0x2400 + 32 * 29 + 31, 0x01); // last tile of $2400
+        ppu.memory.write_byte(0x27FF, 0b0000_0100); // palette 1 for the top right quadrant of the last block
+
+        let white = ppu.apply_post_processing(0x30);
+        let black = ppu.apply_post_processing(0x0F);
+        let frame = ppu.render_nametable(1);
+        assert_eq!((frame.width, frame.height), (Frame::WIDTH, Frame::HEIGHT));
+        assert_eq!(frame.get_background_color(248, 232), white);
+        assert_eq!(frame.get_background_color(255, 239), white);
+        assert_eq!(frame.get_background_color(247, 232), black);
+        // $2C00 mirrors $2400 vertically, $2000 is the other physical table
+        assert_eq!(ppu.render_nametable(3).get_background_color(255, 239), white);
+        assert_eq!(ppu.render_nametable(0).get_background_color(255, 239), black);
+    }
+
+    #[test]
+    fn test_render_ppu_viewer() {
+        let (mut ppu, _) = viewer_ppu();
+        ppu.memory.write_byte(0x3F1F, 0x16);
+        ppu.scroll_ctx.t = 0x0400 | 16;
+        let mut frame = Frame::with_size(PPU_VIEWER_WIDTH, PPU_VIEWER_HEIGHT);
+        render_ppu_viewer(&ppu, &mut frame, 1);
+
+        let white = ppu.apply_post_processing(0x30);
+        let black = ppu.apply_post_processing(0x0F);
+        assert_eq!(frame.get_background_color(200, 100), black);
+        // tile $01 of the first pattern table, colored with palette 1
+        assert_eq!(frame.get_background_color(NAMETABLES_WIDTH + 8, 0), white);
+        assert_eq!(frame.get_background_color(NAMETABLES_WIDTH + 7, 0), black);
+        // the last sprite palette entry
+        assert_eq!(frame.get_background_color(PPU_VIEWER_WIDTH - 1, PATTERN_TABLES_HEIGHT + PALETTES_HEIGHT - 1), ppu.apply_post_processing(0x16));
+    }
+
+    #[test]
+    fn test_scroll_overlay_wraps() {
+        let (mut ppu, mut frame) = viewer_ppu();
+        // nametable $2C00, coarse X 31, coarse Y 29, fine X 7, fine Y 7
+        ppu.scroll_ctx.t = 0x7000 | 0x0C00 | (29 << 5) | 31;
+        ppu.scroll_ctx.x = 7;
+        assert_eq!(get_scroll_origin(&ppu), (511, 479));
+
+        render_nametables(&ppu, &mut frame);
+        assert_eq!(frame.get_background_color(511, 479), SCROLL_OVERLAY_COLOR);
+        assert_eq!(frame.get_background_color(0, 479), SCROLL_OVERLAY_COLOR);
+        assert_eq!(frame.get_background_color(254, 479), SCROLL_OVERLAY_COLOR);
+        assert_eq!(frame.get_background_color(511, 238), SCROLL_OVERLAY_COLOR);
+        assert_eq!(frame.get_background_color(254, 238), SCROLL_OVERLAY_COLOR);
+        assert_ne!(frame.get_background_color(100, 100), SCROLL_OVERLAY_COLOR);
+    }
+
+    #[test]
+    fn test_render_palettes() {
+        let (mut ppu, _) = viewer_ppu();
+        let mut frame = Frame::with_size(PALETTES_WIDTH, PALETTES_HEIGHT);
+        ppu.memory.write_byte(0x3F07, 0x16);
+        ppu.memory.write_byte(0x3F1F, 0x2A);
+        render_palettes(&ppu, &mut frame);
+
+        assert_eq!(frame.get_background_color(0, 0), ppu.apply_post_processing(0x0F));
+        assert_eq!(frame.get_background_color(7 * 16 + 15, 15), ppu.apply_post_processing(0x16));
+        assert_eq!(frame.get_background_color(255, 31), ppu.apply_post_processing(0x2A));
+        // $3F10 shares its storage with the backdrop
+        assert_eq!(frame.get_background_color(0, 16), ppu.apply_post_processing(0x0F));
+        assert_eq!(frame.get_background_color(5 * 16, 0), ppu.apply_post_processing(0x30));
+        assert_eq!(frame.get_background_color(5 * 16, 16), ppu.apply_post_processing(0x00));
+    }
+
+    #[test]
+    fn test_render_sprites() {
+        let (mut ppu, _) = viewer_ppu();
+        let mut frame = Frame::with_size(SPRITES_WIDTH, SPRITES_HEIGHT);
+        ppu.memory.write_byte(0x0020, 0b1000_0000); // tile 2: a single pixel in the top-left corner
+        ppu.memory.write_byte(0x3F11, 0x16);
+        ppu.memory.write_byte(0x3F1D, 0x2A);
+        ppu.oam.memory[0..4].copy_from_slice(&[0x10, 0x01, 0b0000_0000, 0x20]);
+        ppu.oam.memory[4 * 9..4 * 10].copy_from_slice(&[0x10, 0x02, 0b1100_0011, 0x20]);
+        render_sprites(&ppu, &mut frame);
+
+        let backdrop = ppu.apply_post_processing(0x0F);
+        assert_eq!(frame.get_background_color(0, 0), backdrop);
+        assert_eq!(frame.get_background_color(4, 4), ppu.apply_post_processing(0x16));
+        assert_eq!(frame.get_background_color(11, 11), ppu.apply_post_processing(0x16));
+        assert_eq!(frame.get_background_color(12, 12), backdrop);
+        // sprite 9 sits in the second row, second column, flipped both ways
+        assert_eq!(frame.get_background_color(16 + 4, 24 + 4), backdrop);
+        assert_eq!(frame.get_background_color(16 + 11, 24 + 11), ppu.apply_post_processing(0x2A));
+    }
+
+    #[test]
+    fn test_render_pattern_tables() {
+        let (mut ppu, _) = viewer_ppu();
+        let mut frame = Frame::with_size(PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT);
+        // last tile, bottom-right pixel, both planes
+        ppu.memory.write_byte(0x1FF7, 0b0000_0001);
+        ppu.memory.write_byte(0x1FFF, 0b0000_0001);
+        ppu.memory.write_byte(0x3F07, 0x16);
+        ppu.memory.write_byte(0x3F1B, 0x2A);
+
+        render_pattern_tables(&ppu, &mut frame, 1);
+        assert_eq!(frame.get_background_color(0, 0), ppu.apply_post_processing(0x0F));
+        assert_eq!(frame.get_background_color(8, 0), ppu.apply_post_processing(0x30));
+        assert_eq!(frame.get_background_color(255, 127), ppu.apply_post_processing(0x16));
+
+        render_pattern_tables(&ppu, &mut frame, 6);
+        assert_eq!(frame.get_background_color(255, 127), ppu.apply_post_processing(0x2A));
+    }
+
+    #[test]
+    fn test_render_pattern_tables_bank_switch() {
+        let (mut ppu, _) = viewer_ppu();
+        let mut frame = Frame::with_size(PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT);
+        ppu.memory.rom.is_chr_ram = false;
+        ppu.memory.rom.mapper_id = 3;
+        ppu.memory.rom.mapper = create_mapper(&ppu.memory.rom).unwrap();
+        ppu.memory.rom.chr_rom = vec![0x00; 2 * ROM::CHR_ROM_PAGE_SIZE];
+        ppu.memory.rom.chr_rom[ROM::CHR_ROM_PAGE_SIZE..ROM::CHR_ROM_PAGE_SIZE + 8].fill(0xFF);
+
+        render_pattern_tables(&ppu, &mut frame, 0);
+        assert_eq!(frame.get_background_color(0, 0), ppu.apply_post_processing(0x0F));
+
+        // CNROM: switch in the second 8kB CHR bank, from where the PRG ROM doesn't conflict
+        ppu.memory.rom.prg_rom = vec![0xFF; ROM::PRG_ROM_PAGE_SIZE];
+        ppu.memory.rom.write_prg_byte(0x8000, 0x01);
+        render_pattern_tables(&ppu, &mut frame, 0);
+        assert_eq!(frame.get_background_color(0, 0), ppu.apply_post_processing(ppu.memory.read_byte(0x3F01)));
+    }
+}
@@ -1,21 +1,28 @@
 pub mod registers;
 pub mod mappers;
+pub mod database;
+pub mod unif;
+pub mod ips;
+pub mod bps;
 
-use std::fs;
-use std::fs::File;
+use std::{fmt, fs, io};
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use crate::nes::cpu::mem::Memory;
+use crate::nes::rom::database::{find_override, GameOverride};
 use crate::nes::rom::mappers::mapper::Mapper;
 use crate::nes::rom::mappers::mapper0::Mapper0;
-use crate::nes::rom::mappers::mapper1::Mapper1;
-use crate::nes::rom::mappers::mapper2::Mapper2;
-use crate::nes::rom::mappers::mapper3::Mapper3;
-use crate::nes::rom::mappers::mapper4::Mapper4;
-use crate::nes::rom::mappers::mapper66::Mapper66;
+use crate::nes::rom::mappers::mapper20::{Mapper20, DISK_SIDE_SIZE, FDS_BIOS_SIZE};
+use crate::nes::rom::mappers::{create_mapper, is_supported, mapper_name, UnsupportedMapper};
+use crate::nes::rom::unif::{board_mapper, is_unif, read_chunks};
+use crate::nes::rom::ips::{apply_ips, is_ips};
+use crate::nes::rom::bps::{apply_bps, is_bps};
+use crate::util::checksum::crc32_update;
+#[cfg(feature = "zip")]
+use zip::ZipArchive;
 
-#[derive(Serialize, Deserialize,Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize,Debug, PartialEq, Clone, Copy)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
@@ -24,166 +31,874 @@ pub enum Mirroring {
     FourScreen,
 }
 
+// Ref: https://www.nesdev.org/wiki/NES_2.0
+
+// NES 2.0 header extensions:
+// =====================================
+// Byte 7:  ....10..  <- NES 2.0 identifier
+// Byte 8:  SSSSMMMM  <- submapper, mapper number bits 8-11
+// Byte 9:  CCCCPPPP  <- CHR ROM, PRG ROM size MSB (0xF selects the exponent-multiplier encoding)
+// Byte 10: NNNNRRRR  <- PRG NVRAM, PRG RAM shift count (64 << n bytes, 0 means none)
+// Byte 11: NNNNRRRR  <- CHR NVRAM, CHR RAM shift count
+// Byte 12: ......TT  <- CPU/PPU timing (0: NTSC, 1: PAL, 2: multi-region, 3: Dendy)
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RomFormat {
+    InesV1,
+    InesV2,
+    Unif,
+    Fds,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    InvalidMagic([u8; 4]),
+    UnsupportedVersion(u8),
+    UnsupportedMapper(u16),
+    TruncatedHeader,
+    PrgRomTooShort { expected: usize, got: usize },
+    ChrRomTooShort { expected: usize, got: usize },
+    UnevenRomSize { chip: &'static str, size: usize, page_size: usize },
+    NoRomInArchive,
+    UnknownBoard(String),
+    TruncatedChunk(String),
+    InvalidBios(usize),
+    TruncatedDisk(usize),
+    InvalidPatch(String),
+    PatchChecksumMismatch { expected: u32, got: u32 },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(err) => write!(f, "Unable to read ROM file: {}", err),
+            RomError::InvalidMagic(magic) => write!(f, "File is not in iNES file format (magic: {:02X?})", magic),
+            RomError::TruncatedHeader => write!(f, "File is too short to hold an iNES header"),
+            RomError::UnsupportedVersion(version) => write!(f, "Unrecognized iNES header version: {}", version),
+            RomError::UnsupportedMapper(mapper_id) => write!(f, "Unsupported mapper: {}", mapper_id),
+            RomError::PrgRomTooShort { expected, got } =>
+                write!(f, "Header claims 0x{:x} bytes of PRG ROM, but the file only has 0x{:x}", expected, got),
+            RomError::ChrRomTooShort { expected, got } =>
+                write!(f, "Header claims 0x{:x} bytes of CHR ROM, but the file only has 0x{:x}", expected, got),
+            RomError::UnevenRomSize { chip, size, page_size } =>
+                write!(f, "{} ROM is 0x{:x} bytes, which isn't a whole number of 0x{:x} byte pages", chip, size, page_size),
+            RomError::NoRomInArchive => write!(f, "Archive doesn't contain a .nes file"),
+            RomError::UnknownBoard(board) => write!(f, "Unsupported UNIF board: {}", board),
+            RomError::TruncatedChunk(id) => write!(f, "UNIF chunk {} runs past the end of the file", id),
+            RomError::InvalidBios(len) => write!(f, "FDS BIOS should be 0x2000 bytes, got 0x{:x}", len),
+            RomError::TruncatedDisk(len) => write!(f, "FDS disk sides are 65500 bytes, the image only has {}", len),
+            RomError::InvalidPatch(reason) => write!(f, "Invalid patch: {}", reason),
+            RomError::PatchChecksumMismatch { expected, got } =>
+                write!(f, "Patch checksum mismatch (expected {:08X}, got {:08X}), is it for a different dump?", expected, got),
+        }
+    }
+}
+
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> Self {
+        RomError::Io(err)
+    }
+}
+
+impl From<UnsupportedMapper> for RomError {
+    fn from(UnsupportedMapper(mapper_id): UnsupportedMapper) -> Self {
+        RomError::UnsupportedMapper(mapper_id)
+    }
+}
+
+// What a ROM picker needs to show, read without loading the game, see ROM::probe. The database
+// overrides are applied, so it agrees with what loading the ROM would do.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RomInfo {
+    pub format: RomFormat,
+    pub mapper_id: u16,
+    pub submapper: u8,
+    pub mapper_name: &'static str,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    pub timing: Timing,
+    pub crc32: u32,
+    pub is_supported: bool,
+}
+
+impl RomInfo {
+    // Decodes a (cleaned) iNES or NES 2.0 header. The CRC is left for whoever has the ROM data.
+    fn from_header(header: &[u8; ROM::HEADER_SIZE]) -> Result<RomInfo, RomError> {
+        let format = match (header[7] >> 2) & 0b0011 {
+            0 => RomFormat::InesV1,
+            2 => RomFormat::InesV2,
+            version => return Err(RomError::UnsupportedVersion(version)),
+        };
+        let is_nes2 = format == RomFormat::InesV2;
+
+        let (prg_rom_size, chr_rom_size) = if is_nes2 {
+            (ROM::nes2_rom_size(header[4], header[9] & 0x0F, ROM::PRG_ROM_PAGE_SIZE),
+             ROM::nes2_rom_size(header[5], header[9] >> 4, ROM::CHR_ROM_PAGE_SIZE))
+        } else {
+            (header[4] as usize * ROM::PRG_ROM_PAGE_SIZE, header[5] as usize * ROM::CHR_ROM_PAGE_SIZE)
+        };
+
+        let four_screen = header[6] & 0b1000 != 0;
+        let vertical_mirroring = header[6] & 0b0001 != 0;
+        let mut mapper_id = ((header[7] & 0b1111_0000) | (header[6] >> 4)) as u16;
+        if is_nes2 {
+            mapper_id |= ((header[8] & 0x0F) as u16) << 8;
+        }
+        Ok(RomInfo {
+            format,
+            mapper_id,
+            submapper: if is_nes2 { header[8] >> 4 } else { 0 },
+            mapper_name: mapper_name(mapper_id),
+            prg_rom_size,
+            chr_rom_size,
+            mirroring: match (four_screen, vertical_mirroring) {
+                (true, _) => Mirroring::FourScreen,
+                (false, true) => Mirroring::Vertical,
+                (false, false) => Mirroring::Horizontal,
+            },
+            has_battery: header[6] & 0b0010 != 0,
+            has_trainer: header[6] & 0b0100 != 0,
+            timing: if !is_nes2 {
+                Timing::Ntsc
+            } else {
+                match header[12] & 0b11 {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                }
+            },
+            crc32: 0,
+            is_supported: is_supported(mapper_id),
+        })
+    }
+
+    fn apply_override(&mut self, game_override: &GameOverride) {
+        if let Some(mapper_id) = game_override.mapper_id {
+            self.mapper_id = mapper_id;
+            self.mapper_name = mapper_name(mapper_id);
+            self.is_supported = is_supported(mapper_id);
+        }
+        if let Some(submapper) = game_override.submapper {
+            self.submapper = submapper;
+        }
+        if let Some(mirroring) = game_override.mirroring {
+            self.mirroring = mirroring;
+        }
+        if let Some(battery) = game_override.battery {
+            self.has_battery = battery;
+        }
+        if let Some(timing) = game_override.timing {
+            self.timing = timing;
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ROM {
     pub game_title: String,
+    pub save_path: Option<PathBuf>,
+    pub header: [u8; ROM::HEADER_SIZE],
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
-    pub mapper_id: u8,
+    pub format: RomFormat,
+    pub mapper_id: u16,
+    pub submapper: u8,
     pub is_prg_rom_mirror: bool,
     pub is_chr_ram: bool,
     pub has_save_ram: bool,
+    pub trainer: Option<Vec<u8>>,
+    pub is_vs_unisystem: bool,
+    pub is_playchoice: bool,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub timing: Timing,
     pub screen_mirroring: Mirroring,
 
-    pub mapper0: Mapper0,
-    pub mapper1: Mapper1,
-    pub mapper2: Mapper2,
-    pub mapper3: Mapper3,
-    pub mapper4: Mapper4,
-    pub mapper66: Mapper66,
+    pub mapper: Box<dyn Mapper>,
 }
 
 impl ROM {
     const NES_SIGNATURE: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
+    const FDS_SIGNATURE: [u8; 4] = [0x46, 0x44, 0x53, 0x1a];
+    pub const HEADER_SIZE: usize = 16;
     pub const CHR_ROM_PAGE_SIZE: usize = 0x2000; // 8kB
     pub const PRG_ROM_PAGE_SIZE: usize = 0x4000; // 16kB
+    pub const TRAINER_SIZE: usize = 0x200; // 512B
+    pub const TRAINER_START: u16 = 0x7000;
+    pub const PRG_RAM_PAGE_SIZE: usize = 0x2000; // 8kB
 
     pub fn new() -> Self {
         ROM {
             game_title: String::new(),
+            save_path: None,
+            header: [0; ROM::HEADER_SIZE],
             prg_rom: Vec::new(),
             chr_rom: Vec::new(),
+            format: RomFormat::InesV1,
             mapper_id: 0,
+            submapper: 0,
             is_prg_rom_mirror: false,
             is_chr_ram: false,
             has_save_ram: false,
+            trainer: None,
+            is_vs_unisystem: false,
+            is_playchoice: false,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            timing: Timing::Ntsc,
             screen_mirroring: Mirroring::Horizontal,
 
-            mapper0: Mapper0::new(),
-            mapper1: Mapper1::new(),
-            mapper2: Mapper2::new(),
-            mapper3: Mapper3::new(),
-            mapper4: Mapper4::new(),
-            mapper66: Mapper66::new(),
+            mapper: Box::new(Mapper0::new()),
         }
     }
 
-    pub fn from_path(path: &Path) -> Result<ROM, String> {
-        let mut file = File::open(path).expect("no file found");
-        let metadata = fs::metadata(path).expect("unable to read metadata");
-        let mut buffer = vec![0; metadata.len() as usize];
-        file.read(&mut buffer).expect("buffer overflow");
-        let mut rom_result = ROM::from_buffer(&buffer);
+    pub fn from_path(path: &Path) -> Result<ROM, RomError> {
+        #[cfg(feature = "zip")]
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip")) {
+            return ROM::from_zip(path);
+        }
+        let mut rom = ROM::from_reader(fs::File::open(path)?)?;
 
         let game_title = path.file_stem().expect("unable to parse file stem");
-        rom_result.as_mut().unwrap().game_title = game_title.to_str().unwrap().to_string();
+        rom.game_title = game_title.to_string_lossy().to_string();
+        rom.save_path = Some(ROM::default_save_path(path));
 
-        return rom_result;
+        return Ok(rom);
     }
 
-    pub fn from_buffer(raw: &Vec<u8>) -> Result<ROM, String> {
-        if &raw[0..4] != ROM::NES_SIGNATURE {
-            return Err("File is not in iNES file format".to_string());
+    // ROMs are commonly distributed zipped. The first .nes entry in the archive is loaded, and the
+    // game is named after the archive, ex: Zelda.zip -> Zelda.sav
+    #[cfg(feature = "zip")]
+    pub fn from_zip(path: &Path) -> Result<ROM, RomError> {
+        let mut rom = ROM::from_bytes(&ROM::unzip(path)?)?;
+
+        let game_title = path.file_stem().expect("unable to parse file stem");
+        rom.game_title = game_title.to_string_lossy().to_string();
+        rom.save_path = Some(ROM::default_save_path(path));
+
+        Ok(rom)
+    }
+
+    // The image as it would be loaded by from_path, unzipped if need be.
+    fn read_image(path: &Path) -> Result<Vec<u8>, RomError> {
+        #[cfg(feature = "zip")]
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip")) {
+            return ROM::unzip(path);
         }
+        Ok(fs::read(path)?)
+    }
+
+    #[cfg(feature = "zip")]
+    fn unzip(path: &Path) -> Result<Vec<u8>, RomError> {
+        let mut archive = ZipArchive::new(fs::File::open(path)?).map_err(io::Error::other)?;
+        let name = archive.file_names()
+            .find(|name| name.to_ascii_lowercase().ends_with(".nes"))
+            .map(String::from)
+            .ok_or(RomError::NoRomInArchive)?;
+        let mut image = Vec::new();
+        archive.by_name(&name).map_err(io::Error::other)?.read_to_end(&mut image)?;
+        Ok(image)
+    }
 
-        let ines_ver = (raw[7] >> 2) & 0b0011;
-        if ines_ver != 0 {
-            return Err("NES2.0 format is not supported".to_string());
+    // Ref: https://www.nesdev.org/wiki/IPS
+    // Soft-patching: the patch is applied to the image in memory, so the ROM on disk stays untouched.
+    // The game is named after the patch, so a ROM hack doesn't share its saves with the original.
+    pub fn from_path_with_patch(path: &Path, patch_path: &Path) -> Result<ROM, RomError> {
+        let raw = ROM::read_image(path)?;
+        let mut rom = ROM::from_image(&ROM::apply_patch(&raw, &fs::read(patch_path)?)?)?;
+
+        let game_title = patch_path.file_stem().expect("unable to parse file stem");
+        rom.game_title = game_title.to_string_lossy().to_string();
+        // the patched game gets its own save (ie. Zelda.ips.sav), apart from the original's Zelda.sav
+        let mut save_path = patch_path.as_os_str().to_owned();
+        save_path.push(".sav");
+        rom.save_path = Some(PathBuf::from(save_path));
+
+        Ok(rom)
+    }
+
+    // Applies an IPS or BPS patch, told apart by their magic bytes.
+    pub fn apply_patch(raw: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomError> {
+        if is_ips(patch) {
+            apply_ips(raw, patch)
+        } else if is_bps(patch) {
+            apply_bps(raw, patch)
+        } else {
+            Err(RomError::InvalidPatch(String::from("not an IPS or BPS patch")))
         }
+    }
 
-        let four_screen = raw[6] & 0b1000 != 0;
-        let vertical_mirroring = raw[6] & 0b0001 != 0;
+    // A patch with the same name as the ROM, ex: Zelda.nes -> Zelda.ips or Zelda.bps
+    pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+        ["ips", "bps"].iter()
+            .map(|extension| rom_path.with_extension(extension))
+            .find(|patch_path| patch_path.is_file())
+    }
 
-        let prg_rom_size = raw[4] as usize * ROM::PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * ROM::CHR_ROM_PAGE_SIZE;
+    // Reads the whole image from any source, ex: stdin or a network stream.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<ROM, RomError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        ROM::from_image(&data)
+    }
 
-        let has_trainer = raw[6] & 0b0100 != 0;
-        let has_save_ram = raw[6] & 0b0010 != 0;
-        let prg_rom_start = 16 + if has_trainer { 512 } else { 0 };
-        let chr_rom_start = prg_rom_start + prg_rom_size;
+    // Picks the parser from the magic bytes. Headerless images have none, see from_raw_prg.
+    pub fn from_image(raw: &[u8]) -> Result<ROM, RomError> {
+        if is_unif(raw) { ROM::from_unif(raw) } else { ROM::from_bytes(raw) }
+    }
+
+    // battery-backed PRG RAM is saved next to the ROM, ex: Zelda.nes -> Zelda.sav
+    pub fn default_save_path(rom_path: &Path) -> PathBuf {
+        rom_path.with_extension("sav")
+    }
+
+    // Where battery-backed PRG RAM was kept before it moved next to the ROM, ie. Saves/<title>/battery.sav
+    // with `saves_dir` as the Saves directory.
+    pub fn legacy_save_path(&self, saves_dir: &Path) -> PathBuf {
+        saves_dir.join(&self.game_title).join("battery.sav")
+    }
+
+    // Parses an iNES or NES 2.0 image that's already in memory, ex: from include_bytes!
+    pub fn from_bytes(raw: &[u8]) -> Result<ROM, RomError> {
+        let (header, info) = ROM::read_header(raw)?;
+        let (prg_rom, chr_rom) = ROM::split_rom(raw, &info)?;
+        let (prg_rom_size, chr_rom_size) = (info.prg_rom_size, info.chr_rom_size);
+        let has_trainer = info.has_trainer;
+        let has_save_ram = info.has_battery;
+        let is_nes2 = info.format == RomFormat::InesV2;
 
         let mut rom = ROM::new();
-        rom.mapper_id = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
+        rom.header = header;
+        rom.format = info.format;
+        rom.mapper_id = info.mapper_id;
+        rom.submapper = info.submapper;
+        rom.timing = info.timing;
         rom.is_prg_rom_mirror = prg_rom_size == ROM::PRG_ROM_PAGE_SIZE;
         rom.is_chr_ram = chr_rom_size == 0;
         rom.has_save_ram = has_save_ram;
-        rom.prg_rom = raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec();
+        rom.trainer = if has_trainer { Some(raw[ROM::HEADER_SIZE..ROM::HEADER_SIZE + ROM::TRAINER_SIZE].to_vec()) } else { None };
+        rom.is_vs_unisystem = header[7] & 0b0001 != 0;
+        rom.is_playchoice = header[7] & 0b0010 != 0;
+        if is_nes2 {
+            rom.prg_ram_size = ROM::nes2_ram_size(header[10] & 0x0F);
+            rom.prg_nvram_size = ROM::nes2_ram_size(header[10] >> 4);
+            rom.chr_ram_size = ROM::nes2_ram_size(header[11] & 0x0F);
+            rom.chr_nvram_size = ROM::nes2_ram_size(header[11] >> 4);
+        } else {
+            // iNES 1.0 can't describe RAM sizes, so assume the usual 8kB of PRG RAM and CHR RAM
+            rom.prg_ram_size = if has_save_ram { 0 } else { ROM::PRG_RAM_PAGE_SIZE };
+            rom.prg_nvram_size = if has_save_ram { ROM::PRG_RAM_PAGE_SIZE } else { 0 };
+            rom.chr_ram_size = if rom.is_chr_ram { ROM::CHR_ROM_PAGE_SIZE } else { 0 };
+        }
+        rom.prg_rom = prg_rom.to_vec();
         rom.chr_rom = if rom.is_chr_ram {
-            vec![0; ROM::CHR_ROM_PAGE_SIZE]
+            vec![0; usize::max(rom.chr_ram_size + rom.chr_nvram_size, ROM::CHR_ROM_PAGE_SIZE)]
         } else {
-            raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec()
-        };
-        rom.screen_mirroring = match (four_screen, vertical_mirroring) {
-            (true, _) => Mirroring::FourScreen,
-            (false, true) => Mirroring::Vertical,
-            (false, false) => Mirroring::Horizontal,
+            chr_rom.to_vec()
         };
+        rom.screen_mirroring = info.mirroring;
+        rom.check_board()?;
 
-        println!("ROM: mapper: {}, trainer: {}, save_ram: {}, screen_mirroring: {:?}, \
-            is_prg_rom_mirroring: {}, is_chr_ram: {}, prg_rom_size: 0x{:x}, chr_rom_size: 0x{:x}",
-            rom.mapper_id, has_trainer, rom.has_save_ram, rom.screen_mirroring,
-            rom.is_prg_rom_mirror, rom.is_chr_ram, prg_rom_size, chr_rom_size);
+        println!("ROM: format: {:?}, mapper: {}.{}, trainer: {}, save_ram: {}, screen_mirroring: {:?}, \
+            is_prg_rom_mirroring: {}, is_chr_ram: {}, prg_rom_size: 0x{:x}, chr_rom_size: 0x{:x}, \
+            vs_unisystem: {}, playchoice: {}, timing: {:?}",
+            rom.format, rom.mapper_id, rom.submapper, has_trainer, rom.has_save_ram, rom.screen_mirroring,
+            rom.is_prg_rom_mirror, rom.is_chr_ram, prg_rom_size, chr_rom_size,
+            rom.is_vs_unisystem, rom.is_playchoice, rom.timing);
 
         return Ok(rom);
     }
 
+    // Ref: https://www.nesdev.org/wiki/UNIF
+    // UNIF images are built from chunks, see unif.rs. Anything UNIF doesn't say is guessed the same
+    // way as for iNES 1.0.
+    pub fn from_unif(raw: &[u8]) -> Result<ROM, RomError> {
+        if !is_unif(raw) {
+            let mut magic = [0; 4];
+            let len = usize::min(raw.len(), 4);
+            magic[..len].copy_from_slice(&raw[..len]);
+            return Err(RomError::InvalidMagic(magic));
+        }
+        let chunks = read_chunks(raw)?;
+        let mut rom = ROM::new();
+        rom.format = RomFormat::Unif;
+        let mut prg_banks = Vec::new();
+        let mut chr_banks = Vec::new();
+        let mut board = None;
+        for chunk in &chunks {
+            if let Some(index) = chunk.bank_index(b"PRG") {
+                prg_banks.push((index, chunk.data));
+            } else if let Some(index) = chunk.bank_index(b"CHR") {
+                chr_banks.push((index, chunk.data));
+            } else {
+                match &chunk.id {
+                    b"MAPR" => board = Some(chunk.as_str()),
+                    b"NAME" => rom.game_title = chunk.as_str(),
+                    b"BATR" => rom.has_save_ram = true,
+                    b"MIRR" => rom.screen_mirroring = match chunk.data.first() {
+                        Some(1) => Mirroring::Vertical,
+                        Some(2) => Mirroring::OneScreenLower,
+                        Some(3) => Mirroring::OneScreenUpper,
+                        Some(4) => Mirroring::FourScreen,
+                        _ => Mirroring::Horizontal,
+                    },
+                    b"TVCI" => rom.timing = match chunk.data.first() {
+                        Some(1) => Timing::Pal,
+                        Some(2) => Timing::MultiRegion,
+                        _ => Timing::Ntsc,
+                    },
+                    _ => {},
+                }
+            }
+        }
+        let board = board.unwrap_or_default();
+        rom.mapper_id = board_mapper(&board).ok_or(RomError::UnknownBoard(board.clone()))?;
+        prg_banks.sort_by_key(|(index, _)| *index);
+        chr_banks.sort_by_key(|(index, _)| *index);
+        rom.prg_rom = prg_banks.iter().flat_map(|(_, data)| data.iter().copied()).collect();
+        rom.chr_rom = chr_banks.iter().flat_map(|(_, data)| data.iter().copied()).collect();
+        if rom.prg_rom.len() < ROM::PRG_ROM_PAGE_SIZE {
+            return Err(RomError::PrgRomTooShort { expected: ROM::PRG_ROM_PAGE_SIZE, got: rom.prg_rom.len() });
+        }
+        rom.check_page_sizes()?;
+        rom.is_prg_rom_mirror = rom.prg_rom.len() == ROM::PRG_ROM_PAGE_SIZE;
+        rom.is_chr_ram = rom.chr_rom.is_empty();
+        if rom.is_chr_ram {
+            rom.chr_ram_size = ROM::CHR_ROM_PAGE_SIZE;
+            rom.chr_rom = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        }
+        rom.prg_ram_size = if rom.has_save_ram { 0 } else { ROM::PRG_RAM_PAGE_SIZE };
+        rom.prg_nvram_size = if rom.has_save_ram { ROM::PRG_RAM_PAGE_SIZE } else { 0 };
+        rom.check_board()?;
+
+        println!("ROM: format: UNIF, board: {}, mapper: {}, save_ram: {}, screen_mirroring: {:?}, is_chr_ram: {}, \
+            prg_rom_size: 0x{:x}, chr_rom_size: 0x{:x}, timing: {:?}",
+            board, rom.mapper_id, rom.has_save_ram, rom.screen_mirroring, rom.is_chr_ram,
+            rom.prg_rom.len(), rom.chr_rom.len(), rom.timing);
+
+        Ok(rom)
+    }
+
+    // Headerless dumps are just the PRG ROM, so the board has to be given by whoever loads them.
+    // They're assumed to use 8kB of CHR RAM and PRG RAM.
+    pub fn from_raw_prg(raw: &[u8], mapper_id: u16, mirroring: Mirroring) -> Result<ROM, RomError> {
+        if raw.len() < ROM::PRG_ROM_PAGE_SIZE {
+            return Err(RomError::PrgRomTooShort { expected: ROM::PRG_ROM_PAGE_SIZE, got: raw.len() });
+        }
+        let mut rom = ROM::new();
+        rom.mapper_id = mapper_id;
+        rom.screen_mirroring = mirroring;
+        rom.prg_rom = raw.to_vec();
+        rom.check_page_sizes()?;
+        rom.is_prg_rom_mirror = raw.len() == ROM::PRG_ROM_PAGE_SIZE;
+        rom.is_chr_ram = true;
+        rom.chr_ram_size = ROM::CHR_ROM_PAGE_SIZE;
+        rom.chr_rom = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        rom.prg_ram_size = ROM::PRG_RAM_PAGE_SIZE;
+        rom.check_board()?;
+        Ok(rom)
+    }
+
+    // Ref: https://www.nesdev.org/wiki/FDS_file_format
+    // .fds images are the disk sides back to back, 65500 bytes each, sometimes behind a 16 byte fwNES
+    // header ("FDS\x1A", side count). Nothing on the disk runs without the Famicom Disk System's BIOS,
+    // which isn't part of the image and has to be dumped separately.
+    pub fn from_fds(raw: &[u8], bios: &[u8]) -> Result<ROM, RomError> {
+        if bios.len() != FDS_BIOS_SIZE {
+            return Err(RomError::InvalidBios(bios.len()));
+        }
+        let disk = if raw.starts_with(&ROM::FDS_SIGNATURE) { &raw[usize::min(ROM::HEADER_SIZE, raw.len())..] } else { raw };
+        if !disk.starts_with(b"\x01*NINTENDO-HVC*") {
+            let mut magic = [0; 4];
+            let len = usize::min(disk.len(), 4);
+            magic[..len].copy_from_slice(&disk[..len]);
+            return Err(RomError::InvalidMagic(magic));
+        }
+        if disk.len() < DISK_SIDE_SIZE {
+            return Err(RomError::TruncatedDisk(disk.len()));
+        }
+
+        let mut rom = ROM::new();
+        rom.format = RomFormat::Fds;
+        rom.mapper_id = 20;
+        rom.prg_rom = bios.to_vec();
+        rom.is_chr_ram = true;
+        rom.chr_ram_size = ROM::CHR_ROM_PAGE_SIZE;
+        rom.chr_rom = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        rom.prg_ram_size = 0x8000;
+        let sides: Vec<&[u8]> = disk.chunks_exact(DISK_SIDE_SIZE).collect();
+        let mut mapper20 = Mapper20::new();
+        mapper20.load_disk(&sides);
+        rom.mapper = Box::new(mapper20);
+
+        println!("ROM: format: FDS, sides: {} (FDS audio isn't emulated)", sides.len());
+        Ok(rom)
+    }
+
+    pub fn from_fds_path(path: &Path, bios_path: &Path) -> Result<ROM, RomError> {
+        let mut rom = ROM::from_fds(&fs::read(path)?, &fs::read(bios_path)?)?;
+        let game_title = path.file_stem().expect("unable to parse file stem");
+        rom.game_title = game_title.to_string_lossy().to_string();
+        Ok(rom)
+    }
+
+    // Reads what a ROM picker needs to know about an image without loading the game, see RomInfo.
+    // ROMs are small enough that checksumming the whole file is cheap.
+    pub fn probe(path: &Path) -> Result<RomInfo, RomError> {
+        ROM::probe_bytes(&ROM::read_image(path)?)
+    }
+
+    pub fn probe_bytes(raw: &[u8]) -> Result<RomInfo, RomError> {
+        if is_unif(raw) {
+            // boards alpiNES has no mapper for aren't named by a mapper number, so UNIF is only
+            // probed by loading it
+            return ROM::from_unif(raw).map(|rom| rom.get_info());
+        }
+        let (_, mut info) = ROM::read_header(raw)?;
+        let (prg_rom, chr_rom) = ROM::split_rom(raw, &info)?;
+        info.crc32 = crc32_update(crc32_update(0, prg_rom), chr_rom);
+        if let Some(game_override) = find_override(info.crc32) {
+            info.apply_override(game_override);
+        }
+        Ok(info)
+    }
+
+    // Describes a loaded ROM the same way ROM::probe describes a file.
+    pub fn get_info(&self) -> RomInfo {
+        RomInfo {
+            format: self.format,
+            mapper_id: self.mapper_id,
+            submapper: self.submapper,
+            mapper_name: mapper_name(self.mapper_id),
+            prg_rom_size: self.prg_rom.len(),
+            chr_rom_size: if self.is_chr_ram { 0 } else { self.chr_rom.len() },
+            mirroring: self.screen_mirroring,
+            has_battery: self.has_save_ram,
+            has_trainer: self.trainer.is_some(),
+            timing: self.timing,
+            crc32: self.crc32(),
+            is_supported: is_supported(self.mapper_id),
+        }
+    }
+
+    fn read_header(raw: &[u8]) -> Result<([u8; ROM::HEADER_SIZE], RomInfo), RomError> {
+        if raw.len() < 4 || raw[0..4] != ROM::NES_SIGNATURE {
+            let mut magic = [0; 4];
+            let len = usize::min(raw.len(), 4);
+            magic[..len].copy_from_slice(&raw[..len]);
+            return Err(RomError::InvalidMagic(magic));
+        }
+        if raw.len() < ROM::HEADER_SIZE {
+            return Err(RomError::TruncatedHeader);
+        }
+        let mut header = [0; ROM::HEADER_SIZE];
+        header.copy_from_slice(&raw[..ROM::HEADER_SIZE]);
+        ROM::clean_header(&mut header);
+        Ok((header, RomInfo::from_header(&header)?))
+    }
+
+    // Finds the PRG ROM and CHR ROM the header describes, past the trainer if there is one.
+    fn split_rom<'a>(raw: &'a [u8], info: &RomInfo) -> Result<(&'a [u8], &'a [u8]), RomError> {
+        let prg_rom_start = ROM::HEADER_SIZE + if info.has_trainer { ROM::TRAINER_SIZE } else { 0 };
+        let chr_rom_start = prg_rom_start.saturating_add(info.prg_rom_size);
+        if chr_rom_start > raw.len() {
+            let got = raw.len().saturating_sub(prg_rom_start);
+            return Err(RomError::PrgRomTooShort { expected: info.prg_rom_size, got });
+        }
+        let chr_rom_end = chr_rom_start.saturating_add(info.chr_rom_size);
+        if chr_rom_end > raw.len() {
+            let got = raw.len() - chr_rom_start;
+            return Err(RomError::ChrRomTooShort { expected: info.chr_rom_size, got });
+        }
+        Ok((&raw[prg_rom_start..chr_rom_start], &raw[chr_rom_start..chr_rom_end]))
+    }
+
+    // Last step of every loader: the database gets the final word, then the mappers are set up.
+    fn check_board(&mut self) -> Result<(), RomError> {
+        if let Some(game_override) = find_override(self.crc32()) {
+            self.apply_override(game_override);
+        }
+        self.mapper = create_mapper(self)?;
+        Ok(())
+    }
+
+    // UNIF chunks and raw dumps can be any size, but the mappers bank whole 16kB PRG and 8kB CHR
+    // pages, so anything in between would leave part of a bank unmapped.
+    fn check_page_sizes(&self) -> Result<(), RomError> {
+        if !self.prg_rom.len().is_multiple_of(ROM::PRG_ROM_PAGE_SIZE) {
+            return Err(RomError::UnevenRomSize { chip: "PRG", size: self.prg_rom.len(), page_size: ROM::PRG_ROM_PAGE_SIZE });
+        }
+        if !self.chr_rom.len().is_multiple_of(ROM::CHR_ROM_PAGE_SIZE) {
+            return Err(RomError::UnevenRomSize { chip: "CHR", size: self.chr_rom.len(), page_size: ROM::CHR_ROM_PAGE_SIZE });
+        }
+        Ok(())
+    }
+
+    // CRC32 of the PRG ROM followed by the CHR ROM, the checksum ROM databases identify dumps by.
+    // CHR RAM isn't part of the dump, so it's left out.
+    pub fn crc32(&self) -> u32 {
+        let crc = crc32_update(0, &self.prg_rom);
+        if self.is_chr_ram { crc } else { crc32_update(crc, &self.chr_rom) }
+    }
+
+    pub fn apply_override(&mut self, game_override: &GameOverride) {
+        println!("ROM: {:08X} is {} in the database, overriding its header", game_override.crc32, game_override.title);
+        if let Some(mapper_id) = game_override.mapper_id {
+            self.mapper_id = mapper_id;
+        }
+        if let Some(submapper) = game_override.submapper {
+            self.submapper = submapper;
+        }
+        if let Some(mirroring) = game_override.mirroring {
+            self.screen_mirroring = mirroring;
+        }
+        if let Some(battery) = game_override.battery {
+            if battery != self.has_save_ram {
+                // the PRG RAM is the same either way, only whether it's kept changes
+                let prg_ram_size = self.prg_ram_size + self.prg_nvram_size;
+                self.prg_ram_size = if battery { 0 } else { prg_ram_size };
+                self.prg_nvram_size = if battery { prg_ram_size } else { 0 };
+            }
+            self.has_save_ram = battery;
+        }
+        if let Some(timing) = game_override.timing {
+            self.timing = timing;
+        }
+    }
+
+    // Ref: https://www.nesdev.org/wiki/INES#Variant_comparison
+    // Old dumping tools left their signature ("DiskDude!" being the best known) in bytes 7-15, which
+    // iNES 1.0 says should be zero. Read as flags, it turns the mapper number into garbage (ex: 64 +
+    // the real one). An iNES 1.0 header with anything in bytes 12-15 can't be trusted past byte 6, so
+    // bytes 7-15 are zeroed, as most emulators do.
+    fn clean_header(header: &mut [u8; ROM::HEADER_SIZE]) {
+        let is_nes2 = (header[7] >> 2) & 0b0011 == 2;
+        if header[7..16] == *b"DiskDude!" || (!is_nes2 && header[12..16].iter().any(|byte| *byte != 0)) {
+            println!("[WARNING] ROM header has garbage in bytes 7-15 ({:02X?}), ignoring them", &header[7..16]);
+            header[7..16].fill(0);
+        }
+    }
+
+    // The ROM size is a 12-bit page count, unless the MSB nibble is 0xF. Then the LSB byte is
+    // EEEEEEMM and the size is 2^E * (2 * MM + 1) bytes.
+    fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> usize {
+        if msb == 0x0F {
+            let exponent = (lsb >> 2) as u32;
+            let multiplier = 2 * (lsb & 0b11) as usize + 1;
+            2usize.saturating_pow(exponent).saturating_mul(multiplier)
+        } else {
+            ((msb as usize) << 8 | lsb as usize) * page_size
+        }
+    }
+
+    fn nes2_ram_size(shift: u8) -> usize {
+        if shift == 0 { 0 } else { 64 << shift }
+    }
+
+    // Writes the ROM back out as an iNES image, ex: after patching it. The header is rebuilt from
+    // the fields, so database fixes end up in it. NES 2.0 is only used when iNES 1.0 can't describe
+    // the ROM. Bytes the fields don't cover (8-15 of iNES 1.0, 13-15 of NES 2.0) are kept as loaded.
+    pub fn to_ines_bytes(&self) -> Vec<u8> {
+        let is_nes2 = self.format == RomFormat::InesV2 || self.needs_nes2();
+        let (header, prg_rom_size, chr_rom_size) = self.build_header(is_nes2);
+        let chr_rom: &[u8] = if self.is_chr_ram { &[] } else { &self.chr_rom };
+
+        let mut raw = header.to_vec();
+        if let Some(trainer) = &self.trainer {
+            raw.extend_from_slice(trainer);
+            raw.resize(ROM::HEADER_SIZE + ROM::TRAINER_SIZE, 0);
+        }
+        let prg_rom_start = raw.len();
+        raw.extend_from_slice(&self.prg_rom);
+        raw.resize(prg_rom_start + prg_rom_size, 0);
+        raw.extend_from_slice(chr_rom);
+        raw.resize(prg_rom_start + prg_rom_size + chr_rom_size, 0);
+        raw
+    }
+
+    // The ROM's header in the NES 2.0 form, whatever format it was loaded from.
+    pub fn get_nes2_header(&self) -> [u8; ROM::HEADER_SIZE] {
+        self.build_header(true).0
+    }
+
+    // Also returns the PRG ROM and CHR ROM sizes the header gives.
+    fn build_header(&self, is_nes2: bool) -> ([u8; ROM::HEADER_SIZE], usize, usize) {
+        let chr_rom: &[u8] = if self.is_chr_ram { &[] } else { &self.chr_rom };
+        let ((prg_lsb, prg_msb), (chr_lsb, chr_msb)) = if is_nes2 {
+            (ROM::nes2_rom_size_bytes(self.prg_rom.len(), ROM::PRG_ROM_PAGE_SIZE),
+             ROM::nes2_rom_size_bytes(chr_rom.len(), ROM::CHR_ROM_PAGE_SIZE))
+        } else {
+            ((self.prg_rom.len().div_ceil(ROM::PRG_ROM_PAGE_SIZE) as u8, 0),
+             (chr_rom.len().div_ceil(ROM::CHR_ROM_PAGE_SIZE) as u8, 0))
+        };
+        // what the header says, which is what gets written even if the data has to be padded
+        let prg_rom_size = ROM::nes2_rom_size(prg_lsb, prg_msb, ROM::PRG_ROM_PAGE_SIZE);
+        let chr_rom_size = ROM::nes2_rom_size(chr_lsb, chr_msb, ROM::CHR_ROM_PAGE_SIZE);
+
+        let mut header = self.header;
+        header[0..4].copy_from_slice(&ROM::NES_SIGNATURE);
+        header[6] = ((self.mapper_id as u8 & 0x0F) << 4)
+            | if self.screen_mirroring == Mirroring::FourScreen { 0b1000 } else { 0 }
+            | if self.trainer.is_some() { 0b0100 } else { 0 }
+            | if self.has_save_ram { 0b0010 } else { 0 }
+            | if self.screen_mirroring == Mirroring::Vertical { 0b0001 } else { 0 };
+        header[7] = (self.mapper_id as u8 & 0xF0)
+            | if self.is_playchoice { 0b0010 } else { 0 }
+            | if self.is_vs_unisystem { 0b0001 } else { 0 };
+        header[4] = prg_lsb;
+        header[5] = chr_lsb;
+        if is_nes2 {
+            header[7] |= 0b0000_1000;
+            header[8] = (self.submapper << 4) | (self.mapper_id >> 8) as u8 & 0x0F;
+            header[9] = (chr_msb << 4) | prg_msb;
+            header[10] = (ROM::nes2_ram_shift(self.prg_nvram_size) << 4) | ROM::nes2_ram_shift(self.prg_ram_size);
+            header[11] = (ROM::nes2_ram_shift(self.chr_nvram_size) << 4) | ROM::nes2_ram_shift(self.chr_ram_size);
+            header[12] = match self.timing {
+                Timing::Ntsc => 0,
+                Timing::Pal => 1,
+                Timing::MultiRegion => 2,
+                Timing::Dendy => 3,
+            };
+        }
+        (header, prg_rom_size, chr_rom_size)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_ines_bytes())
+    }
+
+    // Anything iNES 1.0 would have to guess differently, see from_bytes.
+    fn needs_nes2(&self) -> bool {
+        let prg_ram = if self.has_save_ram { (0, ROM::PRG_RAM_PAGE_SIZE) } else { (ROM::PRG_RAM_PAGE_SIZE, 0) };
+        let chr_ram = if self.is_chr_ram { ROM::CHR_ROM_PAGE_SIZE } else { 0 };
+        self.mapper_id > 0xFF || self.submapper != 0 || self.timing != Timing::Ntsc
+            || (self.prg_ram_size, self.prg_nvram_size) != prg_ram
+            || (self.chr_ram_size, self.chr_nvram_size) != (chr_ram, 0)
+            || self.prg_rom.len() > 0xFF * ROM::PRG_ROM_PAGE_SIZE
+            || (!self.is_chr_ram && self.chr_rom.len() > 0xFF * ROM::CHR_ROM_PAGE_SIZE)
+    }
+
+    // The inverse of nes2_rom_size. Sizes that aren't a whole number of pages use the exponent form,
+    // or get rounded up to one if even that can't describe them.
+    fn nes2_rom_size_bytes(size: usize, page_size: usize) -> (u8, u8) {
+        if !size.is_multiple_of(page_size) {
+            let exponent = size.trailing_zeros();
+            let multiplier = size >> exponent;
+            if exponent < 64 && multiplier <= 7 {
+                return (((exponent as u8) << 2) | (multiplier as u8 >> 1), 0x0F);
+            }
+        }
+        let pages = usize::min(size.div_ceil(page_size), 0xEFF);
+        (pages as u8, (pages >> 8) as u8)
+    }
+
+    fn nes2_ram_shift(size: usize) -> u8 {
+        if size == 0 { 0 } else { usize::min((size / 64).max(1).ilog2() as usize, 0x0F) as u8 }
+    }
+
     #[inline]
-    pub fn read_prg_byte(&mut self, address: u16) -> u8 {
+    pub fn read_prg_byte(&self, address: u16) -> u8 {
         let mirror_address = self.mirror_prg_address(address);
-        match self.mapper_id {
-            0 => self.mapper0.read_prg_byte(mirror_address, &self.prg_rom),
-            1 => self.mapper1.read_prg_byte(mirror_address, &self.prg_rom),
-            2 => self.mapper2.read_prg_byte(mirror_address, &self.prg_rom),
-            3 => self.mapper3.read_prg_byte(mirror_address, &self.prg_rom),
-            4 => self.mapper4.read_prg_byte(mirror_address, &self.prg_rom),
-            66 => self.mapper66.read_prg_byte(mirror_address, &self.prg_rom),
-            _ => panic!("Unsupported mapper: {}", self.mapper_id)
-        }
+        self.mapper.read_prg_byte(mirror_address, &self.prg_rom)
     }
 
     #[inline]
     pub fn write_prg_byte(&mut self, address: u16, data: u8) {
-        match self.mapper_id {
-            0 => self.mapper0.write_mapper(address, data),
-            1 => {
-                self.mapper1.write_mapper(address, data);
-                self.screen_mirroring = self.mapper1.screen_mirroring.clone();
-            },
-            2 => self.mapper2.write_mapper(address, data),
-            3 => self.mapper3.write_mapper(address, data),
-            4 => {
-                self.mapper4.write_mapper(address, data);
-                self.screen_mirroring = self.mapper4.screen_mirroring.clone();
-            },
-            66 => self.mapper66.write_mapper(address, data),
-            _ => panic!("Attempt to write to Cartridge PRG ROM space: 0x{:0>4X}", address)
-        }
+        self.mapper.write_prg_byte(address, data, &self.prg_rom)
+    }
+
+    // The MMC1 ignores a write on the cycle after another, so it's told when each write happens.
+    #[inline]
+    pub fn set_cpu_cycle(&mut self, cycle: usize) {
+        self.mapper.set_cpu_cycle(cycle)
+    }
+
+    #[inline]
+    pub fn tick(&mut self, cycles: u8) {
+        self.mapper.tick(cycles)
+    }
+
+    // The concrete board, for controls only it has (ie. swapping FDS disks). None if the cartridge
+    // uses a different one.
+    pub fn mapper_as<T: Mapper + 'static>(&self) -> Option<&T> {
+        self.mapper.as_any().downcast_ref::<T>()
+    }
+
+    pub fn mapper_as_mut<T: Mapper + 'static>(&mut self) -> Option<&mut T> {
+        self.mapper.as_any_mut().downcast_mut::<T>()
+    }
+
+    // Some mappers react to the addresses the PPU fetches from: the MMC3 and MMC5 count scanlines by
+    // them, and the MMC2 switches CHR banks when it sees certain tiles go by.
+    #[inline]
+    pub fn watch_ppu_bus(&mut self, address: u16, ppu_dot: u64) {
+        self.mapper.watch_ppu_bus(address, ppu_dot)
+    }
+
+    #[inline]
+    pub fn watch_ppu_ctrl(&mut self, value: u8) {
+        self.mapper.watch_ppu_ctrl(value)
+    }
+
+    #[inline]
+    pub fn poll_irq(&self) -> bool {
+        self.mapper.poll_irq()
+    }
+
+    // The active mapper decides the nametable layout, falling back to the header's mirroring when
+    // the board doesn't control it.
+    #[inline]
+    pub fn get_mirroring(&self) -> Mirroring {
+        self.mapper.get_mirroring().unwrap_or(self.screen_mirroring)
+    }
+
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.screen_mirroring = mirroring;
+        self.mapper.set_mirroring(mirroring)
     }
 
     #[inline]
     pub fn read_chr_byte(&self, address: u16) -> u8 {
-        match self.mapper_id {
-            0 => self.mapper0.read_chr_byte(address, &self.chr_rom),
-            1 => self.mapper1.read_chr_byte(address, &self.chr_rom),
-            2 => self.mapper2.read_chr_byte(address, &self.chr_rom),
-            3 => self.mapper3.read_chr_byte(address, &self.chr_rom),
-            4 => self.mapper4.read_chr_byte(address, &self.chr_rom),
-            66 => self.mapper66.read_chr_byte(address, &self.chr_rom),
-            _ => panic!("Unsupported mapper: {}", self.mapper_id),
-        }
+        self.mapper.read_chr_byte(address, &self.chr_rom)
     }
 
     #[inline]
     pub fn write_chr_byte(&mut self, address: u16, data: u8) {
         if self.is_chr_ram {
-            self.chr_rom[address as usize] = data;
+            self.mapper.write_chr_byte(address, data, &mut self.chr_rom)
         } else {
             println!("[WARNING] Attempt to write to Cartridge CHR ROM space: 0x{:0>4X}", address)
         }
     }
 
     #[inline]
-    fn mirror_prg_address(&mut self, address: u16) -> u16 {
+    fn mirror_prg_address(&self, address: u16) -> u16 {
         let mut offset = address - Memory::PRG_ROM_START;
         if self.is_prg_rom_mirror && address >= ROM::PRG_ROM_PAGE_SIZE as u16 {
             offset = offset % ROM::PRG_ROM_PAGE_SIZE as u16;
@@ -191,13 +906,687 @@ impl ROM {
         Memory::PRG_ROM_START + offset
     }
 
+    // Byte 6, bit 1 of the header: the cartridge keeps its PRG RAM alive with a battery.
+    pub fn has_battery(&self) -> bool {
+        self.has_save_ram
+    }
+
+    // Byte 5 of the header is 0: the cartridge has CHR RAM in place of CHR ROM.
+    #[inline]
+    pub fn has_chr_ram(&self) -> bool {
+        self.is_chr_ram
+    }
+
+    pub fn get_format(&self) -> RomFormat {
+        self.format
+    }
+
+    pub fn is_nes2(&self) -> bool {
+        self.format == RomFormat::InesV2
+    }
+
+    // Board variant within the mapper, always 0 for iNES 1.0 images.
+    pub fn get_submapper(&self) -> u8 {
+        self.submapper
+    }
+
+    // iNES 1.0 images have no timing byte, so they're assumed to be NTSC.
+    pub fn get_timing(&self) -> Timing {
+        self.timing
+    }
+
+    // RAM sizes are in bytes. For iNES 1.0 images they're guessed from the battery and CHR ROM flags.
+    pub fn get_prg_ram_size(&self) -> usize {
+        self.prg_ram_size
+    }
+
+    pub fn get_prg_nvram_size(&self) -> usize {
+        self.prg_nvram_size
+    }
+
+    pub fn get_chr_ram_size(&self) -> usize {
+        self.chr_ram_size
+    }
+
+    pub fn get_chr_nvram_size(&self) -> usize {
+        self.chr_nvram_size
+    }
+
+    // Number of 16kB PRG ROM banks. Mappers with a fixed last bank count back from this.
     #[inline]
     pub fn get_prg_bank_count(&self) -> usize {
         self.prg_rom.len() / ROM::PRG_ROM_PAGE_SIZE
     }
 
+    // Number of 8kB CHR ROM banks, which is 0 for CHR RAM cartridges.
     #[inline]
     pub fn get_chr_bank_count(&self) -> usize {
-        self.chr_rom.len() / ROM::CHR_ROM_PAGE_SIZE
+        if self.is_chr_ram { 0 } else { self.chr_rom.len() / ROM::CHR_ROM_PAGE_SIZE }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::nes::rom::mappers::mapper1::Mapper1;
+    use crate::nes::rom::mappers::mapper71::Mapper71;
+
+    pub(crate) fn ines_image(flags6: u8, flags7: u8, prg_pages: u8, chr_pages: u8) -> Vec<u8> {
+        let mut raw = vec![0x4e, 0x45, 0x53, 0x1a, prg_pages, chr_pages, flags6, flags7, 0, 0, 0, 0, 0, 0, 0, 0];
+        if flags6 & 0b0100 != 0 {
+            raw.extend(vec![0xAA; ROM::TRAINER_SIZE]);
+        }
+        raw.extend(vec![0x11; prg_pages as usize * ROM::PRG_ROM_PAGE_SIZE]);
+        raw.extend(vec![0x22; chr_pages as usize * ROM::CHR_ROM_PAGE_SIZE]);
+        raw
+    }
+
+    // NROM with CHR RAM, running `program` from $8000.
+    pub(crate) fn test_rom(program: &[u8]) -> ROM {
+        let mut raw = vec![0x4e, 0x45, 0x53, 0x1a, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg_rom = vec![0; ROM::PRG_ROM_PAGE_SIZE];
+        prg_rom[..program.len()].copy_from_slice(program);
+        prg_rom[0x3ffc] = 0x00; // reset vector -> $8000
+        prg_rom[0x3ffd] = 0x80;
+        raw.extend(prg_rom);
+        ROM::from_bytes(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_header_flags() {
+        let rom = ROM::from_bytes(&ines_image(0b0010_1011, 0b0100_0001, 2, 1)).unwrap();
+        assert_eq!(rom.mapper_id, 66);
+        assert_eq!(rom.screen_mirroring, Mirroring::FourScreen);
+        assert!(rom.has_battery());
+        assert!(rom.trainer.is_none());
+        assert!(rom.is_vs_unisystem);
+        assert!(!rom.is_playchoice);
+        assert!(!rom.is_prg_rom_mirror);
+        assert_eq!(rom.prg_rom.len(), 2 * ROM::PRG_ROM_PAGE_SIZE);
+
+        let rom = ROM::from_bytes(&ines_image(0b0000_0001, 0b0000_0010, 1, 1)).unwrap();
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert!(!rom.has_battery());
+        assert!(!rom.is_vs_unisystem);
+        assert!(rom.is_playchoice);
+        assert!(rom.is_prg_rom_mirror);
+    }
+
+    #[test]
+    fn test_every_supported_mapper_is_dispatched() {
+        for mapper_id in mappers::supported_mappers() {
+            let mut rom = ROM::from_bytes(&ines_image(0, 0, 2, 1)).unwrap();
+            rom.mapper_id = *mapper_id;
+            rom.mapper = create_mapper(&rom).unwrap();
+            assert_eq!(rom.read_prg_byte(0xFFFF), 0x11, "mapper {}", mapper_id);
+            assert_eq!(rom.read_chr_byte(0x1FFF), 0x22, "mapper {}", mapper_id);
+            assert!(!rom.poll_irq());
+        }
+
+        // boards without the hooks leave the mirroring to the header
+        let mut rom = ROM::from_bytes(&ines_image(0b0001, 0, 2, 1)).unwrap();
+        rom.watch_ppu_bus(0x1000, 0);
+        assert_eq!(rom.get_mirroring(), Mirroring::Vertical);
+        assert!(rom.mapper_as::<Mapper0>().is_some());
+        assert!(rom.mapper_as::<Mapper20>().is_none());
+    }
+
+    #[test]
+    fn test_mmc3_four_screen() {
+        let mut rom = ROM::from_bytes(&ines_image(0b0100_1000, 0, 2, 1)).unwrap();
+        assert_eq!(rom.get_mirroring(), Mirroring::FourScreen);
+        rom.write_prg_byte(0xA000, 1);
+        assert_eq!(rom.get_mirroring(), Mirroring::FourScreen);
+
+        // without the extra RAM, the game picks the layout
+        let mut rom = ROM::from_bytes(&ines_image(0b0100_0000, 0, 2, 1)).unwrap();
+        rom.write_prg_byte(0xA000, 1);
+        assert_eq!(rom.get_mirroring(), Mirroring::Horizontal);
+        rom.write_prg_byte(0xA000, 0);
+        assert_eq!(rom.get_mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_write_chr_byte() {
+        let mut rom = ROM::from_bytes(&ines_image(0, 0, 1, 0)).unwrap();
+        rom.write_chr_byte(0x1234, 0x5A);
+        assert_eq!(rom.read_chr_byte(0x1234), 0x5A);
+
+        // CHR ROM can't be written
+        let mut rom = ROM::from_bytes(&ines_image(0, 0, 1, 1)).unwrap();
+        rom.write_chr_byte(0x1234, 0x5A);
+        assert_eq!(rom.read_chr_byte(0x1234), 0x22);
+    }
+
+    #[test]
+    fn test_header_trainer() {
+        let rom = ROM::from_bytes(&ines_image(0b0000_0100, 0, 1, 1)).unwrap();
+        assert_eq!(rom.trainer, Some(vec![0xAA; ROM::TRAINER_SIZE]));
+        // PRG and CHR ROM start after the trainer
+        assert!(rom.prg_rom.iter().all(|byte| *byte == 0x11));
+        assert!(rom.chr_rom.iter().all(|byte| *byte == 0x22));
+    }
+
+    #[test]
+    fn test_bank_counts() {
+        let rom = ROM::from_bytes(&ines_image(0, 0, 2, 1)).unwrap();
+        assert!(!rom.has_chr_ram());
+        assert_eq!(rom.get_prg_bank_count(), 2);
+        assert_eq!(rom.get_chr_bank_count(), 1);
+
+        let rom = ROM::from_bytes(&ines_image(0, 0, 8, 0)).unwrap();
+        assert!(rom.has_chr_ram());
+        assert_eq!(rom.get_prg_bank_count(), 8);
+        assert_eq!(rom.get_chr_bank_count(), 0);
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let mut raw = ines_image(0, 0, 2, 1);
+        raw.truncate(raw.len() - 1);
+        assert!(matches!(ROM::from_bytes(&raw),
+            Err(RomError::ChrRomTooShort { expected: 0x2000, got: 0x1FFF })));
+
+        let mut raw = ines_image(0, 0, 2, 0);
+        raw.truncate(ROM::HEADER_SIZE + ROM::PRG_ROM_PAGE_SIZE);
+        assert!(matches!(ROM::from_bytes(&raw),
+            Err(RomError::PrgRomTooShort { expected: 0x8000, got: 0x4000 })));
+
+        assert!(matches!(ROM::from_bytes(&ines_image(0, 0, 1, 0)[..8]), Err(RomError::TruncatedHeader)));
+        assert!(matches!(ROM::from_bytes(&[0x4e, 0x45, 0x53]), Err(RomError::InvalidMagic([0x4e, 0x45, 0x53, 0]))));
+    }
+
+    #[test]
+    fn test_from_bytes_invalid_magic() {
+        let mut raw = ines_image(0, 0, 1, 0);
+        raw[3] = 0x00;
+        assert!(matches!(ROM::from_bytes(&raw), Err(RomError::InvalidMagic([0x4e, 0x45, 0x53, 0x00]))));
+    }
+
+    fn nes2_image(header: [u8; 16], prg_size: usize, chr_size: usize) -> Vec<u8> {
+        let mut raw = header.to_vec();
+        raw.extend(vec![0x11; prg_size]);
+        raw.extend(vec![0x22; chr_size]);
+        raw
+    }
+
+    #[test]
+    fn test_ines_v1_defaults() {
+        let rom = ROM::from_bytes(&ines_image(0b0000_0010, 0, 1, 0)).unwrap();
+        assert_eq!(rom.format, RomFormat::InesV1);
+        assert_eq!(rom.submapper, 0);
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.prg_nvram_size, ROM::PRG_RAM_PAGE_SIZE);
+        assert_eq!(rom.chr_ram_size, ROM::CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.timing, Timing::Ntsc);
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0b0100_0011, 0b0000_1000, 0x30, 0x00, 0x97, 0x07, 0x01, 0, 0, 0];
+        let rom = ROM::from_bytes(&nes2_image(header, 2 * ROM::PRG_ROM_PAGE_SIZE, ROM::CHR_ROM_PAGE_SIZE)).unwrap();
+        assert_eq!(rom.format, RomFormat::InesV2);
+        assert_eq!(rom.mapper_id, 4);
+        assert_eq!(rom.submapper, 3);
+        assert_eq!(rom.prg_rom.len(), 2 * ROM::PRG_ROM_PAGE_SIZE);
+        assert_eq!(rom.chr_rom.len(), ROM::CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.prg_ram_size, 64 << 7);
+        assert_eq!(rom.prg_nvram_size, 64 << 9);
+        assert_eq!(rom.chr_ram_size, 64 << 7);
+        assert_eq!(rom.chr_nvram_size, 0);
+        assert_eq!(rom.timing, Timing::Pal);
+        assert!(rom.has_battery());
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        assert!(matches!(ROM::from_bytes(&ines_image(0b0101_0000, 0b0100_0000, 1, 1)), Err(RomError::UnsupportedMapper(0x45))));
+
+        // NES 2.0 adds mapper bits 8-11
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x01, 0x01, 0b0101_0000, 0b0110_1000, 0x3A, 0, 0, 0, 0, 0, 0, 0];
+        let raw = nes2_image(header, ROM::PRG_ROM_PAGE_SIZE, ROM::CHR_ROM_PAGE_SIZE);
+        assert!(matches!(ROM::from_bytes(&raw), Err(RomError::UnsupportedMapper(0xA65))));
+    }
+
+    #[test]
+    fn test_from_path_missing_file() {
+        let err = ROM::from_path(Path::new("rom/does_not_exist.nes")).err().unwrap();
+        assert!(matches!(err, RomError::Io(ref io_err) if io_err.kind() == io::ErrorKind::NotFound));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let raw = ines_image(0b0000_0001, 0, 1, 1);
+        let rom = ROM::from_reader(io::Cursor::new(raw.clone())).unwrap();
+        assert_eq!(rom.crc32(), ROM::from_bytes(&raw).unwrap().crc32());
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert!(matches!(ROM::from_reader(io::Cursor::new(vec![0x4e, 0x45])), Err(RomError::InvalidMagic(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "zip")]
+    fn test_from_zip() {
+        // tests/fixtures/nrom.zip holds a readme followed by nrom.nes, which is ines_image(0, 0, 1, 1)
+        let zip_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/nrom.zip");
+        let nes_path = std::env::temp_dir().join("alpines_test_nrom.nes");
+        fs::write(&nes_path, ines_image(0, 0, 1, 1)).unwrap();
+
+        let zipped = ROM::from_path(&zip_path).unwrap();
+        let unzipped = ROM::from_path(&nes_path).unwrap();
+        assert_eq!(zipped.header, unzipped.header);
+        assert_eq!(zipped.prg_rom, unzipped.prg_rom);
+        assert_eq!(zipped.chr_rom, unzipped.chr_rom);
+        assert_eq!((zipped.mapper_id, zipped.screen_mirroring), (unzipped.mapper_id, unzipped.screen_mirroring));
+        assert_eq!(zipped.game_title, "nrom");
+        assert_eq!(zipped.save_path, Some(zip_path.with_extension("sav")));
+        fs::remove_file(&nes_path).unwrap();
+
+        let zip_path = std::env::temp_dir().join("alpines_test_empty.zip");
+        fs::write(&zip_path, [0x50, 0x4B, 0x05, 0x06, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert!(matches!(ROM::from_zip(&zip_path), Err(RomError::NoRomInArchive)));
+        fs::remove_file(&zip_path).unwrap();
+    }
+
+    #[test]
+    fn test_probe() {
+        let path = std::env::temp_dir().join("alpines_test_probe.nes");
+        let raw = ines_image(0b0100_0111, 0, 2, 0);
+        fs::write(&path, &raw).unwrap();
+        let info = ROM::probe(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(info, RomInfo {
+            format: RomFormat::InesV1,
+            mapper_id: 4,
+            submapper: 0,
+            mapper_name: "MMC3",
+            prg_rom_size: 0x8000,
+            chr_rom_size: 0,
+            mirroring: Mirroring::Vertical,
+            has_battery: true,
+            has_trainer: true,
+            timing: Timing::Ntsc,
+            crc32: ROM::from_bytes(&raw).unwrap().crc32(),
+            is_supported: true,
+        });
+        assert_eq!(ROM::from_bytes(&raw).unwrap().get_info(), info);
+
+        // unsupported mappers can still be probed, they just can't be loaded
+        let mut raw = ines_image(0b0111_0000, 0b0000_1000, 1, 1);
+        raw[12] = 1;
+        let info = ROM::probe_bytes(&raw).unwrap();
+        assert_eq!((info.format, info.mapper_id, info.mapper_name, info.timing), (RomFormat::InesV2, 7, "AxROM", Timing::Pal));
+        assert!(!info.is_supported);
+        assert!(matches!(ROM::probe_bytes(&raw[..0x100]), Err(RomError::PrgRomTooShort { .. })));
+    }
+
+    #[test]
+    #[cfg(feature = "zip")]
+    fn test_probe_zip() {
+        let info = ROM::probe(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/nrom.zip")).unwrap();
+        assert_eq!((info.mapper_id, info.mapper_name, info.prg_rom_size, info.chr_rom_size), (0, "NROM", 0x4000, 0x2000));
+        assert_eq!((info.mirroring, info.has_battery, info.has_trainer), (Mirroring::Horizontal, false, false));
+        assert_eq!(info.crc32, ROM::from_bytes(&ines_image(0, 0, 1, 1)).unwrap().crc32());
+        assert!(info.is_supported);
+    }
+
+    #[test]
+    fn test_from_path_with_patch() {
+        let rom_path = std::env::temp_dir().join("alpines_test_patch.nes");
+        let patch_path = rom_path.with_extension("ips");
+        fs::write(&rom_path, ines_image(0, 0, 1, 1)).unwrap();
+        assert_eq!(ROM::find_patch(&rom_path), None);
+
+        // flips the mirroring bit in the header and writes over the reset vector
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x06, 0x00, 0x01, 0x01]);
+        patch.extend([0x00, 0x40, 0x0C, 0x00, 0x02, 0x00, 0x80]);
+        patch.extend(b"EOF");
+        fs::write(&patch_path, &patch).unwrap();
+        assert_eq!(ROM::find_patch(&rom_path), Some(patch_path.clone()));
+
+        let rom = ROM::from_path_with_patch(&rom_path, &patch_path).unwrap();
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert_eq!(rom.prg_rom[0x3FFC..0x3FFE], [0x00, 0x80]);
+        assert_eq!(rom.prg_rom[0x3FFB], 0x11);
+        assert_eq!(rom.save_path, Some(std::env::temp_dir().join("alpines_test_patch.ips.sav")));
+
+        fs::write(&patch_path, b"PATCH\x00\x00").unwrap();
+        assert!(matches!(ROM::from_path_with_patch(&rom_path, &patch_path), Err(RomError::InvalidPatch(_))));
+        fs::remove_file(&patch_path).unwrap();
+        fs::remove_file(&rom_path).unwrap();
+        assert!(matches!(ROM::apply_patch(&[0; 16], b"UPS1"), Err(RomError::InvalidPatch(_))));
+    }
+
+    fn unif_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    #[test]
+    fn test_from_unif() {
+        let mut raw = b"UNIF".to_vec();
+        raw.extend([7, 0, 0, 0]);
+        raw.extend([0; 24]);
+        raw.extend(unif_chunk(b"MAPR", b"NES-SNROM\0"));
+        raw.extend(unif_chunk(b"NAME", b"alpiNES\0"));
+        // banks are concatenated by number, not by the order they're in the file
+        raw.extend(unif_chunk(b"PRG1", &[0x22; 0x4000]));
+        raw.extend(unif_chunk(b"PRG0", &[0x11; 0x4000]));
+        raw.extend(unif_chunk(b"CHR0", &[0x33; 0x2000]));
+        raw.extend(unif_chunk(b"MIRR", &[1]));
+        raw.extend(unif_chunk(b"BATR", &[1]));
+        raw.extend(unif_chunk(b"TVCI", &[1]));
+
+        let rom = ROM::from_reader(io::Cursor::new(raw.clone())).unwrap();
+        assert_eq!(rom.mapper_id, 1);
+        assert_eq!(rom.game_title, "alpiNES");
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert_eq!((rom.prg_rom[0x3FFF], rom.prg_rom[0x4000]), (0x11, 0x22));
+        assert!(!rom.is_prg_rom_mirror);
+        assert_eq!(rom.chr_rom, vec![0x33; 0x2000]);
+        assert!(!rom.is_chr_ram);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert!(rom.has_save_ram);
+        assert_eq!(rom.get_prg_nvram_size(), ROM::PRG_RAM_PAGE_SIZE);
+        assert_eq!(rom.get_timing(), Timing::Pal);
+
+        // a 4kB CHR chip would leave half of every 8kB bank unmapped
+        let odd_chr = [&raw[..32], &unif_chunk(b"MAPR", b"NES-NROM-128\0"), &unif_chunk(b"PRG0", &[0; 0x4000]),
+            &unif_chunk(b"CHR0", &[0; 0x1000])].concat();
+        assert!(matches!(ROM::from_unif(&odd_chr), Err(RomError::UnevenRomSize { chip: "CHR", size: 0x1000, .. })));
+
+        let raw = [&raw[..32], &unif_chunk(b"MAPR", b"NES-AOROM\0"), &unif_chunk(b"PRG0", &[0; 0x4000])].concat();
+        assert!(matches!(ROM::from_unif(&raw), Err(RomError::UnknownBoard(board)) if board == "NES-AOROM"));
+        assert!(matches!(ROM::from_unif(&ines_image(0, 0, 1, 0)), Err(RomError::InvalidMagic(_))));
+    }
+
+    #[test]
+    fn test_from_raw_prg() {
+        let rom = ROM::from_raw_prg(&[0x11; 0x8000], 2, Mirroring::Vertical).unwrap();
+        assert_eq!(rom.mapper_id, 2);
+        assert_eq!(rom.get_prg_bank_count(), 2);
+        assert!(rom.is_chr_ram);
+        assert_eq!(rom.chr_rom.len(), ROM::CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+
+        assert!(matches!(ROM::from_raw_prg(&[0; 0x100], 0, Mirroring::Horizontal),
+            Err(RomError::PrgRomTooShort { expected: 0x4000, got: 0x100 })));
+        assert!(matches!(ROM::from_raw_prg(&[0; 0x4000], 7, Mirroring::Horizontal), Err(RomError::UnsupportedMapper(7))));
+        assert!(matches!(ROM::from_raw_prg(&[0; 0x6000], 0, Mirroring::Horizontal),
+            Err(RomError::UnevenRomSize { chip: "PRG", size: 0x6000, page_size: 0x4000 })));
+    }
+
+    #[test]
+    fn test_from_fds() {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(65500, 0);
+        let mut bios = vec![0; 0x2000];
+        bios[0] = 0xEA;
+
+        let mut raw = vec![0x46, 0x44, 0x53, 0x1a, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        raw.extend(side.repeat(2));
+        let rom = ROM::from_fds(&raw, &bios).unwrap();
+        assert_eq!((rom.get_format(), rom.mapper_id), (RomFormat::Fds, 20));
+        assert_eq!(rom.mapper_as::<Mapper20>().unwrap().get_side_count(), 2);
+        assert_eq!(rom.read_prg_byte(0xE000), 0xEA);
+        assert!(rom.has_chr_ram());
+
+        // fwNES headers are optional
+        assert_eq!(ROM::from_fds(&side, &bios).unwrap().mapper_as::<Mapper20>().unwrap().get_side_count(), 1);
+        assert!(matches!(ROM::from_fds(&side, &bios[..0x1000]), Err(RomError::InvalidBios(0x1000))));
+        assert!(matches!(ROM::from_fds(&side[..0x100], &bios), Err(RomError::TruncatedDisk(0x100))));
+        assert!(matches!(ROM::from_fds(&ines_image(0, 0, 1, 0), &bios), Err(RomError::InvalidMagic(_))));
+    }
+
+    #[test]
+    fn test_to_ines_bytes_round_trip() {
+        let raw = ines_image(0b0010_0111, 0b0100_0001, 2, 1);
+        assert_eq!(ROM::from_bytes(&raw).unwrap().to_ines_bytes(), raw);
+        let raw = ines_image(0b0001_0000, 0, 8, 0);
+        assert_eq!(ROM::from_bytes(&raw).unwrap().to_ines_bytes(), raw);
+
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0b0100_0011, 0b0000_1000, 0x30, 0x00, 0x97, 0x07, 0x01, 0, 0, 0];
+        let raw = nes2_image(header, 2 * ROM::PRG_ROM_PAGE_SIZE, ROM::CHR_ROM_PAGE_SIZE);
+        assert_eq!(ROM::from_bytes(&raw).unwrap().to_ines_bytes(), raw);
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x01, 0b0010_1110, 0, 0b0000_1000, 0, 0xF0, 0, 0, 0x03, 0, 0, 0];
+        let raw = nes2_image(header, ROM::PRG_ROM_PAGE_SIZE, 5 * 0x800);
+        assert_eq!(ROM::from_bytes(&raw).unwrap().to_ines_bytes(), raw);
+
+        let path = std::env::temp_dir().join("alpines_test_save.nes");
+        let rom = ROM::from_bytes(&ines_image(0b0000_0001, 0, 1, 1)).unwrap();
+        rom.save(&path).unwrap();
+        assert_eq!(ROM::from_path(&path).unwrap().to_ines_bytes(), rom.to_ines_bytes());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_to_ines_bytes_upgrades_to_nes2() {
+        // a PAL game fixed by the database can't be described by iNES 1.0
+        let mut rom = ROM::from_bytes(&ines_image(0, 0, 1, 1)).unwrap();
+        rom.timing = Timing::Pal;
+        rom.submapper = 2;
+        let saved = ROM::from_bytes(&rom.to_ines_bytes()).unwrap();
+        assert_eq!(saved.format, RomFormat::InesV2);
+        assert_eq!((saved.timing, saved.submapper), (Timing::Pal, 2));
+        assert_eq!((saved.prg_ram_size, saved.prg_nvram_size, saved.chr_ram_size), (ROM::PRG_RAM_PAGE_SIZE, 0, 0));
+        assert_eq!((saved.prg_rom, saved.chr_rom), (rom.prg_rom, rom.chr_rom));
+    }
+
+    #[test]
+    fn test_nes2_rom_size_msb() {
+        // 0x102 pages of PRG ROM
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x00, 0, 0b0000_1000, 0, 0x01, 0, 0x07, 0x02, 0, 0, 0];
+        let rom = ROM::from_bytes(&nes2_image(header, 0x102 * ROM::PRG_ROM_PAGE_SIZE, 0)).unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x102 * ROM::PRG_ROM_PAGE_SIZE);
+        assert!(rom.is_chr_ram);
+        assert_eq!(rom.chr_rom.len(), ROM::CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.timing, Timing::MultiRegion);
+    }
+
+    #[test]
+    fn test_nes2_rom_size_exponent() {
+        assert_eq!(ROM::nes2_rom_size(0b0000_1100, 0x0F, ROM::PRG_ROM_PAGE_SIZE), 8);
+        assert_eq!(ROM::nes2_rom_size(0b0011_1001, 0x0F, ROM::PRG_ROM_PAGE_SIZE), (1 << 14) * 3);
+        assert_eq!(ROM::nes2_rom_size(0x20, 0x0E, ROM::CHR_ROM_PAGE_SIZE), 0xE20 * ROM::CHR_ROM_PAGE_SIZE);
+
+        // 3 * 2^13 bytes of CHR ROM
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x01, 0b0011_0101, 0, 0b0000_1000, 0, 0xF0, 0, 0, 0x03, 0, 0, 0];
+        let rom = ROM::from_bytes(&nes2_image(header, ROM::PRG_ROM_PAGE_SIZE, 3 * 0x2000)).unwrap();
+        assert_eq!(rom.chr_rom.len(), 3 * 0x2000);
+        assert_eq!(rom.timing, Timing::Dendy);
+    }
+
+    #[test]
+    fn test_unknown_header_version() {
+        let mut raw = ines_image(0, 0, 1, 1);
+        raw[7] = 0b0000_0100;
+        assert!(matches!(ROM::from_bytes(&raw), Err(RomError::UnsupportedVersion(1))));
+    }
+
+    #[test]
+    fn test_default_save_path() {
+        assert_eq!(ROM::default_save_path(Path::new("rom/mapper1/legend_of_zelda.nes")), PathBuf::from("rom/mapper1/legend_of_zelda.sav"));
+        assert_eq!(ROM::default_save_path(Path::new("metroid")), PathBuf::from("metroid.sav"));
+    }
+
+    #[test]
+    fn test_header_getters() {
+        let rom = ROM::from_bytes(&ines_image(0b0001_0000, 0, 2, 1)).unwrap();
+        assert!(!rom.is_nes2());
+        assert_eq!(rom.get_format(), RomFormat::InesV1);
+        assert_eq!(rom.get_submapper(), 0);
+        assert_eq!(rom.get_timing(), Timing::Ntsc);
+        assert_eq!(rom.get_prg_ram_size(), ROM::PRG_RAM_PAGE_SIZE);
+        assert_eq!(rom.get_prg_nvram_size(), 0);
+        assert_eq!(rom.get_chr_ram_size(), 0);
+        assert_eq!(rom.get_chr_nvram_size(), 0);
+
+        // SEROM (MMC1 submapper 5) with 8kB of battery-backed PRG RAM and 8kB of CHR NVRAM
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x00, 0b0001_0010, 0b0000_1000, 0x50, 0x00, 0x70, 0x70, 0x01, 0, 0, 0];
+        let rom = ROM::from_bytes(&nes2_image(header, 2 * ROM::PRG_ROM_PAGE_SIZE, 0)).unwrap();
+        assert!(rom.is_nes2());
+        assert_eq!(rom.get_format(), RomFormat::InesV2);
+        assert_eq!(rom.get_submapper(), 5);
+        assert_eq!(rom.get_timing(), Timing::Pal);
+        assert_eq!(rom.get_prg_ram_size(), 0);
+        assert_eq!(rom.get_prg_nvram_size(), 0x2000);
+        assert_eq!(rom.get_chr_ram_size(), 0);
+        assert_eq!(rom.get_chr_nvram_size(), 0x2000);
+        assert_eq!(rom.mapper_as::<Mapper1>().unwrap().submapper, 5);
+    }
+
+    #[test]
+    fn test_mmc1_fixed_prg_submapper() {
+        let mut raw = ines_image(0b0001_0000, 0, 2, 1);
+        raw[ROM::HEADER_SIZE + ROM::PRG_ROM_PAGE_SIZE] = 0x33;
+        let write_prg_bank = |rom: &mut ROM, bank: u8| {
+            for bit in 0..5 {
+                rom.write_prg_byte(0xE000, (bank >> bit) & 1);
+            }
+        };
+
+        // a plain MMC1 board swaps the second bank in at $8000
+        let mut rom = ROM::from_bytes(&raw).unwrap();
+        write_prg_bank(&mut rom, 1);
+        assert_eq!(rom.read_prg_byte(0x8000), 0x33);
+
+        // SEROM ignores the bank select
+        raw[7] = 0b0000_1000;
+        raw[8] = 0x50;
+        let mut rom = ROM::from_bytes(&raw).unwrap();
+        write_prg_bank(&mut rom, 1);
+        assert_eq!(rom.read_prg_byte(0x8000), 0x11);
+        assert_eq!(rom.read_prg_byte(0xC000), 0x33);
+    }
+
+    #[test]
+    fn test_diskdude_header() {
+        // mapper 1, with "DiskDude!" turning it into mapper 0x41 and setting the VS. System bit
+        let mut raw = ines_image(0b0001_0000, 0, 2, 1);
+        raw[7..16].copy_from_slice(b"DiskDude!");
+        let rom = ROM::from_bytes(&raw).unwrap();
+        assert_eq!(rom.mapper_id, 1);
+        assert!(!rom.is_vs_unisystem);
+        assert_eq!(rom.format, RomFormat::InesV1);
+        assert_eq!(rom.header[7..16], [0; 9]);
+
+        // any other signature in bytes 12-15 is dropped the same way
+        let mut raw = ines_image(0b0010_0000, 0b0100_0000, 1, 1);
+        raw[12..16].copy_from_slice(b"NI11");
+        assert_eq!(ROM::from_bytes(&raw).unwrap().mapper_id, 2);
+
+        // while a clean header keeps its upper mapper nibble
+        let raw = ines_image(0b0010_0000, 0b0100_0000, 1, 1);
+        assert_eq!(ROM::from_bytes(&raw).unwrap().mapper_id, 66);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut raw = ines_image(0, 0, 1, 1);
+        raw.extend(b"some dumps have a title or padding at the end");
+        let rom = ROM::from_bytes(&raw).unwrap();
+        assert_eq!(rom.prg_rom.len(), ROM::PRG_ROM_PAGE_SIZE);
+        assert!(rom.chr_rom.iter().all(|byte| *byte == 0x22));
+    }
+
+    #[test]
+    fn test_truncated_trainer() {
+        let raw = ines_image(0b0000_0100, 0, 1, 0);
+        let err = ROM::from_bytes(&raw[..ROM::HEADER_SIZE + 0x100]).err().unwrap();
+        assert!(matches!(err, RomError::PrgRomTooShort { expected: 0x4000, got: 0 }));
+        assert_eq!(err.to_string(), "Header claims 0x4000 bytes of PRG ROM, but the file only has 0x0");
+    }
+
+    #[test]
+    fn test_crc32() {
+        let rom = ROM::from_bytes(&ines_image(0, 0, 1, 1)).unwrap();
+        let mut dump = vec![0x11; ROM::PRG_ROM_PAGE_SIZE];
+        dump.extend(vec![0x22; ROM::CHR_ROM_PAGE_SIZE]);
+        assert_eq!(rom.crc32(), crate::util::checksum::crc32(&dump));
+
+        let rom = ROM::from_bytes(&ines_image(0, 0, 1, 0)).unwrap();
+        assert_eq!(rom.crc32(), crate::util::checksum::crc32(&rom.prg_rom));
+    }
+
+    #[test]
+    fn test_apply_override() {
+        // a mapper 3 header on what's really a battery-backed, vertically mirrored mapper 1 board
+        let mut rom = ROM::from_bytes(&ines_image(0b0011_0000, 0, 2, 1)).unwrap();
+        rom.apply_override(&GameOverride {
+            crc32: rom.crc32(),
+            title: "Test",
+            mapper_id: Some(1),
+            submapper: None,
+            mirroring: Some(Mirroring::Vertical),
+            battery: Some(true),
+            timing: None,
+        });
+        assert_eq!(rom.mapper_id, 1);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert!(rom.has_battery());
+        assert_eq!((rom.prg_ram_size, rom.prg_nvram_size), (0, ROM::PRG_RAM_PAGE_SIZE));
+        assert_eq!(rom.timing, Timing::Ntsc);
+    }
+
+    // A CHR RAM dump whose last 4 PRG bytes are picked so it checksums to `crc32`, so it's taken for
+    // whatever game the database has under that checksum.
+    fn database_image(flags6: u8, flags7: u8, prg_pages: u8, crc32: u32) -> Vec<u8> {
+        let table: Vec<u32> = (0..256).map(|index| {
+            (0..8).fold(index, |crc, _| if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 })
+        }).collect();
+        let mut raw = ines_image(flags6, flags7, prg_pages, 0);
+        let patch_start = raw.len() - 4;
+
+        // every table entry has a different top byte, so the last 4 table lookups can be worked out
+        // backwards from the final CRC, then replayed forwards to find the bytes that make them
+        let mut indices = [0; 4];
+        let mut crc = !crc32;
+        for index in indices.iter_mut().rev() {
+            *index = table.iter().position(|entry| entry >> 24 == crc >> 24).unwrap();
+            crc = (crc ^ table[*index]) << 8;
+        }
+        let mut crc = !crate::util::checksum::crc32(&raw[ROM::HEADER_SIZE..patch_start]);
+        for (offset, index) in indices.iter().enumerate() {
+            raw[patch_start + offset] = (crc as u8) ^ *index as u8;
+            crc = (crc >> 8) ^ table[*index];
+        }
+        raw
+    }
+
+    #[test]
+    fn test_database_override() {
+        // Super Mario Bros. with the mirroring bit cleared
+        let rom = ROM::from_bytes(&database_image(0, 0, 2, 0x3337_EC46)).unwrap();
+        assert_eq!(rom.crc32(), 0x3337_EC46);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+
+        // Zelda without its battery
+        let rom = ROM::from_bytes(&database_image(0b0001_0000, 0, 8, 0x3FE2_72FB)).unwrap();
+        assert!(rom.has_battery());
+
+        // MiG 29 as mapper 2
+        let rom = ROM::from_bytes(&database_image(0b0010_0000, 0, 8, 0xE62E_3382)).unwrap();
+        assert_eq!(rom.mapper_id, 71);
+        assert!(!rom.mapper_as::<Mapper71>().unwrap().mirroring_control);
+
+        // Fire Hawk, which needs submapper 1 for its mirroring control
+        let rom = ROM::from_bytes(&database_image(0b0111_0000, 0b0100_0000, 8, 0x1BC6_86A8)).unwrap();
+        assert_eq!((rom.mapper_id, rom.submapper), (71, 1));
+        assert!(rom.mapper_as::<Mapper71>().unwrap().mirroring_control);
+
+        // anything else keeps its header
+        let rom = ROM::from_bytes(&database_image(0b0010_0000, 0, 8, 0x1234_5678)).unwrap();
+        assert_eq!(rom.mapper_id, 2);
+    }
+
+    #[test]
+    fn test_header_chr_ram() {
+        let rom = ROM::from_bytes(&ines_image(0, 0, 1, 0)).unwrap();
+        assert!(rom.is_chr_ram);
+        assert_eq!(rom.chr_rom, vec![0; ROM::CHR_ROM_PAGE_SIZE]);
     }
 }
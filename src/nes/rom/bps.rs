@@ -0,0 +1,240 @@
+use crate::nes::rom::RomError;
+use crate::util::checksum::crc32;
+
+// Ref: https://www.romhacking.net/documents/746/
+
+// BPS patch layout:
+// =====================================
+// Bytes 0-3:   "BPS1"
+// varint       source size
+// varint       target size
+// varint       metadata size, followed by the metadata
+// varint ...   actions: length - 1 in bits 2-, command in bits 0-1
+//                0: SourceRead  <- copy from the source, at the same offset as the output
+//                1: TargetRead  <- copy from the patch
+//                2: SourceCopy  <- copy from the source, at a relative offset (varint)
+//                3: TargetCopy  <- copy from the output so far, at a relative offset (varint)
+// Last 12:     source CRC32 | target CRC32 | patch CRC32 (u32 LE each)
+//
+// Unlike IPS, every part is checksummed, so a patch made for a different dump is caught.
+
+pub const BPS_SIGNATURE: [u8; 4] = *b"BPS1";
+const FOOTER_SIZE: usize = 12;
+
+pub fn is_bps(patch: &[u8]) -> bool {
+    patch.starts_with(&BPS_SIGNATURE)
+}
+
+fn truncated() -> RomError {
+    RomError::InvalidPatch(String::from("BPS patch is truncated"))
+}
+
+fn out_of_range() -> RomError {
+    RomError::InvalidPatch(String::from("BPS patch copies from out of range"))
+}
+
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    offset: usize,
+}
+
+impl PatchReader<'_> {
+    fn read_byte(&mut self) -> Result<u8, RomError> {
+        let byte = *self.patch.get(self.offset).ok_or_else(truncated)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    // Bit 7 ends the number. Each continuation also adds one, so every number has a single encoding.
+    fn read_varint(&mut self) -> Result<usize, RomError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read_byte()?;
+            value = value.checked_add((byte & 0x7F) as usize * shift).ok_or_else(truncated)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or_else(truncated)?;
+            value = value.checked_add(shift).ok_or_else(truncated)?;
+        }
+    }
+
+    // Relative offsets store their sign in bit 0.
+    fn read_relative(&mut self) -> Result<isize, RomError> {
+        let value = self.read_varint()?;
+        let magnitude = (value >> 1) as isize;
+        Ok(if value & 1 != 0 { -magnitude } else { magnitude })
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn check_crc(expected: u32, got: u32) -> Result<(), RomError> {
+    if expected != got {
+        return Err(RomError::PatchChecksumMismatch { expected, got });
+    }
+    Ok(())
+}
+
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomError> {
+    if !is_bps(patch) {
+        return Err(RomError::InvalidPatch(String::from("BPS patch doesn't start with BPS1")));
+    }
+    if patch.len() < BPS_SIGNATURE.len() + FOOTER_SIZE {
+        return Err(truncated());
+    }
+    let footer = &patch[patch.len() - FOOTER_SIZE..];
+    check_crc(read_u32(&footer[8..12]), crc32(&patch[..patch.len() - 4]))?;
+    check_crc(read_u32(&footer[0..4]), crc32(source))?;
+
+    let actions_end = patch.len() - FOOTER_SIZE;
+    let mut reader = PatchReader { patch: &patch[..actions_end], offset: BPS_SIGNATURE.len() };
+    let source_size = reader.read_varint()?;
+    let target_size = reader.read_varint()?;
+    let metadata_size = reader.read_varint()?;
+    reader.offset = reader.offset.checked_add(metadata_size).ok_or_else(truncated)?;
+    if source_size != source.len() {
+        return Err(RomError::InvalidPatch(format!("BPS patch is for a 0x{:x} byte file, got 0x{:x}", source_size, source.len())));
+    }
+
+    // the size comes from the patch, so it isn't trusted with an allocation up front
+    let mut target = Vec::new();
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+    while reader.offset < actions_end {
+        let action = reader.read_varint()?;
+        let length = (action >> 2) + 1;
+        if target.len() + length > target_size {
+            return Err(RomError::InvalidPatch(String::from("BPS patch writes past the end of the file")));
+        }
+        match action & 0b11 {
+            0 => {
+                let data = source.get(target.len()..target.len() + length).ok_or_else(truncated)?;
+                target.extend_from_slice(data);
+            }
+            1 => {
+                let data = reader.patch.get(reader.offset..reader.offset + length).ok_or_else(truncated)?;
+                reader.offset += length;
+                target.extend_from_slice(data);
+            }
+            2 => {
+                source_offset = source_offset.checked_add(reader.read_relative()?).ok_or_else(out_of_range)?;
+                let start = usize::try_from(source_offset).map_err(|_| truncated())?;
+                let data = source.get(start..start + length).ok_or_else(truncated)?;
+                target.extend_from_slice(data);
+                source_offset = source_offset.checked_add(length as isize).ok_or_else(out_of_range)?;
+            }
+            _ => {
+                target_offset = target_offset.checked_add(reader.read_relative()?).ok_or_else(out_of_range)?;
+                // the copy can overlap what it's writing, which is how runs are encoded
+                for _ in 0..length {
+                    let start = usize::try_from(target_offset).map_err(|_| truncated())?;
+                    let byte = *target.get(start).ok_or_else(truncated)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(truncated());
+    }
+    check_crc(read_u32(&footer[4..8]), crc32(&target))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte | 0x80);
+                return bytes;
+            }
+            bytes.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn bps_patch(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_SIGNATURE.to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        patch.extend(actions);
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_apply_bps() {
+        let source = b"alpiNES!";
+        let target = b"alpiNES rocks rocks";
+        let mut actions = varint((7 - 1) << 2); // SourceRead "alpiNES"
+        actions.extend(varint((6 - 1) << 2 | 1)); // TargetRead " rocks"
+        actions.extend(b" rocks");
+        actions.extend(varint((6 - 1) << 2 | 3)); // TargetCopy " rocks"
+        actions.extend(varint(7 << 1));
+        let patch = bps_patch(source, target, &actions);
+        assert_eq!(apply_bps(source, &patch).unwrap(), target);
+
+        // made for a different dump
+        assert!(matches!(apply_bps(b"alpiNES?", &patch), Err(RomError::PatchChecksumMismatch { .. })));
+        let mut corrupt = patch.clone();
+        corrupt[10] ^= 1;
+        assert!(matches!(apply_bps(source, &corrupt), Err(RomError::PatchChecksumMismatch { .. })));
+        assert!(matches!(apply_bps(source, b"PATCH"), Err(RomError::InvalidPatch(_))));
+
+        // the target size is only checked once the actions have run, nothing is allocated for it
+        let mut huge = bps_patch(source, target, &actions);
+        let size_start = BPS_SIGNATURE.len() + varint(source.len()).len();
+        huge.splice(size_start..size_start + varint(target.len()).len(), varint(usize::MAX >> 8));
+        let crc_start = huge.len() - 4;
+        let crc = crc32(&huge[..crc_start]);
+        huge[crc_start..].copy_from_slice(&crc.to_le_bytes());
+        assert!(matches!(apply_bps(source, &huge), Err(RomError::InvalidPatch(_))));
+    }
+
+    #[test]
+    fn test_apply_bps_offset_overflow() {
+        let source = b"alpiNES!";
+        let huge_offset = varint((isize::MAX as usize) << 1);
+
+        // SourceCopy "a", then jump as far ahead as an offset goes
+        let mut actions = varint(2);
+        actions.extend(varint(0));
+        actions.extend(varint(2));
+        actions.extend(&huge_offset);
+        let patch = bps_patch(source, b"aa", &actions);
+        assert!(matches!(apply_bps(source, &patch), Err(RomError::InvalidPatch(_))));
+
+        // TargetRead "a", TargetCopy "a", then the same jump
+        let mut actions = varint(1);
+        actions.push(b'a');
+        actions.extend(varint(3));
+        actions.extend(varint(0));
+        actions.extend(varint(3));
+        actions.extend(&huge_offset);
+        let patch = bps_patch(source, b"aaa", &actions);
+        assert!(matches!(apply_bps(source, &patch), Err(RomError::InvalidPatch(_))));
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, 0x12345678] {
+            let bytes = varint(value);
+            let mut reader = PatchReader { patch: &bytes, offset: 0 };
+            assert_eq!(reader.read_varint().unwrap(), value);
+        }
+    }
+}
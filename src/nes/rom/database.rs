@@ -0,0 +1,85 @@
+use crate::nes::rom::{Mirroring, Timing};
+
+// Ref: https://nescartdb.com
+// Ref: https://www.nesdev.org/wiki/INES#Bad_iNES_headers
+
+// Plenty of dumps in circulation were made before iNES had a way to describe their board, or by
+// tools that got the header wrong. They're recognized by the CRC32 of their PRG ROM followed by
+// their CHR ROM (the header isn't included, as it's the part that's wrong), and whatever the
+// database knows replaces what the header says. Fields left as None are taken from the header.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GameOverride {
+    pub crc32: u32,
+    pub title: &'static str,
+    pub mapper_id: Option<u16>,
+    pub submapper: Option<u8>,
+    pub mirroring: Option<Mirroring>,
+    pub battery: Option<bool>,
+    pub timing: Option<Timing>,
+}
+
+pub const GAME_DATABASE: &[GameOverride] = &[
+    // commonly found with the mirroring bit cleared, which breaks the scrolling
+    GameOverride {
+        crc32: 0x3337_EC46,
+        title: "Super Mario Bros. (World)",
+        mapper_id: Some(0),
+        submapper: None,
+        mirroring: Some(Mirroring::Vertical),
+        battery: Some(false),
+        timing: Some(Timing::Ntsc),
+    },
+    // often dumped without the battery bit, so the save file is never written
+    GameOverride {
+        crc32: 0x3FE2_72FB,
+        title: "Legend of Zelda, The (USA)",
+        mapper_id: Some(1),
+        submapper: None,
+        mirroring: None,
+        battery: Some(true),
+        timing: Some(Timing::Ntsc),
+    },
+    // Camerica boards were dumped before mapper 71 was assigned, and still turn up as mapper 2
+    GameOverride {
+        crc32: 0xE62E_3382,
+        title: "MiG 29 - Soviet Fighter (USA) (Unl)",
+        mapper_id: Some(71),
+        submapper: None,
+        mirroring: None,
+        battery: Some(false),
+        timing: None,
+    },
+    // the only mapper 71 board with mirroring control, which an iNES 1.0 header has no way to say
+    GameOverride {
+        crc32: 0x1BC6_86A8,
+        title: "Fire Hawk (USA) (Unl)",
+        mapper_id: Some(71),
+        submapper: Some(1),
+        mirroring: None,
+        battery: Some(false),
+        timing: None,
+    },
+];
+
+pub fn find_override(crc32: u32) -> Option<&'static GameOverride> {
+    find_override_in(GAME_DATABASE, crc32)
+}
+
+pub fn find_override_in(database: &[GameOverride], crc32: u32) -> Option<&GameOverride> {
+    database.iter().find(|entry| entry.crc32 == crc32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_override() {
+        assert_eq!(find_override(0x3337_EC46).unwrap().mapper_id, Some(0));
+        assert_eq!(find_override(0), None);
+
+        let crc32s: Vec<u32> = GAME_DATABASE.iter().map(|entry| entry.crc32).collect();
+        assert!(crc32s.iter().all(|crc32| crc32s.iter().filter(|other| *other == crc32).count() == 1));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::nes::rom::RomError;
+
+// Ref: https://zerosoft.zophar.net/ips.php
+
+// IPS patch layout:
+// =====================================
+// Bytes 0-4:   "PATCH"
+// Bytes 5-:    [ offset (u24 BE) | size (u16 BE) | data ] ...          <- normal record
+//              [ offset (u24 BE) | 0x0000 | run length (u16 BE) | byte ]  <- RLE record
+//              "EOF"
+//              [ length (u24 BE) ]                                     <- optional, truncates the file
+//
+// Records are applied to the whole file, header included, and may write past the end of it.
+
+pub const IPS_SIGNATURE: [u8; 5] = *b"PATCH";
+const IPS_EOF: usize = 0x454F46; // "EOF"
+
+pub fn is_ips(patch: &[u8]) -> bool {
+    patch.starts_with(&IPS_SIGNATURE)
+}
+
+fn read_be(patch: &[u8], offset: usize, len: usize) -> Result<usize, RomError> {
+    let bytes = patch.get(offset..offset + len)
+        .ok_or_else(|| RomError::InvalidPatch(format!("IPS record at 0x{:x} is truncated", offset)))?;
+    Ok(bytes.iter().fold(0, |value, byte| value << 8 | *byte as usize))
+}
+
+pub fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomError> {
+    if !is_ips(patch) {
+        return Err(RomError::InvalidPatch(String::from("IPS patch doesn't start with PATCH")));
+    }
+    let mut target = source.to_vec();
+    let mut offset = IPS_SIGNATURE.len();
+    loop {
+        let address = read_be(patch, offset, 3)?;
+        offset += 3;
+        if address == IPS_EOF {
+            break;
+        }
+        let size = read_be(patch, offset, 2)?;
+        offset += 2;
+        if size == 0 {
+            let run_length = read_be(patch, offset, 2)?;
+            let value = read_be(patch, offset + 2, 1)? as u8;
+            offset += 3;
+            if target.len() < address + run_length {
+                target.resize(address + run_length, 0);
+            }
+            target[address..address + run_length].fill(value);
+        } else {
+            let data = patch.get(offset..offset + size)
+                .ok_or_else(|| RomError::InvalidPatch(format!("IPS record at 0x{:x} is truncated", offset)))?;
+            offset += size;
+            if target.len() < address + size {
+                target.resize(address + size, 0);
+            }
+            target[address..address + size].copy_from_slice(data);
+        }
+    }
+    // the truncation extension, some patchers use it to shrink the file back down
+    if let Ok(length) = read_be(patch, offset, 3) {
+        target.truncate(length);
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_ips() {
+        let mut patch = IPS_SIGNATURE.to_vec();
+        patch.extend([0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend([0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+        patch.extend(b"EOF");
+        assert_eq!(apply_ips(&[0; 8], &patch).unwrap(), vec![0, 0, 0xAA, 0xBB, 0, 0, 0xCC, 0xCC, 0xCC, 0xCC]);
+
+        patch.extend([0x00, 0x00, 0x03]);
+        assert_eq!(apply_ips(&[0; 8], &patch).unwrap(), vec![0, 0, 0xAA]);
+    }
+
+    #[test]
+    fn test_invalid_ips() {
+        assert!(matches!(apply_ips(&[0; 8], b"PATCQ"), Err(RomError::InvalidPatch(_))));
+        // missing EOF
+        assert!(matches!(apply_ips(&[0; 8], b"PATCH\x00\x00\x01\x00\x04\xAA"), Err(RomError::InvalidPatch(_))));
+    }
+}
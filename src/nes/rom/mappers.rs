@@ -4,4 +4,172 @@ pub mod mapper1;
 pub mod mapper2;
 pub mod mapper3;
 pub mod mapper4;
+pub mod mapper5;
+pub mod mapper9;
+pub mod mapper20;
 pub mod mapper66;
+pub mod mapper71;
+pub mod mapper232;
+
+use crate::nes::rom::{Mirroring, ROM};
+use crate::nes::rom::mappers::mapper::Mapper;
+use crate::nes::rom::mappers::mapper0::Mapper0;
+use crate::nes::rom::mappers::mapper1::Mapper1;
+use crate::nes::rom::mappers::mapper2::Mapper2;
+use crate::nes::rom::mappers::mapper3::Mapper3;
+use crate::nes::rom::mappers::mapper4::Mapper4;
+use crate::nes::rom::mappers::mapper5::Mapper5;
+use crate::nes::rom::mappers::mapper9::Mapper9;
+use crate::nes::rom::mappers::mapper66::Mapper66;
+use crate::nes::rom::mappers::mapper71::Mapper71;
+use crate::nes::rom::mappers::mapper232::Mapper232;
+
+// Ref: https://www.nesdev.org/wiki/Mapper
+
+// Mappers alpiNES has an implementation for. FDS (20) isn't listed, disk images are loaded with
+// ROM::from_fds and never go through the iNES mapper number.
+pub const SUPPORTED_MAPPERS: [u16; 10] = [0, 1, 2, 3, 4, 5, 9, 66, 71, 232];
+
+pub fn supported_mappers() -> &'static [u16] {
+    &SUPPORTED_MAPPERS
+}
+
+pub fn is_supported(mapper_id: u16) -> bool {
+    SUPPORTED_MAPPERS.contains(&mapper_id)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UnsupportedMapper(pub u16);
+
+// The only place that goes from a mapper number to its implementation, so a new mapper needs an
+// arm here and an entry in SUPPORTED_MAPPERS. The board is set up from the header (and database)
+// fields it cares about.
+pub fn create_mapper(rom: &ROM) -> Result<Box<dyn Mapper>, UnsupportedMapper> {
+    let mapper: Box<dyn Mapper> = match rom.mapper_id {
+        0 => Box::new(Mapper0::new()),
+        1 => {
+            let mut mapper1 = Mapper1::new();
+            mapper1.submapper = rom.submapper;
+            mapper1.prg_ram_size = rom.prg_ram_size + rom.prg_nvram_size;
+            Box::new(mapper1)
+        },
+        // NES 2.0 submapper 1 marks UxROM and CNROM boards without bus conflicts, 0 (unknown) is
+        // taken to have them, like Nintendo's own boards
+        2 => {
+            let mut mapper2 = Mapper2::new();
+            mapper2.bus_conflicts = rom.submapper != 1;
+            Box::new(mapper2)
+        },
+        3 => {
+            let mut mapper3 = Mapper3::new();
+            mapper3.bus_conflicts = rom.submapper != 1;
+            Box::new(mapper3)
+        },
+        4 => {
+            let mut mapper4 = Mapper4::new();
+            mapper4.submapper = rom.submapper;
+            mapper4.four_screen = rom.screen_mirroring == Mirroring::FourScreen;
+            Box::new(mapper4)
+        },
+        5 => Box::new(Mapper5::new()),
+        9 => Box::new(Mapper9::new()),
+        66 => Box::new(Mapper66::new()),
+        // only Fire Hawk's board (submapper 1) lets the game pick the nametable
+        71 => {
+            let mut mapper71 = Mapper71::new();
+            mapper71.mirroring_control = rom.submapper == 1;
+            Box::new(mapper71)
+        },
+        232 => Box::new(Mapper232::new()),
+        _ => return Err(UnsupportedMapper(rom.mapper_id)),
+    };
+    Ok(mapper)
+}
+
+// Common name of the board (or chip) behind an iNES mapper number, ex: for a ROM picker. Covers
+// the supported mappers plus the ones most often found in the wild.
+pub fn mapper_name(mapper_id: u16) -> &'static str {
+    match mapper_id {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        13 => "CPROM",
+        19 => "Namco 163",
+        20 => "FDS",
+        21 | 22 | 23 | 25 => "VRC2/VRC4",
+        24 | 26 => "VRC6",
+        34 => "BNROM/NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica",
+        79 => "NINA-03/06",
+        85 => "VRC7",
+        206 => "Namco 118",
+        232 => "Camerica Quattro",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Banks filled with their own number, so a read tells which bank is mapped there.
+    pub(crate) fn numbered_banks(bank_count: usize, bank_size: usize) -> Vec<u8> {
+        (0..bank_count).flat_map(|bank| vec![bank as u8; bank_size]).collect()
+    }
+
+    #[test]
+    fn test_mapper_registry() {
+        assert_eq!(mapper_name(4), "MMC3");
+        assert_eq!(mapper_name(0xFFF), "Unknown");
+        for mapper_id in supported_mappers() {
+            assert_ne!(mapper_name(*mapper_id), "Unknown");
+            assert!(is_supported(*mapper_id));
+        }
+        assert!(!is_supported(7));
+        assert!(!is_supported(20));
+    }
+
+    #[test]
+    fn test_create_mapper() {
+        let mut rom = ROM::new();
+        for mapper_id in supported_mappers() {
+            rom.mapper_id = *mapper_id;
+            assert!(create_mapper(&rom).is_ok(), "mapper {}", mapper_id);
+        }
+        rom.mapper_id = 7;
+        assert_eq!(create_mapper(&rom).err(), Some(UnsupportedMapper(7)));
+
+        rom.mapper_id = 2;
+        rom.submapper = 1;
+        let mapper = create_mapper(&rom).unwrap();
+        assert!(!mapper.as_any().downcast_ref::<Mapper2>().unwrap().bus_conflicts);
+    }
+
+    #[test]
+    fn test_mapper_state() {
+        let mut rom = ROM::new();
+        rom.mapper_id = 4;
+        let mut mapper = create_mapper(&rom).unwrap();
+        mapper.write_mapper(0x8000, 0b0100_0110);
+        mapper.write_mapper(0x8001, 9);
+        let state = mapper.save_state();
+
+        let mut restored = create_mapper(&rom).unwrap();
+        restored.load_state(&state).unwrap();
+        let mapper4 = restored.as_any().downcast_ref::<Mapper4>().unwrap();
+        assert_eq!((mapper4.prg_bank_select_mode, mapper4.prg_bank0_select), (1, 9));
+
+        // a different board can't take it
+        rom.mapper_id = 2;
+        assert!(create_mapper(&rom).unwrap().load_state(&state).is_err());
+    }
+}
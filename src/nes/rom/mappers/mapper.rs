@@ -1,7 +1,131 @@
-pub trait Mapper {
-    fn read_prg_byte(&mut self, address: u16, prg_rom: &Vec<u8>) -> u8;
+use std::any::Any;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::nes::rom::Mirroring;
+
+// What the board does to the PRG RAM at $6000-$7FFF, for boards with control over it (ie. MMC1,
+// MMC3). The bank only matters on boards with more than 8kB of it (SOROM, SXROM).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PrgRamControl {
+    pub enabled: bool,
+    pub write_protect: bool,
+    pub bank: usize,
+}
+
+// Everything the rest of the emulator asks of a cartridge board. Only the memory reads and the
+// register writes are required, the hooks default to a board that ignores them.
+pub trait Mapper: MapperBase {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8;
 
     fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8;
 
     fn write_mapper(&mut self, address: u16, data: u8);
-}
\ No newline at end of file
+
+    // A CPU write to $8000-$FFFF. Boards with bus conflicts also see the PRG ROM byte being read.
+    fn write_prg_byte(&mut self, address: u16, data: u8, _prg_rom: &Vec<u8>) {
+        self.write_mapper(address, data);
+    }
+
+    // Only called on CHR RAM carts. Boards that bank their CHR RAM route writes through the same
+    // banks as reads, otherwise tiles written through one bank would show up in another.
+    fn write_chr_byte(&mut self, address: u16, data: u8, chr_ram: &mut Vec<u8>) {
+        let chr_ram_size = chr_ram.len();
+        chr_ram[address as usize % chr_ram_size] = data;
+    }
+
+    // None leaves the nametable layout to the cartridge header (hardwired by solder pads)
+    fn get_mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    // Only boards that pick the layout at runtime keep it, the rest stay hardwired.
+    fn set_mirroring(&mut self, _mirroring: Mirroring) {}
+
+    // Boards that put their own registers in the expansion area ($4020-$5FFF), ie. the MMC5 and the
+    // FDS. Everywhere else it's backed by plain RAM.
+    fn has_expansion(&self) -> bool {
+        false
+    }
+
+    // None for addresses the board doesn't answer, which read as open bus.
+    fn read_expansion(&mut self, _address: u16, _ppu_dot: u64) -> Option<u8> {
+        None
+    }
+
+    fn write_expansion(&mut self, _address: u16, _data: u8) {}
+
+    // Nametable fetches ($2000-$2FFF), for boards that supply their own (ie. the MMC5). None falls
+    // back to the console's 2kB of VRAM, laid out by get_mirroring.
+    fn read_nametable_byte(&self, _address: u16, _ciram: &[u8]) -> Option<u8> {
+        None
+    }
+
+    // False when the board left the write to the console's VRAM.
+    fn write_nametable_byte(&mut self, _address: u16, _data: u8, _ciram: &mut [u8]) -> bool {
+        false
+    }
+
+    // None leaves the PRG RAM always enabled and writable.
+    fn get_prg_ram_control(&self) -> Option<PrgRamControl> {
+        None
+    }
+
+    // The CPU cycle of the write about to happen, for boards that time writes (ie. MMC1).
+    fn set_cpu_cycle(&mut self, _cycle: usize) {}
+
+    // CPU cycles as they go by, for boards that count them (ie. the FDS timer and disk drive).
+    fn tick(&mut self, _cycles: u8) {}
+
+    // Every address the PPU fetches from, for boards that count scanlines or switch banks by them.
+    fn watch_ppu_bus(&mut self, _address: u16, _ppu_dot: u64) {}
+
+    // Every write to PPUCTRL, for boards that snoop the sprite size.
+    fn watch_ppu_ctrl(&mut self, _value: u8) {}
+
+    fn poll_irq(&self) -> bool {
+        false
+    }
+}
+
+// What every board gets for free from its derives: cloning the ROM, savestates, and getting the
+// concrete board back for controls only it has (ie. swapping FDS disks).
+pub trait MapperBase {
+    fn clone_box(&self) -> Box<dyn Mapper>;
+
+    fn save_state(&self) -> Vec<u8>;
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), serde_cbor::Error>;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Mapper + Clone + Serialize + DeserializeOwned + 'static> MapperBase for T {
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        serde_cbor::to_vec(self).expect("unable to serialize mapper state")
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), serde_cbor::Error> {
+        *self = serde_cbor::from_slice(state)?;
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn Mapper> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
@@ -1,6 +1,7 @@
+use serde::{Serialize, Deserialize};
 use crate::nes::rom::mappers::mapper::Mapper;
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mapper0 { }
 
 impl Mapper0 {
@@ -10,7 +11,7 @@ impl Mapper0 {
 }
 
 impl Mapper for Mapper0 {
-    fn read_prg_byte(&mut self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
         prg_rom[(address - 0x8000) as usize]
     }
 
@@ -1,7 +1,10 @@
-use crate::nes::rom::mappers::mapper::Mapper;
+use serde::{Serialize, Deserialize};
+use crate::nes::rom::mappers::mapper::{Mapper, PrgRamControl};
 use crate::nes::rom::registers::shift::ShiftRegister;
 use crate::nes::rom::{Mirroring, ROM};
 
+// Ref: https://www.nesdev.org/wiki/MMC1
+
 macro_rules! prg_bank0_range { () => {0x8000..=0xBFFF} }
 macro_rules! prg_bank1_range { () => {0xC000..=0xFFFF} }
 
@@ -13,162 +16,310 @@ macro_rules! mapper1_chr0_range { () => {0xA000..=0xBFFF} }
 macro_rules! mapper1_chr1_range { () => {0xC000..=0xDFFF} }
 macro_rules! mapper1_prg_range { () => {0xE000..=0xFFFF} }
 
-#[derive(Clone)]
+const CHR_BANK_SIZE: usize = ROM::CHR_ROM_PAGE_SIZE / 2; // 4kB
+const SUROM_PRG_ROM_SIZE: usize = 0x80000; // 512kB
+const SOROM_PRG_RAM_SIZE: usize = 0x4000; // 16kB
+const SXROM_PRG_RAM_SIZE: usize = 0x8000; // 32kB
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mapper1 {
     pub shift_register: ShiftRegister,
     pub prg_bank_select_mode: u8,
     pub chr_bank_select_mode: u8,
     pub prg_bank_select: u8,
-    pub chr_bank_select: u8,
     pub chr_bank0_select: u8,
     pub chr_bank1_select: u8,
+    pub prg_ram_disable: bool,
     pub screen_mirroring: Mirroring,
+    pub submapper: u8,
+    pub prg_ram_size: usize,
+    pub cpu_cycle: usize,
+    last_write_cycle: Option<usize>,
 }
 
 impl Mapper1 {
+    // Ref: https://www.nesdev.org/wiki/MMC1#Variants
+    // SEROM, SHROM and SH1ROM boards don't connect the PRG bank lines, so their 32 KB of PRG ROM is
+    // always mapped in, whatever bank the game selects.
+    pub const SUBMAPPER_FIXED_PRG: u8 = 5;
+
     pub fn new() -> Self {
         Mapper1 {
             shift_register: ShiftRegister::new(),
             prg_bank_select_mode: 3,
             chr_bank_select_mode: 0,
             prg_bank_select: 0,
-            chr_bank_select: 0,
             chr_bank0_select: 0,
             chr_bank1_select: 0,
+            prg_ram_disable: false,
             screen_mirroring: Mirroring::Horizontal,
+            submapper: 0,
+            prg_ram_size: 0,
+            cpu_cycle: 0,
+            last_write_cycle: None,
+        }
+    }
+
+    // SUROM and SXROM have 512 KB of PRG ROM, twice what the 4 bit bank number reaches. They take
+    // PRG A18 from bit 4 of the CHR bank, which is otherwise unused with 8 KB of CHR RAM.
+    // todo: in 4 KB CHR mode the bit comes from whichever CHR bank the PPU last fetched from
+    fn get_prg_outer_bank(&self, prg_rom: &[u8]) -> usize {
+        if prg_rom.len() >= SUROM_PRG_ROM_SIZE { (self.chr_bank0_select & 0b1_0000) as usize } else { 0 }
+    }
+
+    // SOROM and SXROM bank their 16 KB and 32 KB of PRG RAM at $6000 with the CHR bank bits below
+    // the PRG A18 line.
+    pub fn get_prg_ram_bank(&self) -> usize {
+        match self.prg_ram_size {
+            SOROM_PRG_RAM_SIZE => ((self.chr_bank0_select >> 3) & 0b01) as usize,
+            SXROM_PRG_RAM_SIZE => ((self.chr_bank0_select >> 2) & 0b11) as usize,
+            _ => 0,
         }
     }
+
+    // MMC1B and later: bit 4 of the PRG bank register turns PRG RAM off.
+    pub fn is_prg_ram_enabled(&self) -> bool {
+        !self.prg_ram_disable
+    }
+
+    // Where the PPU address lands in CHR memory, before wrapping to its size.
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = match (self.chr_bank_select_mode, address) {
+            // switch 8 KB at a time, ignoring low bit of bank number
+            (0, chr_bank0_range!()) => self.chr_bank0_select & !1,
+            (0, chr_bank1_range!()) => self.chr_bank0_select | 1,
+            // switch two separate 4 KB banks
+            (_, chr_bank0_range!()) => self.chr_bank0_select,
+            (_, chr_bank1_range!()) => self.chr_bank1_select,
+            _ => panic!("Address out of range on mapper 1: {}", address),
+        };
+        let bank_start = CHR_BANK_SIZE * bank as usize;
+        bank_start + (address as usize & 0x0FFF)
+    }
+
+    fn write_control(&mut self, value: u8) {
+        // 4bit0
+        // -----
+        // CPPMM
+        // |||||
+        // |||++- Mirroring (0: one-screen, lower bank; 1: one-screen, upper bank;
+        // |||               2: vertical; 3: horizontal)
+        // |++--- PRG ROM bank mode (0, 1: switch 32 KB at $8000, ignoring low bit of bank number;
+        // |                         2: fix first bank at $8000 and switch 16 KB bank at $C000;
+        // |                         3: fix last bank at $C000 and switch 16 KB bank at $8000)
+        // +----- CHR ROM bank mode (0: switch 8 KB at a time; 1: switch two separate 4 KB banks)
+        self.screen_mirroring = match value & 0b0000_0011 {
+            0 => Mirroring::OneScreenLower,
+            1 => Mirroring::OneScreenUpper,
+            2 => Mirroring::Vertical,
+            3 => Mirroring::Horizontal,
+            _ => panic!("can't be")
+        };
+        self.prg_bank_select_mode = (value & 0b0000_1100) >> 2;
+        self.chr_bank_select_mode = (value & 0b0001_0000) >> 4;
+    }
 }
 
 impl Mapper for Mapper1 {
-    fn read_prg_byte(&mut self, address: u16, prg_rom: &Vec<u8>) -> u8 {
-        match self.prg_bank_select_mode {
-            0 | 1 => {
-                // switch 32 KB at $8000, ignoring low bit of bank number
-                let prg_bank_select = self.prg_bank_select & 0b1111_1110;
-                let bank_start = 2 * ROM::PRG_ROM_PAGE_SIZE * prg_bank_select as usize;
-                prg_rom[(bank_start + (address - 0x8000) as usize) % prg_rom.len()]
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        if self.submapper == Mapper1::SUBMAPPER_FIXED_PRG {
+            return prg_rom[(address - 0x8000) as usize % prg_rom.len()];
+        }
+        let outer_bank = self.get_prg_outer_bank(prg_rom);
+        let bank_select = (self.prg_bank_select & 0b0000_1111) as usize;
+        let bank = match (self.prg_bank_select_mode, address) {
+            // switch 32 KB at $8000, ignoring low bit of bank number
+            (0 | 1, prg_bank0_range!()) => bank_select & !1,
+            (0 | 1, prg_bank1_range!()) => bank_select | 1,
+            // fix first bank at $8000 and switch 16 KB bank at $C000
+            (2, prg_bank0_range!()) => 0,
+            (2, prg_bank1_range!()) => bank_select,
+            // fix last bank at $C000 and switch 16 KB bank at $8000
+            (3, prg_bank0_range!()) => bank_select,
+            (3, prg_bank1_range!()) => 0b0000_1111,
+            _ => panic!("Address out of range on mapper 1: {}", address),
+        };
+        let bank_start = ROM::PRG_ROM_PAGE_SIZE * (outer_bank | bank);
+        prg_rom[(bank_start + (address as usize & 0x3FFF)) % prg_rom.len()]
+    }
+
+    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+        chr_rom[self.chr_offset(address) % chr_rom.len()]
+    }
+
+    fn write_chr_byte(&mut self, address: u16, data: u8, chr_ram: &mut Vec<u8>) {
+        let chr_ram_size = chr_ram.len();
+        chr_ram[self.chr_offset(address) % chr_ram_size] = data;
+    }
+
+    fn write_mapper(&mut self, address: u16, data: u8) {
+        // The MMC1 ignores a write on the cycle right after another one, which is how it skips the dummy
+        // write of a read-modify-write instruction (ex: INC $8000). The CPU doesn't emulate that double
+        // write yet (see CPU::asl_ab), so this only takes effect once it does.
+        let is_consecutive = self.last_write_cycle.is_some_and(|cycle| self.cpu_cycle == cycle + 1);
+        self.last_write_cycle = Some(self.cpu_cycle);
+        if is_consecutive {
+            return;
+        }
+
+        if data & 0b1000_0000 != 0 {
+            // reset the shift register and fix the last bank at $C000
+            self.shift_register.clear();
+            self.prg_bank_select_mode = 3;
+            return;
+        }
+        let Some(value) = self.shift_register.write(data) else {
+            return;
+        };
+        match address {
+            mapper1_control_range!() => {
+                self.write_control(value);
             },
-            2 => {
-                // fix first bank at $8000 and switch 16 KB bank at $C000
-                match address {
-                    prg_bank0_range!() => {
-                        prg_rom[(address as usize - 0x8000) % prg_rom.len()]
-                    },
-                    prg_bank1_range!() => {
-                        let bank_start = ROM::PRG_ROM_PAGE_SIZE * self.prg_bank_select as usize;
-                        prg_rom[(bank_start + (address - 0xC000) as usize) % prg_rom.len()]
-                    },
-                    _ => {
-                        panic!("Address out of range on mapper 1: {}", address);
-                    }
-                }
+            mapper1_chr0_range!() => {
+                // 4bit0
+                // -----
+                // CCCCC
+                // |||||
+                // +++++- Select 4 KB or 8 KB CHR bank at PPU $0000 (low bit ignored in 8 KB mode)
+                //        SUROM/SXROM: bit 4 selects the 256 KB PRG ROM bank
+                //        SOROM/SXROM: bits 2-3 select the 8 KB PRG RAM bank
+                self.chr_bank0_select = value;
             },
-            3 => {
-                // fix last bank at $C000 and switch 16 KB bank at $8000
-                match address {
-                    prg_bank0_range!() => {
-                        let bank_start = ROM::PRG_ROM_PAGE_SIZE * self.prg_bank_select as usize;
-                        prg_rom[(bank_start + (address - 0x8000) as usize) % prg_rom.len()]
-                    },
-                    prg_bank1_range!() => {
-                        let last_bank_start = prg_rom.len() - ROM::PRG_ROM_PAGE_SIZE;
-                        prg_rom[last_bank_start + (address - 0xC000) as usize]
-                    },
-                    _ => {
-                        panic!("Address out of range on mapper 1: {}", address);
-                    }
-                }
+            mapper1_chr1_range!() => {
+                // 4bit0
+                // -----
+                // CCCCC
+                // |||||
+                // +++++- Select 4 KB CHR bank at PPU $1000 (ignored in 8 KB mode)
+                self.chr_bank1_select = value;
             },
-            _ => panic!("can't be")
+            mapper1_prg_range!() => {
+                // 4bit0
+                // -----
+                // RPPPP
+                // |||||
+                // |++++- Select 16 KB PRG ROM bank (low bit ignored in 32 KB mode)
+                // +----- MMC1B and later: PRG RAM chip enable (0: enabled; 1: disabled; ignored on MMC1A)
+                //        MMC1A: Bit 3 bypasses fixed bank logic in 16K mode (0: affected; 1: bypassed)
+                self.prg_bank_select = value & 0b0000_1111;
+                self.prg_ram_disable = value & 0b0001_0000 != 0;
+            },
+            _ => {
+                panic!("Address out of range on mapper 1: {}", address);
+            }
         }
     }
 
-    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
-        if self.chr_bank_select_mode == 0 {
-            // switch 8 KB at a time
-            let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 2) * self.chr_bank_select as usize;
-            chr_rom[(bank_start + address as usize) % chr_rom.len()]
-        } else {
-            // switch two separate 4 KB banks
-            match address {
-                chr_bank0_range!() => {
-                    let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 2) * self.chr_bank0_select as usize;
-                    chr_rom[(bank_start + address as usize) % chr_rom.len()]
-                },
-                chr_bank1_range!() => {
-                    let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 2) * self.chr_bank1_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x1000) % chr_rom.len()]
-                },
-                _ => {
-                    panic!("Address out of range on mapper 1: {}", address);
-                }
-            }
+    fn get_mirroring(&self) -> Option<Mirroring> {
+        Some(self.screen_mirroring)
+    }
+
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.screen_mirroring = mirroring;
+    }
+
+    fn get_prg_ram_control(&self) -> Option<PrgRamControl> {
+        Some(PrgRamControl { enabled: self.is_prg_ram_enabled(), write_protect: false, bank: self.get_prg_ram_bank() })
+    }
+
+    fn set_cpu_cycle(&mut self, cycle: usize) {
+        self.cpu_cycle = cycle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::rom::mappers::tests::numbered_banks;
+
+    // Writes a 5 bit value to a register, one bit per write, on cycles far enough apart to count.
+    fn write_serial(mapper: &mut Mapper1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.cpu_cycle += 4;
+            mapper.write_mapper(address, (value >> bit) & 1);
         }
     }
 
-    fn write_mapper(&mut self, address: u16, data: u8) {
-        self.shift_register.write(data);
-        if self.shift_register.is_fifth_write() {
-            let value = self.shift_register.value;
-            match address {
-                mapper1_control_range!() => {
-                    // 4bit0
-                    // -----
-                    // CPPMM
-                    // |||||
-                    // |||++- Mirroring (0: one-screen, lower bank; 1: one-screen, upper bank;
-                    // |||               2: vertical; 3: horizontal)
-                    // |++--- PRG ROM bank mode (0, 1: switch 32 KB at $8000, ignoring low bit of bank number;
-                    // |                         2: fix first bank at $8000 and switch 16 KB bank at $C000;
-                    // |                         3: fix last bank at $C000 and switch 16 KB bank at $8000)
-                    // +----- CHR ROM bank mode (0: switch 8 KB at a time; 1: switch two separate 4 KB banks)
-                    self.screen_mirroring = match value & 0b0000_0011 {
-                        0 => Mirroring::OneScreenLower,
-                        1 => Mirroring::OneScreenUpper,
-                        2 => Mirroring::Vertical,
-                        3 => Mirroring::Horizontal,
-                        _ => panic!("can't be")
-                    };
-                    self.prg_bank_select_mode = (value & 0b0000_1100) >> 2;
-                    self.chr_bank_select_mode = (value & 0b0001_0000) >> 4;
-                },
-                mapper1_chr0_range!() => {
-                    // 4bit0
-                    // -----
-                    // CCCCC
-                    // |||||
-                    // +++++- Select 4 KB or 8 KB CHR bank at PPU $0000 (low bit ignored in 8 KB mode)
-                    if self.chr_bank_select_mode == 1 { // todo: use enum
-                        self.chr_bank0_select = value;
-                    } else {
-                        self.chr_bank_select = value;
-                    }
-                },
-                mapper1_chr1_range!() => {
-                    // 4bit0
-                    // -----
-                    // CCCCC
-                    // |||||
-                    // +++++- Select 4 KB CHR bank at PPU $1000 (ignored in 8 KB mode)
-                    if self.chr_bank_select_mode == 1 { // todo: use enum
-                        self.chr_bank1_select = value;
-                    }
-                },
-                mapper1_prg_range!() => {
-                    // 4bit0
-                    // -----
-                    // RPPPP
-                    // |||||
-                    // |++++- Select 16 KB PRG ROM bank (low bit ignored in 32 KB mode)
-                    // +----- MMC1B and later: PRG RAM chip enable (0: enabled; 1: disabled; ignored on MMC1A)
-                    //        MMC1A: Bit 3 bypasses fixed bank logic in 16K mode (0: affected; 1: bypassed)
-                    self.prg_bank_select = value & 0b0000_1111;
-                },
-                _ => {
-                    panic!("Address out of range on mapper 1: {}", address);
-                }
-            }
+    #[test]
+    fn test_prg_bank_modes() {
+        let prg_rom = numbered_banks(16, ROM::PRG_ROM_PAGE_SIZE);
+        let mut mapper = Mapper1::new();
+        write_serial(&mut mapper, 0xE000, 5);
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xC000, &prg_rom)), (5, 15));
+
+        write_serial(&mut mapper, 0x8000, 0b0_10_00);
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xC000, &prg_rom)), (0, 5));
+
+        write_serial(&mut mapper, 0x8000, 0b0_00_00);
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xFFFF, &prg_rom)), (4, 5));
+    }
+
+    #[test]
+    fn test_chr_bank_modes() {
+        let chr_rom = numbered_banks(32, CHR_BANK_SIZE);
+        let mut mapper = Mapper1::new();
+        // the CHR registers are kept whatever the mode, so switching modes doesn't lose them
+        write_serial(&mut mapper, 0xA000, 7);
+        write_serial(&mut mapper, 0xC000, 12);
+        assert_eq!((mapper.read_chr_byte(0x0000, &chr_rom), mapper.read_chr_byte(0x1FFF, &chr_rom)), (6, 7));
+
+        write_serial(&mut mapper, 0x8000, 0b1_11_00);
+        assert_eq!((mapper.read_chr_byte(0x0000, &chr_rom), mapper.read_chr_byte(0x1FFF, &chr_rom)), (7, 12));
+    }
+
+    #[test]
+    fn test_chr_ram_writes() {
+        let mut chr_ram = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        let mut mapper = Mapper1::new();
+        // both 4 KB windows on the upper half of the 8 KB of CHR RAM
+        write_serial(&mut mapper, 0x8000, 0b1_11_00);
+        write_serial(&mut mapper, 0xA000, 1);
+        write_serial(&mut mapper, 0xC000, 1);
+        mapper.write_chr_byte(0x0010, 0xAB, &mut chr_ram);
+        assert_eq!(chr_ram[0x1010], 0xAB);
+        assert_eq!(mapper.read_chr_byte(0x1010, &chr_ram), 0xAB);
+    }
+
+    #[test]
+    fn test_reset_and_consecutive_writes() {
+        let prg_rom = numbered_banks(16, ROM::PRG_ROM_PAGE_SIZE);
+        let mut mapper = Mapper1::new();
+        write_serial(&mut mapper, 0x8000, 0b0_10_10);
+        mapper.write_mapper(0xE000, 1);
+        mapper.cpu_cycle += 4;
+        mapper.write_mapper(0xE000, 0x80);
+        assert_eq!(mapper.shift_register.shift, 0);
+        assert_eq!(mapper.prg_bank_select_mode, 3);
+        assert_eq!(mapper.get_mirroring(), Some(Mirroring::Vertical));
+
+        // the second write of a read-modify-write lands on the next cycle and is dropped
+        mapper.cpu_cycle += 4;
+        mapper.write_mapper(0xE000, 1);
+        mapper.cpu_cycle += 1;
+        mapper.write_mapper(0xE000, 1);
+        assert_eq!(mapper.shift_register.shift, 1);
+        for _ in 0..4 {
+            mapper.cpu_cycle += 4;
+            mapper.write_mapper(0xE000, 0);
         }
+        assert_eq!(mapper.read_prg_byte(0x8000, &prg_rom), 1);
+    }
+
+    #[test]
+    fn test_surom_and_sxrom_banking() {
+        let prg_rom = numbered_banks(32, ROM::PRG_ROM_PAGE_SIZE);
+        let mut mapper = Mapper1::new();
+        mapper.prg_ram_size = SXROM_PRG_RAM_SIZE;
+        write_serial(&mut mapper, 0xE000, 2);
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xC000, &prg_rom)), (2, 15));
+
+        write_serial(&mut mapper, 0xA000, 0b1_10_00);
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xC000, &prg_rom)), (18, 31));
+        assert_eq!(mapper.get_prg_ram_bank(), 2);
+
+        mapper.prg_ram_size = SOROM_PRG_RAM_SIZE;
+        assert_eq!(mapper.get_prg_ram_bank(), 1);
+        write_serial(&mut mapper, 0xE000, 0b1_0000);
+        assert!(!mapper.is_prg_ram_enabled());
     }
 }
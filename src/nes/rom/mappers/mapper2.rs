@@ -1,37 +1,44 @@
+use serde::{Serialize, Deserialize};
 use crate::nes::rom::mappers::mapper::Mapper;
 use crate::nes::rom::ROM;
 
 macro_rules! prg_bank0_range { () => {0x8000..=0xBFFF} }
 macro_rules! prg_bank1_range { () => {0xC000..=0xFFFF} }
 
-#[derive(Clone)]
+// Ref: https://www.nesdev.org/wiki/Bus_conflict
+// The bank register sits on the same bus as the PRG ROM, which keeps driving it while the CPU
+// writes. Where the two disagree 0 wins, so the register gets the AND of the value and the ROM byte.
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mapper2 {
     pub prg_bank_select: u8,
+    pub bus_conflicts: bool,
 }
 
 impl Mapper2 {
     pub fn new() -> Self {
         Mapper2 {
-            prg_bank_select: 0
+            prg_bank_select: 0,
+            bus_conflicts: true,
         }
     }
 }
 
+// UxROM banking, shared with the Camerica boards (mappers 71 and 232): a switchable 16kB bank at
+// $8000 and a fixed one at $C000.
+pub fn read_uxrom_byte(address: u16, switchable_bank: usize, fixed_bank: usize, prg_rom: &[u8]) -> u8 {
+    let bank = match address {
+        prg_bank0_range!() => switchable_bank,
+        prg_bank1_range!() => fixed_bank,
+        _ => panic!("Address out of range on UxROM: {}", address),
+    };
+    prg_rom[(ROM::PRG_ROM_PAGE_SIZE * bank + (address as usize & 0x3FFF)) % prg_rom.len()]
+}
+
 impl Mapper for Mapper2 {
-    fn read_prg_byte(&mut self, address: u16, prg_rom: &Vec<u8>) -> u8 {
-        match address {
-            prg_bank0_range!() => {
-                let bank_start = ROM::PRG_ROM_PAGE_SIZE * self.prg_bank_select as usize;
-                prg_rom[(bank_start + (address - 0x8000) as usize) % prg_rom.len()]
-            },
-            prg_bank1_range!() => {
-                let last_bank_start = prg_rom.len() - ROM::PRG_ROM_PAGE_SIZE;
-                prg_rom[last_bank_start + (address - 0xC000) as usize]
-            },
-            _ => {
-                panic!("Address out of range on mapper 2: {}", address);
-            }
-        }
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        let last_bank = prg_rom.len() / ROM::PRG_ROM_PAGE_SIZE - 1;
+        read_uxrom_byte(address, self.prg_bank_select as usize, last_bank, prg_rom)
     }
 
     fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
@@ -41,4 +48,27 @@ impl Mapper for Mapper2 {
     fn write_mapper(&mut self, _address: u16, data: u8) {
         self.prg_bank_select = data & 0b0000_1111;
     }
+
+    fn write_prg_byte(&mut self, address: u16, data: u8, prg_rom: &Vec<u8>) {
+        let data = if self.bus_conflicts { data & self.read_prg_byte(address, prg_rom) } else { data };
+        self.write_mapper(address, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bus_conflicts() {
+        let mut prg_rom = vec![0; 4 * ROM::PRG_ROM_PAGE_SIZE];
+        prg_rom[0] = 0b0000_0110;
+        let mut mapper = Mapper2::new();
+        mapper.write_prg_byte(0x8000, 0b0000_0011, &prg_rom);
+        assert_eq!(mapper.prg_bank_select, 0b0000_0010);
+
+        mapper.bus_conflicts = false;
+        mapper.write_prg_byte(0x8000, 0b0000_0011, &prg_rom);
+        assert_eq!(mapper.prg_bank_select, 0b0000_0011);
+    }
 }
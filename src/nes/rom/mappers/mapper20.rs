@@ -0,0 +1,470 @@
+use serde::{Serialize, Deserialize};
+use crate::nes::rom::Mirroring;
+use crate::nes::rom::mappers::mapper::Mapper;
+
+// Ref: https://www.nesdev.org/wiki/Family_Computer_Disk_System
+// Ref: https://www.nesdev.org/wiki/FDS_disk_format
+
+// Famicom Disk System memory map:
+// =====================================
+// $4020-$4026: timer IRQ, disk drive and mirroring control (write)
+// $4030-$4033: disk drive status and read data (read)
+// $4040-$4092: wavetable audio (registers only, see below)
+// $6000-$7FFF: PRG RAM (the console side, see Memory)
+// $8000-$DFFF: PRG RAM (kept here)
+// $E000-$FFFF: BIOS ROM (prg_rom)
+// PPU $0000-$1FFF: CHR RAM
+//
+// The drive streams the disk one byte at a time, raising an IRQ as each one arrives. A .fds image
+// only holds the blocks, so the gaps and block marks the drive would see between them are put back
+// in when the disk is loaded:
+// [ leading gap | $80 | block 1 | CRC | gap | $80 | block 2 | CRC | gap | ... ]
+//
+// Block 1: disk info (56 bytes), 2: file count (2 bytes), 3: file header (16 bytes), 4: file data
+//
+// todo: [FEATURE] FDS audio. The wavetable and modulation registers read back what was written, but
+//  nothing is synthesized, so FDS games are missing their expansion channel.
+
+pub const FDS_BIOS_SIZE: usize = 0x2000; // 8kB
+pub const DISK_SIDE_SIZE: usize = 65500;
+const PRG_RAM_SIZE: usize = 0x6000; // 24kB, $8000-$DFFF
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+const BLOCK_MARK: u8 = 0x80;
+// The BIOS only looks at the drive's CRC flag, which is never raised, so a fixed value does.
+const FAKE_CRC: [u8; 2] = [0x4D, 0x62];
+// Cycles from the motor starting to the head reaching the start of the disk, and between bytes.
+const HEAD_RETURN_DELAY: u32 = 50000;
+const BYTE_DELAY: u32 = 150;
+// Swapping disks takes a moment, and the BIOS needs to see the drive empty in between.
+const INSERT_DELAY: u32 = 1_800_000;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Mapper20 {
+    pub screen_mirroring: Mirroring,
+    prg_ram: Vec<u8>,
+    disk_sides: Vec<Vec<u8>>,
+    disk_side: Option<usize>,
+    insert_delay: u32,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    timer_irq: bool,
+
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    disk_irq: bool,
+    transfer_complete: bool,
+    read_data: u8,
+    write_data: u8,
+    disk_position: usize,
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+
+    audio_registers: Vec<u8>, // $4040-$4092
+}
+
+impl Mapper20 {
+    pub fn new() -> Self {
+        Mapper20 {
+            screen_mirroring: Mirroring::Horizontal,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            disk_sides: Vec::new(),
+            disk_side: None,
+            insert_delay: 0,
+
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            transfer_complete: false,
+            read_data: 0,
+            write_data: 0,
+            disk_position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+
+            audio_registers: vec![0; 0x53],
+        }
+    }
+
+    // Takes the sides as they are in the .fds image and inserts side A.
+    pub fn load_disk(&mut self, sides: &[&[u8]]) {
+        self.disk_sides = sides.iter().map(|side| Mapper20::add_gaps(side)).collect();
+        self.disk_side = if self.disk_sides.is_empty() { None } else { Some(0) };
+        self.insert_delay = 0;
+    }
+
+    fn add_gaps(side: &[u8]) -> Vec<u8> {
+        let mut disk = vec![0; LEADING_GAP];
+        let mut position = 0;
+        let mut file_size = 0;
+        while position < side.len() {
+            let block_len = match side[position] {
+                1 => 56,
+                2 => 2,
+                3 => {
+                    file_size = side.get(position + 13..position + 15)
+                        .map_or(0, |size| u16::from_le_bytes([size[0], size[1]]) as usize);
+                    16
+                },
+                4 => 1 + file_size,
+                _ => break, // the rest of the side is unused
+            };
+            let end = usize::min(position + block_len, side.len());
+            disk.push(BLOCK_MARK);
+            disk.extend_from_slice(&side[position..end]);
+            disk.extend_from_slice(&FAKE_CRC);
+            disk.extend(vec![0; BLOCK_GAP]);
+            position = end;
+        }
+        // room for the files games write to the disk
+        disk.resize(usize::max(disk.len(), LEADING_GAP + DISK_SIDE_SIZE), 0);
+        disk
+    }
+
+    pub fn get_side_count(&self) -> usize {
+        self.disk_sides.len()
+    }
+
+    // None while the drive is empty, including for a moment after swapping disks.
+    pub fn get_disk_side(&self) -> Option<usize> {
+        if self.insert_delay > 0 { None } else { self.disk_side }
+    }
+
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.disk_side = side.filter(|side| *side < self.disk_sides.len());
+        self.insert_delay = if self.disk_side.is_some() { INSERT_DELAY } else { 0 };
+    }
+
+    #[inline]
+    fn is_inserted(&self) -> bool {
+        self.get_disk_side().is_some()
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled { return }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            self.irq_enabled = self.irq_repeat;
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+        }
+        let Some(side) = self.get_disk_side() else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.reset_transfer && !self.scanning { return }
+        if self.end_of_head {
+            self.delay = HEAD_RETURN_DELAY;
+            self.end_of_head = false;
+            self.disk_position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        if self.read_mode {
+            let data = self.disk_sides[side][self.disk_position];
+            let mut raise_irq = self.disk_irq_enabled;
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // the block mark ends the gap, the block's first byte is the first one transferred
+                self.gap_ended = true;
+                raise_irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= raise_irq;
+            }
+        } else {
+            if !self.crc_control {
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+                let data = if self.disk_ready { self.write_data } else { 0 };
+                self.disk_sides[side][self.disk_position] = data;
+            }
+            self.gap_ended = false;
+        }
+
+        self.disk_position += 1;
+        if self.disk_position >= self.disk_sides[side].len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+
+    // None for addresses nothing answers, which read as open bus.
+    pub fn read_register(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4030 => {
+                let mut status = 0;
+                if self.timer_irq { status |= 0b0000_0001; }
+                if self.transfer_complete { status |= 0b0000_0010; }
+                if self.end_of_head { status |= 0b0100_0000; }
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+                Some(status)
+            },
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                Some(self.read_data)
+            },
+            0x4032 => {
+                let inserted = self.is_inserted();
+                let mut status = 0b0100_0000;
+                if !inserted { status |= 0b0000_0101; } // no disk, and nothing to write to
+                if !inserted || !self.scanning { status |= 0b0000_0010; }
+                Some(status)
+            },
+            0x4033 => Some(0b1000_0000), // battery is good
+            0x4040..=0x407F | 0x4090 | 0x4092 => Some(self.audio_registers[(address - 0x4040) as usize]),
+            _ => None,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        if !self.disk_registers_enabled && (0x4024..=0x4026).contains(&address) {
+            return;
+        }
+        match address {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | data as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | (data as u16) << 8,
+            0x4022 => {
+                self.irq_repeat = data & 0b0000_0001 != 0;
+                self.irq_enabled = data & 0b0000_0010 != 0 && self.disk_registers_enabled;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            },
+            0x4023 => {
+                self.disk_registers_enabled = data & 0b0000_0001 != 0;
+                self.sound_registers_enabled = data & 0b0000_0010 != 0;
+                if !self.disk_registers_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            },
+            0x4024 => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            },
+            0x4025 => {
+                self.disk_irq = false;
+                self.motor_on = data & 0b0000_0001 != 0;
+                self.reset_transfer = data & 0b0000_0010 != 0;
+                self.read_mode = data & 0b0000_0100 != 0;
+                self.screen_mirroring = if data & 0b0000_1000 != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+                self.crc_control = data & 0b0001_0000 != 0;
+                self.disk_ready = data & 0b0100_0000 != 0;
+                self.disk_irq_enabled = data & 0b1000_0000 != 0;
+            },
+            0x4040..=0x4092 if self.sound_registers_enabled => {
+                self.audio_registers[(address - 0x4040) as usize] = data;
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Mapper for Mapper20 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        match address {
+            0x8000..=0xDFFF => self.prg_ram[(address - 0x8000) as usize],
+            _ => prg_rom[(address - 0xE000) as usize],
+        }
+    }
+
+    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+        chr_rom[address as usize]
+    }
+
+    // Everything below the BIOS is RAM.
+    fn write_mapper(&mut self, address: u16, data: u8) {
+        if let 0x8000..=0xDFFF = address {
+            self.prg_ram[(address - 0x8000) as usize] = data;
+        }
+    }
+
+    fn get_mirroring(&self) -> Option<Mirroring> {
+        Some(self.screen_mirroring)
+    }
+
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.screen_mirroring = mirroring;
+    }
+
+    fn has_expansion(&self) -> bool {
+        true
+    }
+
+    fn read_expansion(&mut self, address: u16, _ppu_dot: u64) -> Option<u8> {
+        self.read_register(address)
+    }
+
+    fn write_expansion(&mut self, address: u16, data: u8) {
+        self.write_register(address, data);
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.clock_timer();
+            self.clock_drive();
+        }
+    }
+
+    #[inline]
+    fn poll_irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a side with the disk info block, a file count of 1, and one 3 byte file
+    fn disk_side() -> Vec<u8> {
+        let mut side = vec![0x01];
+        side.extend(b"*NINTENDO-HVC*");
+        side.resize(56, 0);
+        side.extend([0x02, 0x01]);
+        let mut file_header = vec![0x03, 0x00, 0x00];
+        file_header.extend(b"ALPINES ");
+        file_header.extend([0x00, 0x60, 0x03, 0x00, 0x00]);
+        side.extend(file_header);
+        side.extend([0x04, 0xAA, 0xBB, 0xCC]);
+        side.resize(DISK_SIDE_SIZE, 0);
+        side
+    }
+
+    #[test]
+    fn test_add_gaps() {
+        let disk = Mapper20::add_gaps(&disk_side());
+        assert_eq!(disk[LEADING_GAP], BLOCK_MARK);
+        assert_eq!(&disk[LEADING_GAP + 1..LEADING_GAP + 16], b"\x01*NINTENDO-HVC*");
+        let file_data = LEADING_GAP + 3 * (1 + 2 + BLOCK_GAP) + 56 + 2 + 16;
+        assert_eq!(&disk[file_data..file_data + 7], &[BLOCK_MARK, 0x04, 0xAA, 0xBB, 0xCC, 0x4D, 0x62]);
+        assert!(disk[file_data + 7..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_timer_irq() {
+        let mut mapper = Mapper20::new();
+        mapper.write_register(0x4023, 0b0000_0001);
+        mapper.write_register(0x4020, 0x10);
+        mapper.write_register(0x4021, 0x00);
+        mapper.write_register(0x4022, 0b0000_0010);
+        mapper.tick(0x10);
+        assert!(!mapper.poll_irq());
+        mapper.tick(1);
+        assert!(mapper.poll_irq());
+        assert_eq!(mapper.read_register(0x4030), Some(0b0100_0001));
+        assert!(!mapper.poll_irq());
+
+        // without repeat, the timer stops after firing once
+        mapper.tick(0x20);
+        assert!(!mapper.poll_irq());
+    }
+
+    #[test]
+    fn test_read_disk() {
+        let mut mapper = Mapper20::new();
+        mapper.load_disk(&[&disk_side()]);
+        assert_eq!(mapper.read_register(0x4032), Some(0b0100_0010));
+        mapper.write_register(0x4023, 0b0000_0001);
+        // motor on, read mode, ready, IRQ on each byte
+        mapper.write_register(0x4025, 0b1100_0101);
+
+        let mut data = Vec::new();
+        while data.len() < 15 {
+            mapper.tick(100);
+            if mapper.poll_irq() {
+                data.push(mapper.read_register(0x4031).unwrap());
+            }
+        }
+        assert_eq!(&data, b"\x01*NINTENDO-HVC*");
+        assert_eq!(mapper.read_register(0x4032), Some(0b0100_0000));
+        assert_eq!(mapper.get_mirroring(), Some(Mirroring::Vertical));
+    }
+
+    #[test]
+    fn test_insert_disk() {
+        let mut mapper = Mapper20::new();
+        mapper.load_disk(&[&disk_side(), &disk_side()]);
+        assert_eq!(mapper.get_side_count(), 2);
+        assert_eq!(mapper.get_disk_side(), Some(0));
+
+        mapper.insert_disk(Some(1));
+        assert_eq!(mapper.get_disk_side(), None);
+        assert_eq!(mapper.read_register(0x4032).unwrap() & 0b0000_0001, 1);
+        for _ in 0..INSERT_DELAY / 250 {
+            mapper.tick(250);
+        }
+        assert_eq!(mapper.get_disk_side(), Some(1));
+
+        mapper.insert_disk(Some(2));
+        assert_eq!(mapper.get_disk_side(), None);
+    }
+
+    #[test]
+    fn test_prg_ram() {
+        let mut mapper = Mapper20::new();
+        let bios = vec![0xEA; FDS_BIOS_SIZE];
+        mapper.write_mapper(0x8000, 0x11);
+        mapper.write_mapper(0xDFFF, 0x22);
+        mapper.write_mapper(0xE000, 0x33);
+        assert_eq!(mapper.read_prg_byte(0x8000, &bios), 0x11);
+        assert_eq!(mapper.read_prg_byte(0xDFFF, &bios), 0x22);
+        assert_eq!(mapper.read_prg_byte(0xE000, &bios), 0xEA);
+    }
+}
@@ -0,0 +1,76 @@
+use serde::{Serialize, Deserialize};
+use crate::nes::rom::mappers::mapper::Mapper;
+use crate::nes::rom::mappers::mapper2::read_uxrom_byte;
+
+// Ref: https://www.nesdev.org/wiki/INES_Mapper_232
+
+// Camerica BF9096 (Quattro) memory map:
+// =====================================
+// CPU $8000-$BFFF: 16 KB switchable PRG ROM bank, within the selected 64 KB block
+// CPU $C000-$FFFF: 16 KB PRG ROM bank, fixed to the last bank of the selected block
+//
+// Registers:
+// =====================================
+// $8000-$BFFF: ...B B...  block (64 KB, one game of the pack)
+// $C000-$FFFF: .... ..PP  PRG bank at $8000, within the block
+//
+// Each block is laid out like a UxROM game of its own.
+
+macro_rules! block_select_range { () => {0x8000..=0xBFFF} }
+macro_rules! prg_select_range { () => {0xC000..=0xFFFF} }
+
+const BANKS_PER_BLOCK: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Mapper232 {
+    pub block_select: u8,
+    pub prg_bank_select: u8,
+}
+
+impl Mapper232 {
+    pub fn new() -> Self {
+        Mapper232 {
+            block_select: 0,
+            prg_bank_select: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper232 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        let block_start = BANKS_PER_BLOCK * self.block_select as usize;
+        let switchable_bank = block_start + self.prg_bank_select as usize;
+        read_uxrom_byte(address, switchable_bank, block_start + BANKS_PER_BLOCK - 1, prg_rom)
+    }
+
+    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+        chr_rom[address as usize]
+    }
+
+    fn write_mapper(&mut self, address: u16, data: u8) {
+        match address {
+            block_select_range!() => self.block_select = (data & 0b0001_1000) >> 3,
+            prg_select_range!() => self.prg_bank_select = data & 0b0000_0011,
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::rom::ROM;
+
+    #[test]
+    fn test_block_and_bank() {
+        let prg_rom: Vec<u8> = (0..16).flat_map(|bank| vec![bank as u8; ROM::PRG_ROM_PAGE_SIZE]).collect();
+        let mut mapper = Mapper232::new();
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xC000, &prg_rom)), (0, 3));
+
+        mapper.write_mapper(0x8000, 0b0001_0000);
+        mapper.write_mapper(0xC000, 0b1111_1101);
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xC000, &prg_rom)), (9, 11));
+        mapper.write_mapper(0xBFFF, 0b0001_1000);
+        assert_eq!((mapper.read_prg_byte(0xBFFF, &prg_rom), mapper.read_prg_byte(0xFFFF, &prg_rom)), (13, 15));
+    }
+}
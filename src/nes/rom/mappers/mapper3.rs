@@ -1,22 +1,25 @@
+use serde::{Serialize, Deserialize};
 use crate::nes::rom::mappers::mapper::Mapper;
 use crate::nes::rom::ROM;
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mapper3 {
     pub chr_bank_select: u8,
+    pub bus_conflicts: bool,
 }
 
 impl Mapper3 {
     pub fn new() -> Self {
         Mapper3 {
             chr_bank_select: 0,
+            bus_conflicts: true,
         }
     }
 }
 
 impl Mapper for Mapper3 {
-    fn read_prg_byte(&mut self, address: u16, prg_rom: &Vec<u8>) -> u8 {
-        prg_rom[(address - 0x8000) as usize]
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        prg_rom[(address as usize - 0x8000) % prg_rom.len()]
     }
 
     fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
@@ -27,4 +30,31 @@ impl Mapper for Mapper3 {
     fn write_mapper(&mut self, _address: u16, data: u8) {
         self.chr_bank_select = data;
     }
+
+    // Like UxROM, the bank register only gets the bits the PRG ROM agrees with (see Mapper2).
+    fn write_prg_byte(&mut self, address: u16, data: u8, prg_rom: &Vec<u8>) {
+        let data = if self.bus_conflicts { data & self.read_prg_byte(address, prg_rom) } else { data };
+        self.write_mapper(address, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bus_conflicts() {
+        let mut prg_rom = vec![0; 2 * ROM::PRG_ROM_PAGE_SIZE];
+        prg_rom[0] = 0b0000_0001;
+        prg_rom[1] = 0xFF;
+        let mut mapper = Mapper3::new();
+        mapper.write_prg_byte(0x8000, 0b0000_0011, &prg_rom);
+        assert_eq!(mapper.chr_bank_select, 0b0000_0001);
+        mapper.write_prg_byte(0x8001, 0b0000_0011, &prg_rom);
+        assert_eq!(mapper.chr_bank_select, 0b0000_0011);
+
+        mapper.bus_conflicts = false;
+        mapper.write_prg_byte(0x8000, 0b0000_0010, &prg_rom);
+        assert_eq!(mapper.chr_bank_select, 0b0000_0010);
+    }
 }
@@ -1,4 +1,5 @@
-use crate::nes::rom::mappers::mapper::Mapper;
+use serde::{Serialize, Deserialize};
+use crate::nes::rom::mappers::mapper::{Mapper, PrgRamControl};
 use crate::nes::rom::{Mirroring, ROM};
 
 macro_rules! bank_select_data_range { () => {0x8000..=0x9FFF} }
@@ -25,7 +26,19 @@ macro_rules! chr_subbank1_2kb_range { () => {0x0800..=0x0FFF} }
 macro_rules! chr_subbank2_2kb_range { () => {0x1000..=0x17FF} }
 macro_rules! chr_subbank3_2kb_range { () => {0x1800..=0x1FFF} }
 
-#[derive(Clone)]
+// Ref: https://www.nesdev.org/wiki/MMC3#IRQ_Specifics
+
+// The IRQ counter is clocked by PPU address line A12 going high. With backgrounds at $0000 and
+// sprites at $1000 (or the other way around), that happens once per scanline, when the fetches
+// switch pattern tables. Within a run of fetches from $1000, the nametable fetches in between
+// pull A12 low for a few dots, so the MMC3 only counts a rise after A12 has been low for a while:
+//
+//  dot:   257   261   265   269   273 ...
+//  A12:   ______|‾‾‾‾‾|_____|‾‾‾‾‾|_____ ...     (BG at $0000, sprites at $1000)
+//               ^ clocked   ^ filtered, low for only 4 dots
+const A12_FILTER_DOTS: u64 = 8;
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mapper4 {
     pub bank_select: u8,
     pub prg_bank_select_mode: u8,
@@ -42,12 +55,18 @@ pub struct Mapper4 {
     pub chr_bank1_2kb_select: u8,
 
     pub screen_mirroring: Mirroring,
+    pub prg_ram_disable: bool,
+    pub prg_ram_write_protect: bool,
+    pub submapper: u8,
+    pub four_screen: bool,
 
     pub irq_counter: u8,
     pub irq_latch: u8,
     pub irq_reload: bool,
     pub irq_enable: bool,
     pub irq_flag: bool,
+    a12_high: bool,
+    a12_low_since: u64,
 }
 
 impl Mapper4 {
@@ -68,13 +87,39 @@ impl Mapper4 {
             chr_bank1_2kb_select: 0,
 
             screen_mirroring: Mirroring::Horizontal,
+            prg_ram_disable: false,
+            prg_ram_write_protect: false,
+            submapper: 0,
+            four_screen: false,
 
             irq_counter: 0,
             irq_latch: 0,
             irq_reload: false,
             irq_enable: false,
             irq_flag: false,
+            a12_high: false,
+            a12_low_since: 0,
+        }
+    }
+
+    // $A001: bit 7 enables PRG RAM, bit 6 denies writes to it. MMC6 uses the register differently.
+    pub fn is_prg_ram_enabled(&self) -> bool {
+        !self.prg_ram_disable
+    }
+
+    pub fn is_prg_ram_write_protected(&self) -> bool {
+        self.prg_ram_write_protect
+    }
+
+    #[inline]
+    pub fn watch_a12(&mut self, address: u16, ppu_dot: u64) {
+        let a12_high = address & 0x1000 != 0;
+        if a12_high && !self.a12_high && ppu_dot.saturating_sub(self.a12_low_since) >= A12_FILTER_DOTS {
+            self.decrement_irq_counter();
+        } else if !a12_high && self.a12_high {
+            self.a12_low_since = ppu_dot;
         }
+        self.a12_high = a12_high;
     }
 
     #[inline]
@@ -91,11 +136,6 @@ impl Mapper4 {
         }
     }
 
-    #[inline]
-    pub fn poll_irq(&mut self) -> bool {
-        return self.irq_flag;
-    }
-
     #[inline]
     pub fn set_irq(&mut self) {
         self.irq_flag = true;
@@ -105,75 +145,34 @@ impl Mapper4 {
     pub fn clear_irq(&mut self) {
         self.irq_flag = false
     }
-}
 
-impl Mapper for Mapper4 {
-    fn read_prg_byte(&mut self, address: u16, prg_rom: &Vec<u8>) -> u8 {
-        match address {
-            prg_subbank0_range!() => {
-                if self.prg_bank_select_mode == 0 {
-                    // $8000-$9FFF swappable
-                    // 110: R6: Select 8 KB PRG ROM bank at $8000-$9FFF (or $C000-$DFFF)
-                    let bank_start = (ROM::PRG_ROM_PAGE_SIZE / 2) * self.prg_bank0_select as usize;
-                    prg_rom[(bank_start + (address - 0x8000) as usize) % prg_rom.len()]
-                } else {
-                    // $8000-$9FFF fixed to second-last bank
-                    let last_bank_start = prg_rom.len() - ROM::PRG_ROM_PAGE_SIZE;
-                    prg_rom[last_bank_start + (address - 0x8000) as usize]
-                }
-            },
-            prg_subbank1_range!() => {
-                // 111: R7: Select 8 KB PRG ROM bank at $A000-$BFFF
-                let bank_start = (ROM::PRG_ROM_PAGE_SIZE / 2) * self.prg_bank1_select as usize;
-                prg_rom[(bank_start + (address - 0xA000) as usize) % prg_rom.len()]
-            },
-            prg_subbank2_range!() => {
-                if self.prg_bank_select_mode == 0 {
-                    // $C000-$DFFF fixed to second-last bank;
-                    let last_bank_start = prg_rom.len() - ROM::PRG_ROM_PAGE_SIZE;
-                    prg_rom[last_bank_start + (address - 0xC000) as usize]
-                } else {
-                    // $C000-$DFFF swappable
-                    // 110: R6: Select 8 KB PRG ROM bank at $8000-$9FFF (or $C000-$DFFF)
-                    let bank_start = (ROM::PRG_ROM_PAGE_SIZE / 2) * self.prg_bank0_select as usize;
-                    prg_rom[(bank_start + (address - 0xC000) as usize) % prg_rom.len()]
-                }
-            },
-            prg_subbank3_range!() => {
-                // $E000-$FFFF: 8 KB PRG ROM bank, fixed to the last bank
-                let last_bank_start = prg_rom.len() - (ROM::PRG_ROM_PAGE_SIZE / 2);
-                prg_rom[last_bank_start + (address - 0xE000) as usize]
-            },
-            _ => panic!("can't be")
-        }
-    }
-
-    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+    // Where the PPU address lands in CHR memory, before wrapping to its size.
+    fn chr_offset(&self, address: u16) -> usize {
         if self.chr_bank_select_mode == 0 {
             match address {
                 chr_subbank0_2kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 4) * (self.chr_bank0_2kb_select / 2) as usize;
-                    chr_rom[(bank_start + address as usize) % chr_rom.len()]
+                    bank_start + address as usize
                 },
                 chr_subbank1_2kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 4) * (self.chr_bank1_2kb_select / 2) as usize;
-                    chr_rom[(bank_start + address as usize - 0x0800) % chr_rom.len()]
+                    bank_start + address as usize - 0x0800
                 },
                 chr_subbank4_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank0_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x1000) % chr_rom.len()]
+                    bank_start + address as usize - 0x1000
                 },
                 chr_subbank5_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank1_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x1400) % chr_rom.len()]
+                    bank_start + address as usize - 0x1400
                 },
                 chr_subbank6_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank2_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x1800) % chr_rom.len()]
+                    bank_start + address as usize - 0x1800
                 },
                 chr_subbank7_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank3_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x1C00) % chr_rom.len()]
+                    bank_start + address as usize - 0x1C00
                 },
                 _ => panic!("Address out of range on mapper 4: {}", address)
             }
@@ -181,32 +180,83 @@ impl Mapper for Mapper4 {
             match address {
                 chr_subbank0_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank0_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize) % chr_rom.len()]
+                    bank_start + address as usize
                 },
                 chr_subbank1_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank1_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x0400) % chr_rom.len()]
+                    bank_start + address as usize - 0x0400
                 },
                 chr_subbank2_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank2_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x0800) % chr_rom.len()]
+                    bank_start + address as usize - 0x0800
                 },
                 chr_subbank3_1kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 8) * self.chr_bank3_1kb_select as usize;
-                    chr_rom[(bank_start + address as usize - 0x0C00) % chr_rom.len()]
+                    bank_start + address as usize - 0x0C00
                 },
                 chr_subbank2_2kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 4) * (self.chr_bank0_2kb_select / 2) as usize;
-                    chr_rom[(bank_start + address as usize - 0x1000) % chr_rom.len()]
+                    bank_start + address as usize - 0x1000
                 },
                 chr_subbank3_2kb_range!() => {
                     let bank_start = (ROM::CHR_ROM_PAGE_SIZE / 4) * (self.chr_bank1_2kb_select / 2) as usize;
-                    chr_rom[(bank_start + address as usize - 0x1800) % chr_rom.len()]
+                    bank_start + address as usize - 0x1800
                 },
                 _ => panic!("Address out of range on mapper 4: {}", address)
             }
         }
     }
+}
+
+impl Mapper for Mapper4 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        match address {
+            prg_subbank0_range!() => {
+                if self.prg_bank_select_mode == 0 {
+                    // $8000-$9FFF swappable
+                    // 110: R6: Select 8 KB PRG ROM bank at $8000-$9FFF (or $C000-$DFFF)
+                    let bank_start = (ROM::PRG_ROM_PAGE_SIZE / 2) * self.prg_bank0_select as usize;
+                    prg_rom[(bank_start + (address - 0x8000) as usize) % prg_rom.len()]
+                } else {
+                    // $8000-$9FFF fixed to second-last bank
+                    let last_bank_start = prg_rom.len() - ROM::PRG_ROM_PAGE_SIZE;
+                    prg_rom[last_bank_start + (address - 0x8000) as usize]
+                }
+            },
+            prg_subbank1_range!() => {
+                // 111: R7: Select 8 KB PRG ROM bank at $A000-$BFFF
+                let bank_start = (ROM::PRG_ROM_PAGE_SIZE / 2) * self.prg_bank1_select as usize;
+                prg_rom[(bank_start + (address - 0xA000) as usize) % prg_rom.len()]
+            },
+            prg_subbank2_range!() => {
+                if self.prg_bank_select_mode == 0 {
+                    // $C000-$DFFF fixed to second-last bank;
+                    let last_bank_start = prg_rom.len() - ROM::PRG_ROM_PAGE_SIZE;
+                    prg_rom[last_bank_start + (address - 0xC000) as usize]
+                } else {
+                    // $C000-$DFFF swappable
+                    // 110: R6: Select 8 KB PRG ROM bank at $8000-$9FFF (or $C000-$DFFF)
+                    let bank_start = (ROM::PRG_ROM_PAGE_SIZE / 2) * self.prg_bank0_select as usize;
+                    prg_rom[(bank_start + (address - 0xC000) as usize) % prg_rom.len()]
+                }
+            },
+            prg_subbank3_range!() => {
+                // $E000-$FFFF: 8 KB PRG ROM bank, fixed to the last bank
+                let last_bank_start = prg_rom.len() - (ROM::PRG_ROM_PAGE_SIZE / 2);
+                prg_rom[last_bank_start + (address - 0xE000) as usize]
+            },
+            _ => panic!("can't be")
+        }
+    }
+
+    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+        chr_rom[self.chr_offset(address) % chr_rom.len()]
+    }
+
+    fn write_chr_byte(&mut self, address: u16, data: u8, chr_ram: &mut Vec<u8>) {
+        let chr_ram_size = chr_ram.len();
+        chr_ram[self.chr_offset(address) % chr_ram_size] = data;
+    }
 
     fn write_mapper(&mut self, address: u16, data: u8) {
         match address {
@@ -241,10 +291,11 @@ impl Mapper for Mapper4 {
                 } else {
                     // prg ram protect
                     // println!("mapper4: prg ram protect => 0b{:0>8b}", data);
+                    self.prg_ram_disable = data & 0b1000_0000 == 0;
+                    self.prg_ram_write_protect = data & 0b0100_0000 != 0;
                 }
             },
             irq_latch_reload_range!() => {
-                // todo: implement
                 if address % 2 == 0 {
                     // irq latch
                     // println!("mapper4: irq latch => {}", data);
@@ -257,7 +308,6 @@ impl Mapper for Mapper4 {
                 }
             },
             irq_disable_enable_range!() => {
-                // todo: implement
                 if address % 2 == 0 {
                     // irq disable
                     // println!("mapper4: irq disable");
@@ -272,4 +322,91 @@ impl Mapper for Mapper4 {
             _ => panic!("Address out of range on mapper 4: {}", address)
         }
     }
+
+    // Boards with their own nametable RAM (ie. TVROM in Gauntlet and Rad Racer II) wire it as four
+    // screens, and $A000 does nothing.
+    fn get_mirroring(&self) -> Option<Mirroring> {
+        if self.four_screen { None } else { Some(self.screen_mirroring) }
+    }
+
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.screen_mirroring = mirroring;
+    }
+
+    // todo: MMC6 (submapper 1) protects each 512 byte half of its RAM on its own
+    fn get_prg_ram_control(&self) -> Option<PrgRamControl> {
+        if self.submapper == 1 {
+            return None;
+        }
+        Some(PrgRamControl { enabled: self.is_prg_ram_enabled(), write_protect: self.is_prg_ram_write_protected(), bank: 0 })
+    }
+
+    fn watch_ppu_bus(&mut self, address: u16, ppu_dot: u64) {
+        self.watch_a12(address, ppu_dot);
+    }
+
+    #[inline]
+    fn poll_irq(&self) -> bool {
+        self.irq_flag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prg_ram_protect() {
+        let mut mapper = Mapper4::new();
+        mapper.write_mapper(0xA001, 0b1100_0000);
+        assert!(mapper.is_prg_ram_enabled() && mapper.is_prg_ram_write_protected());
+        mapper.write_mapper(0xBFFF, 0b0000_0000);
+        assert!(!mapper.is_prg_ram_enabled() && !mapper.is_prg_ram_write_protected());
+    }
+
+    #[test]
+    fn test_chr_ram_writes() {
+        // TGROM: 8 KB of CHR RAM banked in 1 KB and 2 KB pieces
+        let mut chr_ram = vec![0; ROM::CHR_ROM_PAGE_SIZE];
+        let mut mapper = Mapper4::new();
+        mapper.write_mapper(0x8000, 2);
+        mapper.write_mapper(0x8001, 7);
+        mapper.write_chr_byte(0x1005, 0xCD, &mut chr_ram);
+        assert_eq!(chr_ram[0x1C05], 0xCD);
+
+        // the same RAM seen through the other CHR mode
+        mapper.write_mapper(0x8000, 0b1000_0000);
+        assert_eq!(mapper.read_chr_byte(0x0005, &chr_ram), 0xCD);
+    }
+
+    #[test]
+    fn test_a12_filter() {
+        let mut mapper = Mapper4::new();
+        mapper.write_mapper(0xC000, 2);
+        mapper.write_mapper(0xE001, 0);
+        mapper.watch_a12(0x0000, 100);
+        mapper.watch_a12(0x1000, 110);
+        assert_eq!(mapper.irq_counter, 2);
+
+        // a rise after only a few dots low doesn't count
+        mapper.watch_a12(0x2000, 112);
+        mapper.watch_a12(0x1000, 116);
+        assert_eq!(mapper.irq_counter, 2);
+
+        mapper.watch_a12(0x0FF0, 120);
+        mapper.watch_a12(0x1FF0, 130);
+        mapper.watch_a12(0x0000, 140);
+        mapper.watch_a12(0x1000, 150);
+        assert_eq!(mapper.irq_counter, 0);
+        assert!(mapper.poll_irq());
+
+        // $E000 acknowledges and disables, $C001 reloads on the next clock
+        mapper.write_mapper(0xE000, 0);
+        assert!(!mapper.poll_irq());
+        mapper.write_mapper(0xC001, 0);
+        mapper.watch_a12(0x0000, 160);
+        mapper.watch_a12(0x1000, 170);
+        assert_eq!(mapper.irq_counter, 2);
+        assert!(!mapper.poll_irq());
+    }
 }
@@ -0,0 +1,446 @@
+use serde::{Serialize, Deserialize};
+use crate::nes::rom::mappers::mapper::Mapper;
+use crate::nes::rom::{Mirroring, ROM};
+
+// Ref: https://www.nesdev.org/wiki/MMC5
+
+// MMC5 memory map:
+// =====================================
+// $5100-$5130: PRG/CHR banking, ExRAM and nametable setup (write)
+// $5203-$5204: scanline IRQ compare and enable (write), IRQ status (read)
+// $5205-$5206: 8x8 -> 16 bit multiplier
+// $5C00-$5FFF: 1kB of ExRAM
+// $6000-$7FFF: PRG RAM (the console side, see Memory)
+// $8000-$FFFF: PRG ROM in 1, 2 or 4 banks, picked by $5113-$5117 according to the PRG mode
+// PPU $0000-$1FFF: CHR ROM in 1, 2, 4 or 8 banks, with separate sprite and background sets
+// PPU $2000-$2FFF: each nametable is either CIRAM page, ExRAM, or filled with a fixed tile
+//
+// The MMC5 sees nothing of the PPU but its bus. A new scanline shows up as three reads in a row
+// from the same nametable address (the two dummy fetches at the end of a line, then the first
+// fetch of the next one), and the PPU going quiet for a few cycles means the frame has ended:
+//
+//  dot:    337       339       1 (next line)
+//  read:   NT $2xxx  NT $2xxx  NT $2xxx      -> scanline detected, the IRQ counter is compared
+//
+// todo: PRG RAM banking ($5113, RAM banks in $8000-$DFFF), split screen ($5200-$5202), audio
+
+macro_rules! exram_range { () => {0x5C00..=0x5FFF} }
+
+const PRG_BANK_SIZE: usize = ROM::PRG_ROM_PAGE_SIZE / 2; // 8kB
+const CHR_BANK_SIZE: usize = ROM::CHR_ROM_PAGE_SIZE; // 8kB, split further by the CHR mode
+const EXRAM_SIZE: usize = 0x400; // 1kB
+const ATTRIBUTE_TABLE_OFFSET: usize = 0x3C0;
+// The PPU reads every other dot while rendering, anything longer than 3 CPU cycles is a pause.
+const IDLE_DOTS: u64 = 3 * 3;
+// Sprite patterns are fetched over dots 257-320, so a fetch watched in this window is followed by a
+// sprite pattern read.
+const SPRITE_FETCH_DOTS: std::ops::Range<u64> = 257..319;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Mapper5 {
+    pub prg_mode: u8,
+    pub chr_mode: u8,
+    pub prg_bank_select: [u8; 5], // $5113-$5117
+    pub chr_sprite_select: [u16; 8], // $5120-$5127, also used for everything with 8x8 sprites
+    pub chr_background_select: [u16; 4], // $5128-$512B, backgrounds with 8x16 sprites
+    pub chr_upper_bits: u8,
+    pub chr_background_written_last: bool,
+    pub exram: Vec<u8>,
+    pub exram_mode: u8,
+    pub nametable_mapping: u8,
+    pub fill_tile: u8,
+    pub fill_attribute: u8,
+    pub sprite_8x16: bool,
+
+    pub irq_compare: u8,
+    pub irq_enabled: bool,
+    pub irq_pending: bool,
+    pub in_frame: bool,
+    pub scanline_counter: u8,
+    pub multiplicand: u8,
+    pub multiplier: u8,
+
+    last_nametable_address: u16,
+    nametable_matches: u8,
+    last_fetch_dot: u64,
+    scanline_start_dot: u64,
+    fetching_sprites: bool,
+    tile_offset: usize,
+}
+
+impl Mapper5 {
+    pub fn new() -> Self {
+        Mapper5 {
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_bank_select: [0, 0, 0, 0, 0xFF],
+            chr_sprite_select: [0; 8],
+            chr_background_select: [0; 4],
+            chr_upper_bits: 0,
+            chr_background_written_last: false,
+            exram: vec![0; EXRAM_SIZE],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            sprite_8x16: false,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline_counter: 0,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            last_nametable_address: 0,
+            nametable_matches: 0,
+            last_fetch_dot: 0,
+            scanline_start_dot: 0,
+            fetching_sprites: false,
+            tile_offset: 0,
+        }
+    }
+
+    // None for addresses nothing answers, which read as open bus.
+    pub fn read_register(&mut self, address: u16, ppu_dot: u64) -> Option<u8> {
+        match address {
+            0x5204 => {
+                self.watch_idle(ppu_dot);
+                let mut status = 0;
+                if self.irq_pending { status |= 0b1000_0000; }
+                if self.in_frame { status |= 0b0100_0000; }
+                self.irq_pending = false;
+                Some(status)
+            },
+            0x5205 => Some(self.get_product() as u8),
+            0x5206 => Some((self.get_product() >> 8) as u8),
+            exram_range!() if self.exram_mode >= 2 => Some(self.exram[(address - 0x5C00) as usize]),
+            _ => None,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            0x5100 => self.prg_mode = data & 0b11,
+            0x5101 => self.chr_mode = data & 0b11,
+            0x5104 => self.exram_mode = data & 0b11,
+            0x5105 => self.nametable_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0b11,
+            0x5113..=0x5117 => self.prg_bank_select[(address - 0x5113) as usize] = data,
+            0x5120..=0x5127 => {
+                self.chr_sprite_select[(address - 0x5120) as usize] = data as u16 | (self.chr_upper_bits as u16) << 8;
+                self.chr_background_written_last = false;
+            },
+            0x5128..=0x512B => {
+                self.chr_background_select[(address - 0x5128) as usize] = data as u16 | (self.chr_upper_bits as u16) << 8;
+                self.chr_background_written_last = true;
+            },
+            0x5130 => self.chr_upper_bits = data & 0b11,
+            0x5203 => self.irq_compare = data,
+            0x5204 => self.irq_enabled = data & 0b1000_0000 != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            // ExRAM is read-only in mode 3
+            exram_range!() if self.exram_mode != 3 => self.exram[(address - 0x5C00) as usize] = data,
+            _ => {},
+        }
+    }
+
+    #[inline]
+    fn get_product(&self) -> u16 {
+        self.multiplicand as u16 * self.multiplier as u16
+    }
+
+    // Reads stop during vblank, or when rendering is switched off.
+    #[inline]
+    fn watch_idle(&mut self, ppu_dot: u64) {
+        if ppu_dot.saturating_sub(self.last_fetch_dot) > IDLE_DOTS {
+            self.in_frame = false;
+            self.nametable_matches = 0;
+        }
+    }
+
+    #[inline]
+    fn detect_scanline(&mut self, ppu_dot: u64) {
+        // the scanline is detected on its first fetch, at dot 1
+        self.scanline_start_dot = ppu_dot.saturating_sub(1);
+        if self.in_frame {
+            self.scanline_counter = self.scanline_counter.wrapping_add(1);
+            if self.scanline_counter == self.irq_compare {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline_counter = 0;
+            self.irq_pending = false;
+        }
+    }
+
+    #[inline]
+    fn get_nametable_source(&self, address: u16) -> u8 {
+        let nametable = ((address & 0x0FFF) / 0x400) as u8;
+        (self.nametable_mapping >> (2 * nametable)) & 0b11
+    }
+
+    #[inline]
+    fn is_extended_attribute_fetch(&self) -> bool {
+        self.exram_mode == 1 && self.in_frame && !self.fetching_sprites
+    }
+
+    // 8x16 sprites fetch from their own set while rendering. Otherwise (and outside of rendering)
+    // whichever set was written last is used for everything.
+    #[inline]
+    fn is_background_set(&self) -> bool {
+        if self.sprite_8x16 && self.in_frame {
+            !self.fetching_sprites
+        } else {
+            self.chr_background_written_last
+        }
+    }
+
+    #[inline]
+    fn get_prg_bank(&self, address: u16) -> usize {
+        // (register, size in 8kB banks) for the window the address falls in
+        let (select, banks) = match (self.prg_mode, address) {
+            (0, _) => (4, 4),
+            (1, 0x8000..=0xBFFF) | (2, 0x8000..=0xBFFF) => (2, 2),
+            (1, _) => (4, 2),
+            (2, 0xC000..=0xDFFF) | (3, 0xC000..=0xDFFF) => (3, 1),
+            (3, 0x8000..=0x9FFF) => (1, 1),
+            (3, 0xA000..=0xBFFF) => (2, 1),
+            _ => (4, 1),
+        };
+        let bank = self.prg_bank_select[select] as usize & 0x7F & !(banks - 1);
+        bank + (address as usize - 0x8000) / PRG_BANK_SIZE % banks
+    }
+}
+
+impl Mapper for Mapper5 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        let bank = self.get_prg_bank(address);
+        prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()]
+    }
+
+    // Registers $5120-$512B pick the banks as follows, by CHR mode (the background set only covers
+    // 4kB, which is repeated at $1000):
+    //
+    //  mode  bank size  sprite set          background set
+    //  0     8kB        $5127               $512B
+    //  1     4kB        $5123 $5127         $512B
+    //  2     2kB        $5121 $5123 ...     $5129 $512B
+    //  3     1kB        $5120 $5121 ...     $5128 $5129 $512A $512B
+    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+        if self.is_extended_attribute_fetch() {
+            let bank = (self.exram[self.tile_offset] & 0b0011_1111) as usize | (self.chr_upper_bits as usize) << 6;
+            return chr_rom[(bank * CHR_BANK_SIZE / 2 + (address as usize & 0x0FFF)) % chr_rom.len()];
+        }
+        let bank_size = CHR_BANK_SIZE >> self.chr_mode;
+        let select = |address: usize| (address / bank_size + 1) * (8 >> self.chr_mode) - 1;
+        let bank = if self.is_background_set() {
+            self.chr_background_select[select(address as usize & 0x0FFF) & 0b11]
+        } else {
+            self.chr_sprite_select[select(address as usize)]
+        };
+        chr_rom[(bank as usize * bank_size + address as usize % bank_size) % chr_rom.len()]
+    }
+
+    // The MMC5 has no registers in $8000-$FFFF.
+    fn write_mapper(&mut self, _address: u16, _data: u8) {}
+
+    // The nametables are wired up through read_nametable_byte instead.
+    fn get_mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    // Each nametable is picked by 2 bits of $5105:
+    //
+    //  7654 3210
+    //  |||| ||++- $2000: 0 = CIRAM page 0, 1 = CIRAM page 1, 2 = ExRAM, 3 = fill mode
+    //  |||| ++--- $2400
+    //  ||++------ $2800
+    //  ++-------- $2C00
+    //
+    // In extended attribute mode ($5104 = 1), the ExRAM byte of each background tile gives its
+    // palette and a 4kB CHR bank of its own:
+    //
+    //  7654 3210
+    //  ||++-++++- CHR bank (4kB), with $5130 on top
+    //  ++-------- palette
+    fn read_nametable_byte(&self, address: u16, ciram: &[u8]) -> Option<u8> {
+        let offset = address as usize & 0x3FF;
+        if self.is_extended_attribute_fetch() && offset >= ATTRIBUTE_TABLE_OFFSET {
+            return Some((self.exram[self.tile_offset] >> 6) * 0b0101_0101);
+        }
+        let data = match self.get_nametable_source(address) {
+            0 => ciram[0x2000 + offset],
+            1 => ciram[0x2400 + offset],
+            2 if self.exram_mode <= 1 => self.exram[offset],
+            2 => 0,
+            _ if offset < ATTRIBUTE_TABLE_OFFSET => self.fill_tile,
+            _ => self.fill_attribute * 0b0101_0101,
+        };
+        Some(data)
+    }
+
+    fn write_nametable_byte(&mut self, address: u16, data: u8, ciram: &mut [u8]) -> bool {
+        let offset = address as usize & 0x3FF;
+        match self.get_nametable_source(address) {
+            0 => ciram[0x2000 + offset] = data,
+            1 => ciram[0x2400 + offset] = data,
+            2 if self.exram_mode <= 1 => self.exram[offset] = data,
+            _ => {},
+        }
+        true
+    }
+
+    fn has_expansion(&self) -> bool {
+        true
+    }
+
+    // The IRQ status and ExRAM are kept up to date by the PPU's fetches, so the PPU has to be caught
+    // up before the read.
+    fn read_expansion(&mut self, address: u16, ppu_dot: u64) -> Option<u8> {
+        self.read_register(address, ppu_dot)
+    }
+
+    fn write_expansion(&mut self, address: u16, data: u8) {
+        self.write_register(address, data);
+    }
+
+    // The PPU's sprite size is needed to tell the sprite and background CHR sets apart, so the MMC5
+    // snoops writes to PPUCTRL.
+    #[inline]
+    fn watch_ppu_ctrl(&mut self, value: u8) {
+        self.sprite_8x16 = value & 0b0010_0000 != 0;
+    }
+
+    #[inline]
+    fn watch_ppu_bus(&mut self, address: u16, ppu_dot: u64) {
+        self.watch_idle(ppu_dot);
+        self.last_fetch_dot = ppu_dot;
+
+        let is_nametable = (0x2000..=0x2FFF).contains(&address);
+        if is_nametable && address == self.last_nametable_address {
+            self.nametable_matches += 1;
+            if self.nametable_matches == 2 {
+                self.detect_scanline(ppu_dot);
+            }
+        } else {
+            self.nametable_matches = 0;
+        }
+        self.last_nametable_address = if is_nametable { address } else { 0 };
+
+        if is_nametable && (address as usize & 0x3FF) < ATTRIBUTE_TABLE_OFFSET {
+            self.tile_offset = address as usize & 0x3FF;
+        }
+        let line_dot = ppu_dot.saturating_sub(self.scanline_start_dot);
+        self.fetching_sprites = self.in_frame && SPRITE_FETCH_DOTS.contains(&line_dot);
+    }
+
+    #[inline]
+    fn poll_irq(&self) -> bool {
+        self.irq_enabled && self.irq_pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::rom::mappers::tests::numbered_banks;
+
+    fn read_prg_banks(mapper: &Mapper5, prg_rom: &Vec<u8>) -> Vec<u8> {
+        [0x8000, 0xA000, 0xC000, 0xE000].iter()
+            .map(|address| mapper.read_prg_byte(*address, prg_rom))
+            .collect()
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        let prg_rom = numbered_banks(32, PRG_BANK_SIZE);
+        let mut mapper = Mapper5::new();
+        assert_eq!(read_prg_banks(&mapper, &prg_rom), [0, 0, 0, 31]);
+
+        for (address, bank) in [(0x5114, 0x85), (0x5115, 0x8A), (0x5116, 0x8C), (0x5117, 0x9F)] {
+            mapper.write_register(address, bank);
+        }
+        assert_eq!(read_prg_banks(&mapper, &prg_rom), [5, 10, 12, 31]);
+        mapper.write_register(0x5100, 2);
+        assert_eq!(read_prg_banks(&mapper, &prg_rom), [10, 11, 12, 31]);
+        mapper.write_register(0x5100, 1);
+        assert_eq!(read_prg_banks(&mapper, &prg_rom), [10, 11, 30, 31]);
+        mapper.write_register(0x5100, 0);
+        assert_eq!(read_prg_banks(&mapper, &prg_rom), [28, 29, 30, 31]);
+    }
+
+    #[test]
+    fn test_chr_sets() {
+        let chr_rom = numbered_banks(128, 0x400);
+        let mut mapper = Mapper5::new();
+        mapper.write_register(0x5101, 3);
+        for i in 0..8 {
+            mapper.write_register(0x5120 + i, 10 + i as u8);
+        }
+        for i in 0..4 {
+            mapper.write_register(0x5128 + i, 20 + i as u8);
+        }
+        // with 8x8 sprites the set written last is used for everything
+        assert_eq!(mapper.read_chr_byte(0x1400, &chr_rom), 21);
+        mapper.write_register(0x5127, 17);
+        assert_eq!(mapper.read_chr_byte(0x1400, &chr_rom), 15);
+
+        // with 8x16 sprites, the sets are picked by what's being fetched
+        mapper.watch_ppu_ctrl(0b0010_0000);
+        mapper.in_frame = true;
+        assert_eq!(mapper.read_chr_byte(0x1400, &chr_rom), 21);
+        mapper.fetching_sprites = true;
+        assert_eq!(mapper.read_chr_byte(0x1400, &chr_rom), 15);
+
+        mapper.write_register(0x5101, 1);
+        assert_eq!(mapper.read_chr_byte(0x1400, &chr_rom), 17 * 4 + 1);
+    }
+
+    #[test]
+    fn test_nametables() {
+        let mut ciram = vec![0; 0x4000];
+        let mut mapper = Mapper5::new();
+        mapper.write_register(0x5105, 0b11_10_01_00);
+        mapper.write_register(0x5106, 0x42);
+        mapper.write_register(0x5107, 2);
+        for address in [0x2000, 0x2400, 0x2800, 0x2C00] {
+            mapper.write_nametable_byte(address + 5, (address >> 8) as u8, &mut ciram);
+        }
+        let read = |address: u16| mapper.read_nametable_byte(address, &ciram).unwrap();
+        assert_eq!([read(0x2005), read(0x2405), read(0x2805), read(0x2C05)], [0x20, 0x24, 0x28, 0x42]);
+        assert_eq!(read(0x2FC0), 0b1010_1010);
+        assert_eq!((ciram[0x2005], ciram[0x2405], mapper.exram[5]), (0x20, 0x24, 0x28));
+
+        // once ExRAM is plain RAM it's no longer a nametable
+        mapper.write_register(0x5104, 2);
+        assert_eq!(mapper.read_nametable_byte(0x2805, &ciram), Some(0));
+        assert_eq!(mapper.read_register(0x5C05, 0), Some(0x28));
+    }
+
+    #[test]
+    fn test_extended_attributes() {
+        let chr_rom = numbered_banks(128, 0x1000);
+        let ciram = vec![0; 0x4000];
+        let mut mapper = Mapper5::new();
+        mapper.write_register(0x5104, 1);
+        mapper.write_register(0x5130, 1);
+        mapper.write_register(0x5C00 + 33, 0b1100_0101);
+        mapper.in_frame = true;
+        mapper.watch_ppu_bus(0x2000 + 33, 0);
+        assert_eq!(mapper.read_nametable_byte(0x23C0, &ciram), Some(0xFF));
+        assert_eq!(mapper.read_chr_byte(0x1008, &chr_rom), 64 + 5);
+    }
+
+    #[test]
+    fn test_multiplier() {
+        let mut mapper = Mapper5::new();
+        assert_eq!((mapper.read_register(0x5205, 0), mapper.read_register(0x5206, 0)), (Some(0x01), Some(0xFE)));
+        mapper.write_register(0x5205, 200);
+        mapper.write_register(0x5206, 3);
+        assert_eq!((mapper.read_register(0x5205, 0), mapper.read_register(0x5206, 0)), (Some(0x58), Some(0x02)));
+        assert_eq!(mapper.read_register(0x5207, 0), None);
+    }
+}
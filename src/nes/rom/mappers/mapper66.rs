@@ -1,7 +1,8 @@
+use serde::{Serialize, Deserialize};
 use crate::nes::rom::mappers::mapper::Mapper;
 use crate::nes::rom::ROM;
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mapper66 {
     pub prg_bank_select: u8,
     pub chr_bank_select: u8,
@@ -17,7 +18,7 @@ impl Mapper66 {
 }
 
 impl Mapper for Mapper66 {
-    fn read_prg_byte(&mut self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
         let bank_start = 2 * ROM::PRG_ROM_PAGE_SIZE * self.prg_bank_select as usize;
         prg_rom[(bank_start + (address - 0x8000) as usize) % prg_rom.len()]
     }
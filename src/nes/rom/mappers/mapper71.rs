@@ -0,0 +1,101 @@
+use serde::{Serialize, Deserialize};
+use crate::nes::rom::mappers::mapper::Mapper;
+use crate::nes::rom::mappers::mapper2::read_uxrom_byte;
+use crate::nes::rom::{Mirroring, ROM};
+
+// Ref: https://www.nesdev.org/wiki/INES_Mapper_071
+
+// Camerica BF9093/BF9097 memory map:
+// =====================================
+// CPU $8000-$BFFF: 16 KB switchable PRG ROM bank
+// CPU $C000-$FFFF: 16 KB PRG ROM bank, fixed to the last bank
+//
+// Registers:
+// =====================================
+// $9000-$9FFF: ...M ....  one-screen mirroring, lower (0) or upper (1) nametable. Only on the
+//                          BF9097 (Fire Hawk, submapper 1), the other boards are hardwired.
+// $C000-$FFFF: .... PPPP  PRG bank at $8000
+//
+// Like UxROM but without bus conflicts.
+
+macro_rules! mirroring_range { () => {0x9000..=0x9FFF} }
+macro_rules! prg_select_range { () => {0xC000..=0xFFFF} }
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Mapper71 {
+    pub prg_bank_select: u8,
+    pub mirroring_control: bool,
+    pub screen_mirroring: Option<Mirroring>,
+}
+
+impl Mapper71 {
+    pub fn new() -> Self {
+        Mapper71 {
+            prg_bank_select: 0,
+            mirroring_control: false,
+            screen_mirroring: None,
+        }
+    }
+}
+
+impl Mapper for Mapper71 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        let last_bank = prg_rom.len() / ROM::PRG_ROM_PAGE_SIZE - 1;
+        read_uxrom_byte(address, self.prg_bank_select as usize, last_bank, prg_rom)
+    }
+
+    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+        chr_rom[address as usize]
+    }
+
+    fn write_mapper(&mut self, address: u16, data: u8) {
+        match address {
+            mirroring_range!() if self.mirroring_control => {
+                let upper = data & 0b0001_0000 != 0;
+                self.screen_mirroring = Some(if upper { Mirroring::OneScreenUpper } else { Mirroring::OneScreenLower });
+            },
+            prg_select_range!() => self.prg_bank_select = data & 0b0000_1111,
+            _ => {},
+        }
+    }
+
+    fn get_mirroring(&self) -> Option<Mirroring> {
+        self.screen_mirroring
+    }
+
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        if self.mirroring_control {
+            self.screen_mirroring = Some(mirroring);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::rom::mappers::tests::numbered_banks;
+
+    #[test]
+    fn test_prg_banks() {
+        let prg_rom = numbered_banks(8, ROM::PRG_ROM_PAGE_SIZE);
+        let mut mapper = Mapper71::new();
+        mapper.write_mapper(0xC000, 0xF3);
+        assert_eq!((mapper.read_prg_byte(0x8000, &prg_rom), mapper.read_prg_byte(0xFFFF, &prg_rom)), (3, 7));
+        // $8000-$BFFF isn't a bank register
+        mapper.write_mapper(0x8000, 5);
+        assert_eq!(mapper.read_prg_byte(0xBFFF, &prg_rom), 3);
+    }
+
+    #[test]
+    fn test_mirroring_control() {
+        let mut mapper = Mapper71::new();
+        mapper.write_mapper(0x9000, 0b0001_0000);
+        assert_eq!(mapper.get_mirroring(), None);
+
+        mapper.mirroring_control = true;
+        mapper.write_mapper(0x9000, 0b0001_0000);
+        assert_eq!(mapper.get_mirroring(), Some(Mirroring::OneScreenUpper));
+        mapper.write_mapper(0x9FFF, 0);
+        assert_eq!(mapper.get_mirroring(), Some(Mirroring::OneScreenLower));
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Serialize, Deserialize};
+use crate::nes::rom::mappers::mapper::Mapper;
+use crate::nes::rom::{Mirroring, ROM};
+
+// Ref: https://www.nesdev.org/wiki/MMC2
+
+// MMC2 memory map:
+// =====================================
+// CPU $8000-$9FFF: 8 KB switchable PRG ROM bank
+// CPU $A000-$FFFF: three 8 KB PRG ROM banks, fixed to the last three banks
+// PPU $0000-$0FFF: 4 KB switchable CHR bank, picked by latch 0
+// PPU $1000-$1FFF: 4 KB switchable CHR bank, picked by latch 1
+//
+// Each pattern table has two CHR banks (an "FD" and an "FE" one) and a latch that picks between
+// them. The latches flip on their own when the PPU fetches the high plane of tile $FD or $FE, so a
+// game can switch banks in the middle of a scanline by placing those tiles in the nametable:
+//
+//  PPU reads $0FD8         -> latch 0 = FD      PPU reads $1FD8-$1FDF -> latch 1 = FD
+//  PPU reads $0FE8         -> latch 0 = FE      PPU reads $1FE8-$1FEF -> latch 1 = FE
+//
+// The fetch that flips a latch still reads from the old bank.
+
+macro_rules! prg_select_range { () => {0xA000..=0xAFFF} }
+macro_rules! chr_fd_0000_range { () => {0xB000..=0xBFFF} }
+macro_rules! chr_fe_0000_range { () => {0xC000..=0xCFFF} }
+macro_rules! chr_fd_1000_range { () => {0xD000..=0xDFFF} }
+macro_rules! chr_fe_1000_range { () => {0xE000..=0xEFFF} }
+macro_rules! mirroring_range { () => {0xF000..=0xFFFF} }
+
+const PRG_BANK_SIZE: usize = ROM::PRG_ROM_PAGE_SIZE / 2; // 8kB
+const CHR_BANK_SIZE: usize = ROM::CHR_ROM_PAGE_SIZE / 2; // 4kB
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum ChrLatch {
+    FD,
+    FE,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Mapper9 {
+    pub prg_bank_select: u8,
+    pub chr_fd_0000_select: u8,
+    pub chr_fe_0000_select: u8,
+    pub chr_fd_1000_select: u8,
+    pub chr_fe_1000_select: u8,
+    pub latch0: ChrLatch,
+    pub latch1: ChrLatch,
+    pub screen_mirroring: Mirroring,
+}
+
+impl Mapper9 {
+    pub fn new() -> Self {
+        Mapper9 {
+            prg_bank_select: 0,
+            chr_fd_0000_select: 0,
+            chr_fe_0000_select: 0,
+            chr_fd_1000_select: 0,
+            chr_fe_1000_select: 0,
+            latch0: ChrLatch::FE,
+            latch1: ChrLatch::FE,
+            screen_mirroring: Mirroring::Vertical,
+        }
+    }
+}
+
+impl Mapper for Mapper9 {
+    fn read_prg_byte(&self, address: u16, prg_rom: &Vec<u8>) -> u8 {
+        let bank_count = prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match address {
+            0x8000..=0x9FFF => self.prg_bank_select as usize,
+            _ => bank_count.saturating_sub(4) + (address as usize - 0x8000) / PRG_BANK_SIZE,
+        };
+        prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()]
+    }
+
+    fn read_chr_byte(&self, address: u16, chr_rom: &Vec<u8>) -> u8 {
+        let bank = match (address, self.latch0, self.latch1) {
+            (0x0000..=0x0FFF, ChrLatch::FD, _) => self.chr_fd_0000_select,
+            (0x0000..=0x0FFF, ChrLatch::FE, _) => self.chr_fe_0000_select,
+            (_, _, ChrLatch::FD) => self.chr_fd_1000_select,
+            (_, _, ChrLatch::FE) => self.chr_fe_1000_select,
+        };
+        chr_rom[(bank as usize * CHR_BANK_SIZE + (address as usize & 0x0FFF)) % chr_rom.len()]
+    }
+
+    fn write_mapper(&mut self, address: u16, data: u8) {
+        match address {
+            prg_select_range!() => self.prg_bank_select = data & 0b0000_1111,
+            chr_fd_0000_range!() => self.chr_fd_0000_select = data & 0b0001_1111,
+            chr_fe_0000_range!() => self.chr_fe_0000_select = data & 0b0001_1111,
+            chr_fd_1000_range!() => self.chr_fd_1000_select = data & 0b0001_1111,
+            chr_fe_1000_range!() => self.chr_fe_1000_select = data & 0b0001_1111,
+            mirroring_range!() => {
+                self.screen_mirroring = if data & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            },
+            _ => {},
+        }
+    }
+
+    fn get_mirroring(&self) -> Option<Mirroring> {
+        Some(self.screen_mirroring)
+    }
+
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.screen_mirroring = mirroring;
+    }
+
+    #[inline]
+    fn watch_ppu_bus(&mut self, address: u16, _ppu_dot: u64) {
+        match address {
+            0x0FD8 => self.latch0 = ChrLatch::FD,
+            0x0FE8 => self.latch0 = ChrLatch::FE,
+            0x1FD8..=0x1FDF => self.latch1 = ChrLatch::FD,
+            0x1FE8..=0x1FEF => self.latch1 = ChrLatch::FE,
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::rom::mappers::tests::numbered_banks;
+
+    #[test]
+    fn test_prg_banks() {
+        let prg_rom = numbered_banks(16, PRG_BANK_SIZE);
+        let mut mapper = Mapper9::new();
+        mapper.write_mapper(0xA000, 5);
+        let banks: Vec<u8> = [0x8000, 0xA000, 0xC000, 0xE000].iter()
+            .map(|address| mapper.read_prg_byte(*address, &prg_rom))
+            .collect();
+        assert_eq!(banks, [5, 13, 14, 15]);
+    }
+
+    #[test]
+    fn test_chr_latches() {
+        let chr_rom = numbered_banks(32, CHR_BANK_SIZE);
+        let mut mapper = Mapper9::new();
+        mapper.write_mapper(0xB000, 1);
+        mapper.write_mapper(0xC000, 2);
+        mapper.write_mapper(0xD000, 3);
+        mapper.write_mapper(0xE000, 4);
+        assert_eq!((mapper.read_chr_byte(0x0000, &chr_rom), mapper.read_chr_byte(0x1000, &chr_rom)), (2, 4));
+
+        // latch 0 only flips on the exact address, latch 1 on any row of the tile's high plane
+        mapper.watch_ppu_bus(0x0FD9, 0);
+        mapper.watch_ppu_bus(0x1FDC, 0);
+        assert_eq!((mapper.read_chr_byte(0x0000, &chr_rom), mapper.read_chr_byte(0x1000, &chr_rom)), (2, 3));
+        mapper.watch_ppu_bus(0x0FD8, 0);
+        assert_eq!(mapper.read_chr_byte(0x0FFF, &chr_rom), 1);
+        mapper.watch_ppu_bus(0x0FE8, 0);
+        mapper.watch_ppu_bus(0x1FEF, 0);
+        assert_eq!((mapper.read_chr_byte(0x0000, &chr_rom), mapper.read_chr_byte(0x1FFF, &chr_rom)), (2, 4));
+
+        mapper.write_mapper(0xF000, 1);
+        assert_eq!(mapper.get_mirroring(), Some(Mirroring::Horizontal));
+    }
+}
@@ -1,4 +1,9 @@
-#[derive(Clone)]
+use serde::{Serialize, Deserialize};
+
+// Ref: https://www.nesdev.org/wiki/MMC1#Registers
+// MMC1 registers are written one bit at a time, LSB first. The fifth write hands the collected
+// value to the register picked by its address and starts the next one over.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ShiftRegister {
     pub value: u8,
     pub shift: u8
@@ -12,25 +17,20 @@ impl ShiftRegister {
         }
     }
 
-    pub fn write(&mut self, value: u8) {
-        if self.shift == 5 {
-            self.clear();
-        }
-        if (value >> 7) & 1 == 0 {
-            let bit_0 = value & 1;
-            self.value |= bit_0 << self.shift;
-            self.shift += 1;
-        } else {
-            self.clear();
+    // Returns the full 5 bit value on the fifth write.
+    pub fn write(&mut self, value: u8) -> Option<u8> {
+        self.value |= (value & 1) << self.shift;
+        self.shift += 1;
+        if self.shift < 5 {
+            return None;
         }
+        let full_value = self.value;
+        self.clear();
+        Some(full_value)
     }
 
     pub fn clear(&mut self) {
         self.value = 0;
         self.shift = 0;
     }
-
-    pub fn is_fifth_write(&self) -> bool {
-        self.shift == 5
-    }
-}
\ No newline at end of file
+}
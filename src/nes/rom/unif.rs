@@ -0,0 +1,125 @@
+use crate::nes::rom::RomError;
+
+// Ref: https://www.nesdev.org/wiki/UNIF
+
+// UNIF file layout:
+// =====================================
+// Bytes 0-3:   "UNIF"
+// Bytes 4-7:   revision (u32 LE)
+// Bytes 8-31:  reserved
+// Bytes 32-:   [ ID (4 bytes) | length (u32 LE) | data ] ...   <- chunks, in any order
+//
+// Chunks used here:
+// MAPR       <- board name (null terminated), ex: "NES-SNROM"
+// PRG0-PRGF  <- PRG ROM, concatenated in hex order
+// CHR0-CHRF  <- CHR ROM, concatenated in hex order (none means CHR RAM)
+// MIRR       <- 0: horizontal, 1: vertical, 2: one-screen $2000, 3: one-screen $2400, 4: four-screen, 5: mapper
+// BATR       <- present if the board has battery-backed PRG RAM
+// NAME       <- game title (null terminated)
+// TVCI       <- 0: NTSC, 1: PAL, 2: both
+//
+// UNIF describes the board by name instead of by iNES mapper number, so boards are looked up in a
+// table of the ones alpiNES has a mapper for.
+
+pub const UNIF_SIGNATURE: [u8; 4] = *b"UNIF";
+pub const UNIF_HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+
+pub struct UnifChunk<'a> {
+    pub id: [u8; 4],
+    pub data: &'a [u8],
+}
+
+impl UnifChunk<'_> {
+    // MAPR, NAME and friends are C strings, some dumps leave out the terminator.
+    pub fn as_str(&self) -> String {
+        let end = self.data.iter().position(|byte| *byte == 0).unwrap_or(self.data.len());
+        String::from_utf8_lossy(&self.data[..end]).to_string()
+    }
+
+    // PRG0-PRGF and CHR0-CHRF are numbered with a hex digit.
+    pub fn bank_index(&self, prefix: &[u8; 3]) -> Option<u8> {
+        if self.id[..3] != *prefix { return None }
+        (self.id[3] as char).to_digit(16).map(|digit| digit as u8)
+    }
+}
+
+pub fn is_unif(raw: &[u8]) -> bool {
+    raw.len() >= 4 && raw[0..4] == UNIF_SIGNATURE
+}
+
+pub fn read_chunks(raw: &[u8]) -> Result<Vec<UnifChunk<'_>>, RomError> {
+    if raw.len() < UNIF_HEADER_SIZE {
+        return Err(RomError::TruncatedHeader);
+    }
+    let mut chunks = Vec::new();
+    let mut offset = UNIF_HEADER_SIZE;
+    while offset < raw.len() {
+        let header = raw.get(offset..offset + CHUNK_HEADER_SIZE)
+            .ok_or_else(|| RomError::TruncatedChunk(String::from_utf8_lossy(&raw[offset..]).to_string()))?;
+        let id = [header[0], header[1], header[2], header[3]];
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let start = offset + CHUNK_HEADER_SIZE;
+        let data = raw.get(start..start.saturating_add(len))
+            .ok_or_else(|| RomError::TruncatedChunk(String::from_utf8_lossy(&id).to_string()))?;
+        chunks.push(UnifChunk { id, data });
+        offset = start + len;
+    }
+    Ok(chunks)
+}
+
+// Board names are prefixed with who made the board (NES-, HVC-, UNL-, ...), which doesn't change
+// how it's mapped.
+pub fn board_mapper(board: &str) -> Option<u16> {
+    let board = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-", "KONAMI-", "TAITO-", "CAMERICA-"].iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(board);
+    match board {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => Some(0),
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM" | "SKROM" |
+        "SLROM" | "SL1ROM" | "SNROM" | "SOROM" | "SUROM" | "SXROM" => Some(1),
+        "UNROM" | "UOROM" => Some(2),
+        "CNROM" => Some(3),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TL1ROM" | "TNROM" | "TR1ROM" |
+        "TSROM" | "TVROM" | "HKROM" => Some(4),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => Some(5),
+        "PNROM" | "PEEOROM" => Some(9),
+        "GNROM" | "MHROM" => Some(66),
+        "BF9093" | "BF9097" => Some(71),
+        "BF9096" => Some(232),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_board_mapper() {
+        assert_eq!(board_mapper("NES-NROM-256"), Some(0));
+        assert_eq!(board_mapper("HVC-SNROM"), Some(1));
+        assert_eq!(board_mapper("NES-TLROM"), Some(4));
+        assert_eq!(board_mapper("MHROM"), Some(66));
+        assert_eq!(board_mapper("CAMERICA-BF9097"), Some(71));
+        assert_eq!(board_mapper("NES-EKROM"), Some(5));
+        assert_eq!(board_mapper("NES-AOROM"), None);
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let mut raw = UNIF_SIGNATURE.to_vec();
+        raw.extend([7, 0, 0, 0]);
+        raw.extend([0; 24]);
+        raw.extend(b"NAME\x06\x00\x00\x00alpi\x00\x00");
+        raw.extend(b"PRG1\x01\x00\x00\x00\xAA");
+        let chunks = read_chunks(&raw).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].as_str(), "alpi");
+        assert_eq!(chunks[1].bank_index(b"PRG"), Some(1));
+        assert_eq!(chunks[1].bank_index(b"CHR"), None);
+
+        raw.pop();
+        assert!(matches!(read_chunks(&raw), Err(RomError::TruncatedChunk(id)) if id == "PRG1"));
+    }
+}
@@ -2,4 +2,11 @@ pub mod bitvec;
 pub mod logger;
 pub mod sleep;
 pub mod audio;
+pub mod ringbuf;
 pub mod savestate;
+pub mod checksum;
+pub mod display;
+pub mod timestamp;
+pub mod replay;
+pub mod wav;
+pub mod font;
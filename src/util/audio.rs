@@ -1,13 +1,32 @@
+#[cfg(feature = "sdl")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "sdl")]
 use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+#[cfg(feature = "sdl")]
 use sdl2::AudioSubsystem;
+#[cfg(feature = "sdl")]
+use crate::util::ringbuf::RingBuffer;
+
+// Ref: https://www.nesdev.org/wiki/APU_Mixer
+
+// The pulses share one resistor network and the triangle, noise and DMC share another, so a channel
+// gets quieter the more the others on its network are playing:
+//
+//  pulse_out = 95.88 / (8128 / (pulse_one + pulse_two) + 100)
+//  tnd_out = 159.79 / (1 / (triangle / 8227 + noise / 12241 + dmc / 22638) + 100)
+//
+// Both are 0 when their inputs are, which the formulas only reach as a limit.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Channel {
+    PulseOne,
+    PulseTwo,
+    Triangle,
+    Noise,
+    Dmc,
+}
 
 pub struct APUMixer {
-    pub pulse_one: PulseWave,
-    pub pulse_two: PulseWave,
-    pub triangle: TriangleWave,
-    pub noise: NoiseWave,
-    pub dmc: DMCWave,
-
     pub volume: f32,
     pub mute: bool,
     pub mute_pulse_one: bool,
@@ -15,17 +34,34 @@ pub struct APUMixer {
     pub mute_triangle: bool,
     pub mute_noise: bool,
     pub mute_dmc: bool,
+    channel_volumes: [f32; 5],
+}
+
+// The 2A03's own mixing, before any of the volume controls of APUMixer.
+pub struct Mixer;
+
+impl Mixer {
+    // Mixes the pulse, triangle and noise channel outputs (0-15) and the DMC output level (0-127)
+    // into a sample between 0.0 and 1.0.
+    pub fn mix(pulse_one: u8, pulse_two: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        Mixer::mix_levels(pulse_one as f32, pulse_two as f32, triangle as f32, noise as f32, dmc as f32)
+    }
+
+    // Channel volumes can leave the levels between two steps, which the formulas take as they are.
+    fn mix_levels(pulse_one: f32, pulse_two: f32, triangle: f32, noise: f32, dmc: f32) -> f32 {
+        let pulse = pulse_one + pulse_two;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+        pulse_out + tnd_out
+    }
 }
 
 impl APUMixer {
     pub fn new() -> Self {
         Self {
-            pulse_one: PulseWave::new(1),
-            pulse_two: PulseWave::new(2),
-            triangle: TriangleWave::new(),
-            noise: NoiseWave::new(),
-            dmc: DMCWave::new(),
-
             volume: 1.0,
             mute: false,
             mute_pulse_one: false,
@@ -33,420 +69,273 @@ impl APUMixer {
             mute_triangle: false,
             mute_noise: false,
             mute_dmc: false,
+            channel_volumes: [1.0; 5],
         }
     }
-}
-
-impl AudioCallback for APUMixer {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        for sample in out.iter_mut() {
-            let pulse_one = if self.mute_pulse_one { 0.0 } else { self.pulse_one.sample() as f32 };
-            let pulse_two = if self.mute_pulse_two { 0.0 } else { self.pulse_two.sample() as f32 };
-            let pulse_out = 95.88 / (8128.0 / (pulse_one + pulse_two) + 100.0);
-
-            let triangle = if self.mute_triangle { 0.0 } else { self.triangle.sample() as f32 };
-            let noise = if self.mute_noise { 0.0 } else { self.noise.sample() as f32 };
-            let dmc = if self.mute_dmc { 0.0 } else { self.dmc.sample() as f32 };
-            let tnd = 1.0 / (triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0);
-            let tnd_out = 159.79 / (tnd + 100.0);
-
-            let sample_out = pulse_out + tnd_out;
-            let system_volume = if self.mute { 0.0 } else { 1.0 } * self.volume;
-            *sample = system_volume * sample_out;
-        }
-    }
-}
-
-pub struct PulseWave {
-    phase: f32,
-    phase_inc: f32,
-    envelope_enable: bool,
-    env_phase: f32,
-    env_phase_inc: f32,
-    sweep_enable: bool,
-    sweep_negate: bool,
-    sweep_phase: f32,
-    sweep_phase_inc: f32,
-    sweep_shift: u8,
-    sweep_timer: u16,
-    duration_enable: bool,
-    duration: f32,
-    duration_counter: f32,
-    volume: u8,
-    duty: u8,
-    channel: u8,
-}
-
-impl PulseWave {
-    pub fn new(channel: u8) -> Self {
-        Self {
-            phase: 0.0,
-            phase_inc: 0.0,
-            envelope_enable: false,
-            env_phase: 0.0,
-            env_phase_inc: 0.0,
-            sweep_enable: false,
-            sweep_negate: false,
-            sweep_phase: 0.0,
-            sweep_phase_inc: 0.0,
-            sweep_shift: 0,
-            sweep_timer: 0,
-            duration_enable: false,
-            duration: 0.0,
-            duration_counter: 0.0,
-            volume: 0,
-            duty: 0,
-            channel: channel
-        }
-    }
-
-    pub fn sample(&mut self) -> u8 {
-        // duty
-        let mut sample = match self.duty {
-            0 => if self.phase >= 0.125 && self.phase <= 0.250 { self.volume } else { 0 },
-            1 => if self.phase >= 0.125 && self.phase <= 0.375 { self.volume } else { 0 },
-            2 => if self.phase >= 0.125 && self.phase <= 0.625 { self.volume } else { 0 },
-            3 => if self.phase >= 0.125 && self.phase <= 0.375 { 0 } else { self.volume },
-            _ => panic!("can't be")
-        };
-
-        // waveform
-        self.phase = (self.phase + self.phase_inc) % 1.0;
-
-        // envelope
-        if self.envelope_enable {
-            let old_env_phase = self.env_phase;
-            self.env_phase = (self.env_phase + self.env_phase_inc) % 1.0;
-            if self.env_phase < old_env_phase && self.volume > 0 {
-                self.volume -= 1;
-            }
-        }
-
-        // sweep
-        // todo: sweep has some issues with timing:
-        //  - sometimes extra pitch at the end of mario's jump
-        //  - sometimes fire balls are noticeably higher pitched
-        let target_timer = self.get_sweep_target_timer();
-        if self.sweep_enable {
-            let old_sweep_phase = self.sweep_phase;
-            self.sweep_phase = (self.sweep_phase + self.sweep_phase_inc) % 1.0;
-            if self.sweep_phase < old_sweep_phase {
-                self.set_frequency_from_timer(target_timer);
-            }
-        }
-        if self.sweep_timer < 8 || target_timer > 0x7FF {
-            sample = 0; // mute
-        }
-
-        // loop vs one-shot
-        if !self.duration_enable {
-            return sample;
-        } else if self.duration_counter < self.duration {
-            self.duration_counter += 1.0;
-            return sample;
-        }
-        return 0;
-    }
 
-    pub fn silence(&mut self) {
-        self.volume = 0;
-        // self.phase = 0.0;
-        // self.env_phase = 0.0;
-        // self.sweep_phase = 0.0;
+    // Scales a channel's output before it is mixed, where 1.0 is the channel's natural level.
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.channel_volumes[channel as usize] = volume.max(0.0);
     }
 
-    // todo: is this actually the correct way to reset?
-    // pub fn reset(&mut self) {
-    //     self.phase = 0.0;
-    //     self.duration_counter = 0.0;
-    //     self.env_phase = 0.0;
-    //     self.volume = 15;
-    // }
-
-    pub fn reset(&mut self) {
-        self.phase = 0.0;
-        if self.sweep_enable {
-            self.sweep_phase = 0.0; // todo: do I need to reset this?
-        }
-        if self.envelope_enable {
-            self.env_phase = 0.0;
-            self.volume = 15;
-        }
+    pub fn get_channel_volume(&self, channel: Channel) -> f32 {
+        self.channel_volumes[channel as usize]
     }
 
-    fn get_sweep_target_timer(&mut self) -> u16 {
-        let mut delta = self.sweep_timer >> self.sweep_shift;
-        if self.sweep_negate {
-            delta = if self.channel == 1 { !delta } else { delta.wrapping_neg() };
+    pub fn mute_channel(&mut self, channel: Channel, mute: bool) {
+        match channel {
+            Channel::PulseOne => self.mute_pulse_one = mute,
+            Channel::PulseTwo => self.mute_pulse_two = mute,
+            Channel::Triangle => self.mute_triangle = mute,
+            Channel::Noise => self.mute_noise = mute,
+            Channel::Dmc => self.mute_dmc = mute,
         }
-        self.sweep_timer.wrapping_add(delta)
-    }
-
-    pub fn set_frequency_from_timer(&mut self, timer: u16) {
-        self.sweep_timer = timer;
-        self.set_frequency(1_789_773.0 / (16.0 * (timer as f32 + 1.0)));
     }
 
-    fn set_frequency(&mut self, freq: f32) {
-        self.phase_inc = freq / AudioPlayer::FREQ as f32;
-    }
-
-    pub fn set_envelope_enable(&mut self, envelope_enable: bool) {
-        // todo: this is super suspect, but it seems to work. make sure it works!
-        if !self.envelope_enable && envelope_enable {
-            self.env_phase = 0.0;
-            self.volume = 15;
+    pub fn is_channel_muted(&self, channel: Channel) -> bool {
+        match channel {
+            Channel::PulseOne => self.mute_pulse_one,
+            Channel::PulseTwo => self.mute_pulse_two,
+            Channel::Triangle => self.mute_triangle,
+            Channel::Noise => self.mute_noise,
+            Channel::Dmc => self.mute_dmc,
         }
-        self.envelope_enable = envelope_enable;
-    }
-
-    pub fn set_envelope_frequency(&mut self, env_freq: f32) {
-        self.env_phase_inc = env_freq / AudioPlayer::FREQ as f32;
-        self.env_phase = 0.0;
-    }
-
-    pub fn set_sweep_enable(&mut self, sweep_enable: bool) {
-        self.sweep_enable = sweep_enable;
-    }
-
-    pub fn set_sweep_negate(&mut self, sweep_negate: bool) {
-        self.sweep_negate = sweep_negate;
     }
 
-    pub fn set_sweep_frequency(&mut self, sweep_freq: f32) {
-        self.sweep_phase_inc = sweep_freq / AudioPlayer::FREQ as f32;
-        self.sweep_phase = 0.0;
+    // Mixes the channel outputs like Mixer::mix, after the channel volumes and mutes are applied.
+    pub fn sample(&self, pulse_one: u8, pulse_two: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        let sample_out = Mixer::mix_levels(
+            self.get_level(Channel::PulseOne, pulse_one),
+            self.get_level(Channel::PulseTwo, pulse_two),
+            self.get_level(Channel::Triangle, triangle),
+            self.get_level(Channel::Noise, noise),
+            self.get_level(Channel::Dmc, dmc),
+        );
+        let system_volume = if self.mute { 0.0 } else { 1.0 } * self.volume;
+        system_volume * sample_out
     }
 
-    pub fn set_sweep_shift(&mut self, sweep_shift: u8) {
-        self.sweep_shift = sweep_shift;
+    fn get_level(&self, channel: Channel, output: u8) -> f32 {
+        if self.is_channel_muted(channel) { 0.0 } else { output as f32 * self.get_channel_volume(channel) }
     }
+}
 
-    pub fn set_duration_enable(&mut self, duration_enable: bool) {
-        self.duration_enable = duration_enable;
-    }
+// Ref: https://docs.libretro.com/development/cores/dynamic-rate-control/
 
-    pub fn set_duration(&mut self, duration: f32) {
-        self.duration = duration;
-        self.duration_counter = 0.0;
-    }
+// The frame timer and the audio device run off different clocks, so a fixed number of samples per
+// frame slowly drains the audio queue (pops) or fills it up (growing latency). Instead, the queue
+// fill is checked as samples are handed over, and the resampler is nudged to make a fraction of a
+// percent more samples when it is below the target latency, and fewer when it is above:
+//
+//  adjustment = max_adjustment * (target - queued) / target     (clamped to +-max_adjustment)
+//
+// At 0.5% the pitch change is well below what can be heard.
 
-    pub fn set_volume(&mut self, volume: u8) {
-        self.volume = volume;
-    }
-
-    pub fn set_duty(&mut self, duty: u8) {
-        self.duty = duty;
-    }
+pub struct RateControl {
+    pub target_latency_ms: f64,
+    pub max_adjustment: f64,
 }
 
-pub struct TriangleWave {
-    phase: f32,
-    phase_inc: f32,
-    duration: f32,
-    duration_counter: f32,
-}
-
-impl TriangleWave {
-    const WAVEFORM: [u8; 32] = [
-        15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
-         0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15
-    ];
+impl RateControl {
+    pub const DEFAULT_TARGET_LATENCY_MS: f64 = 50.0;
+    pub const DEFAULT_MAX_ADJUSTMENT: f64 = 0.005;
 
     pub fn new() -> Self {
-        Self {
-            phase: 0.0,
-            phase_inc: 0.0,
-            duration: 0.0,
-            duration_counter: 0.0
+        RateControl {
+            target_latency_ms: RateControl::DEFAULT_TARGET_LATENCY_MS,
+            max_adjustment: RateControl::DEFAULT_MAX_ADJUSTMENT,
         }
     }
 
-    #[inline]
-    pub fn sample(&mut self) -> u8 {
-        if self.duration_counter < self.duration {
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-            self.duration_counter += 1.0;
-        }
-        let index = (32.0 * self.phase).floor() as usize;
-        TriangleWave::WAVEFORM[index]
-    }
-
-    #[inline]
-    pub fn silence(&mut self) {
-        self.phase = 0.0;
-        self.duration = 0.0;
-        self.duration_counter = 0.0;
-    }
-
-    pub fn set_duration(&mut self, duration: f32) {
-        self.duration = duration;
-        self.duration_counter = 0.0;
+    pub fn get_target_samples(&self, sample_rate: i32) -> f64 {
+        sample_rate as f64 * self.target_latency_ms / 1000.0
     }
 
-    pub fn set_frequency(&mut self, freq: f32) {
-        self.phase_inc = freq / AudioPlayer::FREQ as f32;
+    pub fn get_adjustment(&self, queued_samples: u32, sample_rate: i32) -> f64 {
+        let target = self.get_target_samples(sample_rate);
+        let error = (target - queued_samples as f64) / target;
+        error.clamp(-1.0, 1.0) * self.max_adjustment
     }
 }
 
-pub struct NoiseWave {
-    shift_register: u16,
-    phase: f32,
-    phase_inc: f32,
-    duration: f32,
-    duration_counter: f32,
-    volume: u8,
-    tone_mode: bool,
+// The sample rate asked of the audio device, and how much audio is buffered ahead of it. A bigger
+// buffer rides out longer hiccups on the emulation thread, at the cost of sound lagging the picture.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    pub buffer_ms: u32,
 }
 
-impl NoiseWave {
-    pub fn new() -> Self {
-        Self {
-            shift_register: 1,
-            phase: 0.0,
-            phase_inc: 0.0,
-            duration: 0.0,
-            duration_counter: 0.0,
-            volume: 0,
-            tone_mode: true
-        }
-    }
+impl AudioConfig {
+    pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
 
-    #[inline]
-    pub fn sample(&mut self) -> u8 {
-        let old_phase = self.phase;
-        if self.duration_counter < self.duration {
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-            self.duration_counter += 1.0;
-        }
-        if self.phase < old_phase {
-            let mode_bit = if self.tone_mode { 6 } else { 1 };
-            let feedback = (self.shift_register & 1) ^ ((self.shift_register >> mode_bit) & 1);
-            self.shift_register = self.shift_register >> 1;
-            self.shift_register = self.shift_register | (feedback << 14);
+    pub fn new() -> Self {
+        AudioConfig {
+            sample_rate: AudioConfig::DEFAULT_SAMPLE_RATE,
+            buffer_ms: RateControl::DEFAULT_TARGET_LATENCY_MS as u32,
         }
-        self.volume * (self.shift_register & 1) as u8
-
-        // todo: this is the fceux implementation. Which one is better?
-        // if self.phase < old_phase {
-        //     self.shift_register = (self.shift_register << 1) + (((self.shift_register >> 13) ^ ( self.shift_register >> 14)) & 1);
-        //     // self.shift_register = ( self.shift_register<<1)+(((self.shift_register>>8)^( self.shift_register>>14))&1);
-        // }
-        // self.volume * ((self.shift_register >> 14) & 1) as u8
-    }
-
-    #[inline]
-    pub fn silence(&mut self) {
-        self.phase = 0.0;
-        self.volume = 0;
-        self.duration = 0.0;
-    }
-
-    pub fn set_frequency(&mut self, freq: f32) {
-        self.phase_inc = freq / AudioPlayer::FREQ as f32;
-        self.phase = 0.0;
-    }
-
-    pub fn set_duration(&mut self, duration: f32) {
-        self.duration = duration;
-        self.duration_counter = 0.0;
-    }
-
-    pub fn set_is_tone_mode(&mut self, tone_mode: bool) {
-        self.tone_mode = tone_mode;
-    }
-
-    pub fn set_volume(&mut self, volume: u8) {
-        self.volume = volume;
     }
 }
 
-// todo: fully implement DMC
-pub struct DMCWave {
-    phase: f32,
-    phase_inc: f32,
-    duration: f32,
-    duration_counter: f32,
-    volume: u8,
-    silence: bool,
-    dpcm_samples: Vec<u8>,
+// Samples are generated on the emulation thread and handed to SDL's audio thread through a ring
+// buffer, which the device's callback drains as it needs them:
+//
+//  APU -> resampler -> play() -> [ ring buffer ] -> callback() -> device
+//
+// When the emulator falls behind, the callback plays silence instead of waiting, and when it runs
+// ahead (ie. fast-forward), samples that don't fit in the buffer are dropped.
+#[cfg(feature = "sdl")]
+struct RingBufferCallback {
+    buffer: Arc<Mutex<RingBuffer<f32>>>,
 }
 
-impl DMCWave {
-    pub fn new() -> Self {
-        Self {
-            phase: 0.0,
-            phase_inc: 0.0,
-            duration: 0.0,
-            duration_counter: 0.0,
-            volume: 0,
-            silence: false,
-            dpcm_samples: Vec::new(),
-        }
-    }
+#[cfg(feature = "sdl")]
+impl AudioCallback for RingBufferCallback {
+    type Channel = f32;
 
-    #[inline]
-    pub fn sample(&mut self) -> u8 {
-        if self.duration_counter < self.duration {
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-            self.duration_counter += 1.0;
+    fn callback(&mut self, out: &mut [f32]) {
+        match self.buffer.lock() {
+            Ok(mut buffer) => { buffer.pop_into(out, 0.0); },
+            Err(_) => out.fill(0.0),
         }
-        self.volume
-    }
-
-    #[inline]
-    pub fn silence(&mut self) {
-        self.phase = 0.0;
-        self.silence = true;
-    }
-
-    pub fn set_frequency(&mut self, freq: f32) {
-        self.phase_inc = freq / AudioPlayer::FREQ as f32;
-        self.phase = 0.0;
-    }
-
-    pub fn set_duration(&mut self, duration: f32) {
-        self.duration = duration;
-        self.duration_counter = 0.0;
-    }
-
-    pub fn set_volume(&mut self, volume: u8) {
-        self.volume = volume;
-    }
-
-    pub fn add_dpcm_sample(&mut self, sample: u8) {
-        self.dpcm_samples.push(sample);
     }
 }
 
+#[cfg(feature = "sdl")]
 pub struct AudioPlayer {
     pub sdl_audio: AudioSubsystem,
     pub spec: AudioSpecDesired,
-    pub device: AudioDevice<APUMixer>,
+    device: AudioDevice<RingBufferCallback>,
+    buffer: Arc<Mutex<RingBuffer<f32>>>,
+    freq: i32,
 }
 
+#[cfg(feature = "sdl")]
 impl AudioPlayer {
-    pub const FREQ: i32 = 16 * 44100;
-    pub const LENGTH_LOOKUP: [u16; 32] = [
-        10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
-        12, 16,  24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30
-    ];
-
-    pub fn new(sdl_audio: AudioSubsystem) -> Self {
+    pub fn new(sdl_audio: AudioSubsystem, config: &AudioConfig) -> Self {
         let spec = AudioSpecDesired {
-            freq: Some(AudioPlayer::FREQ),
+            freq: Some(config.sample_rate as i32),
             channels: Some(1),
             samples: None
         };
-        let device = sdl_audio.open_playback(None, &spec, |_| {
-            APUMixer::new()
-        }).unwrap();
-        device.resume();
-        AudioPlayer { sdl_audio, spec, device }
+        let buffer = Arc::new(Mutex::new(RingBuffer::new(0)));
+        let callback = RingBufferCallback { buffer: Arc::clone(&buffer) };
+        let device = sdl_audio.open_playback(None, &spec, |_| callback).unwrap();
+        // the device is free to pick another rate than the one asked for
+        let freq = device.spec().freq;
+        let mut audio_player = AudioPlayer { sdl_audio, spec, device, buffer, freq };
+        audio_player.set_buffer_ms(config.buffer_ms as f64);
+        audio_player.device.resume();
+        audio_player
     }
 
-    pub fn play(&self) {
+    // The sample rate the device was actually opened with.
+    pub fn get_freq(&self) -> i32 {
+        self.freq
     }
-}
\ No newline at end of file
+
+    pub fn get_queued_samples(&self) -> u32 {
+        self.buffer.lock().map_or(0, |buffer| buffer.len() as u32)
+    }
+
+    // The buffer holds twice the target latency, so the rate control has room to steer either way.
+    pub fn set_buffer_ms(&mut self, buffer_ms: f64) {
+        let capacity = 2.0 * self.freq as f64 * buffer_ms / 1000.0;
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.set_capacity(capacity as usize);
+        }
+    }
+
+    pub fn play(&self, samples: &[f32]) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.push_slice(samples);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{} != {}", actual, expected);
+    }
+
+    fn tnd_formula(triangle: f32, noise: f32, dmc: f32) -> f32 {
+        159.79 / (1.0 / (triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0) + 100.0)
+    }
+
+    #[test]
+    fn test_mix() {
+        assert_eq!(Mixer::mix(0, 0, 0, 0, 0), 0.0);
+        assert_close(Mixer::mix(15, 0, 0, 0, 0), 95.88 / (8128.0 / 15.0 + 100.0));
+        assert_close(Mixer::mix(15, 15, 0, 0, 0), 95.88 / (8128.0 / 30.0 + 100.0));
+        assert_close(Mixer::mix(0, 0, 15, 0, 0), tnd_formula(15.0, 0.0, 0.0));
+        assert_close(Mixer::mix(0, 0, 0, 0, 127), tnd_formula(0.0, 0.0, 127.0));
+        assert_close(Mixer::mix(8, 4, 15, 15, 127), 95.88 / (8128.0 / 12.0 + 100.0) + tnd_formula(15.0, 15.0, 127.0));
+
+        // everything at full volume lands on 1.0
+        assert_close(Mixer::mix(15, 15, 15, 15, 127), 1.0);
+        assert_eq!(APUMixer::new().sample(8, 4, 15, 15, 127), Mixer::mix(8, 4, 15, 15, 127));
+    }
+
+    #[test]
+    fn test_mix_is_non_linear() {
+        // a channel gets quieter the more the others on its network are playing
+        let pulse_one = Mixer::mix(15, 0, 0, 0, 0);
+        assert!(Mixer::mix(15, 15, 0, 0, 0) < 2.0 * pulse_one);
+        let triangle = Mixer::mix(0, 0, 15, 0, 0);
+        assert!(Mixer::mix(0, 0, 15, 0, 127) - Mixer::mix(0, 0, 0, 0, 127) < triangle);
+        // but the two networks don't affect each other
+        assert_close(Mixer::mix(15, 0, 15, 0, 0), pulse_one + triangle);
+    }
+
+    #[test]
+    fn test_channel_volume_and_mute() {
+        let mut mixer = APUMixer::new();
+        mixer.set_channel_volume(Channel::Triangle, 0.5);
+        assert_close(mixer.sample(0, 0, 10, 0, 0), Mixer::mix(0, 0, 5, 0, 0));
+        mixer.set_channel_volume(Channel::Triangle, 0.25);
+        assert_close(mixer.sample(0, 0, 10, 0, 0), tnd_formula(2.5, 0.0, 0.0));
+
+        mixer.mute_channel(Channel::PulseTwo, true);
+        assert!(mixer.is_channel_muted(Channel::PulseTwo));
+        assert_close(mixer.sample(6, 15, 0, 0, 0), Mixer::mix(6, 0, 0, 0, 0));
+        mixer.mute_channel(Channel::PulseTwo, false);
+        assert_close(mixer.sample(6, 15, 0, 0, 0), Mixer::mix(6, 15, 0, 0, 0));
+
+        mixer.mute = true;
+        assert_eq!(mixer.sample(15, 15, 15, 15, 127), 0.0);
+    }
+
+    #[test]
+    fn test_rate_control_adjustment() {
+        let rate_control = RateControl::new();
+        assert_eq!(rate_control.get_target_samples(44_100), 2205.0);
+        assert_eq!(rate_control.get_adjustment(2205, 44_100), 0.0);
+        assert_eq!(rate_control.get_adjustment(0, 44_100), 0.005);
+        assert_eq!(rate_control.get_adjustment(10_000, 44_100), -0.005);
+        assert!((rate_control.get_adjustment(1102, 44_100) - 0.0025).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_rate_control_keeps_queue_centered() {
+        // an hour of frames, with the audio device clock running 0.3% fast against the frame timer
+        let rate_control = RateControl::new();
+        let device_rate = 44_100.0 * 1.003;
+        let samples_per_frame = 44_100.0 / 60.0988;
+        let mut queued: f64 = 0.0;
+        let mut max_queued: f64 = 0.0;
+        for frame in 0..60 * 60 * 60 {
+            let adjustment = rate_control.get_adjustment(queued as u32, 44_100);
+            queued += samples_per_frame * (1.0 + adjustment);
+            queued = f64::max(0.0, queued - device_rate / 60.0988);
+            if frame > 60 * 60 {
+                assert!(queued > 0.0, "underrun on frame {}", frame);
+                max_queued = max_queued.max(queued);
+            }
+        }
+        assert!((queued - 2205.0).abs() < 2205.0 * 0.75);
+        assert!(max_queued < 2205.0 * 1.5);
+    }
+}
@@ -0,0 +1,37 @@
+// Ref: https://www.w3.org/TR/png/#D-CRCAppendix
+
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320; // reflected 0x04C11DB7
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
+
+// Continues a CRC over more bytes, so chunked data can be checked without concatenating it first.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (CRC32_POLYNOMIAL & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0x0000_0000);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn test_crc32_update() {
+        assert_eq!(crc32_update(crc32(b"12345"), b"6789"), crc32(b"123456789"));
+    }
+}
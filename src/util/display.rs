@@ -0,0 +1,96 @@
+// Ref: https://www.nesdev.org/wiki/Overscan
+
+use crate::nes::io::frame::Frame;
+use crate::nes::io::viewport::Viewport;
+
+// TVs hide a few pixels on every edge of the picture, and most games leave garbage there (ex: the
+// attribute glitches of horizontal scrolling). NTSC pixels are also a bit wider than they are tall,
+// so square pixels make the picture look squashed horizontally.
+//
+//  visible width:  (256 - left - right) * scale * 8/7 (with aspect correction)
+//  visible height: (240 - top - bottom) * scale
+
+pub struct Overscan {
+    pub top: u8,
+    pub bottom: u8,
+    pub left: u8,
+    pub right: u8,
+}
+
+impl Overscan {
+    pub fn new(top: u8, bottom: u8, left: u8, right: u8) -> Self {
+        Overscan { top, bottom, left, right }
+    }
+}
+
+pub struct DisplayConfig {
+    pub scale: f32,
+    pub aspect_correction: bool,
+    pub overscan: Overscan,
+}
+
+impl DisplayConfig {
+    pub const NTSC_PIXEL_ASPECT_RATIO: f32 = 8.0 / 7.0;
+
+    pub fn new() -> Self {
+        DisplayConfig {
+            scale: 3.0,
+            aspect_correction: true,
+            overscan: Overscan::new(8, 8, 0, 0),
+        }
+    }
+
+    // The part of the frame left after trimming the overscan, in frame pixels.
+    pub fn get_viewport(&self) -> Viewport {
+        let overscan = &self.overscan;
+        let x1 = usize::min(overscan.left as usize, Frame::WIDTH);
+        let y1 = usize::min(overscan.top as usize, Frame::HEIGHT);
+        let x2 = usize::max(x1, Frame::WIDTH.saturating_sub(overscan.right as usize));
+        let y2 = usize::max(y1, Frame::HEIGHT.saturating_sub(overscan.bottom as usize));
+        Viewport::new(x1, y1, x2, y2)
+    }
+
+    pub fn get_window_size(&self) -> (u32, u32) {
+        let viewport = self.get_viewport();
+        let pixel_aspect_ratio = if self.aspect_correction { DisplayConfig::NTSC_PIXEL_ASPECT_RATIO } else { 1.0 };
+        let width = (viewport.x2 - viewport.x1) as f32 * self.scale * pixel_aspect_ratio;
+        let height = (viewport.y2 - viewport.y1) as f32 * self.scale;
+        (u32::max(1, width.round() as u32), u32::max(1, height.round() as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_window_size() {
+        let config = DisplayConfig::new();
+        let viewport = config.get_viewport();
+        assert_eq!((viewport.x1, viewport.y1, viewport.x2, viewport.y2), (0, 8, 256, 232));
+        assert_eq!(config.get_window_size(), (878, 672));
+    }
+
+    #[test]
+    fn test_window_size() {
+        let mut config = DisplayConfig::new();
+        config.scale = 2.0;
+        config.aspect_correction = false;
+        config.overscan = Overscan::new(0, 0, 0, 0);
+        assert_eq!(config.get_window_size(), (512, 480));
+
+        config.overscan = Overscan::new(8, 8, 8, 8);
+        assert_eq!(config.get_window_size(), (480, 448));
+        config.aspect_correction = true;
+        assert_eq!(config.get_window_size(), (549, 448));
+    }
+
+    #[test]
+    fn test_overscan_larger_than_frame() {
+        let mut config = DisplayConfig::new();
+        config.overscan = Overscan::new(200, 200, 255, 255);
+        let viewport = config.get_viewport();
+        assert_eq!((viewport.x1, viewport.y1, viewport.x2, viewport.y2), (255, 200, 255, 200));
+        assert_eq!(config.get_window_size(), (1, 1));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::nes::io::frame::Frame;
+
+// A 5x7 bitmap font with just enough glyphs for hex dumps: 0-9, A-F, '$', ':', '>', '_' and space.
+// Every row is 5 bits wide, most significant bit on the left. Glyphs are drawn in 6x8 cells, which
+// leaves a pixel of spacing between characters and lines.
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+pub const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+pub const CELL_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+pub fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    let rows = match c.to_ascii_uppercase() {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        '$' => [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100],
+        ':' => [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000],
+        '>' => [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000],
+        '_' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111],
+        ' ' => [0; GLYPH_HEIGHT],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+// Draws text onto the frame's background with its top-left corner at (x, y). Characters without a
+// glyph are left blank, and anything past the edge of the frame is clipped.
+pub fn draw_text(frame: &mut Frame, x: usize, y: usize, text: &str, rgb: (u8, u8, u8)) {
+    for (i, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else { continue };
+        let origin_x = x + CELL_WIDTH * i;
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                let (px, py) = (origin_x + col, y + row);
+                if bits & (0b10000 >> col) != 0 && px < frame.width && py < frame.height {
+                    frame.set_background_color(px, py, rgb);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glyphs() {
+        for c in "0123456789ABCDEFabcdef$:>_ ".chars() {
+            let rows = glyph(c).unwrap();
+            assert!(rows.iter().all(|row| *row < 1 << GLYPH_WIDTH));
+        }
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('G'), None);
+    }
+
+    #[test]
+    fn test_draw_text() {
+        let mut frame = Frame::with_size(16, 8);
+        let white = (0xFF, 0xFF, 0xFF);
+        draw_text(&mut frame, 0, 0, "1_7", white);
+        // the stem of the 1, the bottom of the underscore, and the 7 clipped off the right edge
+        assert_eq!(frame.get_background_color(2, 0), white);
+        assert_eq!(frame.get_background_color(0, 0), (0, 0, 0));
+        assert_eq!(frame.get_background_color(6, 6), white);
+        assert_eq!(frame.get_background_color(6, 5), (0, 0, 0));
+        assert_eq!(frame.get_background_color(15, 0), white);
+    }
+}
@@ -1,5 +1,8 @@
 use std::fs::File;
-use std::io::Write;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::nes::cpu::trace::OPCODES;
 
 #[macro_export]
 macro_rules! logln {
@@ -8,23 +11,112 @@ macro_rules! logln {
     }
 }
 
+// Instruction traces are written in the layout of Nintendulator's nestest.log (see cpu/trace.rs),
+// so they can be diffed against a reference log with compare_log:
+//
+// C000  4C        JMP                             A:00 X:00 Y:00 P:24 SP:FD CYC:7
+//
+// Only the opcode is known here, so the operand bytes and the disassembled operand are left out.
+// The CPU only calls into the logger when built with `--features trace`, otherwise tracing compiles
+// away entirely.
+
 pub struct Logger {
-    file: File,
+    writer: Box<dyn Write>,
+    max_lines: Option<usize>,
+    lines: usize,
 }
 
 impl Logger {
     pub fn new(path: &str) -> Self {
-        Self {
-            file: File::create(path).unwrap()
-        }
+        Logger::to_file(Path::new(path)).unwrap()
+    }
+
+    pub fn to_file(path: &Path) -> io::Result<Self> {
+        Ok(Logger::to_writer(Box::new(BufWriter::new(File::create(path)?))))
+    }
+
+    pub fn to_writer(writer: Box<dyn Write>) -> Self {
+        Logger { writer, max_lines: None, lines: 0 }
+    }
+
+    // Once the limit is reached, further lines are dropped. Traces of a whole game add up quickly.
+    pub fn set_max_lines(&mut self, max_lines: Option<usize>) {
+        self.max_lines = max_lines;
+    }
+
+    pub fn get_max_lines(&self) -> Option<usize> {
+        self.max_lines
+    }
+
+    pub fn get_line_count(&self) -> usize {
+        self.lines
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max_lines.is_some_and(|max_lines| self.lines >= max_lines)
     }
 
     pub fn log(&mut self, text: &str) {
-        self.file.write(text.as_ref()).unwrap();
+        if self.is_full() { return }
+        self.writer.write_all(text.as_bytes()).unwrap();
     }
 
     pub fn logln(&mut self, text: &str) {
-        self.file.write(text.as_ref()).unwrap();
-        self.file.write("\n".as_ref()).unwrap();
+        if self.is_full() { return }
+        self.writer.write_all(text.as_bytes()).unwrap();
+        self.writer.write_all(b"\n").unwrap();
+        self.lines += 1;
     }
-}
\ No newline at end of file
+
+    pub fn trace_instruction(&mut self, pc: u16, opcode: u8, a: u8, x: u8, y: u8, sp: u8, p: u8, cycle: u64) {
+        let info = OPCODES[opcode as usize];
+        let line = format!("{:04X}  {:02X}       {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            pc, opcode, if info.unofficial { '*' } else { ' ' }, info.mnemonic, a, x, y, p, sp, cycle);
+        self.logln(&line);
+    }
+
+    // Writes out anything still buffered, ex: before comparing a trace against a reference log.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::nes::cpu::trace::CpuLogLine;
+
+    #[test]
+    fn test_trace_instruction() {
+        let path = std::env::temp_dir().join("alpines_test_trace.log");
+        let mut logger = Logger::to_file(&path).unwrap();
+        logger.trace_instruction(0xC000, 0x4C, 0x00, 0x00, 0x00, 0xFD, 0x24, 7);
+        logger.trace_instruction(0xC72A, 0x04, 0xAA, 0x97, 0x4E, 0xF5, 0xEF, 1654);
+        logger.flush().unwrap();
+
+        let log = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines[0], "C000  4C        JMP                             A:00 X:00 Y:00 P:24 SP:FD CYC:7");
+        let line = CpuLogLine::parse(lines[1]).unwrap();
+        assert_eq!((line.pc, line.bytes.clone(), line.mnemonic.as_str()), (0xC72A, vec![0x04], "NOP"));
+        assert!(line.unofficial);
+        assert_eq!((line.a, line.x, line.y, line.p, line.sp, line.cycles), (0xAA, 0x97, 0x4E, 0xEF, 0xF5, 1654));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_max_lines() {
+        let path = std::env::temp_dir().join("alpines_test_max_lines.log");
+        let mut logger = Logger::to_file(&path).unwrap();
+        logger.set_max_lines(Some(2));
+        for i in 0..5 {
+            logln!(logger, "line {}", i);
+        }
+        assert!(logger.is_full());
+        assert_eq!(logger.get_line_count(), 2);
+        logger.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line 0\nline 1\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
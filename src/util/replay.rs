@@ -0,0 +1,256 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::nes::rom::ROM;
+use sha2::{Digest, Sha256};
+
+// Replay file layout:
+// =====================================
+// [ magic "ARPL" | version | PRG ROM SHA-256 (32 bytes) | RNG seed (u64 LE) | NES 2.0 header (16 bytes) ]
+// [ frame (u32 LE) | controller 1 | controller 2 ]   <- one event per frame the inputs changed on
+// ...
+//
+// A replay always starts from power-on, so the inputs are all that's needed to play it back. The
+// last event marks the frame recording stopped on, even if the inputs didn't change. The header is
+// written in the NES 2.0 form even for iNES 1.0 dumps, so it says what the ROM was loaded as.
+
+const REPLAY_MAGIC: [u8; 4] = *b"ARPL";
+const REPLAY_VERSION: u8 = 1;
+const REPLAY_HEADER_SIZE: usize = 4 + 1 + 32 + 8 + ROM::HEADER_SIZE;
+const REPLAY_EVENT_SIZE: usize = 6;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReplayHeader {
+    pub rom_hash: [u8; 32],
+    pub seed: u64,
+    pub nes2_header: [u8; ROM::HEADER_SIZE],
+}
+
+impl ReplayHeader {
+    pub fn new(rom: &ROM, seed: u64) -> Self {
+        ReplayHeader {
+            rom_hash: Sha256::digest(&rom.prg_rom).into(),
+            seed,
+            nes2_header: rom.get_nes2_header(),
+        }
+    }
+
+    pub fn matches(&self, rom: &ROM) -> bool {
+        self.rom_hash == <[u8; 32]>::from(Sha256::digest(&rom.prg_rom))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(REPLAY_HEADER_SIZE);
+        bytes.extend_from_slice(&REPLAY_MAGIC);
+        bytes.push(REPLAY_VERSION);
+        bytes.extend_from_slice(&self.rom_hash);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.nes2_header);
+        bytes
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ReplayEvent {
+    pub frame: u32,
+    pub controller_one: u8,
+    pub controller_two: u8,
+}
+
+impl ReplayEvent {
+    fn to_bytes(self) -> [u8; REPLAY_EVENT_SIZE] {
+        let frame = self.frame.to_le_bytes();
+        [frame[0], frame[1], frame[2], frame[3], self.controller_one, self.controller_two]
+    }
+}
+
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        Replay::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Replay> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if bytes.len() < REPLAY_HEADER_SIZE || bytes[0..4] != REPLAY_MAGIC {
+            return Err(invalid("not a replay file"));
+        }
+        if bytes[4] != REPLAY_VERSION {
+            return Err(invalid("unsupported replay version"));
+        }
+        if !(bytes.len() - REPLAY_HEADER_SIZE).is_multiple_of(REPLAY_EVENT_SIZE) {
+            return Err(invalid("truncated replay event"));
+        }
+
+        let header = ReplayHeader {
+            rom_hash: bytes[5..37].try_into().unwrap(),
+            seed: u64::from_le_bytes(bytes[37..45].try_into().unwrap()),
+            nes2_header: bytes[45..REPLAY_HEADER_SIZE].try_into().unwrap(),
+        };
+        let events = bytes[REPLAY_HEADER_SIZE..].chunks_exact(REPLAY_EVENT_SIZE)
+            .map(|event| ReplayEvent {
+                frame: u32::from_le_bytes(event[0..4].try_into().unwrap()),
+                controller_one: event[4],
+                controller_two: event[5],
+            })
+            .collect();
+        Ok(Replay { header, events })
+    }
+}
+
+pub struct ReplayRecorder {
+    writer: BufWriter<File>,
+    frame: u32,
+    last_event: Option<ReplayEvent>,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &Path, header: &ReplayHeader) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&header.to_bytes())?;
+        Ok(ReplayRecorder { writer, frame: 0, last_event: None })
+    }
+
+    pub fn get_frame(&self) -> u32 {
+        self.frame
+    }
+
+    // Called once per frame with the buttons held on each controller.
+    pub fn record_frame(&mut self, controller_one: u8, controller_two: u8) -> io::Result<()> {
+        let event = ReplayEvent { frame: self.frame, controller_one, controller_two };
+        let changed = self.last_event.is_none_or(|last| (last.controller_one, last.controller_two) != (controller_one, controller_two));
+        if changed {
+            self.write_event(event)?;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(last) = self.last_event {
+            if last.frame + 1 < self.frame {
+                self.write_event(ReplayEvent { frame: self.frame - 1, ..last })?;
+            }
+        }
+        self.writer.flush()
+    }
+
+    fn write_event(&mut self, event: ReplayEvent) -> io::Result<()> {
+        self.writer.write_all(&event.to_bytes())?;
+        self.last_event = Some(event);
+        Ok(())
+    }
+}
+
+pub struct ReplayPlayer {
+    events: Vec<ReplayEvent>,
+    next_event: usize,
+    frame: u32,
+    inputs: (u8, u8),
+}
+
+impl ReplayPlayer {
+    pub fn new(events: Vec<ReplayEvent>) -> Self {
+        ReplayPlayer { events, next_event: 0, frame: 0, inputs: (0, 0) }
+    }
+
+    pub fn get_frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.last().is_none_or(|last| self.frame > last.frame)
+    }
+
+    // Returns the buttons held on each controller for the next frame, or None once the replay is over.
+    pub fn next_frame(&mut self) -> Option<(u8, u8)> {
+        if self.is_finished() { return None }
+
+        while let Some(event) = self.events.get(self.next_event) {
+            if event.frame > self.frame { break }
+            self.inputs = (event.controller_one, event.controller_two);
+            self.next_event += 1;
+        }
+        self.frame += 1;
+        Some(self.inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::rom::{RomFormat, RomInfo};
+    use crate::nes::rom::tests::test_rom;
+
+    fn test_header() -> ReplayHeader {
+        ReplayHeader::new(&test_rom(&[]), 0x1234_5678_9ABC_DEF0)
+    }
+
+    #[test]
+    fn test_header_matches_rom() {
+        let rom = test_rom(&[]);
+        let header = test_header();
+        assert!(header.matches(&rom));
+        assert!(!header.matches(&test_rom(&[0xEA])));
+        // loaded from an iNES 1.0 image, recorded as NES 2.0
+        assert_eq!(rom.header[7] & 0b1100, 0);
+        assert_eq!(header.nes2_header[7] & 0b1100, 0b1000);
+        let raw: Vec<u8> = header.nes2_header.iter().chain(&rom.prg_rom).copied().collect();
+        let info = ROM::from_bytes(&raw).unwrap().get_info();
+        assert_eq!(info.format, RomFormat::InesV2);
+        assert_eq!(RomInfo { format: RomFormat::InesV1, ..info }, rom.get_info());
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let path = std::env::temp_dir().join("alpines_test_replay.rpl");
+        let header = test_header();
+        let mut recorder = ReplayRecorder::create(&path, &header).unwrap();
+        for inputs in [(0, 0), (0, 0), (0x08, 0), (0x08, 0), (0x09, 0x80), (0, 0), (0, 0), (0, 0)] {
+            recorder.record_frame(inputs.0, inputs.1).unwrap();
+        }
+        assert_eq!(recorder.get_frame(), 8);
+        recorder.finish().unwrap();
+
+        let replay = Replay::load(&path).unwrap();
+        assert_eq!(replay.header, header);
+        let events: Vec<(u32, u8, u8)> = replay.events.iter()
+            .map(|event| (event.frame, event.controller_one, event.controller_two))
+            .collect();
+        assert_eq!(events, vec![(0, 0, 0), (2, 0x08, 0), (4, 0x09, 0x80), (5, 0, 0), (7, 0, 0)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_play() {
+        let events = vec![
+            ReplayEvent { frame: 0, controller_one: 0, controller_two: 0 },
+            ReplayEvent { frame: 2, controller_one: 0x08, controller_two: 0 },
+            ReplayEvent { frame: 3, controller_one: 0x01, controller_two: 0x02 },
+            ReplayEvent { frame: 5, controller_one: 0x01, controller_two: 0x02 },
+        ];
+        let mut player = ReplayPlayer::new(events);
+        let inputs: Vec<(u8, u8)> = std::iter::from_fn(|| player.next_frame()).collect();
+        assert_eq!(inputs, vec![(0, 0), (0, 0), (0x08, 0), (0x01, 0x02), (0x01, 0x02), (0x01, 0x02)]);
+        assert!(player.is_finished());
+        assert_eq!(player.get_frame(), 6);
+    }
+
+    #[test]
+    fn test_invalid_replay() {
+        let mut bytes = test_header().to_bytes();
+        assert!(Replay::from_bytes(&bytes).unwrap().events.is_empty());
+
+        bytes.extend_from_slice(&[0, 0, 0]);
+        assert_eq!(Replay::from_bytes(&bytes).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        bytes[4] = 2;
+        assert_eq!(Replay::from_bytes(&bytes).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(Replay::from_bytes(b"NES\x1a").err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
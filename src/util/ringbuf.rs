@@ -0,0 +1,113 @@
+// A fixed size FIFO that wraps around the end of its storage:
+//
+//  storage:  [ e | f | _ | _ | a | b | c | d ]
+//                      ^ tail  ^ head              <- pops come from head, pushes go to tail
+//
+// Nothing is allocated after it's created, which keeps it cheap to share with the audio thread.
+
+pub struct RingBuffer<T: Copy + Default> {
+    storage: Vec<T>,
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy + Default> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            storage: vec![T::default(); capacity],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.storage.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+
+    // Drops whatever is buffered, so it's only meant for when the buffer isn't in use.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.storage = vec![T::default(); capacity];
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    // Returns false if the buffer is full, in which case the value is dropped.
+    pub fn push(&mut self, value: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let tail = (self.head + self.len) % self.storage.len();
+        self.storage[tail] = value;
+        self.len += 1;
+        true
+    }
+
+    // Returns how many of the values fit, the rest are dropped.
+    pub fn push_slice(&mut self, values: &[T]) -> usize {
+        values.iter().take_while(|value| self.push(**value)).count()
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = self.storage[self.head];
+        self.head = (self.head + 1) % self.storage.len();
+        self.len -= 1;
+        Some(value)
+    }
+
+    // Fills `out` from the buffer, padding with `fill` once it runs dry. Returns how many values
+    // came from the buffer.
+    pub fn pop_into(&mut self, out: &mut [T], fill: T) -> usize {
+        let mut popped = 0;
+        for slot in out.iter_mut() {
+            match self.pop() {
+                Some(value) => {
+                    *slot = value;
+                    popped += 1;
+                },
+                None => *slot = fill,
+            }
+        }
+        popped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop_wraps_around() {
+        let mut ring = RingBuffer::new(4);
+        assert_eq!(ring.push_slice(&[1, 2, 3]), 3);
+        assert_eq!((ring.pop(), ring.pop()), (Some(1), Some(2)));
+        assert_eq!(ring.push_slice(&[4, 5, 6, 7]), 3);
+        assert!(ring.is_full());
+        assert!(!ring.push(8));
+        assert_eq!(ring.len(), 4);
+
+        let mut out = [0; 6];
+        assert_eq!(ring.pop_into(&mut out, -1), 4);
+        assert_eq!(out, [3, 4, 5, 6, -1, -1]);
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+    }
+}
@@ -5,13 +5,8 @@ use serde::{Serialize, Deserialize};
 use crate::nes::NES;
 use crate::nes::cpu::CPU;
 use crate::nes::ppu::PPU;
-use crate::nes::rom::{Mirroring, ROM};
-use crate::nes::rom::mappers::mapper1::Mapper1;
-use crate::nes::rom::mappers::mapper2::Mapper2;
-use crate::nes::rom::mappers::mapper3::Mapper3;
-use crate::nes::rom::mappers::mapper4::Mapper4;
-use crate::nes::rom::mappers::mapper66::Mapper66;
-use crate::{custom_ram_range, palletes_ram_range, prg_ram_range, ram_range, vram_range};
+use crate::nes::rom::ROM;
+use crate::{custom_ram_range, palletes_ram_range, ram_range, vram_range};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CPUState {
@@ -27,6 +22,8 @@ pub struct CPUState {
     pub prg_ram: Vec<u8>,
 
     pub cycles: usize,
+    #[serde(default)]
+    pub rng_state: u64,
 }
 
 impl CPUState {
@@ -40,8 +37,9 @@ impl CPUState {
             program_counter: cpu.program_counter,
             ram: cpu.memory.memory[ram_range!()].to_vec(),
             custom_ram: cpu.memory.memory[custom_ram_range!()].to_vec(),
-            prg_ram: cpu.memory.memory[prg_ram_range!()].to_vec(),
-            cycles: cpu.cycles
+            prg_ram: cpu.memory.get_prg_ram(),
+            cycles: cpu.cycles,
+            rng_state: cpu.get_rng_state(),
         }
     }
 }
@@ -70,6 +68,7 @@ pub struct PPUState {
 
     pub cycles: usize,
     pub scanline: isize,
+    pub dot: usize,
     pub nmi_flag: bool,
 }
 
@@ -98,6 +97,7 @@ impl PPUState {
 
             cycles: ppu.cycles,
             scanline: ppu.scanline,
+            dot: ppu.dot,
             nmi_flag: ppu.nmi_flag,
         }
     }
@@ -106,141 +106,16 @@ impl PPUState {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ROMState {
     pub chr_ram: Option<Vec<u8>>,
-    pub mapper1: Mapper1State,
-    pub mapper2: Mapper2State,
-    pub mapper3: Mapper3State,
-    pub mapper4: Mapper4State,
-    pub mapper66: Mapper66State,
+    // the board's own serialization, empty in savestates from before mappers kept their own
+    #[serde(default)]
+    pub mapper_state: Vec<u8>,
 }
 
 impl ROMState {
-    pub fn new(cpu_rom: &ROM, ppu_rom: &ROM) -> Self {
+    pub fn new(rom: &ROM) -> Self {
         ROMState {
-            chr_ram: if ppu_rom.is_chr_ram { Some(ppu_rom.chr_rom.to_vec()) } else { None },
-            mapper1: Mapper1State::new(&cpu_rom.mapper1),
-            mapper2: Mapper2State::new(&cpu_rom.mapper2),
-            mapper3: Mapper3State::new(&cpu_rom.mapper3),
-            mapper4: Mapper4State::new(&cpu_rom.mapper4),
-            mapper66: Mapper66State::new(&cpu_rom.mapper66),
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Mapper1State {
-    pub shift_reg_value: u8,
-    pub shift_reg_shift: u8,
-    pub prg_bank_select_mode: u8,
-    pub chr_bank_select_mode: u8,
-    pub prg_bank_select: u8,
-    pub chr_bank_select: u8,
-    pub chr_bank0_select: u8,
-    pub chr_bank1_select: u8,
-    pub screen_mirroring: Mirroring,
-}
-
-impl Mapper1State {
-    pub fn new(mapper1: &Mapper1) -> Self {
-        Mapper1State {
-            shift_reg_value: mapper1.shift_register.value,
-            shift_reg_shift: mapper1.shift_register.shift,
-            prg_bank_select_mode: mapper1.prg_bank_select_mode,
-            chr_bank_select_mode: mapper1.chr_bank_select_mode,
-            prg_bank_select: mapper1.prg_bank_select,
-            chr_bank_select: mapper1.chr_bank_select,
-            chr_bank0_select: mapper1.chr_bank0_select,
-            chr_bank1_select: mapper1.chr_bank1_select,
-            screen_mirroring: mapper1.screen_mirroring.clone(),
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Mapper2State {
-    pub prg_bank_select: u8,
-}
-
-impl Mapper2State {
-    pub fn new(mapper2: &Mapper2) -> Self {
-        Mapper2State {
-            prg_bank_select: mapper2.prg_bank_select,
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Mapper3State {
-    pub chr_bank_select: u8,
-}
-
-impl Mapper3State {
-    pub fn new(mapper3: &Mapper3) -> Self {
-        Mapper3State {
-            chr_bank_select: mapper3.chr_bank_select,
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Mapper4State {
-    pub bank_select: u8,
-    pub prg_bank_select_mode: u8,
-    pub chr_bank_select_mode: u8,
-    pub prg_bank0_select:u8,
-    pub prg_bank1_select:u8,
-    pub chr_bank0_select: u8,
-    pub chr_bank1_select: u8,
-    pub chr_bank0_1kb_select: u8,
-    pub chr_bank1_1kb_select: u8,
-    pub chr_bank2_1kb_select: u8,
-    pub chr_bank3_1kb_select: u8,
-    pub chr_bank0_2kb_select: u8,
-    pub chr_bank1_2kb_select: u8,
-    pub screen_mirroring: Mirroring,
-    pub irq_counter: Option<u8>,
-    pub irq_latch: Option<u8>,
-    pub irq_reload: Option<bool>,
-    pub irq_enable: Option<bool>,
-    pub irq_flag: Option<bool>,
-}
-
-impl Mapper4State {
-    pub fn new(mapper4: &Mapper4) -> Self {
-        Mapper4State {
-            bank_select: mapper4.bank_select,
-            prg_bank_select_mode: mapper4.prg_bank_select_mode,
-            chr_bank_select_mode: mapper4.chr_bank_select_mode,
-            prg_bank0_select: mapper4.prg_bank0_select,
-            prg_bank1_select: mapper4.prg_bank1_select,
-            chr_bank0_select: mapper4.chr_bank0_select,
-            chr_bank1_select: mapper4.chr_bank1_select,
-            chr_bank0_1kb_select: mapper4.chr_bank0_1kb_select,
-            chr_bank1_1kb_select: mapper4.chr_bank1_1kb_select,
-            chr_bank2_1kb_select: mapper4.chr_bank2_1kb_select,
-            chr_bank3_1kb_select: mapper4.chr_bank3_1kb_select,
-            chr_bank0_2kb_select: mapper4.chr_bank0_2kb_select,
-            chr_bank1_2kb_select: mapper4.chr_bank1_2kb_select,
-            screen_mirroring: mapper4.screen_mirroring.clone(),
-            irq_counter: Some(mapper4.irq_counter),
-            irq_latch: Some(mapper4.irq_latch),
-            irq_reload: Some(mapper4.irq_reload),
-            irq_enable: Some(mapper4.irq_enable),
-            irq_flag: Some(mapper4.irq_flag),
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Mapper66State {
-    pub prg_bank_select: u8,
-    pub chr_bank_select: u8,
-}
-
-impl Mapper66State {
-    pub fn new(mapper66: &Mapper66) -> Self {
-        Mapper66State {
-            prg_bank_select: mapper66.prg_bank_select,
-            chr_bank_select: mapper66.chr_bank_select,
+            chr_ram: if rom.is_chr_ram { Some(rom.chr_rom.to_vec()) } else { None },
+            mapper_state: rom.mapper.save_state(),
         }
     }
 }
@@ -257,7 +132,7 @@ impl SaveState {
         SaveState {
             cpu_state: CPUState::new(&nes.cpu),
             ppu_state: PPUState::new(&nes.cpu.memory.ppu),
-            rom_state: ROMState::new(&nes.cpu.memory.rom, &nes.cpu.memory.ppu.memory.rom),
+            rom_state: ROMState::new(nes.cpu.memory.rom()),
         }
     }
 
@@ -292,8 +167,12 @@ impl SaveState {
         // todo: [FEATURE] add apu restore for savestates
 
         let rom_state = &save_state.rom_state;
-        Self::load_rom_state(&mut nes.cpu.memory.rom, rom_state);
-        Self::load_rom_state(&mut nes.cpu.memory.ppu.memory.rom, rom_state);
+        Self::load_rom_state(nes.cpu.memory.rom_mut(), rom_state);
+
+        // PRG RAM goes in last, once the mapper state says which bank is mapped at $6000
+        // todo: [BUG] Need to also restore battery.sav file on load savestate
+        nes.cpu.memory.sync_prg_ram_control();
+        nes.cpu.memory.load_prg_ram(&cpu_state.prg_ram);
     }
 
     fn load_cpu_state(cpu: &mut CPU, cpu_state: &CPUState) {
@@ -305,9 +184,8 @@ impl SaveState {
         cpu.program_counter = cpu_state.program_counter;
         cpu.memory.memory[ram_range!()].copy_from_slice(cpu_state.ram.as_slice());
         cpu.memory.memory[custom_ram_range!()].copy_from_slice(cpu_state.custom_ram.as_slice());
-        // todo: [BUG] Need to also restore battery.sav file on load savestate
-        cpu.memory.memory[prg_ram_range!()].copy_from_slice(cpu_state.prg_ram.as_slice());
         cpu.cycles = cpu_state.cycles;
+        cpu.set_rng_state(cpu_state.rng_state);
     }
 
     fn load_ppu_state(ppu: &mut PPU, ppu_state: &PPUState) {
@@ -330,6 +208,7 @@ impl SaveState {
         ppu.scroll_ctx.w = ppu_state.scroll_ctx_w;
         ppu.data_buffer = ppu_state.data_buffer;
         ppu.scanline = ppu_state.scanline;
+        ppu.dot = ppu_state.dot;
         ppu.cycles = ppu_state.cycles;
         ppu.nmi_flag = ppu_state.nmi_flag;
     }
@@ -338,55 +217,10 @@ impl SaveState {
         if let Some(chr_ram) = &rom_state.chr_ram {
             rom.chr_rom.copy_from_slice(chr_ram.as_slice());
         }
-        match rom.mapper_id {
-            0 => {
-                // do nothing
-            },
-            1 => {
-                rom.mapper1.shift_register.value = rom_state.mapper1.shift_reg_value;
-                rom.mapper1.shift_register.shift = rom_state.mapper1.shift_reg_shift;
-                rom.mapper1.prg_bank_select_mode = rom_state.mapper1.prg_bank_select_mode;
-                rom.mapper1.chr_bank_select_mode = rom_state.mapper1.chr_bank_select_mode;
-                rom.mapper1.prg_bank_select = rom_state.mapper1.prg_bank_select;
-                rom.mapper1.chr_bank_select = rom_state.mapper1.chr_bank_select;
-                rom.mapper1.chr_bank0_select = rom_state.mapper1.chr_bank0_select;
-                rom.mapper1.chr_bank1_select = rom_state.mapper1.chr_bank1_select;
-                rom.mapper1.screen_mirroring = rom_state.mapper1.screen_mirroring.clone();
-                rom.screen_mirroring = rom_state.mapper1.screen_mirroring.clone();
-            },
-            2 => {
-                rom.mapper2.prg_bank_select = rom_state.mapper2.prg_bank_select;
-            },
-            3 => {
-                rom.mapper3.chr_bank_select = rom_state.mapper3.chr_bank_select;
-            },
-            4 => {
-                rom.mapper4.bank_select = rom_state.mapper4.bank_select;
-                rom.mapper4.prg_bank_select_mode = rom_state.mapper4.prg_bank_select_mode;
-                rom.mapper4.chr_bank_select_mode = rom_state.mapper4.chr_bank_select_mode;
-                rom.mapper4.prg_bank0_select = rom_state.mapper4.prg_bank0_select;
-                rom.mapper4.prg_bank1_select = rom_state.mapper4.prg_bank1_select;
-                rom.mapper4.chr_bank0_select = rom_state.mapper4.chr_bank0_select;
-                rom.mapper4.chr_bank1_select = rom_state.mapper4.chr_bank1_select;
-                rom.mapper4.chr_bank0_1kb_select = rom_state.mapper4.chr_bank0_1kb_select;
-                rom.mapper4.chr_bank1_1kb_select = rom_state.mapper4.chr_bank1_1kb_select;
-                rom.mapper4.chr_bank2_1kb_select = rom_state.mapper4.chr_bank2_1kb_select;
-                rom.mapper4.chr_bank3_1kb_select = rom_state.mapper4.chr_bank3_1kb_select;
-                rom.mapper4.chr_bank0_2kb_select = rom_state.mapper4.chr_bank0_2kb_select;
-                rom.mapper4.chr_bank1_2kb_select = rom_state.mapper4.chr_bank1_2kb_select;
-                rom.mapper4.screen_mirroring = rom_state.mapper4.screen_mirroring.clone();
-                rom.screen_mirroring = rom_state.mapper4.screen_mirroring.clone();
-                rom.mapper4.irq_counter = rom_state.mapper4.irq_counter.unwrap_or(0);
-                rom.mapper4.irq_latch = rom_state.mapper4.irq_latch.unwrap_or(0);
-                rom.mapper4.irq_reload = rom_state.mapper4.irq_reload.unwrap_or(false);
-                rom.mapper4.irq_enable = rom_state.mapper4.irq_enable.unwrap_or(false);
-                rom.mapper4.irq_flag = rom_state.mapper4.irq_flag.unwrap_or(false);
-            },
-            66 => {
-                rom.mapper66.prg_bank_select = rom_state.mapper66.prg_bank_select;
-                rom.mapper66.chr_bank_select = rom_state.mapper66.chr_bank_select;
-            },
-            _ => panic!("Save state for mapper is not supported: mapper {}", rom.mapper_id)
+        if rom_state.mapper_state.is_empty() {
+            println!("[WARNING] Savestate has no mapper state, the mapper is left as it is");
+        } else if let Err(err) = rom.mapper.load_state(&rom_state.mapper_state) {
+            println!("[WARNING] Unable to restore mapper state: {}", err);
         }
     }
 }
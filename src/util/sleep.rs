@@ -44,3 +44,67 @@ impl PreciseSleeper {
     }
 }
 
+
+// Paces the emulator to a fixed frame rate. Each frame gets a deadline one frame period after the
+// previous one, and `sync` sleeps until it. A frame that blows its budget pushes the deadline out
+// from the current time instead, so slow frames are skipped over rather than paid back later.
+pub struct FrameTimer {
+    frame_duration: Duration,
+    next_frame: Instant,
+    sleeper: PreciseSleeper,
+}
+
+impl FrameTimer {
+    pub const NES_FPS: f64 = 60.0988;
+
+    pub fn new(target_fps: f64) -> Self {
+        let frame_duration = Duration::from_secs_f64(1.0 / target_fps);
+        FrameTimer {
+            frame_duration,
+            next_frame: Instant::now() + frame_duration,
+            sleeper: PreciseSleeper::new(),
+        }
+    }
+
+    // Returns the number of microseconds slept.
+    pub fn sync(&mut self) -> u64 {
+        let now = Instant::now();
+        if now >= self.next_frame {
+            self.next_frame = now + self.frame_duration;
+            return 0;
+        }
+
+        let remaining = self.next_frame - now;
+        self.sleeper.precise_sleep(remaining.as_secs_f64());
+        self.next_frame += self.frame_duration;
+        remaining.as_micros() as u64
+    }
+
+    pub fn get_frame_duration(&self) -> Duration {
+        self.frame_duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_timer_sync() {
+        let mut timer = FrameTimer::new(50.0);
+        let start = Instant::now();
+        let slept = timer.sync();
+        assert!(slept > 0 && slept <= 20_000);
+        timer.sync();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_frame_timer_skips_late_frames() {
+        let mut timer = FrameTimer::new(50.0);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(timer.sync(), 0);
+        // the missed frames aren't caught up on
+        assert!(timer.sync() > 0);
+    }
+}
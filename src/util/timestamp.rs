@@ -0,0 +1,43 @@
+// Ref: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Formats the current UTC time as YYYY-MM-DD_HH-MM-SS, which sorts by name and is safe in file names.
+pub fn now() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+    format_unix_time(seconds)
+}
+
+pub fn format_unix_time(seconds: u64) -> String {
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let time_of_day = seconds % 86400;
+    format!("{:04}-{:02}-{:02}_{:02}-{:02}-{:02}", year, month, day,
+        time_of_day / 3600, (time_of_day / 60) % 60, time_of_day % 60)
+}
+
+// days since 1970-01-01 -> (year, month, day) in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153; // March is 0
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_unix_time() {
+        assert_eq!(format_unix_time(0), "1970-01-01_00-00-00");
+        assert_eq!(format_unix_time(951_782_400), "2000-02-29_00-00-00");
+        assert_eq!(format_unix_time(1_700_000_000), "2023-11-14_22-13-20");
+        assert_eq!(format_unix_time(4_102_444_799), "2099-12-31_23-59-59");
+    }
+}
@@ -0,0 +1,114 @@
+// Ref: http://soundfile.sapp.org/doc/WaveFormat/
+
+// WAV file layout (16-bit mono PCM):
+// =====================================
+// [ "RIFF" | file size - 8 | "WAVE" ]
+// [ "fmt " | 16 | format (1 = PCM) | channels | sample rate | byte rate | block align | bits per sample ]
+// [ "data" | data size | samples (i16 LE) ... ]
+//
+// The sizes aren't known until recording stops, so they're written as 0 and patched in by `finish`.
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const WAV_HEADER_SIZE: u32 = 44;
+const PCM_FORMAT: u16 = 1;
+const CHANNELS: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_SAMPLE: u32 = (BITS_PER_SAMPLE / 8) as u32;
+
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    sample_rate: u32,
+    sample_count: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut wav = WavWriter {
+            writer: BufWriter::new(File::create(path)?),
+            sample_rate,
+            sample_count: 0,
+        };
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    // Samples are clamped to -1.0..1.0, which is the full range of a 16-bit sample.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.writer.write_all(&pcm.to_le_bytes())?;
+        }
+        self.sample_count += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.flush()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let data_size = self.sample_count * BYTES_PER_SAMPLE;
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&PCM_FORMAT.to_le_bytes());
+        header.extend_from_slice(&CHANNELS.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * CHANNELS as u32 * BYTES_PER_SAMPLE).to_le_bytes());
+        header.extend_from_slice(&(CHANNELS * BITS_PER_SAMPLE / 8).to_le_bytes());
+        header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+        self.writer.write_all(&header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_wav_writer() {
+        let path = std::env::temp_dir().join("alpines_test_wav_writer.wav");
+        let mut wav = WavWriter::create(&path, 44_100).unwrap();
+        wav.write_samples(&[0.0, 1.0, -1.0]).unwrap();
+        wav.write_samples(&[0.5, 2.0]).unwrap();
+        assert_eq!(wav.get_sample_count(), 5);
+        wav.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 10);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(read_u32(&bytes, 4), 36 + 10);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(read_u32(&bytes, 16), 16);
+        assert_eq!(bytes[20..24], [1, 0, 1, 0]);
+        assert_eq!(read_u32(&bytes, 24), 44_100);
+        assert_eq!(read_u32(&bytes, 28), 88_200);
+        assert_eq!(bytes[32..36], [2, 0, 16, 0]);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(read_u32(&bytes, 40), 10);
+
+        let samples: Vec<i16> = bytes[44..].chunks_exact(2).map(|pcm| i16::from_le_bytes([pcm[0], pcm[1]])).collect();
+        assert_eq!(samples, vec![0, 32767, -32767, 16384, 32767]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+// Ref: https://rustwasm.github.io/docs/wasm-bindgen/reference/types/boxed-number-slices.html
+
+// The emulator as a browser front end sees it: ROM bytes in, a frame of RGBA pixels (ready for
+// ImageData) and a batch of audio samples out, with the controllers as plain button bitmasks.
+// Everything crosses the boundary as a byte or float buffer, which wasm-bindgen hands to JS as a
+// Uint8Array / Float32Array:
+//
+//  const emulator = new WasmEmulator(romBytes);
+//  const pixels = emulator.step_frame(buttons1, buttons2);
+//  ctx.putImageData(new ImageData(new Uint8ClampedArray(pixels.buffer), 256, 240), 0, 0);
+//
+// It runs the bare console, the windowed front end in emu.rs needs SDL2 and isn't built for the
+// browser.
+
+use wasm_bindgen::prelude::*;
+use crate::nes::io::frame::Frame;
+use crate::nes::NES;
+use crate::nes::rom::ROM;
+
+// ~0.19s at 44.1kHz, a front end should be asking for samples every frame
+const AUDIO_BUFFER_SIZE: usize = 8192;
+
+#[wasm_bindgen]
+pub struct WasmEmulator {
+    nes: NES,
+}
+
+#[wasm_bindgen]
+impl WasmEmulator {
+    // Throws the ROM error's message if the bytes aren't a ROM we can run.
+    #[wasm_bindgen(constructor)]
+    pub fn new_from_bytes(rom_bytes: &[u8]) -> Result<WasmEmulator, String> {
+        let rom = ROM::from_image(rom_bytes).map_err(|err| err.to_string())?;
+        let mut nes = NES::new();
+        nes.load_rom(&rom);
+        nes.cpu.memory.apu.start_sample_capture(AUDIO_BUFFER_SIZE);
+        Ok(WasmEmulator { nes })
+    }
+
+    pub fn get_width(&self) -> usize {
+        Frame::WIDTH
+    }
+
+    pub fn get_height(&self) -> usize {
+        Frame::HEIGHT
+    }
+
+    // Runs a frame with the buttons held (A in bit 0, Right in bit 7) and returns its pixels as
+    // RGBA, row by row.
+    pub fn step_frame(&mut self, controller1: u8, controller2: u8) -> Vec<u8> {
+        let memory = &mut self.nes.cpu.memory;
+        memory.joycon1.set_buttons(controller1);
+        memory.joycon2.set_buttons(controller2);
+        self.nes.run_frame();
+        let mut frame = self.nes.cpu.memory.ppu.frame.clone();
+        frame.compose().chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+            .collect()
+    }
+
+    // The samples made since the last call, at the APU's output rate (44.1kHz unless changed).
+    pub fn get_audio_samples(&mut self) -> Vec<f32> {
+        self.nes.cpu.memory.apu.take_captured_samples()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::cpu::CPU;
+    use crate::nes::rom::tests::test_rom;
+
+    #[test]
+    fn test_wasm_emulator() {
+        // turns on the square wave at full volume and spins
+        let program = [
+            CPU::LDA_IM, 0x01, CPU::STA_AB, 0x15, 0x40, CPU::LDA_IM, 0xBF, CPU::STA_AB, 0x00, 0x40,
+            CPU::LDA_IM, 0xFD, CPU::STA_AB, 0x02, 0x40, CPU::LDA_IM, 0x00, CPU::STA_AB, 0x03, 0x40,
+            CPU::JMP_AB, 0x14, 0x80,
+        ];
+        let raw = test_rom(&program).to_ines_bytes();
+
+        let mut emulator = WasmEmulator::new_from_bytes(&raw).unwrap();
+        let pixels = emulator.step_frame(0x01, 0x80);
+        assert_eq!(pixels.len(), 4 * emulator.get_width() * emulator.get_height());
+        assert!(pixels.chunks_exact(4).all(|rgba| rgba[3] == 0xFF));
+        assert_eq!(emulator.nes.cpu.memory.joycon2.get_buttons(), 0x80);
+
+        let samples = emulator.get_audio_samples();
+        assert!(samples.len() > 700 && samples.len() < 800, "{} samples", samples.len());
+        assert!(samples.iter().any(|sample| sample.abs() > 0.01));
+        assert!(emulator.get_audio_samples().is_empty());
+
+        assert!(WasmEmulator::new_from_bytes(b"NES").is_err());
+    }
+}
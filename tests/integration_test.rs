@@ -1,4 +1,49 @@
-#[cfg(test)]
-mod tests {
-    use super::*;
-}
\ No newline at end of file
+use alpines::nes::io::frame::Frame;
+use alpines::nes::NES;
+use alpines::nes::rom::{Mirroring, ROM};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+
+// NROM cartridge that turns on the background and spins, with CHR RAM and a zeroed nametable
+fn background_rom() -> ROM {
+    let mut prg_rom = vec![0; ROM::PRG_ROM_PAGE_SIZE];
+    let program = [
+        0xA9, 0x0A, 0x8D, 0x01, 0x20, // LDA #$0A, STA $2001
+        0x4C, 0x05, 0x80,             // JMP $8005
+    ];
+    prg_rom[..program.len()].copy_from_slice(&program);
+    prg_rom[0x3FFC] = 0x00; // reset vector -> $8000
+    prg_rom[0x3FFD] = 0x80;
+    ROM::from_raw_prg(&prg_rom, 0, Mirroring::Horizontal).unwrap()
+}
+
+#[test]
+fn test_headless_frame_to_png() {
+    let mut nes = NES::new();
+    nes.load_rom(&background_rom());
+    nes.cpu.memory.ppu.memory.write_byte(0x3F00, 0x21);
+
+    // run until the PPU enters vblank twice, so a whole frame has been drawn with rendering on
+    let mut frames = 0;
+    while frames < 2 {
+        nes.step().unwrap();
+        if nes.cpu.memory.ppu.status.get_value() & 0x80 != 0 {
+            nes.cpu.memory.ppu.read_status_register();
+            frames += 1;
+        }
+    }
+
+    // every tile is blank, so the whole picture is the backdrop color
+    let backdrop = nes.cpu.memory.ppu.apply_post_processing(0x21);
+    let frame = &mut nes.cpu.memory.ppu.frame;
+    frame.compose();
+    assert_eq!(frame.get_background_color(0, 0), backdrop);
+    assert_eq!(frame.get_background_color(255, 239), backdrop);
+
+    let png = frame.to_png_bytes();
+    assert_eq!(png[0..8], PNG_SIGNATURE);
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), Frame::WIDTH as u32);
+    assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), Frame::HEIGHT as u32);
+    assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+}